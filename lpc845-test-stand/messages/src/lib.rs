@@ -4,10 +4,12 @@
 pub use protocol::{
     AssistantToHost,
     HostToAssistant,
-    InputPin,
-    OutputPin,
+    PinId,
     UsartMode,
+    i2c,
     pin,
+    spi,
+    usart,
 };
 
 
@@ -33,15 +35,98 @@ pub enum HostToTarget<'r> {
         data: &'r [u8],
     },
 
+    /// Instruct the target to send a sequence of bytes via USART
+    ///
+    /// Byte `n` of the sequence has the value `n % 251`. The period is prime,
+    /// so losing or duplicating a block of data changes the sequence, even if
+    /// the block size is a power of two.
+    SendUsartSequence {
+        mode: UsartMode,
+        len:  u32,
+    },
+
+    /// Instruct the target to change the line parameters of a USART
+    ///
+    /// `mode` selects the USART that is used for that mode. The target replies
+    /// with `UsartConfigured`, or `UsartConfigError`.
+    ConfigureUsart {
+        mode:   UsartMode,
+        config: usart::LineConfig,
+    },
+
+    /// Instruct the target to change how it splits received USART data
+    ///
+    /// `mode` selects the USART that is used for that mode. Unless `framing` is
+    /// `RxFraming::Chunks`, the target reports received data using
+    /// `UsartFrame`.
+    ConfigureUsartFraming {
+        mode:    UsartMode,
+        framing: usart::RxFraming,
+    },
+
+    /// Instruct the target to take part in a USART benchmark
+    ///
+    /// Depending on `direction`, the target either sends `bytes` bytes, or
+    /// expects to receive them. It replies with `UsartBenchmarkResult`, once
+    /// it's done.
+    UsartBenchmark {
+        mode:      UsartMode,
+        direction: usart::Direction,
+        bytes:     u32,
+    },
+
+    /// Instruct the target to act as LIN slave on the regular USART
+    ///
+    /// Breaks are no longer reported as errors. Received data is reported with
+    /// `UsartMode::Lin` instead of `UsartMode::Regular`.
+    StartLinSlave,
+
+    /// Instruct the target to stop acting as LIN slave
+    StopLinSlave,
+
+    /// Instruct the target to respond to a LIN header
+    ///
+    /// Once the target receives a header with the protected identifier `pid`,
+    /// it sends `data`, which must include the checksum. Replaces any previous
+    /// response. If `data` is empty, the target no longer responds.
+    SetLinResponse {
+        pid:  u8,
+        data: &'r [u8],
+    },
+
     /// Instruct the target to ignore USART data until address is matched
     WaitForAddress(u8),
 
+    /// Instruct the target to filter received USART data by address
+    ///
+    /// Requires 9 data bits to be configured for `UsartMode::Regular`. The
+    /// target only receives data sent to one of `addresses`, and reports it
+    /// using `UsartAddressed`.
+    StartAddressFilter {
+        addresses: &'r [u8],
+    },
+
+    /// Instruct the target to stop filtering received USART data by address
+    StopAddressFilter,
+
     /// Instruct the device to change the electrical level of the pin
     SetPin(pin::SetLevel<()>),
 
     /// Ask the target for the current level of the input pin
     ReadPin(pin::ReadLevel<()>),
 
+    /// Instruct the target to change the configuration of the input pin
+    ConfigurePin(pin::Configure<()>),
+
+    /// Instruct the target to mirror its input pin on its output pin
+    ///
+    /// The level of the output pin is updated from the input pin's interrupt
+    /// handler. This is used to measure interrupt latency.
+    StartPinInterruptEcho,
+
+    /// Instruct the target to stop mirroring its input pin
+    StopPinInterruptEcho,
+
     /// Instruct the target to start the timer interrupt
     StartTimerInterrupt { period_ms: u32 },
 
@@ -55,17 +140,54 @@ pub enum HostToTarget<'r> {
     StopPwmSignal,
 
     /// Instruct the target to start an I2C transaction
+    ///
+    /// 10-bit addresses are only supported in `DmaMode::Regular`, as LPC8xx
+    /// HAL's DMA API only supports 7-bit addresses.
     StartI2cTransaction {
         /// Which mode to use for the transaction
         mode: DmaMode,
 
         /// The address of the slave
-        address: u8,
+        address: i2c::Address,
 
         /// The data to send to the slave
         data: u8,
     },
 
+    /// Instruct the target to start a multi-byte I2C transaction
+    ///
+    /// The target writes `write` to the slave, then reads `read_len` bytes
+    /// from it. If both are non-zero, the read follows the write after a
+    /// repeated start, without a stop condition in between. Neither may exceed
+    /// `i2c::MAX_TRANSFER`.
+    ///
+    /// In `DmaMode::Dma`, LPC8xx HAL completes every transfer with a stop
+    /// condition. If a read follows, the LPC845 target therefore writes
+    /// without DMA, and only uses DMA for the read.
+    ///
+    /// The target replies with `I2cTransferReply`, which carries the data read.
+    StartI2cTransfer {
+        /// Which mode to use for the transaction
+        mode: DmaMode,
+
+        /// The address of the slave
+        address: u8,
+
+        /// The data to write to the slave
+        write: &'r [u8],
+
+        /// The number of bytes to read from the slave
+        read_len: u8,
+    },
+
+    /// Instruct the target to scan the I2C bus
+    ///
+    /// The target addresses every 7-bit address from `i2c::SCAN_FIRST` to
+    /// `i2c::SCAN_LAST`, without transferring any data. It replies with
+    /// `I2cScanResult`, or with `I2cError`, if an error other than a NACK
+    /// occurs.
+    ScanI2cBus,
+
     /// Instruct the target to start an SPI transaction
     StartSpiTransaction {
         /// Which mode to use for the transaction
@@ -75,6 +197,37 @@ pub enum HostToTarget<'r> {
         data: u8,
     },
 
+    /// Instruct the target to reconfigure its SPI master
+    ///
+    /// Applies to all following SPI transactions and transfers, regardless of
+    /// the `DmaMode` they use.
+    ConfigureSpi(spi::Config),
+
+    /// Instruct the target to start a multi-byte SPI transfer
+    ///
+    /// The target asserts slave select, sends `data`, which must not be longer
+    /// than `spi::MAX_TRANSFER`, then deasserts slave select. It replies with
+    /// `SpiTransferReply`, which carries the data received at the same time.
+    StartSpiTransfer {
+        /// Which mode to use for the transfer
+        mode: DmaMode,
+
+        /// The data to send to the slave
+        data: &'r [u8],
+    },
+
+    /// Instruct the target to switch its SPI to slave mode
+    ///
+    /// The target sends `SpiSlaveStarted`, once it is ready. It answers every
+    /// byte with the byte it received before it, shifted left by one bit.
+    /// After slave select has been deasserted, it sends `SpiReceived`. Until
+    /// `StopSpiSlave`, the target can't start SPI transactions or transfers,
+    /// and replies to them with `SpiSlaveActive`.
+    StartSpiSlave,
+
+    /// Instruct the target to switch its SPI back to master mode
+    StopSpiSlave,
+
     /// Instruct the target to read from the ADC
     ReadAdc,
 }
@@ -91,6 +244,12 @@ impl From<pin::ReadLevel<()>> for HostToTarget<'_> {
     }
 }
 
+impl From<pin::Configure<()>> for HostToTarget<'_> {
+    fn from(configure: pin::Configure<()>) -> Self {
+        Self::ConfigurePin(configure)
+    }
+}
+
 
 /// An message from the target to the test suite on the host
 ///
@@ -106,15 +265,95 @@ pub enum TargetToHost<'r> {
         data: &'r [u8],
     },
 
+    /// Notify the host that a frame has been received via USART
+    ///
+    /// Only sent, if framing has been enabled using `ConfigureUsartFraming`.
+    UsartFrame {
+        mode: UsartMode,
+        data: &'r [u8],
+        end:  usart::FrameEnd,
+    },
+
+    /// Notify the host that data sent to an address has been received
+    ///
+    /// Only sent, if an address filter has been started using
+    /// `StartAddressFilter`. Data sent to one address might be reported using
+    /// multiple messages.
+    UsartAddressed {
+        address: u8,
+        data:    &'r [u8],
+    },
+
+    /// Reply to a `ConfigureUsart` request
+    UsartConfigured,
+
+    /// Reply to a `ConfigureUsart` request that failed
+    ///
+    /// The USART keeps its previous line parameters.
+    UsartConfigError(usart::ConfigError),
+
+    /// Notify the host that an error occurred while receiving via USART
+    UsartError {
+        mode:  UsartMode,
+        error: usart::RxError,
+    },
+
+    /// Notify the host that errors occurred while receiving via USART
+    ///
+    /// Errors on the USART used for `UsartMode::Regular` are reported
+    /// individually, using `UsartError`. For that USART, this only reports
+    /// bytes dropped because the receive queue was full.
+    ///
+    /// The host reports this as an error from whatever call comes next.
+    Diagnostics(usart::Diagnostics),
+
+    /// Reply to a `UsartBenchmark` request
+    UsartBenchmarkResult(usart::BenchmarkResult),
+
     /// Reply to a `ReadPin` request
     ReadPinResult(Option<pin::ReadLevelResult<()>>),
 
     /// Notify the host that the I2C transaction completed
     I2cReply(u8),
 
+    /// Reply to a `StartI2cTransfer` request, carrying the data read
+    I2cTransferReply(&'r [u8]),
+
+    /// Reply to a `ScanI2cBus` request
+    ///
+    /// Carries the 7-bit addresses that were acknowledged, in ascending order.
+    I2cScanResult(&'r [u8]),
+
+    /// Notify the host that an I2C transaction failed
+    ///
+    /// Sent instead of `I2cReply`, `I2cTransferReply`, or `I2cScanResult`.
+    /// The LPC845 target only reports it for transactions in
+    /// `DmaMode::Regular`.
+    I2cError(i2c::Error),
+
     /// Notify the host that the SPI transaction completed
     SpiReply(u8),
 
+    /// Notify the host that the SPI transfer completed
+    ///
+    /// Carries the data received from the slave, one byte for every byte sent.
+    SpiTransferReply(&'r [u8]),
+
+    /// Notify the host that the SPI has switched to slave mode
+    SpiSlaveStarted,
+
+    /// Notify the host that an SPI transaction or transfer was refused
+    ///
+    /// Sent instead of `SpiReply` or `SpiTransferReply`, while the SPI is in
+    /// slave mode.
+    SpiSlaveActive,
+
+    /// Notify the host of the data received by the SPI slave
+    ///
+    /// Sent after slave select has been deasserted, while in slave mode.
+    /// Contains at most `spi::MAX_TRANSFER` bytes.
+    SpiReceived(&'r [u8]),
+
     /// Reply to `ReadAdc` request
     AdcValue(u16),
 }
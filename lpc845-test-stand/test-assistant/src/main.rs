@@ -17,11 +17,17 @@ use heapless::FnvIndexMap;
 use lpc8xx_hal::{
     prelude::*,
     Peripherals,
-    cortex_m::interrupt,
+    cortex_m::{
+        interrupt,
+        peripheral::{
+            SYST,
+            syst::SystClkSource,
+        },
+    },
     gpio::{
         self,
         GpioPin,
-        direction::Output,
+        direction::Dynamic,
         direction::Input,
     },
     i2c,
@@ -37,8 +43,11 @@ use lpc8xx_hal::{
         block,
     },
     pac::{
+        GPIO,
         I2C0,
+        IOCON,
         SPI0,
+        SWM0,
         USART0,
         USART1,
         USART2,
@@ -51,6 +60,7 @@ use lpc8xx_hal::{
         PININT3,
     },
     pins::{
+        DynamicPinDirection,
         PIO0_8,
         PIO0_9,
         PIO0_20,
@@ -58,6 +68,7 @@ use lpc8xx_hal::{
         PIO1_0,
         PIO1_1,
         PIO1_2,
+        Trait as _,
     },
     spi::{
         self,
@@ -76,29 +87,86 @@ use lpc8xx_hal::{
     },
 };
 use rtt_target::rprintln;
+use void::ResultVoidExt;
 
 #[cfg(feature = "sleep")]
 use lpc8xx_hal::cortex_m::asm;
 
 use firmware_lib::{
+    benchmark::{
+        self,
+        Benchmark,
+    },
+    configure_iocon,
+    i2c::{
+        fault::{
+            self as i2c_fault,
+            Action as I2cAction,
+            Injector as I2cFaultInjector,
+        },
+        master as i2c_master,
+        slave::{
+            self as i2c_slave,
+            AddressMatch,
+            Addressing as I2cAddressing,
+            Device,
+            Echo,
+            RegisterMap,
+        },
+    },
+    latency::LatencyMeasurement,
+    pin_config,
     pin_interrupt::{
         self,
         PinInterrupt,
     },
+    rs485::Rs485Monitor,
+    signal::Measurement,
+    spi::{
+        config as spi_config,
+        master as spi_master,
+        slave::{
+            Device as SpiDevice,
+            Transfer as SpiTransfer,
+        },
+        timing::Monitor as SpiMonitor,
+    },
+    systick,
     usart::{
         RxIdle,
         RxInt,
         Tx,
         Usart,
+        address as usart_address,
+        config as usart_config,
+        fault as usart_fault,
+        lin as usart_lin,
+        soak::Soak,
     },
+    waveform::Player,
 };
 use lpc845_messages::{
     AssistantToHost,
     HostToAssistant,
-    InputPin,
-    OutputPin,
+    PinId,
     UsartMode,
+    i2c::{
+        Address as I2cAddress,
+        Fault as I2cFault,
+        MAX_TRANSFER as I2C_MAX_TRANSFER,
+    },
     pin,
+    spi::{
+        Config as SpiConfig,
+        MAX_TRANSFER as SPI_MAX_TRANSFER,
+    },
+    usart::{
+        ConfigError,
+        Diagnostics,
+        Direction,
+        LineConfig,
+        Link,
+    },
 };
 
 
@@ -133,17 +201,26 @@ const APP: () = {
         pwm_int:  pin_interrupt::Int<'static, PININT3, PIO0_23, MRT3>,
         pwm_idle: pin_interrupt::Idle<'static>,
 
-        pin_5: GpioPin<PIO0_20, Output>,
-        cts: GpioPin<PIO0_8, Output>,
-        red: GpioPin<PIO1_2, Output>,
-        green: GpioPin<PIO1_0, Input>,
+        outputs:  Outputs,
+        waveform: WaveformGenerator,
+        soak:     Option<Soak>,
+        green:    GpioPin<PIO1_0, Input>,
+        iocon:    IOCON,
+
+        i2c:         i2c::Slave<I2C0, Enabled<PhantomData<IOSC>>, Enabled>,
+        i2c_address: I2cAddressing,
+        i2c_device:  Device,
+        i2c_fault:   I2cFaultInjector,
 
-        i2c: i2c::Slave<I2C0, Enabled<PhantomData<IOSC>>, Enabled>,
-        spi: SPI<SPI0, Enabled<spi::Slave>>,
+        spi:          SPI<SPI0, Enabled<spi::Slave>>,
+        spi_device:   SpiDevice,
+        spi_transfer: SpiTransfer,
+
+        sampled_pins: SampledPins,
     }
 
     #[init]
-    fn init(_: init::Context) -> init::LateResources {
+    fn init(cx: init::Context) -> init::LateResources {
         // Normally, access to a `static mut` would be unsafe, but we know that
         // this method is only called once, which means we have exclusive access
         // here. RTFM knows this too, and by putting these statics right here,
@@ -201,22 +278,30 @@ const APP: () = {
         pwm_int.enable_falling_edge();
 
         // Configure GPIO pin 5
-        let pin_5 = p.pins.pio0_20.into_output_pin(
+        let pin_5 = p.pins.pio0_20.into_dynamic_pin(
             gpio.tokens.pio0_20,
             gpio::Level::Low,
+            DynamicPinDirection::Output,
         );
 
         // Configure pin connected to target's input pin
-        let red = p.pins.pio1_2.into_output_pin(
+        let red = p.pins.pio1_2.into_dynamic_pin(
             gpio.tokens.pio1_2,
             gpio::Level::High,
+            DynamicPinDirection::Output,
         );
 
-        let cts = p.pins.pio0_8.into_output_pin(
+        let cts = p.pins.pio0_8.into_dynamic_pin(
             gpio.tokens.pio0_8,
             gpio::Level::Low,
+            DynamicPinDirection::Output,
         );
 
+        // The output pins are all dynamic, so the host can switch them between
+        // input and output. Pull resistors and open-drain mode are configured
+        // in IOCON directly, as the HAL doesn't support this.
+        syscon.handle.enable_clock(&p.IOCON);
+
         // Configure the clock for USART0, using the Fractional Rate Generator
         // (FRG) and the USART's own baud rate divider value (BRG). See user
         // manual, section 17.7.1.
@@ -226,8 +311,8 @@ const APP: () = {
         // rate is roughly 115200 baud.
         let clock_config = {
             syscon.frg0.select_clock(frg::Clock::FRO);
-            syscon.frg0.set_mult(22);
-            syscon.frg0.set_div(0xFF);
+            syscon.frg0.set_mult(usart_config::FRG0_MULT);
+            syscon.frg0.set_div(usart_config::FRG0_DIV);
             usart::Clock::new(&syscon.frg0, 5, 16)
         };
 
@@ -291,6 +376,7 @@ const APP: () = {
             .select(rts.inner(), &mut syscon.handle);
         rts_int.enable_rising_edge();
         rts_int.enable_falling_edge();
+        let rts_pin = rts.inner().port() * 32 + rts.inner().id();
         let (rts_int, rts_idle) = RTS.init(rts_int, timers.mrt2);
 
         // Assign pins to USART2.
@@ -363,12 +449,16 @@ const APP: () = {
                 i2c0_sda,
                 &mut syscon.handle,
             )
+            .enable_master_mode(
+                &i2c::Clock::new_400khz(),
+            )
             .enable_slave_mode(
-                0x48,
+                I2C_ADDRESS,
             )
             .expect("Not using a valid address");
         i2c.enable_interrupts(i2c::Interrupts {
-            slave_pending: true,
+            slave_pending:  true,
+            slave_deselect: true,
             .. i2c::Interrupts::default()
         });
 
@@ -402,6 +492,30 @@ const APP: () = {
             rx_ready: true,
             .. Default::default()
         });
+
+        // Sound, as we're only reading the pin assignments made above.
+        let swm_config = unsafe { &*SWM0::ptr() };
+        let sampled_pins = SampledPins {
+            rs485_de:   port0_mask(rts_pin),
+            rs485_data: port0_mask(
+                swm_config.pinassign1.read().u1_rxd_i().bits()
+            ),
+            spi_ssel:   port0_mask(
+                swm_config.pinassign4.read().spi0_ssel0_io().bits()
+            ),
+            spi_sck:    port0_mask(
+                swm_config.pinassign3.read().spi0_sck_io().bits()
+            ),
+        };
+
+        // Only relevant while acting as master. Use the same clock as the
+        // target does by default.
+        //
+        // Sound, as nothing else accesses the SPI during initialization.
+        spi_config::configure(
+            unsafe { &*SPI0::ptr() },
+            &SpiConfig::default(),
+        );
         spi.enable_interrupts(spi::Interrupts {
             rx_ready: true,
             slave_select_asserted: true,
@@ -434,13 +548,29 @@ const APP: () = {
             pwm_int,
             pwm_idle,
 
-            pin_5,
-            red,
+            outputs: Outputs {
+                pin_5,
+                cts,
+                red,
+            },
+            waveform: WaveformGenerator {
+                systick: cx.core.SYST,
+                current: None,
+            },
+            soak: None,
             green,
-            cts,
+            iocon: p.IOCON,
+
+            i2c:         i2c.slave,
+            i2c_address: I2cAddressing::new(I2cAddress::SevenBit(I2C_ADDRESS)),
+            i2c_device:  Device::Echo(Echo::new()),
+            i2c_fault:   I2cFaultInjector::new(CORE_CLOCK_HZ),
 
-            i2c: i2c.slave,
             spi,
+            spi_device:   SpiDevice::new(),
+            spi_transfer: SpiTransfer::new(),
+
+            sampled_pins,
         }
     }
 
@@ -457,10 +587,19 @@ const APP: () = {
             blue_idle,
             pwm_idle,
             target_rts_idle,
-            pin_5,
-            red,
+            outputs,
+            waveform,
+            soak,
             green,
-            cts,
+            iocon,
+            i2c,
+            i2c_address,
+            i2c_device,
+            i2c_fault,
+            spi,
+            spi_device,
+            spi_transfer,
+            sampled_pins,
         ]
     )]
     fn idle(cx: idle::Context) -> ! {
@@ -475,10 +614,20 @@ const APP: () = {
         let blue           = cx.resources.blue_idle;
         let pwm            = cx.resources.pwm_idle;
         let rts            = cx.resources.target_rts_idle;
-        let pin_5          = cx.resources.pin_5;
-        let red            = cx.resources.red;
         let green          = cx.resources.green;
-        let cts            = cx.resources.cts;
+        let iocon          = cx.resources.iocon;
+        let sampled_pins   = cx.resources.sampled_pins;
+
+        let mut outputs  = cx.resources.outputs;
+        let mut waveform = cx.resources.waveform;
+        let mut soak     = cx.resources.soak;
+        let mut i2c      = cx.resources.i2c;
+        let mut address  = cx.resources.i2c_address;
+        let mut device   = cx.resources.i2c_device;
+        let mut fault    = cx.resources.i2c_fault;
+        let mut spi      = cx.resources.spi;
+        let mut flash    = cx.resources.spi_device;
+        let mut transfer = cx.resources.spi_transfer;
 
         let mut pins = FnvIndexMap::<_, _, 8>::new();
 
@@ -489,9 +638,78 @@ const APP: () = {
         };
         pins.insert(InputPin::Green as usize, (level, None)).unwrap();
 
+        let mut measurement: Option<(InputPin, Measurement)> = None;
+        let mut latency: Option<(OutputPin, LatencyMeasurement)> = None;
+        // Measurements that read SysTick directly keep its previous
+        // configuration, to restore it when they're done.
+        let mut benchmark: Option<(Benchmark, systick::Saved)> = None;
+        let mut soak_systick: Option<systick::Saved> = None;
+
+        // The line parameters of USART1, which fault injection is relative to.
+        let mut line_config = LineConfig::default();
+
         let mut buf = [0; 256];
 
         loop {
+            // While receiving for a benchmark, data is counted instead of
+            // forwarded to the host, which wouldn't be able to keep up.
+            if let Some((benchmark, _)) = &mut benchmark {
+                let mut bytes = 0;
+                while let Some(_) = target_rx.queue.dequeue() {
+                    bytes += 1;
+                }
+
+                match bytes {
+                    0     => benchmark.update(systick::now()),
+                    bytes => benchmark.add(bytes, systick::now()),
+                }
+            }
+
+            let done = benchmark
+                .as_ref()
+                .map_or(false, |(benchmark, _)| benchmark.is_done());
+            if done {
+                if let Some((finished, saved)) = benchmark.take() {
+                    waveform.lock(|waveform| waveform.resume(saved));
+                    host_tx
+                        .send_message(
+                            &AssistantToHost::UsartBenchmarkResult(
+                                finished.result()
+                            ),
+                            &mut buf,
+                        )
+                        .unwrap();
+                }
+            }
+
+            if soak_systick.is_some() {
+                let queue_len = target_rx.queue.len();
+
+                // The interrupt handler might stop the target at any time, so
+                // CTS must be set while holding the lock.
+                soak.lock(|soak| {
+                    if let Some(soak) = soak {
+                        let enabled = soak.update(systick::now(), queue_len);
+                        let level   = match enabled {
+                            true  => pin::Level::Low,
+                            false => pin::Level::High,
+                        };
+                        outputs.lock(|outputs| {
+                            outputs.set_level(OutputPin::Cts, level)
+                        });
+                    }
+                });
+            }
+
+            if let Some(data) = transfer.lock(|transfer| transfer.take()) {
+                host_tx
+                    .send_message(
+                        &AssistantToHost::SpiReceived(&data),
+                        &mut buf,
+                    )
+                    .unwrap();
+            }
+
             target_rx
                 .process_raw(|data| {
                     host_tx.send_message(
@@ -515,15 +733,84 @@ const APP: () = {
                 })
                 .expect("Error processing USART data");
 
+            let links: [(Link, &mut RxIdle); 3] = [
+                (Link::Host,                      &mut *host_rx),
+                (Link::Usart(UsartMode::Regular), &mut *target_rx),
+                (Link::Usart(UsartMode::Sync),    &mut *target_sync_rx),
+            ];
+            for (link, rx) in links.iter_mut() {
+                if let Some(rx_errors) = rx.new_errors() {
+                    host_tx
+                        .send_message(
+                            &AssistantToHost::Diagnostics(Diagnostics {
+                                link: *link,
+                                rx_errors,
+                            }),
+                            &mut buf,
+                        )
+                        .unwrap();
+                }
+            }
+
             host_rx
                 .process_message(|message| {
                     match message {
+                        HostToAssistant::Handshake => {
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::HandshakeResult {
+                                        pins: PIN_MAP.len() as u8,
+                                    },
+                                    &mut buf,
+                                )
+                                .unwrap();
+                            for &info in &PIN_MAP {
+                                host_tx
+                                    .send_message(
+                                        &AssistantToHost::HandshakePin(info),
+                                        &mut buf,
+                                    )
+                                    .unwrap();
+                            }
+
+                            Ok(())
+                        }
                         HostToAssistant::SendUsart {
                             mode: UsartMode::Regular,
                             data,
+                        }
+                        | HostToAssistant::SendUsart {
+                            mode: UsartMode::Rs485,
+                            data,
+                        } => {
+                            // The assistant doesn't control driver enable, so
+                            // sending in RS-485 mode is the same as regular.
+                            target_tx.send_raw(data)
+                        }
+                        HostToAssistant::SendUsart {
+                            mode: UsartMode::Lin,
+                            data,
                         } => {
+                            // Sound, as we only access the registers to send a
+                            // break, which the HAL doesn't support.
+                            let usart = unsafe { &*USART1::ptr() };
+
+                            usart_lin::send_break(
+                                usart,
+                                &line_config,
+                                CORE_CLOCK_HZ,
+                            );
                             target_tx.send_raw(data)
                         }
+                        HostToAssistant::SendUsartAddressed { address, data } => {
+                            // Sound, as we only write to the transmitter, which
+                            // the HAL can't do in 9-bit mode, and nothing else
+                            // is sending at the same time.
+                            let usart = unsafe { &*USART1::ptr() };
+
+                            usart_address::send(usart, address, data);
+                            Ok(())
+                        }
                         HostToAssistant::SendUsart {
                             mode: UsartMode::Dma,
                             data,
@@ -543,62 +830,708 @@ const APP: () = {
                         } => {
                             target_sync_tx.send_raw(data)
                         }
-                        HostToAssistant::SetPin(
-                            pin::SetLevel {
-                                pin: OutputPin::Pin5,
-                                level,
+                        HostToAssistant::ConfigureUsart { mode, config } => {
+                            rprintln!(
+                                "Configuring USART ({:?}): {:?}",
+                                mode,
+                                config,
+                            );
+
+                            // Sound, as we only access the registers to
+                            // change the configuration, which the HAL doesn't
+                            // touch after initialization.
+                            let usart = match mode {
+                                UsartMode::Regular
+                                | UsartMode::FlowControl
+                                | UsartMode::Rs485
+                                | UsartMode::Lin => {
+                                    Ok(unsafe { &*USART1::ptr() })
+                                }
+                                UsartMode::Dma => {
+                                    Ok(unsafe { &*USART2::ptr() })
+                                }
+                                UsartMode::Sync => {
+                                    Err(ConfigError::UnsupportedMode)
+                                }
+                            };
+
+                            let result = usart.and_then(|usart| {
+                                usart_config::configure(
+                                    usart,
+                                    &config,
+                                    usart_config::USART_CLOCK_HZ,
+                                )
+                                .map_err(|err| err.into())
+                            });
+                            let reply = match result {
+                                Ok(()) => {
+                                    if mode != UsartMode::Dma {
+                                        line_config = config;
+                                    }
+                                    AssistantToHost::UsartConfigured
+                                }
+                                Err(err) => {
+                                    rprintln!(
+                                        "Error configuring USART: {:?}",
+                                        err,
+                                    );
+                                    AssistantToHost::UsartConfigError(err)
+                                }
+                            };
+
+                            host_tx
+                                .send_message(&reply, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::InjectUsartFault {
+                            mode: UsartMode::Regular,
+                            fault,
+                        } => {
+                            rprintln!("Injecting USART fault: {:?}", fault);
+
+                            // Sound, as we only access the registers to
+                            // change the configuration, which the HAL doesn't
+                            // touch after initialization, and to send a break,
+                            // which the HAL doesn't support.
+                            let usart = unsafe { &*USART1::ptr() };
+
+                            let result = usart_fault::inject(
+                                target_tx,
+                                usart,
+                                &line_config,
+                                &fault,
+                                usart_config::USART_CLOCK_HZ,
+                                CORE_CLOCK_HZ,
+                            );
+                            if let Err(err) = result {
+                                rprintln!("Error injecting fault: {:?}", err);
                             }
-                        ) => {
-                            match level {
-                                pin::Level::High => {
-                                    pin_5.set_high();
+
+                            Ok(())
+                        }
+                        HostToAssistant::UsartBenchmark {
+                            mode: UsartMode::Regular,
+                            direction: Direction::Receive,
+                            bytes,
+                        }
+                        | HostToAssistant::UsartBenchmark {
+                            mode: UsartMode::Dma,
+                            direction: Direction::Receive,
+                            bytes,
+                        } => {
+                            // Whatever the target uses to send, it arrives
+                            // here on USART1.
+                            let saved =
+                                waveform.lock(|waveform| waveform.run_free());
+                            let receiver = Benchmark::new(
+                                Direction::Receive,
+                                bytes,
+                                CORE_CLOCK_HZ,
+                                systick::MAX_RELOAD,
+                            );
+                            benchmark = Some((receiver, saved));
+
+                            Ok(())
+                        }
+                        HostToAssistant::UsartBenchmark {
+                            mode: mode @ UsartMode::Regular,
+                            direction: Direction::Send,
+                            bytes,
+                        }
+                        | HostToAssistant::UsartBenchmark {
+                            mode: mode @ UsartMode::Dma,
+                            direction: Direction::Send,
+                            bytes,
+                        } => {
+                            let mut sender = Benchmark::new(
+                                Direction::Send,
+                                bytes,
+                                CORE_CLOCK_HZ,
+                                systick::MAX_RELOAD,
+                            );
+
+                            let saved =
+                                waveform.lock(|waveform| waveform.run_free());
+                            sender.start(systick::now());
+
+                            let mut remaining = bytes as usize;
+                            while remaining > 0 {
+                                let len = Ord::min(
+                                    remaining,
+                                    benchmark::DATA.len(),
+                                );
+                                let data = &benchmark::DATA[..len];
+
+                                match mode {
+                                    UsartMode::Dma => {
+                                        target_tx_dma.bwrite_all(data)
+                                            .void_unwrap();
+                                    }
+                                    _ => {
+                                        target_tx.send_raw(data)
+                                            .void_unwrap();
+                                    }
+                                }
+
+                                sender.add(len as u32, systick::now());
+                                remaining -= len;
+                            }
+
+                            // Writing data only means it has been handed over
+                            // to the USART. We need to know when it has
+                            // actually been sent. Sound, as we're only reading
+                            // the status register.
+                            let usart = match mode {
+                                UsartMode::Dma => unsafe { &*USART2::ptr() },
+                                _              => unsafe { &*USART1::ptr() },
+                            };
+                            while usart.stat.read().txidle().bit_is_clear() {}
+
+                            sender.add(0, systick::now());
+                            waveform.lock(|waveform| waveform.resume(saved));
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::UsartBenchmarkResult(
+                                        sender.result()
+                                    ),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::UsartBenchmark { mode, .. } => {
+                            rprintln!("Can't run benchmark ({:?})", mode);
+                            Ok(())
+                        }
+                        HostToAssistant::InjectUsartFault { mode, fault: _ } => {
+                            rprintln!("Can't inject faults ({:?})", mode);
+                            Ok(())
+                        }
+                        HostToAssistant::StartFlowControlSoak(config) => {
+                            rprintln!("Soak testing flow control: {:?}", config);
+
+                            let saved =
+                                waveform.lock(|waveform| waveform.run_free());
+                            soak.lock(|soak| {
+                                *soak = Some(
+                                    Soak::new(
+                                        &config,
+                                        SOAK_HIGH_WATER,
+                                        SOAK_LOW_WATER,
+                                        CORE_CLOCK_HZ,
+                                        systick::MAX_RELOAD,
+                                    )
+                                );
+                            });
+                            // If a soak test is already running, the timer
+                            // was already running freely, and the
+                            // configuration it saved must be kept.
+                            soak_systick.get_or_insert(saved);
+
+                            Ok(())
+                        }
+                        HostToAssistant::StopFlowControlSoak => {
+                            let result = soak.lock(|soak| {
+                                let result = soak
+                                    .take()
+                                    .map(|soak| soak.result());
+
+                                outputs.lock(|outputs| {
+                                    outputs.set_level(
+                                        OutputPin::Cts,
+                                        pin::Level::Low,
+                                    )
+                                });
+
+                                result
+                            });
+                            if let Some(saved) = soak_systick.take() {
+                                waveform.lock(|waveform| {
+                                    waveform.resume(saved)
+                                });
+                            }
+
+                            match result {
+                                Some(result) => {
+                                    host_tx
+                                        .send_message(
+                                            &AssistantToHost::
+                                                FlowControlSoakResult(result),
+                                            &mut buf,
+                                        )
+                                        .unwrap();
                                 }
-                                pin::Level::Low => {
-                                    pin_5.set_low();
+                                None => {
+                                    rprintln!("No soak test running");
                                 }
                             }
+
                             Ok(())
                         }
-                        HostToAssistant::SetPin(
-                            pin::SetLevel {
-                                pin: OutputPin::Red,
-                                level,
+                        HostToAssistant::MonitorRs485 { timeout_ms } => {
+                            let mut monitor = Rs485Monitor::new(
+                                timeout_ms,
+                                CORE_CLOCK_HZ,
+                                systick::MAX_RELOAD,
+                            );
+
+                            // This blocks everything but interrupts until the
+                            // transmission is over, but the host is waiting
+                            // for the result anyway.
+                            let saved =
+                                waveform.lock(|waveform| waveform.run_free());
+                            while !monitor.is_done() {
+                                let (de, data) = read_rs485_pins(sampled_pins);
+                                monitor.sample(de, data, systick::now());
+                            }
+                            waveform.lock(|waveform| waveform.resume(saved));
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::Rs485Timing(
+                                        monitor.timing()
+                                    ),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::MonitorSpi { timeout_ms } => {
+                            let mut monitor = SpiMonitor::new(
+                                timeout_ms,
+                                CORE_CLOCK_HZ,
+                                systick::MAX_RELOAD,
+                            );
+
+                            // Like `MonitorRs485`, this blocks everything but
+                            // interrupts. The SPI slave keeps working, but its
+                            // interrupt handler delays some samples.
+                            let saved =
+                                waveform.lock(|waveform| waveform.run_free());
+                            while !monitor.is_done() {
+                                let (ssel, sck) = read_spi_pins(sampled_pins);
+                                monitor.sample(ssel, sck, systick::now());
                             }
+                            waveform.lock(|waveform| waveform.resume(saved));
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::SpiTiming(
+                                        monitor.timing()
+                                    ),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::StartI2cSlave(config) => {
+                            rprintln!("I2C: Emulating {:?}", config);
+
+                            // Sound, as we only change the slave address,
+                            // which the HAL doesn't touch after enabling it.
+                            let i2c = unsafe { &*I2C0::ptr() };
+
+                            device.lock(|device| {
+                                *device = Device::Registers(
+                                    RegisterMap::new(config)
+                                );
+                                address.lock(|address| {
+                                    address.set(i2c, config.address)
+                                });
+                            });
+
+                            Ok(())
+                        }
+                        HostToAssistant::WriteI2cRegisters {
+                            offset,
+                            data,
+                            read_only,
+                        } => {
+                            let written = device.lock(|device| {
+                                match device {
+                                    Device::Registers(registers) => {
+                                        registers
+                                            .set(offset, data, read_only)
+                                            .is_ok()
+                                    }
+                                    Device::Echo(_) => {
+                                        false
+                                    }
+                                }
+                            });
+
+                            let reply = match written {
+                                true => {
+                                    AssistantToHost::I2cRegistersWritten
+                                }
+                                false => {
+                                    rprintln!(
+                                        "I2C: Can't write {} at {}",
+                                        data.len(),
+                                        offset,
+                                    );
+                                    AssistantToHost::I2cRegistersError
+                                }
+                            };
+
+                            host_tx
+                                .send_message(&reply, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::ReadI2cRegisters { offset, len } => {
+                            let mut data = [0; I2C_MAX_TRANSFER];
+                            let len = len as usize;
+
+                            let read = device.lock(|device| {
+                                match device {
+                                    Device::Registers(registers)
+                                        if len <= data.len() =>
+                                    {
+                                        registers
+                                            .get(offset, len)
+                                            .map(|registers| {
+                                                data[..len]
+                                                    .copy_from_slice(registers)
+                                            })
+                                            .is_ok()
+                                    }
+                                    _ => {
+                                        false
+                                    }
+                                }
+                            });
+
+                            let reply = match read {
+                                true => {
+                                    AssistantToHost::I2cRegisters(&data[..len])
+                                }
+                                false => {
+                                    rprintln!(
+                                        "I2C: Can't read {} at {}",
+                                        len,
+                                        offset,
+                                    );
+                                    AssistantToHost::I2cRegistersError
+                                }
+                            };
+
+                            host_tx
+                                .send_message(&reply, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::InjectI2cFault(
+                            I2cFault::HoldSda { duration_us }
                         ) => {
-                            match level {
-                                pin::Level::High => {
-                                    red.set_high();
+                            rprintln!(
+                                "I2C: Holding SDA for {} us",
+                                duration_us,
+                            );
+
+                            // Sound, as we only take SDA away from the I2C
+                            // temporarily, and nothing else uses the pin.
+                            let swm  = unsafe { &*SWM0::ptr() };
+                            let gpio = unsafe { &*GPIO::ptr() };
+
+                            let cycles = duration_us as u64
+                                * CORE_CLOCK_HZ as u64
+                                / 1_000_000;
+                            i2c_fault::hold_sda_low(
+                                swm,
+                                gpio,
+                                I2C_SDA_PIN,
+                                cycles as u32,
+                            );
+
+                            Ok(())
+                        }
+                        HostToAssistant::InjectI2cFault(injected) => {
+                            rprintln!("I2C: Injecting {:?}", injected);
+
+                            fault.lock(|fault| fault.arm(injected));
+
+                            Ok(())
+                        }
+                        HostToAssistant::ReadI2cDeselections => {
+                            let deselections = address.lock(|address| {
+                                address.take_deselections()
+                            });
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::I2cDeselections(
+                                        deselections,
+                                    ),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::StartI2cTransfer {
+                            address,
+                            write,
+                            read_len,
+                        } => {
+                            rprintln!(
+                                "I2C: Master transfer to {:#04x}",
+                                address,
+                            );
+
+                            let mut rx_buf = [0; I2C_MAX_TRANSFER];
+                            let read = &mut rx_buf[..read_len as usize];
+
+                            let result = i2c.lock(|_| {
+                                // Sound, as we hold the lock, so the interrupt
+                                // handler can't use the I2C meanwhile.
+                                let i2c = unsafe { &*I2C0::ptr() };
+                                i2c_master::write_read(
+                                    i2c,
+                                    I2cAddress::SevenBit(address),
+                                    write,
+                                    read,
+                                )
+                            });
+
+                            let reply = match result {
+                                Ok(()) => {
+                                    AssistantToHost::I2cTransferReply(read)
+                                }
+                                Err(err) => {
+                                    rprintln!("I2C: Error: {:?}", err);
+                                    AssistantToHost::I2cError(err.into())
+                                }
+                            };
+
+                            host_tx
+                                .send_message(&reply, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::StartSpiTransfer { data } => {
+                            rprintln!(
+                                "SPI: Master transfer of {} byte(s)",
+                                data.len(),
+                            );
+
+                            let mut rx_buf = [0; SPI_MAX_TRANSFER];
+                            let reply = &mut rx_buf[..data.len()];
+                            reply.copy_from_slice(data);
+
+                            spi.lock(|_| {
+                                // Sound, as we hold the lock, so the interrupt
+                                // handler can't use the SPI meanwhile.
+                                let spi = unsafe { &*SPI0::ptr() };
+
+                                spi_config::set_master(spi, true);
+                                spi_master::transfer(spi, reply);
+                                spi_config::set_master(spi, false);
+                            });
+
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::SpiTransferReply(reply),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::ConfigureSpi(config) => {
+                            rprintln!("SPI: Configure {:?}", config);
+
+                            spi.lock(|_| {
+                                // Sound, as we hold the lock, so the interrupt
+                                // handler can't use the SPI meanwhile.
+                                let spi = unsafe { &*SPI0::ptr() };
+                                spi_config::configure(spi, &config);
+                            });
+
+                            Ok(())
+                        }
+                        HostToAssistant::StartSpiFlash(config) => {
+                            rprintln!("SPI: Emulating flash {:?}", config);
+
+                            flash.lock(|device| device.start_flash(config));
+
+                            Ok(())
+                        }
+                        HostToAssistant::WriteSpiFlash { address, data } => {
+                            flash.lock(|device| {
+                                match device.flash() {
+                                    Some(flash) => {
+                                        if let Err(err) =
+                                            flash.set(address, data)
+                                        {
+                                            rprintln!(
+                                                "SPI: Can't write {:?}",
+                                                err,
+                                            );
+                                        }
+                                    }
+                                    None => {
+                                        rprintln!("SPI: No flash");
+                                    }
+                                }
+                            });
+
+                            Ok(())
+                        }
+                        HostToAssistant::ReadSpiFlash { address, len } => {
+                            let mut data = [0; SPI_MAX_TRANSFER];
+                            let len = len as usize;
+
+                            let read = flash.lock(|device| {
+                                match device.flash() {
+                                    Some(flash) if len <= data.len() => {
+                                        flash
+                                            .get(address, len)
+                                            .map(|memory| {
+                                                data[..len]
+                                                    .copy_from_slice(memory)
+                                            })
+                                            .is_ok()
+                                    }
+                                    _ => {
+                                        false
+                                    }
                                 }
-                                pin::Level::Low => {
-                                    red.set_low();
+                            });
+
+                            match read {
+                                true => {
+                                    host_tx
+                                        .send_message(
+                                            &AssistantToHost::SpiFlash(
+                                                &data[..len],
+                                            ),
+                                            &mut buf,
+                                        )
+                                        .unwrap();
+                                }
+                                false => {
+                                    rprintln!(
+                                        "SPI: Can't read {} at {}",
+                                        len,
+                                        address,
+                                    );
+
+                                    host_tx
+                                        .send_message(
+                                            &AssistantToHost::SpiFlashError,
+                                            &mut buf,
+                                        )
+                                        .unwrap();
                                 }
                             }
+
+                            Ok(())
+                        }
+                        HostToAssistant::StopSpiFlash => {
+                            rprintln!("SPI: Stop emulating flash");
+
+                            flash.lock(|device| device.stop_flash());
+
+                            Ok(())
+                        }
+                        HostToAssistant::StopI2cSlave => {
+                            // Sound, as we only change the slave address,
+                            // which the HAL doesn't touch after enabling it.
+                            let i2c = unsafe { &*I2C0::ptr() };
+
+                            device.lock(|device| {
+                                *device = Device::Echo(Echo::new());
+                                address.lock(|address| {
+                                    address.set(
+                                        i2c,
+                                        I2cAddress::SevenBit(I2C_ADDRESS),
+                                    )
+                                });
+                            });
+
                             Ok(())
                         }
                         HostToAssistant::SetPin(
-                            pin::SetLevel {
-                                pin: OutputPin::Cts,
-                                level: pin::Level::High,
+                            pin::SetLevel { pin, level }
+                        ) => {
+                            match OutputPin::from_id(pin) {
+                                Some(pin) => {
+                                    outputs.lock(|outputs| {
+                                        outputs.set_level(pin, level)
+                                    });
+                                }
+                                None => {
+                                    rprintln!("Can't set {:?}", pin);
+                                }
                             }
+                            Ok(())
+                        }
+                        HostToAssistant::ConfigurePin(
+                            pin::Configure { pin, config }
                         ) => {
-                            rprintln!("Setting CTS HIGH");
-                            cts.set_high();
+                            rprintln!("Configuring {:?}: {:?}", pin, config);
+
+                            match (
+                                OutputPin::from_id(pin),
+                                InputPin::from_id(pin),
+                            ) {
+                                (Some(pin), _) => {
+                                    outputs.lock(|outputs| {
+                                        outputs.configure(pin, config, iocon)
+                                    });
+                                }
+                                (None, Some(pin)) => {
+                                    configure_input(pin, config, iocon);
+                                }
+                                (None, None) => {
+                                    rprintln!("Unknown pin: {:?}", pin);
+                                }
+                            }
                             Ok(())
                         }
-                        HostToAssistant::SetPin(
-                            pin::SetLevel {
-                                pin: OutputPin::Cts,
-                                level: pin::Level::Low,
+                        HostToAssistant::GenerateWaveform(
+                            pin::GenerateWaveform { pin, waveform: wave }
+                        ) => {
+                            rprintln!("Generating waveform on {:?}", pin);
+
+                            match OutputPin::from_id(pin) {
+                                Some(pin) => {
+                                    outputs.lock(|outputs| {
+                                        waveform.lock(|waveform| {
+                                            waveform.start(pin, wave, outputs)
+                                        })
+                                    });
+                                }
+                                None => {
+                                    rprintln!("Can't set {:?}", pin);
+                                }
                             }
+                            Ok(())
+                        }
+                        HostToAssistant::StopWaveform(
+                            pin::StopWaveform { pin }
                         ) => {
-                            rprintln!("Setting CTS LOW");
-                            cts.set_low();
+                            rprintln!("Stopping waveform on {:?}", pin);
+
+                            if let Some(pin) = OutputPin::from_id(pin) {
+                                waveform.lock(|waveform| waveform.stop(pin));
+                            }
                             Ok(())
                         }
                         HostToAssistant::ReadPin(
                             pin::ReadLevel { pin }
                         ) => {
-                            let result = pins.get(&(pin as usize))
+                            let result = pins.get(&(pin.0 as usize))
                                 .map(|&(level, period_ms)| {
                                     pin::ReadLevelResult {
                                         pin,
@@ -616,15 +1549,211 @@ const APP: () = {
 
                             Ok(())
                         }
+                        HostToAssistant::MeasureSignal(
+                            pin::MeasureSignal { pin, cycles }
+                        ) => {
+                            // The pin interrupt timers run at the system clock
+                            // frequency, which we haven't changed from its
+                            // default of 12 MHz.
+                            let kind = match InputPin::from_id(pin) {
+                                Some(_) if cycles == 0 => {
+                                    pin::MeasureErrorKind::NothingToMeasure
+                                }
+                                Some(pin) => {
+                                    measurement = Some((
+                                        pin,
+                                        Measurement::new(
+                                            cycles,
+                                            CORE_CLOCK_HZ,
+                                        ),
+                                    ));
+                                    return Ok(());
+                                }
+                                None => {
+                                    pin::MeasureErrorKind::UnsupportedPin
+                                }
+                            };
+
+                            rprintln!("Can't measure {:?}: {:?}", pin, kind);
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::MeasureError(
+                                        pin::MeasureError { pin, kind }
+                                    ),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToAssistant::MeasureLatency(
+                            pin::MeasureLatency {
+                                output,
+                                input,
+                                samples,
+                                bin_width_ns,
+                            }
+                        ) => {
+                            // We need the SysTick timer to measure, so we
+                            // can't while it's generating a waveform.
+                            let playing = waveform.lock(|waveform| {
+                                waveform.is_playing()
+                            });
+
+                            // Only green can be polled directly, which is
+                            // required for an accurate measurement.
+                            let error = match (
+                                OutputPin::from_id(output),
+                                InputPin::from_id(input),
+                            ) {
+                                (None, _) => {
+                                    pin::MeasureError {
+                                        pin:  output,
+                                        kind: pin::MeasureErrorKind::
+                                            UnsupportedPin,
+                                    }
+                                }
+                                (_, Some(InputPin::Green)) if playing => {
+                                    pin::MeasureError {
+                                        pin:  output,
+                                        kind: pin::MeasureErrorKind::Busy,
+                                    }
+                                }
+                                (Some(output), Some(InputPin::Green)) => {
+                                    waveform.lock(|waveform| {
+                                        waveform.stop_all()
+                                    });
+
+                                    // SysTick uses the system clock, which
+                                    // runs at the default of 12 MHz.
+                                    latency = Some((
+                                        output,
+                                        LatencyMeasurement::new(
+                                            samples,
+                                            bin_width_ns,
+                                            CORE_CLOCK_HZ,
+                                        ),
+                                    ));
+                                    return Ok(());
+                                }
+                                (Some(_), _) => {
+                                    pin::MeasureError {
+                                        pin:  input,
+                                        kind: pin::MeasureErrorKind::
+                                            UnsupportedPin,
+                                    }
+                                }
+                            };
+
+                            rprintln!(
+                                "Can't measure latency from {:?} to {:?}: \
+                                {:?}",
+                                output,
+                                input,
+                                error.kind,
+                            );
+                            host_tx
+                                .send_message(
+                                    &AssistantToHost::MeasureError(error),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
                     }
                 })
                 .expect("Error processing host request");
             host_rx.clear_buf();
 
-            handle_pin_interrupt(green_idle, InputPin::Green, &mut pins);
-            handle_pin_interrupt(blue,  InputPin::Blue,  &mut pins);
-            handle_pin_interrupt(rts,   InputPin::Rts,   &mut pins);
-            handle_pin_interrupt(pwm,   InputPin::Pwm,   &mut pins);
+            handle_pin_interrupt(
+                green_idle,
+                InputPin::Green,
+                &mut pins,
+                &mut measurement,
+            );
+            handle_pin_interrupt(
+                blue,
+                InputPin::Blue,
+                &mut pins,
+                &mut measurement,
+            );
+            handle_pin_interrupt(
+                rts,
+                InputPin::Rts,
+                &mut pins,
+                &mut measurement,
+            );
+            handle_pin_interrupt(
+                pwm,
+                InputPin::Pwm,
+                &mut pins,
+                &mut measurement,
+            );
+
+            let result = measurement
+                .as_ref()
+                .and_then(|(pin, measurement)| measurement.result(pin.id()));
+            if let Some(result) = result {
+                host_tx
+                    .send_message(
+                        &AssistantToHost::MeasureSignalResult(result),
+                        &mut buf,
+                    )
+                    .unwrap();
+                measurement = None;
+            }
+
+            if let Some((output, latency_measurement)) = &mut latency {
+                let level = latency_measurement.next_level();
+                let expected = level == pin::Level::High;
+
+                let result = waveform.lock(|waveform| {
+                    outputs.lock(|outputs| {
+                        // If the input pin already has the level, this edge
+                        // can't be measured.
+                        if green.is_high() == expected {
+                            outputs.set_level(*output, level);
+                            return None;
+                        }
+
+                        // We need to disable interrupts, as they would
+                        // disturb the measurement. This blocks everything
+                        // else for up to `LATENCY_TIMEOUT_TICKS`, but the
+                        // host is waiting for the result anyway.
+                        interrupt::free(|_| {
+                            Some(
+                                measure_edge(
+                                    &mut waveform.systick,
+                                    outputs,
+                                    *output,
+                                    level,
+                                    green,
+                                )
+                            )
+                        })
+                    })
+                });
+                if let Some(result) = result {
+                    latency_measurement.add(result);
+                }
+            }
+
+            let result = latency
+                .as_ref()
+                .and_then(|(output, latency_measurement)| {
+                    latency_measurement
+                        .result(output.id(), InputPin::Green.id())
+                });
+            if let Some(result) = result {
+                host_tx
+                    .send_message(
+                        &AssistantToHost::MeasureLatencyResult(result),
+                        &mut buf,
+                    )
+                    .unwrap();
+                latency = None;
+            }
 
             // We need this critical section to protect against a race
             // conditions with the interrupt handlers. Otherwise, the following
@@ -641,7 +1770,10 @@ const APP: () = {
                 let should_sleep =
                     !host_rx.can_process()
                     && !target_rx.can_process()
-                    && !green_idle.is_ready();
+                    && !green_idle.is_ready()
+                    && latency.is_none()
+                    && benchmark.is_none()
+                    && soak_systick.is_none();
 
                 if should_sleep {
                     // On LPC84x MCUs, debug mode is not supported when
@@ -657,20 +1789,31 @@ const APP: () = {
 
     #[task(binds = USART0, resources = [host_rx_int])]
     fn usart0(cx: usart0::Context) {
-        cx.resources.host_rx_int.receive()
-            .expect("Error receiving from USART0");
+        // Errors are counted, and reported to the host from the idle loop.
+        let _ = cx.resources.host_rx_int.receive();
     }
 
-    #[task(binds = USART1, resources = [target_rx_int])]
+    #[task(binds = USART1, resources = [target_rx_int, soak, outputs])]
     fn usart1(cx: usart1::Context) {
-        cx.resources.target_rx_int.receive()
-            .expect("Error receiving from USART1");
+        let rx_int = cx.resources.target_rx_int;
+
+        // Errors are counted, and reported to the host from the idle loop.
+        let _ = rx_int.receive();
+
+        // The idle loop might be busy forwarding data to the host, so the
+        // target needs to be stopped from here.
+        if let Some(soak) = cx.resources.soak {
+            if soak.check_queue(rx_int.queue.len()) {
+                cx.resources.outputs
+                    .set_level(OutputPin::Cts, pin::Level::High);
+            }
+        }
     }
 
     #[task(binds = PIN_INT6_USART3, resources = [target_sync_rx_int])]
     fn usart3(cx: usart3::Context) {
-        cx.resources.target_sync_rx_int.receive()
-            .expect("Error receiving from USART3");
+        // Errors are counted, and reported to the host from the idle loop.
+        let _ = cx.resources.target_sync_rx_int.receive();
     }
 
     #[task(binds = PIN_INT0, resources = [green_int])]
@@ -693,35 +1836,104 @@ const APP: () = {
         context.resources.pwm_int.handle_interrupt();
     }
 
-    #[task(binds = I2C0, resources = [i2c])]
+    #[task(binds = SysTick, resources = [outputs, waveform])]
+    fn syst(context: syst::Context) {
+        let outputs = context.resources.outputs;
+        context.resources.waveform.step(outputs);
+    }
+
+    #[task(
+        binds = I2C0,
+        resources = [i2c, i2c_address, i2c_device, i2c_fault],
+    )]
     fn i2c0(context: i2c0::Context) {
-        static mut DATA: Option<u8> = None;
+        let address = context.resources.i2c_address;
+        let device  = context.resources.i2c_device;
+        let fault   = context.resources.i2c_fault;
+
+        // Sound, as we only read the slave data register, which the HAL
+        // doesn't do while the address is matched, and clear a status flag
+        // that the HAL doesn't use.
+        let regs = unsafe { &*I2C0::ptr() };
 
         rprintln!("I2C: Handling I2C0 interrupt...");
 
+        if regs.stat.read().slvdesel().bit_is_set() {
+            regs.stat.write(|w| w.slvdesel().set_bit());
+            address.on_deselect();
+            rprintln!("I2C: Deselected.");
+        }
+
         match context.resources.i2c.wait() {
             Ok(i2c::slave::State::AddressMatched(i2c)) => {
                 rprintln!("I2C: Address matched.");
 
-                i2c.ack().unwrap();
+                let read   = i2c_slave::is_read(regs);
+                let action = match address.on_address(read) {
+                    AddressMatch::Complete => {
+                        device.on_address();
+                        fault.on_address()
+                    }
+                    AddressMatch::Partial => {
+                        fault.on_address()
+                    }
+                    AddressMatch::Mismatch => {
+                        I2cAction::Nack
+                    }
+                };
 
-                rprintln!("I2C: Ack'ed address.");
+                match action {
+                    I2cAction::Ack => {
+                        i2c.ack().unwrap();
+                        rprintln!("I2C: Ack'ed address.");
+                    }
+                    I2cAction::Nack => {
+                        i2c.nack().unwrap();
+                        rprintln!("I2C: Nack'ed address.");
+                    }
+                }
             }
             Ok(i2c::slave::State::RxReady(i2c)) => {
                 rprintln!("I2C: Ready to receive.");
 
-                *DATA = Some(i2c.read().unwrap());
-                i2c.ack().unwrap();
+                let data = i2c.read().unwrap();
+
+                let action = match address.on_write(data) {
+                    Some(AddressMatch::Complete) => {
+                        device.on_address();
+                        I2cAction::Ack
+                    }
+                    Some(_) => {
+                        I2cAction::Nack
+                    }
+                    None => {
+                        let action = fault.on_write();
+                        if action == I2cAction::Ack {
+                            device.on_write(data);
+                        }
+                        action
+                    }
+                };
 
-                rprintln!("I2C: Received and ack'ed.");
+                match action {
+                    I2cAction::Ack => {
+                        i2c.ack().unwrap();
+                        rprintln!("I2C: Received and ack'ed.");
+                    }
+                    I2cAction::Nack => {
+                        i2c.nack().unwrap();
+                        rprintln!("I2C: Received and nack'ed.");
+                    }
+                }
             }
             Ok(i2c::slave::State::TxReady(i2c)) => {
                 rprintln!("I2C: Ready to transmit.");
 
-                if let Some(data) = *DATA {
-                    i2c.transmit(data << 1).unwrap();
-                    rprintln!("I2C: Transmitted.");
-                }
+                // The slave stretches the clock until it transmits something,
+                // so send a filler byte, if the device has no data.
+                let data = device.on_read().unwrap_or(0xff);
+                i2c.transmit(data).unwrap();
+                rprintln!("I2C: Transmitted.");
             }
             Err(nb::Error::WouldBlock) => {
                 // I2C not ready; nothing to do
@@ -732,33 +1944,386 @@ const APP: () = {
         }
     }
 
-    #[task(binds = SPI0, resources = [spi])]
+    #[task(binds = SPI0, resources = [spi, spi_device, spi_transfer])]
     fn spi0(context: spi0::Context) {
         static mut ACTIVE: bool = false;
 
-        let spi = context.resources.spi;
+        let spi      = context.resources.spi;
+        let device   = context.resources.spi_device;
+        let transfer = context.resources.spi_transfer;
+
+        // Transfers to the emulated flash aren't reported to the host.
+        let report = device.flash().is_none();
 
         if spi.is_slave_select_asserted() {
             *ACTIVE = true;
+            device.on_select();
+            if report {
+                transfer.start();
+            }
         }
         if *ACTIVE {
             if spi.is_ready_to_receive() {
                 let data = spi.receive().unwrap();
-                block!(spi.transmit(data << 1))
+                if report {
+                    transfer.on_receive(data);
+                }
+                block!(spi.transmit(device.on_receive(data)))
                     .unwrap();
             }
         }
         if spi.is_slave_select_deasserted() {
             *ACTIVE = false;
+            device.on_deselect();
+            if report {
+                transfer.finish();
+            }
         }
     }
 };
 
 
+/// The pins that the assistant is monitoring
+///
+/// The discriminants are the pin ids that are reported to the host.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum InputPin {
+    Blue  = 0,
+    Green = 1,
+    Rts   = 2,
+    Pwm   = 3,
+}
+
+impl InputPin {
+    fn from_id(id: PinId) -> Option<Self> {
+        match id.0 {
+            0 => Some(InputPin::Blue),
+            1 => Some(InputPin::Green),
+            2 => Some(InputPin::Rts),
+            3 => Some(InputPin::Pwm),
+            _ => None,
+        }
+    }
+
+    fn id(self) -> PinId {
+        PinId(self as u8)
+    }
+}
+
+
+/// The pins that the host can set
+///
+/// The discriminants are the pin ids that are reported to the host.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum OutputPin {
+    Pin5 = 4,
+    Cts  = 5,
+    Red  = 6,
+}
+
+impl OutputPin {
+    fn from_id(id: PinId) -> Option<Self> {
+        match id.0 {
+            4 => Some(OutputPin::Pin5),
+            5 => Some(OutputPin::Cts),
+            6 => Some(OutputPin::Red),
+            _ => None,
+        }
+    }
+
+    fn id(self) -> PinId {
+        PinId(self as u8)
+    }
+}
+
+
+/// The pin map that is reported to the host during the handshake
+const PIN_MAP: [pin::PinInfo; 7] = [
+    pin::PinInfo { id: PinId(InputPin::Blue  as u8), name: Some("blue")  },
+    pin::PinInfo { id: PinId(InputPin::Green as u8), name: Some("green") },
+    pin::PinInfo { id: PinId(InputPin::Rts   as u8), name: Some("rts")   },
+    pin::PinInfo { id: PinId(InputPin::Pwm   as u8), name: Some("pwm")   },
+    pin::PinInfo { id: PinId(OutputPin::Pin5 as u8), name: Some("pin5")  },
+    pin::PinInfo { id: PinId(OutputPin::Cts  as u8), name: Some("cts")   },
+    pin::PinInfo { id: PinId(OutputPin::Red  as u8), name: Some("red")   },
+];
+
+
+/// The pins that the host can control
+///
+/// These pins are outputs by default, but the host can reconfigure them.
+struct Outputs {
+    pin_5: GpioPin<PIO0_20, Dynamic>,
+    cts:   GpioPin<PIO0_8, Dynamic>,
+    red:   GpioPin<PIO1_2, Dynamic>,
+}
+
+impl Outputs {
+    fn set_level(&mut self, pin: OutputPin, level: pin::Level) {
+        match (pin, level) {
+            (OutputPin::Pin5, pin::Level::High) => self.pin_5.set_high(),
+            (OutputPin::Pin5, pin::Level::Low)  => self.pin_5.set_low(),
+            (OutputPin::Cts,  pin::Level::High) => self.cts.set_high(),
+            (OutputPin::Cts,  pin::Level::Low)  => self.cts.set_low(),
+            (OutputPin::Red,  pin::Level::High) => self.red.set_high(),
+            (OutputPin::Red,  pin::Level::Low)  => self.red.set_low(),
+        }
+    }
+
+    fn configure(&mut self,
+        pin:    OutputPin,
+        config: pin::Config,
+        iocon:  &IOCON,
+    ) {
+        match pin {
+            OutputPin::Pin5 => {
+                configure_iocon!(iocon.pio0_20, config);
+                pin_config::configure_direction(&mut self.pin_5, &config);
+            }
+            OutputPin::Cts => {
+                configure_iocon!(iocon.pio0_8, config);
+                pin_config::configure_direction(&mut self.cts, &config);
+            }
+            OutputPin::Red => {
+                configure_iocon!(iocon.pio1_2, config);
+                pin_config::configure_direction(&mut self.red, &config);
+            }
+        }
+    }
+}
+
+
+/// Configures one of the pins that the assistant is monitoring
+///
+/// These pins are used with pin interrupts and must stay inputs, so only the
+/// pull resistor can be configured.
+fn configure_input(pin: InputPin, config: pin::Config, iocon: &IOCON) {
+    if let pin::Direction::Output(_) = config.direction {
+        rprintln!("Can't switch {:?} to output. Ignoring direction.", pin);
+    }
+
+    match pin {
+        InputPin::Green => configure_iocon!(iocon.pio1_0,  config),
+        InputPin::Blue  => configure_iocon!(iocon.pio1_1,  config),
+        InputPin::Rts   => configure_iocon!(iocon.pio0_9,  config),
+        InputPin::Pwm   => configure_iocon!(iocon.pio0_23, config),
+    }
+}
+
+
+// The receive queue fill levels at which a flow control soak test stops and
+// restarts the target. Leaves room for characters that arrive after stopping,
+// and keeps the data forwarded to the host within the message buffer.
+const SOAK_HIGH_WATER: usize = 192;
+const SOAK_LOW_WATER:  usize = 64;
+
+// The address of the I2C slave, unless an emulated one is configured
+const I2C_ADDRESS: u8 = 0x48;
+
+// The port 0 pin that I2C0_SDA is assigned to (PIO0_11, see `init`)
+const I2C_SDA_PIN: u8 = 11;
+
+// The frequency of the core clock. FRO runs at 12 MHz, and is used directly.
+const CORE_CLOCK_HZ: u32 = 12_000_000;
+
+// How long to wait for the input pin to follow, when measuring latency. 1 ms at
+// 12 MHz.
+const LATENCY_TIMEOUT_TICKS: u32 = 12_000;
+
+
+/// Plays waveforms on the output pins, timed by the SysTick timer
+///
+/// Only one waveform can be played at a time. Starting a new one replaces
+/// whatever waveform is currently playing.
+struct WaveformGenerator {
+    systick: SYST,
+    current: Option<(OutputPin, Player)>,
+}
+
+impl WaveformGenerator {
+    fn start(&mut self,
+        pin:      OutputPin,
+        waveform: pin::Waveform,
+        outputs:  &mut Outputs,
+    ) {
+        // We haven't changed the system clock, so it runs at the default of
+        // 12 MHz. The SysTick timer uses the system clock directly.
+        let player = Player::new(waveform, CORE_CLOCK_HZ, systick::MAX_RELOAD);
+        self.current = Some((pin, player));
+
+        self.systick.disable_counter();
+        self.systick.set_clock_source(SystClkSource::Core);
+        self.step(outputs);
+    }
+
+    fn stop(&mut self, pin: OutputPin) {
+        if let Some((current, _)) = self.current {
+            if current == pin {
+                self.stop_all();
+            }
+        }
+    }
+
+    /// Indicates whether a waveform is currently playing
+    fn is_playing(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Stop the current waveform, regardless of the pin it's playing on
+    fn stop_all(&mut self) {
+        self.systick.disable_interrupt();
+        self.systick.disable_counter();
+        self.current = None;
+    }
+
+    /// Pause the current waveform, and let the timer run freely
+    ///
+    /// Used for measurements that read the timer directly. Pass the returned
+    /// configuration to `resume`, once the measurement is done.
+    fn run_free(&mut self) -> systick::Saved {
+        let saved = systick::start(&mut self.systick);
+        self.systick.set_clock_source(SystClkSource::Core);
+
+        saved
+    }
+
+    /// Restore the timer after `run_free`, resuming a paused waveform
+    fn resume(&mut self, saved: systick::Saved) {
+        systick::restore(&mut self.systick, saved);
+    }
+
+    /// Advance to the next step of the waveform
+    ///
+    /// Must be called from the SysTick interrupt handler.
+    fn step(&mut self, outputs: &mut Outputs) {
+        let next = self.current
+            .as_mut()
+            .and_then(|(pin, player)| {
+                player.next().map(|(level, ticks)| (*pin, level, ticks))
+            });
+
+        match next {
+            Some((pin, level, ticks)) => {
+                outputs.set_level(pin, level);
+
+                // A reload value of zero would disable the timer.
+                self.systick.set_reload(Ord::max(ticks - 1, 1));
+                self.systick.clear_current();
+                self.systick.enable_interrupt();
+                self.systick.enable_counter();
+            }
+            None => {
+                self.systick.disable_interrupt();
+                self.systick.disable_counter();
+                self.current = None;
+            }
+        }
+    }
+}
+
+
+/// The pins that the monitors sample through the GPIO port register
+///
+/// Each field is the pin's mask in the port 0 register. The masks are derived
+/// from the pin configuration in `init`.
+#[derive(Clone, Copy)]
+struct SampledPins {
+    rs485_de:   u32,
+    rs485_data: u32,
+    spi_ssel:   u32,
+    spi_sck:    u32,
+}
+
+
+/// Returns the port 0 mask of a pin, as numbered by the switch matrix
+///
+/// Port 1 pins are numbered from 32 upwards. All sampled pins must be on port
+/// 0, so they're sampled at the same time.
+fn port0_mask(pin: u8) -> u32 {
+    assert!(pin < 32, "Sampled pin PIO1_{} is not on port 0", pin % 32);
+    1 << pin
+}
+
+
+/// Reads the target's RS-485 driver enable signal and data line
+///
+/// Driver enable is the target's RTS signal, the data line is the one that
+/// USART1 receives on. Returns both levels, `true` meaning high. The HAL can't
+/// read a pin that is assigned to a USART, so this reads the GPIO register
+/// directly.
+fn read_rs485_pins(pins: &SampledPins) -> (bool, bool) {
+    // Sound, as we're only reading a register.
+    let gpio = unsafe { &*GPIO::ptr() };
+    let port = gpio.pin[0].read().port().bits();
+
+    let de   = port & pins.rs485_de   != 0;
+    let data = port & pins.rs485_data != 0;
+
+    (de, data)
+}
+
+
+/// Reads the slave select and clock signals of the SPI slave
+///
+/// Returns both levels, `true` meaning high. Like `read_rs485_pins`, this
+/// reads the GPIO register directly, as the pins are assigned to the SPI.
+fn read_spi_pins(pins: &SampledPins) -> (bool, bool) {
+    // Sound, as we're only reading a register.
+    let gpio = unsafe { &*GPIO::ptr() };
+    let port = gpio.pin[0].read().port().bits();
+
+    let ssel = port & pins.spi_ssel != 0;
+    let sck  = port & pins.spi_sck  != 0;
+
+    (ssel, sck)
+}
+
+
+/// Sets an output pin and measures how long it takes until green follows
+///
+/// Returns the latency in SysTick ticks, or `None`, if green didn't follow
+/// within `LATENCY_TIMEOUT_TICKS`. Should be called with interrupts disabled,
+/// as they would disturb the measurement.
+fn measure_edge(
+    systick: &mut SYST,
+    outputs: &mut Outputs,
+    output:  OutputPin,
+    level:   pin::Level,
+    green:   &GpioPin<PIO1_0, Input>,
+)
+    -> Option<u32>
+{
+    let expected = level == pin::Level::High;
+
+    systick.set_clock_source(SystClkSource::Core);
+    systick.set_reload(systick::MAX_RELOAD);
+    systick.clear_current();
+    systick.enable_counter();
+
+    outputs.set_level(output, level);
+
+    let latency = loop {
+        let elapsed = systick::now();
+
+        if green.is_high() == expected {
+            break Some(elapsed);
+        }
+        if elapsed > LATENCY_TIMEOUT_TICKS {
+            break None;
+        }
+    };
+
+    systick.disable_counter();
+
+    latency
+}
+
+
 fn handle_pin_interrupt(
-    int:  &mut pin_interrupt::Idle,
-    pin:  InputPin,
-    pins: &mut FnvIndexMap<usize, (pin::Level, Option<u32>), 8>,
+    int:         &mut pin_interrupt::Idle,
+    pin:         InputPin,
+    pins:        &mut FnvIndexMap<usize, (pin::Level, Option<u32>), 8>,
+    measurement: &mut Option<(InputPin, Measurement)>,
 ) {
     while let Some(event) = int.next() {
         match event {
@@ -768,6 +2333,12 @@ fn handle_pin_interrupt(
                     gpio::Level::Low  => pin::Level::Low,
                 };
 
+                if let Some((measured_pin, measurement)) = measurement {
+                    if *measured_pin == pin {
+                        measurement.add(level, period);
+                    }
+                }
+
                 let period_ms = period.map(|value| value / 12_000);
                 pins.insert(pin as usize, (level, period_ms)).unwrap();
             }
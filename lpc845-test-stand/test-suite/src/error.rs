@@ -1,15 +1,28 @@
 /// Test-suite specific error module
 
 
-use host_lib::assistant::AssistantError;
+use host_lib::{
+    assistant::AssistantError,
+    lin::FrameError,
+    rs485::IncompleteTimingError as IncompleteRs485TimingError,
+    spi::IncompleteTimingError as IncompleteSpiTimingError,
+};
 use super::{
     target::{
+        TargetAddressFilterError,
+        TargetConfigurePinError,
+        TargetConfigureSpiError,
+        TargetConfigureUsartError,
+        TargetConfigureUsartFramingError,
         TargetI2cError,
+        TargetLinSlaveError,
         TargetPinReadError,
         TargetSetPinHighError,
         TargetSetPinLowError,
         TargetSpiError,
+        TargetStartPinInterruptEchoError,
         TargetStartTimerInterruptError,
+        TargetUsartBenchmarkError,
         TargetUsartSendError,
         TargetUsartWaitError,
         TargetWaitForAddressError,
@@ -26,12 +39,23 @@ pub type Result<T = ()> = std::result::Result<T, Error>;
 #[derive(Debug)]
 pub enum Error {
     Assistant(AssistantError),
+    IncompleteRs485Timing(IncompleteRs485TimingError),
+    IncompleteSpiTiming(IncompleteSpiTimingError),
+    LinFrame(FrameError),
+    TargetAddressFilter(TargetAddressFilterError),
+    TargetConfigurePin(TargetConfigurePinError),
+    TargetConfigureSpi(TargetConfigureSpiError),
+    TargetConfigureUsart(TargetConfigureUsartError),
+    TargetConfigureUsartFraming(TargetConfigureUsartFramingError),
     TargetI2c(TargetI2cError),
+    TargetLinSlave(TargetLinSlaveError),
     TargetPinRead(TargetPinReadError),
     TargetSetPinHigh(TargetSetPinHighError),
     TargetSetPinLow(TargetSetPinLowError),
     TargetSpi(TargetSpiError),
+    TargetStartPinInterruptEcho(TargetStartPinInterruptEchoError),
     TargetStartTimerInterrupt(TargetStartTimerInterruptError),
+    TargetUsartBenchmark(TargetUsartBenchmarkError),
     TargetUsartSend(TargetUsartSendError),
     TargetUsartWait(TargetUsartWaitError),
     TargetWaitForAddress(TargetWaitForAddressError),
@@ -44,12 +68,66 @@ impl From<AssistantError> for Error {
     }
 }
 
+impl From<IncompleteRs485TimingError> for Error {
+    fn from(err: IncompleteRs485TimingError) -> Self {
+        Self::IncompleteRs485Timing(err)
+    }
+}
+
+impl From<IncompleteSpiTimingError> for Error {
+    fn from(err: IncompleteSpiTimingError) -> Self {
+        Self::IncompleteSpiTiming(err)
+    }
+}
+
+impl From<FrameError> for Error {
+    fn from(err: FrameError) -> Self {
+        Self::LinFrame(err)
+    }
+}
+
+impl From<TargetAddressFilterError> for Error {
+    fn from(err: TargetAddressFilterError) -> Self {
+        Self::TargetAddressFilter(err)
+    }
+}
+
+impl From<TargetConfigurePinError> for Error {
+    fn from(err: TargetConfigurePinError) -> Self {
+        Self::TargetConfigurePin(err)
+    }
+}
+
+impl From<TargetConfigureSpiError> for Error {
+    fn from(err: TargetConfigureSpiError) -> Self {
+        Self::TargetConfigureSpi(err)
+    }
+}
+
+impl From<TargetConfigureUsartError> for Error {
+    fn from(err: TargetConfigureUsartError) -> Self {
+        Self::TargetConfigureUsart(err)
+    }
+}
+
+impl From<TargetConfigureUsartFramingError> for Error {
+    fn from(err: TargetConfigureUsartFramingError) -> Self {
+        Self::TargetConfigureUsartFraming(err)
+    }
+}
+
 impl From<TargetI2cError> for Error {
     fn from(err: TargetI2cError) -> Self {
         Self::TargetI2c(err)
     }
 }
 
+impl From<TargetLinSlaveError> for Error {
+    fn from(err: TargetLinSlaveError) -> Self {
+        Self::TargetLinSlave(err)
+    }
+}
+
 impl From<TargetPinReadError> for Error {
     fn from(err: TargetPinReadError) -> Self {
         Self::TargetPinRead(err)
@@ -62,12 +140,24 @@ impl From<TargetSpiError> for Error {
     }
 }
 
+impl From<TargetStartPinInterruptEchoError> for Error {
+    fn from(err: TargetStartPinInterruptEchoError) -> Self {
+        Self::TargetStartPinInterruptEcho(err)
+    }
+}
+
 impl From<TargetStartTimerInterruptError> for Error {
     fn from(err: TargetStartTimerInterruptError) -> Self {
         Self::TargetStartTimerInterrupt(err)
     }
 }
 
+impl From<TargetUsartBenchmarkError> for Error {
+    fn from(err: TargetUsartBenchmarkError) -> Self {
+        Self::TargetUsartBenchmark(err)
+    }
+}
+
 impl From<TargetUsartSendError> for Error {
     fn from(err: TargetUsartSendError) -> Self {
         Self::TargetUsartSend(err)
@@ -8,19 +8,26 @@ use lpc845_messages::{
     HostToTarget,
     TargetToHost,
     UsartMode,
+    i2c,
     pin,
+    spi,
+    usart,
 };
 
 use host_lib::{
     conn::{
+        self,
         Conn,
         ConnReceiveError,
         ConnSendError,
+        Route,
     },
+    lin,
     pin::{
         Pin,
         ReadLevelError,
     },
+    usart::WaitForErrorError,
 };
 
 
@@ -31,7 +38,9 @@ pub struct Target {
 }
 
 impl Target {
-    pub(crate) fn new(conn: Conn) -> Self {
+    pub(crate) fn new(mut conn: Conn) -> Self {
+        conn.set_router(route);
+
         Self {
             conn,
             pin: Pin::new(()),
@@ -58,6 +67,21 @@ impl Target {
             .map_err(|err| TargetSetPinLowError(err))
     }
 
+    /// Instruct the target to change the configuration of its input pin
+    ///
+    /// The input pin is the pin whose level is read by [`pin_is_high`] and
+    /// [`pin_is_low`].
+    ///
+    /// [`pin_is_high`]: #method.pin_is_high
+    /// [`pin_is_low`]: #method.pin_is_low
+    pub fn configure_pin(&mut self, config: pin::Config)
+        -> Result<(), TargetConfigurePinError>
+    {
+        self.pin
+            .configure::<HostToTarget>(config, &mut self.conn)
+            .map_err(|err| TargetConfigurePinError(err))
+    }
+
     /// Indicates whether the input pin is set high
     ///
     /// Uses `pin_state` internally.
@@ -80,6 +104,62 @@ impl Target {
         Ok(pin_state.0 == pin::Level::Low)
     }
 
+    /// Instruct the target to change the line parameters of a USART
+    ///
+    /// `mode` selects the USART that is used in that mode. Waits for the target
+    /// to confirm the new configuration.
+    pub fn configure_usart(&mut self,
+        mode:   UsartMode,
+        config: usart::LineConfig,
+    )
+        -> Result<(), TargetConfigureUsartError>
+    {
+        self.conn
+            .send(&HostToTarget::ConfigureUsart { mode, config })
+            .map_err(|err| TargetConfigureUsartError::Send(err))?;
+
+        let timeout = Duration::from_millis(50);
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_from::<TargetToHost>(Route::Other, timeout, &mut tmp)
+            .map_err(|err| TargetConfigureUsartError::Receive(err))?;
+
+        match message {
+            TargetToHost::UsartConfigured => {
+                Ok(())
+            }
+            TargetToHost::UsartConfigError(err) => {
+                Err(TargetConfigureUsartError::Config(err))
+            }
+            message => {
+                Err(
+                    TargetConfigureUsartError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the target to change how it splits received USART data
+    ///
+    /// Only `UsartMode::Dma` supports framing. Since the target's frame
+    /// detection depends on the line parameters, this should be called after
+    /// [`configure_usart`].
+    ///
+    /// [`configure_usart`]: #method.configure_usart
+    pub fn configure_usart_framing(&mut self,
+        mode:    UsartMode,
+        framing: usart::RxFraming,
+    )
+        -> Result<(), TargetConfigureUsartFramingError>
+    {
+        self.conn
+            .send(&HostToTarget::ConfigureUsartFraming { mode, framing })
+            .map_err(|err| TargetConfigureUsartFramingError(err))
+    }
+
     /// Instruct the target to send this message via USART
     pub fn send_usart(&mut self, data: &[u8])
         -> Result<(), TargetUsartSendError>
@@ -119,6 +199,81 @@ impl Target {
             .map_err(|err| TargetUsartSendError(err))
     }
 
+    /// Instruct the target to send a sequence of bytes via USART
+    ///
+    /// Byte `n` of the sequence has the value `n % 251`. Use [`sequence`] to
+    /// create the expected data.
+    ///
+    /// [`sequence`]: fn.sequence.html
+    pub fn send_usart_sequence(&mut self, mode: UsartMode, len: u32)
+        -> Result<(), TargetUsartSendError>
+    {
+        self.conn
+            .send(&HostToTarget::SendUsartSequence { mode, len })
+            .map_err(|err| TargetUsartSendError(err))
+    }
+
+    /// Instruct the target to send this message via USART in RS-485 mode
+    pub fn send_usart_rs485(&mut self, data: &[u8])
+        -> Result<(), TargetUsartSendError>
+    {
+        self.conn
+            .send(&HostToTarget::SendUsart { mode: UsartMode::Rs485, data })
+            .map_err(|err| TargetUsartSendError(err))
+    }
+
+    /// Instruct the target to filter received USART data by address
+    ///
+    /// Requires 9 data bits to be configured for `UsartMode::Regular`. Use
+    /// [`wait_for_usart_rx_addressed`] to receive the data.
+    ///
+    /// [`wait_for_usart_rx_addressed`]: #method.wait_for_usart_rx_addressed
+    pub fn start_address_filter(&mut self, addresses: &[u8])
+        -> Result<(), TargetAddressFilterError>
+    {
+        self.conn
+            .send(&HostToTarget::StartAddressFilter { addresses })
+            .map_err(|err| TargetAddressFilterError(err))
+    }
+
+    /// Instruct the target to stop filtering received USART data by address
+    pub fn stop_address_filter(&mut self)
+        -> Result<(), TargetAddressFilterError>
+    {
+        self.conn
+            .send(&HostToTarget::StopAddressFilter)
+            .map_err(|err| TargetAddressFilterError(err))
+    }
+
+    /// Instruct the target to act as LIN slave
+    pub fn start_lin_slave(&mut self) -> Result<(), TargetLinSlaveError> {
+        self.conn
+            .send(&HostToTarget::StartLinSlave)
+            .map_err(|err| TargetLinSlaveError(err))
+    }
+
+    /// Instruct the target to stop acting as LIN slave
+    pub fn stop_lin_slave(&mut self) -> Result<(), TargetLinSlaveError> {
+        self.conn
+            .send(&HostToTarget::StopLinSlave)
+            .map_err(|err| TargetLinSlaveError(err))
+    }
+
+    /// Instruct the target to respond to the header of this frame
+    ///
+    /// The target sends the response of `frame`, once it receives a header
+    /// with the same protected identifier.
+    pub fn set_lin_response(&mut self, frame: &lin::Frame)
+        -> Result<(), TargetLinSlaveError>
+    {
+        self.conn
+            .send(&HostToTarget::SetLinResponse {
+                pid:  frame.pid,
+                data: &frame.response(),
+            })
+            .map_err(|err| TargetLinSlaveError(err))
+    }
+
     /// Wait to receive the provided data via USART
     ///
     /// Returns the receive buffer, once the data was received. Returns an
@@ -149,6 +304,155 @@ impl Target {
         self.wait_for_usart_rx_inner(data, timeout, UsartMode::Sync)
     }
 
+    /// Wait to receive the provided data via USART, as LIN slave
+    ///
+    /// Returns the receive buffer, once the data was received. Returns an
+    /// error, if it times out before that, or an I/O error occurs.
+    pub fn wait_for_usart_rx_lin(&mut self, data: &[u8], timeout: Duration)
+        -> Result<Vec<u8>, TargetUsartWaitError>
+    {
+        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Lin)
+    }
+
+    /// Wait to receive the provided data via USART, sent to `address`
+    ///
+    /// Only works while an address filter is active. Returns an error, if data
+    /// sent to another address is received before the provided data.
+    pub fn wait_for_usart_rx_addressed(&mut self,
+        address: u8,
+        data:    &[u8],
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, TargetUsartWaitError>
+    {
+        let mut buf   = Vec::new();
+        let     start = Instant::now();
+
+        loop {
+            if buf.windows(data.len()).any(|window| window == data) {
+                return Ok(buf);
+            }
+            if start.elapsed() > timeout {
+                return Err(TargetUsartWaitError::Timeout);
+            }
+
+            let mut tmp = Vec::new();
+            let message = self.conn
+                .receive_from::<TargetToHost>(
+                    Route::Usart(UsartMode::Regular),
+                    timeout,
+                    &mut tmp,
+                )
+                .map_err(|err| TargetUsartWaitError::Receive(err))?;
+
+            match message {
+                TargetToHost::UsartAddressed { address: received, data }
+                    if received == address =>
+                {
+                    buf.extend(data)
+                }
+                message => {
+                    return Err(
+                        TargetUsartWaitError::UnexpectedMessage(
+                            format!("{:?}", message)
+                        )
+                    );
+                }
+            }
+        }
+    }
+
+    /// Wait for the target to report the provided USART receive error
+    ///
+    /// Returns all errors that the target reported until then, including the
+    /// expected one. Received data is ignored. Returns an error, if it times
+    /// out before that, or an I/O error occurs.
+    ///
+    /// Once the expected error has been reported, keeps receiving until the
+    /// target has been quiet for a moment, so anything else caused by the
+    /// fault doesn't affect later calls.
+    pub fn wait_for_usart_error(&mut self,
+        error:   usart::RxError,
+        timeout: Duration,
+    )
+        -> Result<Vec<usart::RxError>, TargetUsartWaitError>
+    {
+        host_lib::usart::wait_for_error(
+            error,
+            timeout,
+            Route::Usart(UsartMode::Regular),
+            &mut self.conn,
+            |message| {
+                match message {
+                    TargetToHost::UsartError {
+                        mode: UsartMode::Regular,
+                        error,
+                    } => {
+                        Ok(Some(error))
+                    }
+                    TargetToHost::UsartReceive {
+                        mode: UsartMode::Regular,
+                        data: _,
+                    } => {
+                        Ok(None)
+                    }
+                    message => {
+                        Err(message)
+                    }
+                }
+            },
+        )
+        .map_err(|err| match err {
+            WaitForErrorError::Receive(err) => {
+                TargetUsartWaitError::Receive(err)
+            }
+            WaitForErrorError::Timeout => {
+                TargetUsartWaitError::Timeout
+            }
+            WaitForErrorError::UnexpectedMessage(message) => {
+                TargetUsartWaitError::UnexpectedMessage(message)
+            }
+        })
+    }
+
+    /// Wait for the target to report a frame received via USART
+    ///
+    /// Requires framing to be enabled using [`configure_usart_framing`].
+    /// Returns an error, if it times out before a frame is reported, or an I/O
+    /// error occurs.
+    ///
+    /// [`configure_usart_framing`]: #method.configure_usart_framing
+    pub fn wait_for_usart_frame(&mut self,
+        expected_mode: UsartMode,
+        timeout:       Duration,
+    )
+        -> Result<UsartFrame, TargetUsartWaitError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_from::<TargetToHost>(
+                Route::Usart(expected_mode),
+                timeout,
+                &mut tmp,
+            )
+            .map_err(|err| TargetUsartWaitError::Receive(err))?;
+
+        match message {
+            TargetToHost::UsartFrame { mode, data, end }
+                if mode == expected_mode =>
+            {
+                Ok(UsartFrame { data: data.to_vec(), end })
+            }
+            message => {
+                Err(
+                    TargetUsartWaitError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
     fn wait_for_usart_rx_inner(&mut self,
         data:          &[u8],
         timeout:       Duration,
@@ -169,7 +473,11 @@ impl Target {
 
             let mut tmp = Vec::new();
             let message = self.conn
-                .receive::<TargetToHost>(timeout, &mut tmp)
+                .receive_from::<TargetToHost>(
+                    Route::Usart(expected_mode),
+                    timeout,
+                    &mut tmp,
+                )
                 .map_err(|err| TargetUsartWaitError::Receive(err))?;
 
             match message {
@@ -189,6 +497,49 @@ impl Target {
         }
     }
 
+    /// Instruct the target to run its part of a USART benchmark
+    ///
+    /// `direction` is the direction in which the target transfers data. When
+    /// receiving, the target starts measuring once the first byte arrives, so
+    /// this should be called before the sender is started. Use
+    /// [`wait_for_usart_benchmark`] to get the result.
+    ///
+    /// [`wait_for_usart_benchmark`]: #method.wait_for_usart_benchmark
+    pub fn start_usart_benchmark(&mut self,
+        mode:      UsartMode,
+        direction: usart::Direction,
+        bytes:     u32,
+    )
+        -> Result<(), TargetUsartBenchmarkError>
+    {
+        self.conn
+            .send(&HostToTarget::UsartBenchmark { mode, direction, bytes })
+            .map_err(|err| TargetUsartBenchmarkError::Send(err))
+    }
+
+    /// Wait for the target to report the result of a USART benchmark
+    pub fn wait_for_usart_benchmark(&mut self, timeout: Duration)
+        -> Result<usart::BenchmarkResult, TargetUsartBenchmarkError>
+    {
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_from::<TargetToHost>(Route::Other, timeout, &mut tmp)
+            .map_err(|err| TargetUsartBenchmarkError::Receive(err))?;
+
+        match message {
+            TargetToHost::UsartBenchmarkResult(result) => {
+                Ok(result)
+            }
+            message => {
+                Err(
+                    TargetUsartBenchmarkError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
     /// Enable address matching
     pub fn wait_for_address(&mut self, address: u8)
         -> Result<(), TargetWaitForAddressError>
@@ -198,6 +549,20 @@ impl Target {
             .map_err(|err| TargetWaitForAddressError(err))
     }
 
+    /// Instruct the target to mirror its input pin on its output pin
+    ///
+    /// The target updates its output pin from the input pin's interrupt
+    /// handler, until the returned struct is dropped.
+    pub fn start_pin_interrupt_echo(&mut self)
+        -> Result<PinInterruptEcho, TargetStartPinInterruptEchoError>
+    {
+        self.conn
+            .send(&HostToTarget::StartPinInterruptEcho)
+            .map_err(|err| TargetStartPinInterruptEchoError(err))?;
+
+        Ok(PinInterruptEcho(self))
+    }
+
     /// Start a timer interrupt with the given period in milliseconds
     pub fn start_timer_interrupt(&mut self, period_ms: u32)
         -> Result<TimerInterrupt, TargetStartTimerInterruptError>
@@ -215,7 +580,32 @@ impl Target {
     pub fn start_i2c_transaction(&mut self, data: u8, timeout: Duration)
         -> Result<u8, TargetI2cError>
     {
-        self.start_i2c_transaction_inner(data, timeout, DmaMode::Regular)
+        let address = i2c::Address::SevenBit(0x48);
+        self.start_i2c_transaction_inner(
+            address,
+            data,
+            timeout,
+            DmaMode::Regular,
+        )
+    }
+
+    /// Start an I2C transaction with the slave at `address`
+    ///
+    /// Sends the provided `data` and returns the reply. Supports 10-bit
+    /// addresses.
+    pub fn start_i2c_transaction_to(&mut self,
+        address: i2c::Address,
+        data:    u8,
+        timeout: Duration,
+    )
+        -> Result<u8, TargetI2cError>
+    {
+        self.start_i2c_transaction_inner(
+            address,
+            data,
+            timeout,
+            DmaMode::Regular,
+        )
     }
 
     /// Start an I2C/DMA transaction
@@ -224,31 +614,172 @@ impl Target {
     pub fn start_i2c_transaction_dma(&mut self, data: u8, timeout: Duration)
         -> Result<u8, TargetI2cError>
     {
-        self.start_i2c_transaction_inner(data, timeout, DmaMode::Dma)
+        let address = i2c::Address::SevenBit(0x48);
+        self.start_i2c_transaction_inner(address, data, timeout, DmaMode::Dma)
+    }
+
+    /// Start an I2C/DMA transaction with the slave at `address`
+    ///
+    /// Sends the provided `data` and returns the reply. The target doesn't
+    /// support 10-bit addresses in this mode, and reports an error.
+    pub fn start_i2c_transaction_dma_to(&mut self,
+        address: i2c::Address,
+        data:    u8,
+        timeout: Duration,
+    )
+        -> Result<u8, TargetI2cError>
+    {
+        self.start_i2c_transaction_inner(address, data, timeout, DmaMode::Dma)
     }
 
     fn start_i2c_transaction_inner(&mut self,
+        address: i2c::Address,
         data:    u8,
         timeout: Duration,
         mode:    DmaMode,
     )
         -> Result<u8, TargetI2cError>
     {
-        let address = 0x48;
-
         self.conn
             .send(&HostToTarget::StartI2cTransaction { mode, address, data })
             .map_err(|err| TargetI2cError::Send(err))?;
 
         let mut tmp = Vec::new();
         let message = self.conn
-            .receive::<TargetToHost>(timeout, &mut tmp)
+            .receive_from::<TargetToHost>(Route::Bus, timeout, &mut tmp)
             .map_err(|err| TargetI2cError::Receive(err))?;
 
         match message {
             TargetToHost::I2cReply(reply) => {
                 Ok(reply)
             }
+            TargetToHost::I2cError(err) => {
+                Err(TargetI2cError::I2c(err))
+            }
+            message => {
+                Err(
+                    TargetI2cError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Scan the I2C bus for slaves
+    ///
+    /// Returns the 7-bit addresses that were acknowledged, in ascending order.
+    pub fn scan_i2c_bus(&mut self, timeout: Duration)
+        -> Result<Vec<i2c::Address>, TargetI2cError>
+    {
+        self.conn
+            .send(&HostToTarget::ScanI2cBus)
+            .map_err(|err| TargetI2cError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_from::<TargetToHost>(Route::Bus, timeout, &mut tmp)
+            .map_err(|err| TargetI2cError::Receive(err))?;
+
+        match message {
+            TargetToHost::I2cScanResult(addresses) => {
+                let addresses = addresses
+                    .iter()
+                    .map(|&address| i2c::Address::SevenBit(address))
+                    .collect();
+                Ok(addresses)
+            }
+            TargetToHost::I2cError(err) => {
+                Err(TargetI2cError::I2c(err))
+            }
+            message => {
+                Err(
+                    TargetI2cError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Write to the I2C slave at `address`
+    pub fn i2c_write(&mut self,
+        mode:    DmaMode,
+        address: u8,
+        data:    &[u8],
+        timeout: Duration,
+    )
+        -> Result<(), TargetI2cError>
+    {
+        self.i2c_transfer(mode, address, data, 0, timeout)?;
+        Ok(())
+    }
+
+    /// Read `len` bytes from the I2C slave at `address`
+    pub fn i2c_read(&mut self,
+        mode:    DmaMode,
+        address: u8,
+        len:     usize,
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, TargetI2cError>
+    {
+        self.i2c_transfer(mode, address, &[], len, timeout)
+    }
+
+    /// Write to the I2C slave at `address`, then read `len` bytes from it
+    ///
+    /// The read follows the write after a repeated start, except in
+    /// `DmaMode::Dma`, in which they are separate transactions.
+    pub fn i2c_write_read(&mut self,
+        mode:    DmaMode,
+        address: u8,
+        data:    &[u8],
+        len:     usize,
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, TargetI2cError>
+    {
+        self.i2c_transfer(mode, address, data, len, timeout)
+    }
+
+    fn i2c_transfer(&mut self,
+        mode:     DmaMode,
+        address:  u8,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, TargetI2cError>
+    {
+        let too_long = Ord::max(write.len(), read_len);
+        if too_long > i2c::MAX_TRANSFER {
+            return Err(TargetI2cError::TransferTooLong(too_long));
+        }
+
+        self.conn
+            .send(
+                &HostToTarget::StartI2cTransfer {
+                    mode,
+                    address,
+                    write,
+                    read_len: read_len as u8,
+                }
+            )
+            .map_err(|err| TargetI2cError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_from::<TargetToHost>(Route::Bus, timeout, &mut tmp)
+            .map_err(|err| TargetI2cError::Receive(err))?;
+
+        match message {
+            TargetToHost::I2cTransferReply(data) if data.len() == read_len => {
+                Ok(data.to_vec())
+            }
+            TargetToHost::I2cError(err) => {
+                Err(TargetI2cError::I2c(err))
+            }
             message => {
                 Err(
                     TargetI2cError::UnexpectedMessage(
@@ -277,6 +808,90 @@ impl Target {
         self.start_spi_transaction_inner(data, timeout, DmaMode::Dma)
     }
 
+    /// Instruct the target to reconfigure its SPI master
+    pub fn configure_spi(&mut self, config: spi::Config)
+        -> Result<(), TargetConfigureSpiError>
+    {
+        self.conn
+            .send(&HostToTarget::ConfigureSpi(config))
+            .map_err(|err| TargetConfigureSpiError(err))
+    }
+
+    /// Start a multi-byte SPI transfer
+    ///
+    /// Sends the provided `data` and returns the data received at the same
+    /// time, which has the same length.
+    pub fn spi_transfer(&mut self,
+        mode:    DmaMode,
+        data:    &[u8],
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, TargetSpiError>
+    {
+        if data.len() > spi::MAX_TRANSFER {
+            return Err(TargetSpiError::TransferTooLong(data.len()));
+        }
+
+        self.conn
+            .send(&HostToTarget::StartSpiTransfer { mode, data })
+            .map_err(|err| TargetSpiError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_from::<TargetToHost>(Route::Bus, timeout, &mut tmp)
+            .map_err(|err| TargetSpiError::Receive(err))?;
+
+        match message {
+            TargetToHost::SpiTransferReply(reply)
+                if reply.len() == data.len() =>
+            {
+                Ok(reply.to_vec())
+            }
+            TargetToHost::SpiSlaveActive => {
+                Err(TargetSpiError::SlaveActive)
+            }
+            message => {
+                Err(
+                    TargetSpiError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the target to switch its SPI to slave mode
+    ///
+    /// The target answers every byte with the previous one, shifted left by
+    /// one bit, and reports the data of every transfer. It switches back to
+    /// master mode, once the returned struct is dropped.
+    pub fn start_spi_slave(&mut self, timeout: Duration)
+        -> Result<SpiSlave<'_>, TargetSpiError>
+    {
+        self.conn
+            .send(&HostToTarget::StartSpiSlave)
+            .map_err(|err| TargetSpiError::Send(err))?;
+
+        // Once this returns, the assistant can start a transfer right away.
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_from::<TargetToHost>(Route::Bus, timeout, &mut tmp)
+            .map_err(|err| TargetSpiError::Receive(err))?;
+
+        match message {
+            TargetToHost::SpiSlaveStarted => {
+                Ok(SpiSlave(self))
+            }
+            message => {
+                Err(
+                    TargetSpiError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
     fn start_spi_transaction_inner(&mut self,
         data:    u8,
         timeout: Duration,
@@ -288,13 +903,17 @@ impl Target {
             .map_err(|err| TargetSpiError::Send(err))?;
 
         let mut tmp = Vec::new();
-        let message = self.conn.receive::<TargetToHost>(timeout, &mut tmp)
+        let message = self.conn
+            .receive_from::<TargetToHost>(Route::Bus, timeout, &mut tmp)
             .map_err(|err| TargetSpiError::Receive(err))?;
 
         match message {
             TargetToHost::SpiReply(reply) => {
                 Ok(reply)
             }
+            TargetToHost::SpiSlaveActive => {
+                Err(TargetSpiError::SlaveActive)
+            }
             message => {
                 Err(
                     TargetSpiError::UnexpectedMessage(
@@ -307,6 +926,59 @@ impl Target {
 }
 
 
+/// Decides where messages from the target are queued
+///
+/// See [`Conn::set_router`].
+///
+/// [`Conn::set_router`]: ../../host_lib/conn/struct.Conn.html#method.set_router
+fn route(frame: &[u8]) -> Route {
+    let message = conn::decode::<TargetToHost>(frame);
+
+    match message {
+        Some(TargetToHost::UsartReceive { mode, .. })
+        | Some(TargetToHost::UsartFrame { mode, .. })
+        | Some(TargetToHost::UsartError { mode, .. }) => {
+            Route::Usart(mode)
+        }
+        Some(TargetToHost::Diagnostics(diagnostics)) => {
+            Route::Diagnostics(diagnostics)
+        }
+        // The address filter is only available on the regular USART.
+        Some(TargetToHost::UsartAddressed { .. }) => {
+            Route::Usart(UsartMode::Regular)
+        }
+        Some(TargetToHost::ReadPinResult(_)) => {
+            Route::Pin
+        }
+        Some(TargetToHost::I2cReply(_))
+        | Some(TargetToHost::I2cTransferReply(_))
+        | Some(TargetToHost::I2cScanResult(_))
+        | Some(TargetToHost::I2cError(_))
+        | Some(TargetToHost::SpiReply(_))
+        | Some(TargetToHost::SpiTransferReply(_))
+        | Some(TargetToHost::SpiSlaveStarted)
+        | Some(TargetToHost::SpiSlaveActive)
+        | Some(TargetToHost::SpiReceived(_)) => {
+            Route::Bus
+        }
+        _ => {
+            Route::Other
+        }
+    }
+}
+
+
+/// A frame that the target received via USART
+#[derive(Debug)]
+pub struct UsartFrame {
+    /// The data of the frame
+    pub data: Vec<u8>,
+
+    /// The reason the target considered the frame to be complete
+    pub end: usart::FrameEnd,
+}
+
+
 /// Represent a timer interrupt that's currently configured on the target
 ///
 /// This timer interrupt will be stopped when this struct is dropped.
@@ -320,12 +992,96 @@ impl Drop for TimerInterrupt<'_> {
 }
 
 
+/// Represents the target mirroring its input pin on its output pin
+///
+/// The target will stop doing that when this struct is dropped.
+pub struct PinInterruptEcho<'r>(&'r mut Target);
+
+impl Drop for PinInterruptEcho<'_> {
+    fn drop(&mut self) {
+        (self.0).conn.send(&HostToTarget::StopPinInterruptEcho)
+            .unwrap()
+    }
+}
+
+
+/// Represents the target's SPI in slave mode
+///
+/// The target will switch back to master mode when this struct is dropped.
+pub struct SpiSlave<'r>(&'r mut Target);
+
+impl SpiSlave<'_> {
+    /// Wait for the target to report the data of an SPI transfer
+    pub fn received(&mut self, timeout: Duration)
+        -> Result<Vec<u8>, TargetSpiError>
+    {
+        let mut tmp = Vec::new();
+        let message = (self.0).conn
+            .receive_from::<TargetToHost>(Route::Bus, timeout, &mut tmp)
+            .map_err(|err| TargetSpiError::Receive(err))?;
+
+        match message {
+            TargetToHost::SpiReceived(data) => {
+                Ok(data.to_vec())
+            }
+            message => {
+                Err(
+                    TargetSpiError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+}
+
+impl Drop for SpiSlave<'_> {
+    fn drop(&mut self) {
+        (self.0).conn.send(&HostToTarget::StopSpiSlave)
+            .unwrap()
+    }
+}
+
+
+/// Creates the data the target sends in response to `send_usart_sequence`
+pub fn sequence(len: u32) -> Vec<u8> {
+    (0 .. len)
+        .map(|n| (n % 251) as u8)
+        .collect()
+}
+
+
 #[derive(Debug)]
 pub struct TargetSetPinHighError(ConnSendError);
 
 #[derive(Debug)]
 pub struct TargetSetPinLowError(ConnSendError);
 
+#[derive(Debug)]
+pub struct TargetAddressFilterError(ConnSendError);
+
+#[derive(Debug)]
+pub struct TargetConfigurePinError(ConnSendError);
+
+#[derive(Debug)]
+pub enum TargetConfigureUsartError {
+    /// The target can't apply the configuration
+    Config(usart::ConfigError),
+
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub struct TargetConfigureUsartFramingError(ConnSendError);
+
+#[derive(Debug)]
+pub struct TargetConfigureSpiError(ConnSendError);
+
+#[derive(Debug)]
+pub struct TargetLinSlaveError(ConnSendError);
+
 #[derive(Debug)]
 pub struct TargetPinReadError(ReadLevelError);
 
@@ -339,16 +1095,28 @@ impl From<ReadLevelError> for TargetPinReadError {
 #[derive(Debug)]
 pub struct TargetUsartSendError(ConnSendError);
 
+#[derive(Debug)]
+pub struct TargetStartPinInterruptEchoError(ConnSendError);
+
 #[derive(Debug)]
 pub struct TargetStartTimerInterruptError(ConnSendError);
 
 #[derive(Debug)]
 pub enum TargetUsartWaitError {
     Receive(ConnReceiveError),
+
     Timeout,
     UnexpectedMessage(String),
 }
 
+#[derive(Debug)]
+pub enum TargetUsartBenchmarkError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+
+    UnexpectedMessage(String),
+}
+
 #[derive(Debug)]
 pub struct TargetWaitForAddressError(ConnSendError);
 
@@ -356,6 +1124,8 @@ pub struct TargetWaitForAddressError(ConnSendError);
 pub enum TargetI2cError {
     Send(ConnSendError),
     Receive(ConnReceiveError),
+    I2c(i2c::Error),
+    TransferTooLong(usize),
     UnexpectedMessage(String),
 }
 
@@ -363,5 +1133,10 @@ pub enum TargetI2cError {
 pub enum TargetSpiError {
     Send(ConnSendError),
     Receive(ConnReceiveError),
+
+    /// The target's SPI is in slave mode, see `Target::start_spi_slave`
+    SlaveActive,
+
+    TransferTooLong(usize),
     UnexpectedMessage(String),
 }
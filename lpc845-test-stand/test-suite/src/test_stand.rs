@@ -1,13 +1,30 @@
-use std::sync::{
-    LockResult,
-    MutexGuard,
+use std::{
+    sync::{
+        LockResult,
+        MutexGuard,
+    },
+    thread::sleep,
+    time::Duration,
 };
 
 use host_lib::{
     assistant::Assistant,
+    benchmark::{
+        Transfer,
+        UsartBenchmark,
+    },
+    rs485::DriverEnableTiming,
+    spi::SlaveSelectTiming,
     test_stand::NotConfiguredError,
 };
 
+use lpc845_messages::{
+    DmaMode,
+    UsartMode,
+    spi,
+    usart,
+};
+
 use super::target::Target;
 
 
@@ -38,6 +55,148 @@ impl TestStand {
             }
         )
     }
+
+    /// Changes the line parameters of a USART on both target and assistant
+    ///
+    /// Configures both ends of the connection that is used in `mode`, then
+    /// waits a bit, to give target and assistant time to apply the change.
+    pub fn configure_usart(&mut self,
+        mode:   UsartMode,
+        config: usart::LineConfig,
+    )
+        -> crate::Result
+    {
+        self.target.configure_usart(mode, config)?;
+        self.assistant.configure_usart(mode, config)?;
+
+        sleep(Duration::from_millis(10));
+
+        Ok(())
+    }
+
+    /// Changes the SPI configuration on both target and assistant
+    ///
+    /// Waits a bit afterwards, like [`configure_usart`].
+    ///
+    /// [`configure_usart`]: #method.configure_usart
+    pub fn configure_spi(&mut self, config: spi::Config) -> crate::Result {
+        self.target.configure_spi(config)?;
+        self.assistant.configure_spi(config)?;
+
+        sleep(Duration::from_millis(10));
+
+        Ok(())
+    }
+
+    /// Runs a USART benchmark between target and assistant
+    ///
+    /// `direction` is the direction in which the target transfers data. The
+    /// receiver is started first, so it's ready when the sender starts.
+    /// `timeout` applies to each result separately.
+    pub fn usart_benchmark(&mut self,
+        mode:      UsartMode,
+        direction: usart::Direction,
+        bytes:     u32,
+        timeout:   Duration,
+    )
+        -> crate::Result<UsartBenchmark>
+    {
+        let (sender, receiver) = match direction {
+            usart::Direction::Send => {
+                self.assistant.start_usart_benchmark(
+                    mode,
+                    usart::Direction::Receive,
+                    bytes,
+                )?;
+                self.target.start_usart_benchmark(mode, direction, bytes)?;
+
+                let sender   = self.target
+                    .wait_for_usart_benchmark(timeout)?;
+                let receiver = self.assistant
+                    .wait_for_usart_benchmark(timeout)?;
+
+                (sender, receiver)
+            }
+            usart::Direction::Receive => {
+                self.target.start_usart_benchmark(mode, direction, bytes)?;
+                self.assistant.start_usart_benchmark(
+                    mode,
+                    usart::Direction::Send,
+                    bytes,
+                )?;
+
+                let sender   = self.assistant
+                    .wait_for_usart_benchmark(timeout)?;
+                let receiver = self.target
+                    .wait_for_usart_benchmark(timeout)?;
+
+                (sender, receiver)
+            }
+        };
+
+        Ok(
+            UsartBenchmark {
+                sender:   Transfer::new(&sender),
+                receiver: Transfer::new(&receiver),
+            }
+        )
+    }
+
+    /// Measures the driver enable timing of an RS-485 transmission
+    ///
+    /// The assistant starts monitoring first, then the target sends `data` in
+    /// RS-485 mode. Also verifies that the assistant received `data`. Assumes
+    /// the default line parameters.
+    pub fn rs485_timing(&mut self, data: &[u8], timeout: Duration)
+        -> crate::Result<DriverEnableTiming>
+    {
+        self.assistant.start_rs485_monitor(timeout)?;
+
+        // Give the assistant time to start monitoring, before the target
+        // starts sending.
+        sleep(Duration::from_millis(10));
+
+        self.target.send_usart_rs485(data)?;
+
+        let timing = self.assistant.wait_for_rs485_timing(timeout)?;
+        self.assistant.receive_from_target_usart(data, timeout)?;
+
+        let timing = DriverEnableTiming::new(
+            &timing,
+            data.len(),
+            &usart::LineConfig::default(),
+        )?;
+
+        Ok(timing)
+    }
+
+    /// Measures the slave select timing of an SPI transfer
+    ///
+    /// The assistant starts monitoring first, then the target transfers
+    /// `data`. Also waits for the assistant to report the data it received, so
+    /// that report doesn't get in the way of later transfers.
+    pub fn spi_timing(&mut self,
+        mode:    DmaMode,
+        data:    &[u8],
+        timeout: Duration,
+    )
+        -> crate::Result<SlaveSelectTiming>
+    {
+        self.assistant.start_spi_monitor(timeout)?;
+
+        // Give the assistant time to start monitoring, before the target
+        // starts the transfer.
+        sleep(Duration::from_millis(10));
+
+        self.target.spi_transfer(mode, data, timeout)?;
+
+        let timing = self.assistant.wait_for_spi_timing(timeout)?;
+        self.assistant.receive_from_target_spi(timeout)?;
+
+        let timing = SlaveSelectTiming::new(&timing)?;
+
+        Ok(timing)
+    }
 }
 
 
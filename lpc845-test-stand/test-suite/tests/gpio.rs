@@ -4,6 +4,9 @@
 //! wiring instructions.
 
 
+use std::time::Duration;
+
+use lpc845_messages::pin;
 use lpc845_test_suite::{
     Result,
     TestStand,
@@ -42,3 +45,116 @@ fn it_should_read_input_level_without_level_change() -> Result {
     assert!(test_stand.assistant.pin_is_high()?);
     Ok(())
 }
+
+#[test]
+fn it_should_follow_a_waveform_on_the_input_pin() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // Different durations, so the measurement shows which step is which.
+    let low  = Duration::from_micros(2000);
+    let high = Duration::from_micros(3000);
+
+    let step = |level, duration: Duration| pin::Step {
+        level,
+        duration_us: duration.as_micros() as u32,
+    };
+    let waveform = pin::Waveform::steps(
+        &[
+            step(pin::Level::Low,  low),
+            step(pin::Level::High, high),
+        ],
+        true,
+    )
+    .unwrap();
+
+    // The target mirrors its input pin on its output pin, which the assistant
+    // measures. When `_echo` is dropped, the target stops doing that.
+    let _echo = test_stand.target.start_pin_interrupt_echo()?;
+
+    test_stand.assistant.generate_waveform("red", waveform)?;
+    let measurement = test_stand.assistant
+        .measure_signal("green", 10, Duration::from_millis(200));
+    test_stand.assistant.stop_waveform("red")?;
+
+    let measurement = measurement?;
+    println!("{:?}", measurement);
+
+    // Allows for the target's interrupt latency, and for the resolution of
+    // the assistant's waveform timer.
+    let tolerance = Duration::from_micros(100);
+
+    assert_eq!(measurement.cycles, 10);
+    assert!(measurement.low.min  >= low  - tolerance);
+    assert!(measurement.low.max  <= low  + tolerance);
+    assert!(measurement.high.min >= high - tolerance);
+    assert!(measurement.high.max <= high + tolerance);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_stop_a_square_wave() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let waveform = pin::Waveform::square(1000, 50);
+    test_stand.assistant.generate_waveform("red", waveform)?;
+    test_stand.assistant.stop_waveform("red")?;
+
+    test_stand.assistant.set_pin_low()?;
+    assert!(test_stand.target.pin_is_low()?);
+
+    test_stand.assistant.set_pin_high()?;
+    assert!(test_stand.target.pin_is_high()?);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_enable_pull_resistors_on_the_input_pin() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant.configure_pin("red", pin::Config::input())?;
+
+    test_stand.target
+        .configure_pin(pin::Config::input_with_pull(pin::Pull::Up))?;
+    assert!(test_stand.target.pin_is_high()?);
+
+    test_stand.target
+        .configure_pin(pin::Config::input_with_pull(pin::Pull::Down))?;
+    assert!(test_stand.target.pin_is_low()?);
+
+    test_stand.target.configure_pin(pin::Config::input())?;
+    test_stand.assistant.configure_pin(
+        "red",
+        pin::Config::output(pin::Level::High),
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn it_should_read_an_open_drain_output() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.target
+        .configure_pin(pin::Config::input_with_pull(pin::Pull::Up))?;
+
+    test_stand.assistant.configure_pin(
+        "red",
+        pin::Config::open_drain(pin::Level::Low, pin::Pull::None),
+    )?;
+    assert!(test_stand.target.pin_is_low()?);
+
+    // The open-drain output releases the line, so it's pulled up by the
+    // target.
+    test_stand.assistant.set_pin_high()?;
+    assert!(test_stand.target.pin_is_high()?);
+
+    test_stand.target.configure_pin(pin::Config::input())?;
+    test_stand.assistant.configure_pin(
+        "red",
+        pin::Config::output(pin::Level::High),
+    )?;
+
+    Ok(())
+}
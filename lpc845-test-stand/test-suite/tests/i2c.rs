@@ -4,14 +4,39 @@
 //! wiring instructions.
 
 
-use std::time::Duration;
+use std::{
+    fmt::Debug,
+    thread,
+    time::Duration,
+};
 
+use host_lib::assistant::{
+    AssistantError,
+    AssistantI2cMasterError,
+};
+use lpc845_messages::{
+    DmaMode,
+    i2c::{
+        self,
+        Address,
+        Fault,
+        SlaveConfig,
+    },
+};
 use lpc845_test_suite::{
     Result,
     TestStand,
+    target::TargetI2cError,
 };
 
 
+// The address of the assistant's I2C slave, unless it emulates another one
+const ECHO: u8 = 0x48;
+
+// The address of the target's I2C slave
+const TARGET: u8 = 0x24;
+
+
 #[test]
 fn it_should_start_a_transaction() -> Result {
     let mut test_stand = TestStand::new()?;
@@ -37,3 +62,444 @@ fn it_should_start_a_transaction_using_dma() -> Result {
 
     Ok(())
 }
+
+#[test]
+fn it_should_write_and_read_multiple_bytes() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let data = [0x11, 0x22, 0x33, 0x44];
+    let timeout = Duration::from_millis(50);
+    test_stand.target.i2c_write(DmaMode::Regular, ECHO, &data, timeout)?;
+    let reply = test_stand.target
+        .i2c_read(DmaMode::Regular, ECHO, 4, timeout)?;
+
+    let expected: Vec<_> = data.iter().map(|b| b << 1).collect();
+    assert_eq!(reply, expected);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_write_and_read_multiple_bytes_using_dma() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let data = [0x11, 0x22, 0x33, 0x44];
+    let timeout = Duration::from_millis(50);
+    test_stand.target.i2c_write(DmaMode::Dma, ECHO, &data, timeout)?;
+    let reply = test_stand.target
+        .i2c_read(DmaMode::Dma, ECHO, 4, timeout)?;
+
+    let expected: Vec<_> = data.iter().map(|b| b << 1).collect();
+    assert_eq!(reply, expected);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_read_after_a_repeated_start() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // Like a register address, followed by a value.
+    let data = [0x05, 0x3c];
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.i2c_deselections(timeout)?;
+    let reply = test_stand.target
+        .i2c_write_read(DmaMode::Regular, ECHO, &data, 3, timeout)?;
+
+    // The slave repeats the written data, if more is read.
+    assert_eq!(reply, [0x05 << 1, 0x3c << 1, 0x05 << 1]);
+
+    // Only the stop condition at the end deselects the slave.
+    assert_eq!(test_stand.assistant.i2c_deselections(timeout)?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_write_then_read_using_dma() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let data = [0x05, 0x3c];
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.i2c_deselections(timeout)?;
+    let reply = test_stand.target
+        .i2c_write_read(DmaMode::Dma, ECHO, &data, 3, timeout)?;
+
+    assert_eq!(reply, [0x05 << 1, 0x3c << 1, 0x05 << 1]);
+
+    // Only the stop condition at the end deselects the slave.
+    assert_eq!(test_stand.assistant.i2c_deselections(timeout)?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_read_from_an_emulated_eeprom() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = SlaveConfig {
+        address:        Address::SevenBit(0x50),
+        auto_increment: true,
+    };
+    let mut eeprom = test_stand.assistant.start_i2c_slave(config)?;
+    let timeout = Duration::from_millis(50);
+    eeprom.write_registers(0x10, &[0x01, 0x02, 0x03, 0x04], false, timeout)?;
+
+    let data = test_stand.target
+        .i2c_write_read(DmaMode::Regular, 0x50, &[0x10], 4, timeout)?;
+
+    assert_eq!(data, [0x01, 0x02, 0x03, 0x04]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_write_to_an_emulated_eeprom() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = SlaveConfig {
+        address:        Address::SevenBit(0x50),
+        auto_increment: true,
+    };
+    let mut eeprom = test_stand.assistant.start_i2c_slave(config)?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.target
+        .i2c_write(DmaMode::Regular, 0x50, &[0x20, 0xaa, 0xbb], timeout)?;
+
+    let registers = eeprom.read_registers(0x20, 2, timeout)?;
+    assert_eq!(registers, [0xaa, 0xbb]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_not_write_to_read_only_registers() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = SlaveConfig {
+        address:        Address::SevenBit(0x50),
+        auto_increment: true,
+    };
+    let mut eeprom = test_stand.assistant.start_i2c_slave(config)?;
+    let timeout = Duration::from_millis(50);
+    eeprom.write_registers(0x00, &[0x12], true, timeout)?;
+
+    test_stand.target
+        .i2c_write(DmaMode::Regular, 0x50, &[0x00, 0x34, 0x56], timeout)?;
+
+    let registers = eeprom.read_registers(0x00, 2, timeout)?;
+    assert_eq!(registers, [0x12, 0x56]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_poll_a_register_without_auto_increment() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = SlaveConfig {
+        address:        Address::SevenBit(0x1d),
+        auto_increment: false,
+    };
+    let mut sensor = test_stand.assistant.start_i2c_slave(config)?;
+    let timeout = Duration::from_millis(50);
+    sensor.write_registers(0x05, &[0x77, 0x88], true, timeout)?;
+
+    let data = test_stand.target
+        .i2c_write_read(DmaMode::Regular, 0x1d, &[0x05], 3, timeout)?;
+
+    assert_eq!(data, [0x77, 0x77, 0x77]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_report_a_nacked_address() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant.inject_i2c_fault(Fault::NackAddress)?;
+
+    let timeout = Duration::from_millis(50);
+    let result = test_stand.target
+        .i2c_write(DmaMode::Regular, ECHO, &[0x11], timeout);
+
+    expect_i2c_error(result, &[i2c::Error::AddressNack]);
+    Ok(())
+}
+
+#[test]
+fn it_should_report_a_nacked_data_byte() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant.inject_i2c_fault(Fault::NackData { index: 1 })?;
+
+    let timeout = Duration::from_millis(50);
+    let result = test_stand.target
+        .i2c_write(DmaMode::Regular, ECHO, &[0x11, 0x22, 0x33], timeout);
+
+    expect_i2c_error(result, &[i2c::Error::DataNack]);
+    Ok(())
+}
+
+#[test]
+fn it_should_tolerate_short_clock_stretching() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant
+        .inject_i2c_fault(Fault::StretchClock { duration_us: 100 })?;
+
+    let timeout = Duration::from_millis(50);
+    let reply = test_stand.target
+        .i2c_write_read(DmaMode::Regular, ECHO, &[0x11, 0x22], 2, timeout)?;
+
+    assert_eq!(reply, [0x22, 0x44]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_time_out_on_long_clock_stretching() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant
+        .inject_i2c_fault(Fault::StretchClock { duration_us: 20_000 })?;
+
+    let timeout = Duration::from_millis(100);
+    let result = test_stand.target
+        .i2c_write(DmaMode::Regular, ECHO, &[0x11], timeout);
+
+    expect_i2c_error(result, &[i2c::Error::Timeout]);
+    Ok(())
+}
+
+#[test]
+fn it_should_report_an_error_while_sda_is_held_low() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant
+        .inject_i2c_fault(Fault::HoldSda { duration_us: 20_000 })?;
+
+    // Make sure the assistant has received the message, and SDA is low.
+    thread::sleep(Duration::from_millis(5));
+
+    let timeout = Duration::from_millis(100);
+    let result = test_stand.target
+        .i2c_write(DmaMode::Regular, ECHO, &[0x11], timeout);
+
+    // The master can't tell a stuck bus from another master that occupies it.
+    expect_i2c_error(
+        result,
+        &[i2c::Error::Timeout, i2c::Error::ArbitrationLoss],
+    );
+    Ok(())
+}
+
+#[test]
+fn it_should_report_a_nacked_address_from_the_hal() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant.inject_i2c_fault(Fault::NackAddress)?;
+
+    // Unlike the tests above, this goes through the HAL's I2C API.
+    let timeout = Duration::from_millis(50);
+    let result = test_stand.target.start_i2c_transaction(0x11, timeout);
+
+    expect_i2c_error(result, &[i2c::Error::AddressNack]);
+    Ok(())
+}
+
+#[test]
+fn it_should_report_a_nacked_data_byte_from_the_hal() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant.inject_i2c_fault(Fault::NackData { index: 0 })?;
+
+    let timeout = Duration::from_millis(50);
+    let result = test_stand.target.start_i2c_transaction(0x11, timeout);
+
+    expect_i2c_error(result, &[i2c::Error::DataNack]);
+    Ok(())
+}
+
+#[test]
+fn it_should_report_a_timeout_from_the_hal() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant
+        .inject_i2c_fault(Fault::StretchClock { duration_us: 20_000 })?;
+
+    let timeout = Duration::from_millis(100);
+    let result = test_stand.target.start_i2c_transaction(0x11, timeout);
+
+    expect_i2c_error(result, &[i2c::Error::Timeout]);
+    Ok(())
+}
+
+#[test]
+fn it_should_report_a_stuck_bus_from_the_hal() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant
+        .inject_i2c_fault(Fault::HoldSda { duration_us: 20_000 })?;
+
+    // Make sure the assistant has received the message, and SDA is low.
+    thread::sleep(Duration::from_millis(5));
+
+    let timeout = Duration::from_millis(100);
+    let result = test_stand.target.start_i2c_transaction(0x11, timeout);
+
+    expect_i2c_error(
+        result,
+        &[i2c::Error::Timeout, i2c::Error::ArbitrationLoss],
+    );
+    Ok(())
+}
+
+#[test]
+fn it_should_find_the_assistant_when_scanning_the_bus() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(500);
+    let addresses = test_stand.target.scan_i2c_bus(timeout)?;
+
+    assert!(addresses.contains(&Address::SevenBit(ECHO)));
+
+    Ok(())
+}
+
+#[test]
+fn it_should_find_an_emulated_slave_when_scanning_the_bus() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = SlaveConfig {
+        address:        Address::SevenBit(0x50),
+        auto_increment: true,
+    };
+    let _slave = test_stand.assistant.start_i2c_slave(config)?;
+
+    let timeout = Duration::from_millis(500);
+    let addresses = test_stand.target.scan_i2c_bus(timeout)?;
+
+    assert!(addresses.contains(&Address::SevenBit(0x50)));
+    assert!(!addresses.contains(&Address::SevenBit(ECHO)));
+
+    Ok(())
+}
+
+#[test]
+fn it_should_start_a_transaction_with_a_10_bit_address() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let address = Address::TenBit(0x2a5);
+    let config = SlaveConfig {
+        address,
+        auto_increment: false,
+    };
+    let mut slave = test_stand.assistant.start_i2c_slave(config)?;
+    let timeout = Duration::from_millis(50);
+    slave.write_registers(0x22, &[0x99], false, timeout)?;
+
+    // The transaction writes the register pointer, then reads the register.
+    let reply = test_stand.target
+        .start_i2c_transaction_to(address, 0x22, timeout)?;
+
+    assert_eq!(reply, 0x99);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_not_acknowledge_another_10_bit_address() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = SlaveConfig {
+        address:        Address::TenBit(0x2a5),
+        auto_increment: false,
+    };
+    let _slave = test_stand.assistant.start_i2c_slave(config)?;
+
+    // Same first address byte, different second one
+    let timeout = Duration::from_millis(50);
+    let result = test_stand.target
+        .start_i2c_transaction_to(Address::TenBit(0x2a6), 0x22, timeout);
+
+    expect_i2c_error(result, &[i2c::Error::AddressNack]);
+    Ok(())
+}
+
+#[test]
+fn it_should_reject_a_10_bit_address_using_dma() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // The target must report an error, and keep working.
+    let timeout = Duration::from_millis(50);
+    let result = test_stand.target
+        .start_i2c_transaction_dma_to(Address::TenBit(0x2a5), 0x22, timeout);
+    expect_i2c_error(result, &[i2c::Error::Other]);
+
+    let data  = 0x22;
+    let reply = test_stand.target.start_i2c_transaction_dma(data, timeout)?;
+    assert_eq!(reply, data << 1);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_write_to_and_read_from_the_target_slave() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.assistant.i2c_transfer(TARGET, &[0x11, 0x22], 0, timeout)?;
+    let reply = test_stand.assistant.i2c_transfer(TARGET, &[], 2, timeout)?;
+
+    assert_eq!(reply, [0x22, 0x44]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_read_from_the_target_slave_after_a_repeated_start() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    let reply = test_stand.assistant
+        .i2c_transfer(TARGET, &[0x11, 0x22, 0x33], 3, timeout)?;
+
+    assert_eq!(reply, [0x22, 0x44, 0x66]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_not_acknowledge_another_address_as_slave() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    let result = test_stand.assistant
+        .i2c_transfer(TARGET + 1, &[0x11], 0, timeout);
+
+    match result {
+        Err(AssistantError::I2cMaster(
+            AssistantI2cMasterError::I2c(i2c::Error::AddressNack)
+        )) => {}
+        result => {
+            panic!("Expected address NACK, got {:?}", result);
+        }
+    }
+
+    Ok(())
+}
+
+
+fn expect_i2c_error<T: Debug>(
+    result:   std::result::Result<T, TargetI2cError>,
+    expected: &[i2c::Error],
+) {
+    match result {
+        Err(TargetI2cError::I2c(err)) if expected.contains(&err) => {}
+        result => {
+            panic!("Expected one of {:?}, got {:?}", expected, result);
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! Test Suite for interrupt latency of the target hardware
+//!
+//! This test suite communicates with hardware. See top-level README.md for
+//! wiring instructions.
+
+
+use std::time::Duration;
+
+use lpc845_test_suite::{
+    Result,
+    TestStand,
+};
+
+
+#[test]
+fn it_should_react_to_pin_interrupts_quickly() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let samples = 100;
+
+    // When `_echo` is dropped, the target will stop mirroring its input pin.
+    let _echo = test_stand.target.start_pin_interrupt_echo()?;
+
+    let measurement = test_stand.assistant.measure_interrupt_latency(
+        samples,
+        Duration::from_micros(1),
+        Duration::from_secs(1),
+    )?;
+    println!("{:?}", measurement);
+
+    assert_eq!(measurement.timeouts, 0);
+    assert_eq!(measurement.histogram.bins.iter().sum::<u32>(), samples);
+
+    // The target runs at 12 MHz. Reacting to the interrupt should take a few
+    // hundred cycles at most.
+    let max_acceptable = Duration::from_micros(50);
+    assert!(measurement.latency.unwrap().max <= max_acceptable);
+
+    Ok(())
+}
@@ -6,12 +6,27 @@
 
 use std::time::Duration;
 
+use lpc845_messages::{
+    DmaMode,
+    spi::{
+        BitOrder,
+        Config,
+        FlashConfig,
+        Mode,
+        instruction,
+    },
+};
 use lpc845_test_suite::{
     Result,
     TestStand,
 };
 
 
+// The clock period of the default configuration: 12 MHz, divided by 4096
+const CLOCK_PERIOD: Duration =
+    Duration::from_nanos(4096 * 1_000_000_000 / 12_000_000);
+
+
 #[test]
 fn it_should_start_a_transaction() -> Result {
     let mut test_stand = TestStand::new()?;
@@ -37,3 +52,312 @@ fn it_should_start_a_transaction_using_dma() -> Result {
 
     Ok(())
 }
+
+#[test]
+fn it_should_transfer_multiple_bytes() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let data = [0x11, 0x22, 0x33, 0x44];
+    let timeout = Duration::from_millis(50);
+    let reply = test_stand.target
+        .spi_transfer(DmaMode::Regular, &data, timeout)?;
+    let received = test_stand.assistant.receive_from_target_spi(timeout)?;
+
+    // The assistant answers every byte with the one it received before.
+    assert_eq!(reply[1..], [0x22, 0x44, 0x66]);
+    assert_eq!(received, data);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_transfer_multiple_bytes_using_dma() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let data = [0x11, 0x22, 0x33, 0x44];
+    let timeout = Duration::from_millis(50);
+    let reply = test_stand.target
+        .spi_transfer(DmaMode::Dma, &data, timeout)?;
+    let received = test_stand.assistant.receive_from_target_spi(timeout)?;
+
+    assert_eq!(reply[1..], [0x22, 0x44, 0x66]);
+    assert_eq!(received, data);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_transfer_in_all_modes() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let modes = [Mode::Mode0, Mode::Mode1, Mode::Mode2, Mode::Mode3];
+    let mut results = Vec::new();
+
+    for &mode in &modes {
+        test_stand.configure_spi(Config { mode, .. Config::default() })?;
+        results.push(transfer(&mut test_stand, &[0x0f, 0xa5, 0x3c]));
+    }
+
+    // Restore the default, so other tests aren't affected.
+    test_stand.configure_spi(Config::default())?;
+
+    for (mode, result) in modes.iter().zip(results) {
+        let (reply, received) = result?;
+        assert_eq!(reply[1..], [0x1e, 0x4a], "{:?}", mode);
+        assert_eq!(received, [0x0f, 0xa5, 0x3c], "{:?}", mode);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_should_transfer_lsb_first() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let lsb_first = Config {
+        bit_order: BitOrder::LsbFirst,
+        .. Config::default()
+    };
+
+    // Only reconfigure the target, so the assistant sees the bits reversed.
+    test_stand.target.configure_spi(lsb_first)?;
+    let mismatched = transfer(&mut test_stand, &[0x01, 0x06]);
+
+    test_stand.configure_spi(lsb_first)?;
+    let matched = transfer(&mut test_stand, &[0x01, 0x06]);
+
+    // Restore the default, so other tests aren't affected.
+    test_stand.configure_spi(Config::default())?;
+
+    let (_, received) = mismatched?;
+    assert_eq!(received, [0x80, 0x60]);
+
+    let (reply, received) = matched?;
+    assert_eq!(reply[1..], [0x02]);
+    assert_eq!(received, [0x01, 0x06]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_transfer_with_a_faster_clock() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.configure_spi(Config {
+        clock_divider: 0x00ff,
+        .. Config::default()
+    })?;
+    let result = transfer(&mut test_stand, &[0x11, 0x22, 0x33]);
+
+    // Restore the default, so other tests aren't affected.
+    test_stand.configure_spi(Config::default())?;
+
+    let (reply, received) = result?;
+    assert_eq!(reply[1..], [0x22, 0x44]);
+    assert_eq!(received, [0x11, 0x22, 0x33]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_select_the_slave_for_the_whole_transfer() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let data = [0x11, 0x22, 0x33, 0x44];
+    let timeout = Duration::from_millis(100);
+    let timing = test_stand.spi_timing(DmaMode::Regular, &data, timeout)?;
+
+    println!("{:?}", timing);
+
+    // The assistant samples the signals in software, so the setup and hold
+    // times might be measured as zero. They're not expected to be longer than
+    // a clock period, though.
+    assert_eq!(timing.bytes, data.len() as u32);
+    assert!(timing.setup < CLOCK_PERIOD);
+    assert!(timing.hold < CLOCK_PERIOD);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_transfer_bytes_back_to_back_using_dma() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let data = [0x11, 0x22, 0x33, 0x44];
+    let timeout = Duration::from_millis(100);
+    let regular = test_stand.spi_timing(DmaMode::Regular, &data, timeout)?;
+    let dma     = test_stand.spi_timing(DmaMode::Dma,     &data, timeout)?;
+
+    println!("Regular: {:?}", regular);
+    println!("DMA:     {:?}", dma);
+
+    // Back to back, the gap between bytes is half a clock period. The regular
+    // transfer handles each byte before starting the next one, which stretches
+    // its gaps. DMA must not do that.
+    let (_, regular_max) = regular.gaps.expect("No gaps measured");
+    let (_, dma_max)     = dma.gaps.expect("No gaps measured");
+    assert_eq!(dma.bytes, data.len() as u32);
+    assert!(dma_max < regular_max);
+    assert!(dma_max < CLOCK_PERIOD);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_read_the_jedec_id_of_an_emulated_flash() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = FlashConfig { jedec_id: [0xef, 0x40, 0x16] };
+    let _flash = test_stand.assistant.start_spi_flash(config)?;
+
+    let timeout = Duration::from_millis(50);
+    let reply = test_stand.target.spi_transfer(
+        DmaMode::Regular,
+        &[instruction::READ_JEDEC_ID, 0xff, 0xff, 0xff],
+        timeout,
+    )?;
+
+    assert_eq!(reply[1..], config.jedec_id);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_read_from_an_emulated_flash() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = FlashConfig { jedec_id: [0xef, 0x40, 0x16] };
+    let mut flash = test_stand.assistant.start_spi_flash(config)?;
+    flash.write(0x0100, &[0x12, 0x34, 0x56, 0x78])?;
+
+    let timeout = Duration::from_millis(50);
+    let reply = test_stand.target.spi_transfer(
+        DmaMode::Regular,
+        &[instruction::READ_DATA, 0x00, 0x01, 0x00, 0xff, 0xff, 0xff, 0xff],
+        timeout,
+    )?;
+
+    assert_eq!(reply[4..], [0x12, 0x34, 0x56, 0x78]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_program_an_emulated_flash() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = FlashConfig { jedec_id: [0xef, 0x40, 0x16] };
+    let mut flash = test_stand.assistant.start_spi_flash(config)?;
+
+    let timeout = Duration::from_millis(50);
+    let target  = &mut test_stand.target;
+
+    target.spi_transfer(
+        DmaMode::Regular,
+        &[instruction::WRITE_ENABLE],
+        timeout,
+    )?;
+    let status = target.spi_transfer(
+        DmaMode::Regular,
+        &[instruction::READ_STATUS, 0xff],
+        timeout,
+    )?;
+    assert_eq!(status[1], instruction::STATUS_WEL);
+
+    target.spi_transfer(
+        DmaMode::Regular,
+        &[instruction::PAGE_PROGRAM, 0x00, 0x00, 0x10, 0xaa, 0xbb],
+        timeout,
+    )?;
+
+    // Programming is done and has cleared the write enable latch.
+    let status = target.spi_transfer(
+        DmaMode::Regular,
+        &[instruction::READ_STATUS, 0xff],
+        timeout,
+    )?;
+    assert_eq!(status[1], 0);
+
+    let memory = flash.read(0x0010, 2, timeout)?;
+    assert_eq!(memory, [0xaa, 0xbb]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_only_clear_bits_when_programming_an_emulated_flash() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = FlashConfig { jedec_id: [0xef, 0x40, 0x16] };
+    let mut flash = test_stand.assistant.start_spi_flash(config)?;
+    flash.write(0x0000, &[0xf0])?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.target.spi_transfer(
+        DmaMode::Regular,
+        &[instruction::WRITE_ENABLE],
+        timeout,
+    )?;
+    test_stand.target.spi_transfer(
+        DmaMode::Regular,
+        &[instruction::PAGE_PROGRAM, 0x00, 0x00, 0x00, 0x3c],
+        timeout,
+    )?;
+
+    let memory = flash.read(0x0000, 1, timeout)?;
+    assert_eq!(memory, [0x30]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_not_program_an_emulated_flash_without_write_enable() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = FlashConfig { jedec_id: [0xef, 0x40, 0x16] };
+    let mut flash = test_stand.assistant.start_spi_flash(config)?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.target.spi_transfer(
+        DmaMode::Regular,
+        &[instruction::PAGE_PROGRAM, 0x00, 0x00, 0x10, 0xaa, 0xbb],
+        timeout,
+    )?;
+
+    let memory = flash.read(0x0010, 2, timeout)?;
+    assert_eq!(memory, [0xff, 0xff]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_transfer_as_slave() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let timeout = Duration::from_millis(50);
+    let mut slave = test_stand.target.start_spi_slave(timeout)?;
+
+    let data = [0x11, 0x22, 0x33, 0x44];
+    let reply = test_stand.assistant.spi_transfer(&data, timeout)?;
+    let received = slave.received(timeout)?;
+
+    // The target answers every byte with the one it received before.
+    assert_eq!(reply[1..], [0x22, 0x44, 0x66]);
+    assert_eq!(received, data);
+
+    Ok(())
+}
+
+
+/// Transfers `data`, returns the target's reply and what the assistant got
+fn transfer(test_stand: &mut TestStand, data: &[u8])
+    -> Result<(Vec<u8>, Vec<u8>)>
+{
+    let timeout = Duration::from_millis(50);
+
+    let reply = test_stand.target
+        .spi_transfer(DmaMode::Regular, data, timeout)?;
+    let received = test_stand.assistant.receive_from_target_spi(timeout)?;
+
+    Ok((reply, received))
+}
@@ -4,11 +4,34 @@
 //! wiring instructions.
 
 
-use std::time::Duration;
+use std::{
+    thread::sleep,
+    time::Duration,
+};
 
+use host_lib::lin::{
+    Checksum,
+    Frame,
+};
+use lpc845_messages::{
+    UsartMode,
+    usart::{
+        DataBits,
+        Direction,
+        Fault,
+        FlowControlSoak,
+        FrameEnd,
+        LineConfig,
+        Parity,
+        RxError,
+        RxFraming,
+        StopBits,
+    },
+};
 use lpc845_test_suite::{
     Result,
     TestStand,
+    target::sequence,
 };
 
 
@@ -94,6 +117,135 @@ fn it_should_send_using_flow_control() -> Result {
     Ok(())
 }
 
+#[test]
+fn it_should_not_lose_data_while_flow_control_is_toggled() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // Faster than the assistant can forward to the host, so its receive queue
+    // fills up.
+    let config = LineConfig {
+        baud_rate: 460_800,
+        .. LineConfig::default()
+    };
+    test_stand.configure_usart(UsartMode::FlowControl, config)?;
+
+    let len = 4000;
+
+    test_stand.assistant.start_flow_control_soak(FlowControlSoak {
+        seed:            0x2545_f491,
+        min_interval_us: 100,
+        max_interval_us: 2000,
+    })?;
+    test_stand.target.send_usart_sequence(UsartMode::FlowControl, len)?;
+
+    let expected = sequence(len);
+    let timeout  = Duration::from_secs(5);
+    let received = test_stand.assistant
+        .receive_from_target_usart(&expected, timeout);
+    let result = test_stand.assistant
+        .stop_flow_control_soak(Duration::from_millis(50));
+
+    // Restore the default, so other tests aren't affected.
+    test_stand.configure_usart(
+        UsartMode::FlowControl,
+        LineConfig::default(),
+    )?;
+
+    let result = result?;
+    println!("{:?}", result);
+
+    // All of the data must match, not just a part of it. Otherwise, some of it
+    // was duplicated.
+    assert_eq!(received?, expected);
+    assert!(result.toggles > 0);
+    assert!(result.throttled > 0);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_receive_frames_delimited_by_idle_line() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let messages: &[&[u8]] = &[
+        b"a",
+        b"Hello",
+        b"Hello, world!",
+        &[0x55; 40],
+    ];
+
+    let frames = with_framing(&mut test_stand, RxFraming::Idle, |test_stand| {
+        let timeout = Duration::from_millis(50);
+
+        messages
+            .iter()
+            .map(|&message| {
+                test_stand.assistant.send_to_target_usart_dma(message)?;
+                let frame = test_stand.target
+                    .wait_for_usart_frame(UsartMode::Dma, timeout)?;
+                Ok(frame)
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    for (frame, &message) in frames.iter().zip(messages) {
+        assert_eq!(frame.data, message);
+        assert_eq!(frame.end, FrameEnd::Idle);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_should_receive_frames_delimited_by_character_timeout() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let framing = RxFraming::CharTimeout { timeout_us: 20_000 };
+    let frame   = with_framing(&mut test_stand, framing, |test_stand| {
+        // The assistant sends both parts well within the timeout, so they
+        // should end up in the same frame.
+        test_stand.assistant.send_to_target_usart_dma(b"Hello, ")?;
+        test_stand.assistant.send_to_target_usart_dma(b"world!")?;
+
+        let timeout = Duration::from_millis(100);
+        let frame   = test_stand.target
+            .wait_for_usart_frame(UsartMode::Dma, timeout)?;
+        Ok(frame)
+    })?;
+
+    assert_eq!(frame.data, b"Hello, world!");
+    assert_eq!(frame.end, FrameEnd::Timeout);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_split_frames_that_exceed_the_receive_buffer() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // The target's frame buffer holds 64 bytes.
+    let message: Vec<u8> = (0 .. 80).collect();
+
+    let frames = with_framing(&mut test_stand, RxFraming::Idle, |test_stand| {
+        test_stand.assistant.send_to_target_usart_dma(&message)?;
+
+        let timeout = Duration::from_millis(50);
+        let first   = test_stand.target
+            .wait_for_usart_frame(UsartMode::Dma, timeout)?;
+        let second  = test_stand.target
+            .wait_for_usart_frame(UsartMode::Dma, timeout)?;
+        Ok((first, second))
+    })?;
+    let (first, second) = frames;
+
+    assert_eq!(first.data, &message[.. 64]);
+    assert_eq!(first.end, FrameEnd::BufferFull);
+    assert_eq!(second.data, &message[64 ..]);
+    assert_eq!(second.end, FrameEnd::Idle);
+
+    Ok(())
+}
+
 #[test]
 fn it_should_send_in_sync_mode() -> Result {
     let mut test_stand = TestStand::new()?;
@@ -150,3 +302,294 @@ fn it_should_ignore_received_data_until_an_address_is_matched() -> Result {
     assert_eq!(received, message);
     Ok(())
 }
+
+#[test]
+fn it_should_filter_multiple_addresses_in_9_bit_mode() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = LineConfig {
+        data_bits: DataBits::Nine,
+        .. LineConfig::default()
+    };
+    test_stand.configure_usart(UsartMode::Regular, config)?;
+
+    test_stand.target.start_address_filter(&[0x10, 0x20])?;
+
+    // Data without an address, or sent to an address that isn't in the
+    // filter, must not arrive.
+    test_stand.assistant.send_to_target_usart(b"111")?;
+    test_stand.assistant.send_to_target_usart_addressed(0x30, b"222")?;
+    test_stand.assistant.send_to_target_usart_addressed(0x10, b"Hello")?;
+    test_stand.assistant.send_to_target_usart_addressed(0x30, b"333")?;
+    test_stand.assistant.send_to_target_usart_addressed(0x20, b"world")?;
+
+    let timeout = Duration::from_millis(50);
+    let first   = test_stand.target
+        .wait_for_usart_rx_addressed(0x10, b"Hello", timeout);
+    let second  = test_stand.target
+        .wait_for_usart_rx_addressed(0x20, b"world", timeout);
+
+    // Restore the defaults, so other tests aren't affected.
+    test_stand.target.stop_address_filter()?;
+    test_stand.configure_usart(UsartMode::Regular, LineConfig::default())?;
+
+    assert_eq!(first?, b"Hello");
+    assert_eq!(second?, b"world");
+
+    Ok(())
+}
+
+#[test]
+fn it_should_send_and_receive_with_different_line_parameters() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let baud_rates = [9600, 57_600, 115_200, 230_400];
+    let formats = [
+        (DataBits::Eight, Parity::None, StopBits::One),
+        (DataBits::Eight, Parity::Even, StopBits::One),
+        (DataBits::Eight, Parity::Odd,  StopBits::Two),
+        (DataBits::Seven, Parity::Even, StopBits::One),
+    ];
+
+    // Only uses 7-bit characters, so it works with all formats.
+    let message = b"Hello, world!";
+    let timeout = Duration::from_millis(100);
+
+    // Collect the result first, so the default is restored on every path.
+    let result = (|| -> Result {
+        for &baud_rate in &baud_rates {
+            for &(data_bits, parity, stop_bits) in &formats {
+                let config = LineConfig {
+                    baud_rate,
+                    data_bits,
+                    parity,
+                    stop_bits,
+                };
+                test_stand.configure_usart(UsartMode::Regular, config)?;
+
+                test_stand.target.send_usart(message)?;
+                let received = test_stand.assistant
+                    .receive_from_target_usart(message, timeout)?;
+                assert_eq!(received, message, "Sending with {:?}", config);
+
+                test_stand.assistant.send_to_target_usart(message)?;
+                let received = test_stand.target
+                    .wait_for_usart_rx(message, timeout)?;
+                assert_eq!(received, message, "Receiving with {:?}", config);
+            }
+        }
+
+        Ok(())
+    })();
+
+    // Restore the default, so other tests aren't affected.
+    test_stand.configure_usart(UsartMode::Regular, LineConfig::default())?;
+
+    result
+}
+
+#[test]
+fn it_should_report_parity_errors() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = LineConfig {
+        parity: Parity::Even,
+        .. LineConfig::default()
+    };
+    test_stand.configure_usart(UsartMode::Regular, config)?;
+
+    test_stand.assistant.inject_usart_fault(Fault::Parity)?;
+
+    let timeout = Duration::from_millis(50);
+    let result = test_stand.target
+        .wait_for_usart_error(RxError::Parity, timeout);
+
+    // Restore the default, so other tests aren't affected.
+    test_stand.configure_usart(UsartMode::Regular, LineConfig::default())?;
+
+    result?;
+    Ok(())
+}
+
+#[test]
+fn it_should_report_framing_errors() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant.inject_usart_fault(Fault::Framing)?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.target.wait_for_usart_error(RxError::Framing, timeout)?;
+
+    Ok(())
+}
+
+#[test]
+fn it_should_report_breaks() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant
+        .inject_usart_fault(Fault::Break { duration_us: 1000 })?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.target.wait_for_usart_error(RxError::Break, timeout)?;
+
+    Ok(())
+}
+
+#[test]
+fn it_should_report_errors_on_baud_rate_mismatch() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // At half the expected baud rate, some characters of this message are
+    // guaranteed to produce framing errors.
+    test_stand.assistant.inject_usart_fault(Fault::BaudRate {
+        baud_rate: LineConfig::default().baud_rate / 2,
+        data:      b"Hello, world!",
+    })?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.target.wait_for_usart_error(RxError::Framing, timeout)?;
+
+    Ok(())
+}
+
+#[test]
+fn it_should_transfer_data_at_line_speed() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // The target receives via DMA in chunks of 13 bytes. Make sure the last
+    // chunk is complete.
+    let bytes = 1300;
+
+    let config  = LineConfig::default();
+    let timeout = Duration::from_millis(500);
+
+    // Characters can't be sent faster than the line allows. Leave some room
+    // for the receiver's measurement error.
+    let line_speed = config.baud_rate as f64 / config.bits_per_char() as f64;
+    let min_speed  = line_speed * 0.9;
+
+    for &mode in &[UsartMode::Regular, UsartMode::Dma] {
+        for &direction in &[Direction::Send, Direction::Receive] {
+            let benchmark = test_stand.usart_benchmark(
+                mode,
+                direction,
+                bytes,
+                timeout,
+            )?;
+
+            println!(
+                "{:?}, {:?}: {:?} bytes/s, gap {:?}",
+                mode,
+                direction,
+                benchmark.receiver.throughput,
+                benchmark.receiver.gap(&config),
+            );
+
+            assert_eq!(benchmark.sender.bytes, bytes);
+            assert_eq!(benchmark.receiver.bytes, bytes);
+            assert!(benchmark.receiver.throughput.unwrap() >= min_speed);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn it_should_control_driver_enable_in_rs485_mode() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let message = b"Hello, world!";
+    let timeout = Duration::from_millis(100);
+
+    let timing = test_stand.rs485_timing(message, timeout)?;
+
+    let config    = LineConfig::default();
+    let bit_time  = 1_000_000_000 / config.baud_rate as i64;
+    let char_time = bit_time * config.bits_per_char() as i64;
+
+    println!("{:?}", timing);
+
+    // Driver enable must be asserted before the first start bit, and released
+    // right after the last stop bit. The assistant samples both signals in
+    // software, so allow for a measurement error of about one bit time.
+    assert!(timing.setup_ns >= -bit_time);
+    assert!(timing.hold_ns >= -bit_time);
+    assert!(timing.hold_ns < char_time);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_receive_lin_frames_as_slave() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let frame = Frame::new(0x21, b"LIN", Checksum::Enhanced)?;
+
+    test_stand.target.start_lin_slave()?;
+    test_stand.assistant.send_lin_frame(&frame)?;
+
+    let timeout = Duration::from_millis(50);
+    let result  = test_stand.target
+        .wait_for_usart_rx_lin(&frame.to_bytes(), timeout);
+
+    // Stop acting as LIN slave, so other tests aren't affected.
+    test_stand.target.stop_lin_slave()?;
+
+    let received = Frame::parse(&result?, Checksum::Enhanced)?;
+    assert_eq!(received, frame);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_respond_to_lin_headers_as_slave() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let frame = Frame::new(0x10, &[0x12, 0x34], Checksum::Classic)?;
+
+    test_stand.target.start_lin_slave()?;
+    test_stand.target.set_lin_response(&frame)?;
+    test_stand.assistant.send_lin_header(&frame)?;
+
+    let timeout = Duration::from_millis(50);
+    let result  = test_stand.target
+        .wait_for_usart_rx_lin(&frame.header(), timeout);
+    let response = test_stand.assistant
+        .receive_from_target_usart(&frame.response(), timeout);
+
+    // Stop acting as LIN slave, so other tests aren't affected.
+    test_stand.target.stop_lin_slave()?;
+
+    let mut bytes = result?;
+    bytes.extend(response?);
+
+    let received = Frame::parse(&bytes, Checksum::Classic)?;
+    assert_eq!(received, frame);
+
+    Ok(())
+}
+
+
+/// Runs `f` while the target's DMA receiver uses `framing`
+///
+/// Restores `RxFraming::Chunks` afterwards, even if `f` fails, so other tests
+/// aren't affected.
+fn with_framing<T>(
+    test_stand: &mut TestStand,
+    framing:    RxFraming,
+    f:          impl FnOnce(&mut TestStand) -> Result<T>,
+)
+    -> Result<T>
+{
+    test_stand.target.configure_usart_framing(UsartMode::Dma, framing)?;
+    // Give the target time to apply the change.
+    sleep(Duration::from_millis(10));
+
+    let result = f(test_stand);
+
+    test_stand.target
+        .configure_usart_framing(UsartMode::Dma, RxFraming::Chunks)?;
+
+    result
+}
@@ -13,11 +13,15 @@ extern crate panic_rtt_target;
 
 use core::marker::PhantomData;
 
-use heapless::spsc;
+use heapless::{
+    Vec,
+    spsc,
+};
 use lpc8xx_hal::{
     prelude::*,
     Peripherals,
     cortex_m::{
+        asm,
         interrupt,
         peripheral::SYST,
     },
@@ -29,7 +33,7 @@ use lpc8xx_hal::{
         GpioPin,
         Level,
         direction::{
-            Input,
+            Dynamic,
             Output,
         },
     },
@@ -40,7 +44,9 @@ use lpc8xx_hal::{
         block,
     },
     pac::{
+        DMA0,
         I2C0,
+        IOCON,
         SPI0,
         USART0,
         USART1,
@@ -53,6 +59,7 @@ use lpc8xx_hal::{
     },
     pins::{
         self,
+        DynamicPinDirection,
         Pin,
         PIO0_8,
         PIO0_9,
@@ -87,22 +94,68 @@ use lpc8xx_hal::{
     },
 };
 use rtt_target::rprintln;
+use void::ResultVoidExt;
 
-#[cfg(feature = "sleep")]
-use lpc8xx_hal::cortex_m::asm;
-
-use firmware_lib::usart::{
-    RxIdle,
-    RxInt,
-    Tx,
-    Usart,
+use firmware_lib::{
+    benchmark::{
+        self,
+        Benchmark,
+    },
+    configure_iocon,
+    i2c::{
+        master as i2c_master,
+        slave::{
+            Device as I2cDevice,
+            Echo as I2cEcho,
+        },
+    },
+    pin_config,
+    spi::{
+        config as spi_config,
+        slave::Transfer as SpiTransfer,
+    },
+    systick,
+    usart::{
+        RxIdle,
+        RxInt,
+        Tx,
+        Usart,
+        address::{
+            AddressFilter,
+            Word,
+        },
+        config as usart_config,
+        frame::FrameDetector,
+        lin::LinSlave,
+    },
 };
 use lpc845_messages::{
     DmaMode,
     HostToTarget,
     TargetToHost,
     UsartMode,
+    i2c::{
+        Address as I2cAddress,
+        Error as I2cError,
+        MAX_TRANSFER as I2C_MAX_TRANSFER,
+        SCAN_FIRST as I2C_SCAN_FIRST,
+        SCAN_LAST as I2C_SCAN_LAST,
+    },
     pin,
+    spi::{
+        Config as SpiConfig,
+        MAX_TRANSFER as SPI_MAX_TRANSFER,
+    },
+    usart::{
+        ConfigError,
+        Diagnostics,
+        Direction,
+        FrameEnd,
+        LineConfig,
+        Link,
+        RxError,
+        RxErrorCounts,
+    },
 };
 
 
@@ -128,31 +181,37 @@ const APP: () = {
 
         green: GpioPin<PIO1_0, Output>,
         blue:  GpioPin<PIO1_1, Output>,
-        red:   GpioPin<PIO1_2, Input>,
+        red:   GpioPin<PIO1_2, Dynamic>,
+        iocon: IOCON,
 
-        red_int: pinint::Interrupt<PININT0, PIO1_2, Enabled>,
+        red_int:  pinint::Interrupt<PININT0, PIO1_2, Enabled>,
+        pin_echo: bool,
 
         systick: SYST,
         i2c:     Option<i2c::Master<I2C0, Enabled<PhantomData<IOSC>>, Enabled>>,
         i2c_dma: Option<dma::Channel<dma::Channel15, Enabled>>,
 
+        i2c_slave:  i2c::Slave<I2C0, Enabled<PhantomData<IOSC>>, Enabled>,
+        i2c_device: I2cDevice,
+
         spi:        Option<SPI<SPI0, Enabled<spi::Master>>>,
-        ssel:       GpioPin<PIO0_19, Output>,
+        ssel:       GpioPin<PIO0_19, Dynamic>,
         spi_rx_dma: Option<dma::Channel<dma::Channel10, Enabled>>,
         spi_tx_dma: Option<dma::Channel<dma::Channel11, Enabled>>,
 
         usart_dma_tx_channel: Option<dma::Channel<dma::Channel3, Enabled>>,
-        usart_dma_rx_transfer: Option<
-            dma::Transfer<
-                Started,
-                dma::Channel4,
-                usart::Rx<USART2, usart::state::Enabled<u8, AsyncMode>>,
-                &'static mut [u8],
-            >
-        >,
+        usart_dma_rx_transfer: Option<DmaRxTransfer>,
+        usart_dma_rx_spare:    Option<&'static mut [u8]>,
 
         dma_rx_prod: spsc::Producer<'static, u8, 32>,
         dma_rx_cons: spsc::Consumer<'static, u8, 32>,
+
+        usart_err_prod: spsc::Producer<'static, RxError, 8>,
+        usart_err_cons: spsc::Consumer<'static, RxError, 8>,
+
+        usart_addr_filter: Option<AddressFilter>,
+        usart_addr_prod:   spsc::Producer<'static, Word, 32>,
+        usart_addr_cons:   spsc::Consumer<'static, Word, 32>,
     }
 
     #[init]
@@ -167,7 +226,11 @@ const APP: () = {
         static mut USART_SYNC: Usart = Usart::new();
 
         static mut DMA_QUEUE: spsc::Queue<u8, 32> = spsc::Queue::new();
-        static mut DMA_BUFFER: [u8; 13] = [0; 13];
+        static mut DMA_BUFFER: [u8; CHUNK_BUFFER_LEN] = [0; CHUNK_BUFFER_LEN];
+        static mut FRAME_BUFFER: [u8; FRAME_BUFFER_LEN] = [0; FRAME_BUFFER_LEN];
+
+        static mut USART_ERRORS: spsc::Queue<RxError, 8> = spsc::Queue::new();
+        static mut USART_WORDS:  spsc::Queue<Word, 32>    = spsc::Queue::new();
 
         rtt_target::rtt_init_print!();
         rprintln!("Starting target.");
@@ -190,8 +253,16 @@ const APP: () = {
             .into_output_pin(gpio.tokens.pio1_0, Level::High);
         let blue = p.pins.pio1_1
             .into_output_pin(gpio.tokens.pio1_1, Level::High);
-        let red = p.pins.pio1_2
-            .into_input_pin(gpio.tokens.pio1_2);
+        let red = p.pins.pio1_2.into_dynamic_pin(
+            gpio.tokens.pio1_2,
+            Level::Low,
+            DynamicPinDirection::Input,
+        );
+
+        // Required to configure the input pin at runtime. The HAL doesn't
+        // support pull resistors or open-drain mode, so we need to access
+        // IOCON directly.
+        syscon.handle.enable_clock(&p.IOCON);
 
         // Set up interrupt for input pin
         let mut red_int = pinint
@@ -210,8 +281,8 @@ const APP: () = {
         // rate is roughly 115200 baud.
         let clock_config = {
             syscon.frg0.select_clock(frg::Clock::FRO);
-            syscon.frg0.set_mult(22);
-            syscon.frg0.set_div(0xFF);
+            syscon.frg0.set_mult(usart_config::FRG0_MULT);
+            syscon.frg0.set_div(usart_config::FRG0_DIV);
             usart::Clock::new(&syscon.frg0, 5, 16)
         };
 
@@ -271,6 +342,12 @@ const APP: () = {
             .. usart::Interrupts::default()
         });
 
+        // The HAL doesn't support break detection, so enable the interrupt
+        // directly. Sound, as writing to INTENSET only enables the interrupts
+        // whose bits are set.
+        let usart1 = unsafe { &*USART1::ptr() };
+        usart1.intenset.write(|w| w.deltarxbrken().set_bit());
+
         // Assign pins to USART3.
         let (u3_rxd, _) = swm.movable_functions.u3_rxd.assign(
             p.pins.pio0_13.into_swm_pin(),
@@ -332,7 +409,7 @@ const APP: () = {
             .i2c0_scl
             .assign(p.pins.pio0_10.into_swm_pin(), &mut swm_handle);
 
-        let i2c = p.I2C0
+        let mut i2c = p.I2C0
             .enable(
                 &syscon.iosc,
                 i2c0_scl,
@@ -341,7 +418,21 @@ const APP: () = {
             )
             .enable_master_mode(
                 &i2c::Clock::new_400khz(),
-            );
+            )
+            .enable_slave_mode(
+                I2C_SLAVE_ADDRESS,
+            )
+            .expect("Not using a valid address");
+        i2c.enable_interrupts(i2c::Interrupts {
+            slave_pending: true,
+            .. i2c::Interrupts::default()
+        });
+
+        // Sound, as the HAL doesn't touch the timeout configuration.
+        i2c_master::enable_timeout(
+            unsafe { &*I2C0::ptr() },
+            I2C_TIMEOUT_CLOCKS,
+        );
 
         let (spi0_sck, _) = swm
             .movable_functions
@@ -355,9 +446,11 @@ const APP: () = {
             .movable_functions
             .spi0_miso
             .assign(p.pins.pio0_18.into_swm_pin(), &mut swm_handle);
-        let ssel = p.pins.pio0_19.into_output_pin(
+        // An input while in slave mode, see `StartSpiSlave`.
+        let ssel = p.pins.pio0_19.into_dynamic_pin(
             gpio.tokens.pio0_19,
             Level::High,
+            DynamicPinDirection::Output,
         );
 
         let spi = p.SPI0.enable_as_master(
@@ -373,12 +466,16 @@ const APP: () = {
 
         let mut dma_rx_channel = dma.channels.channel4;
         dma_rx_channel.enable_interrupts();
-        let mut usart_dma_rx_transfer = usart2.rx
-            .read_all(&mut DMA_BUFFER[..], dma_rx_channel);
-        usart_dma_rx_transfer.set_a_when_complete();
-        let usart_dma_rx_transfer =  usart_dma_rx_transfer.start();
+        let usart_dma_rx_transfer = start_dma_rx(
+            usart2.rx,
+            &mut DMA_BUFFER[..],
+            dma_rx_channel,
+            true,
+        );
 
         let (dma_rx_prod, dma_rx_cons) = DMA_QUEUE.split();
+        let (usart_err_prod, usart_err_cons) = USART_ERRORS.split();
+        let (usart_addr_prod, usart_addr_cons) = USART_WORDS.split();
 
         init::LateResources {
             swm: Some(swm_handle),
@@ -401,13 +498,18 @@ const APP: () = {
             green,
             blue,
             red,
+            iocon: p.IOCON,
 
             red_int,
+            pin_echo: false,
 
             systick,
             i2c:     Some(i2c.master),
             i2c_dma: Some(dma.channels.channel15),
 
+            i2c_slave:  i2c.slave,
+            i2c_device: I2cDevice::Echo(I2cEcho::new()),
+
             spi: Some(spi),
             ssel,
             spi_rx_dma: Some(dma.channels.channel10),
@@ -415,9 +517,16 @@ const APP: () = {
 
             usart_dma_tx_channel:  Some(dma.channels.channel3),
             usart_dma_rx_transfer: Some(usart_dma_rx_transfer),
+            usart_dma_rx_spare:    Some(&mut FRAME_BUFFER[..]),
 
             dma_rx_prod,
             dma_rx_cons,
+
+            usart_err_prod,
+            usart_err_cons,
+            usart_addr_filter: None,
+            usart_addr_prod,
+            usart_addr_cons,
         }
     }
 
@@ -429,6 +538,8 @@ const APP: () = {
         usart_sync_rx_idle, usart_sync_tx,
         green,
         red,
+        iocon,
+        pin_echo,
         systick,
         i2c,
         i2c_dma,
@@ -437,7 +548,12 @@ const APP: () = {
         spi_rx_dma,
         spi_tx_dma,
         usart_dma_tx_channel,
+        usart_dma_rx_transfer,
+        usart_dma_rx_spare,
         dma_rx_cons,
+        usart_err_cons,
+        usart_addr_filter,
+        usart_addr_cons,
     ])]
     fn idle(cx: idle::Context) -> ! {
         let swm            = cx.resources.swm;
@@ -450,8 +566,7 @@ const APP: () = {
         let usart_sync_tx  = cx.resources.usart_sync_tx;
         let host_rx        = cx.resources.host_rx_idle;
         let host_tx        = cx.resources.host_tx;
-        let green          = cx.resources.green;
-        let red            = cx.resources.red;
+        let iocon          = cx.resources.iocon;
         let systick        = cx.resources.systick;
         let i2c            = cx.resources.i2c;
         let i2c_dma        = cx.resources.i2c_dma;
@@ -461,19 +576,118 @@ const APP: () = {
         let spi_tx_dma     = cx.resources.spi_tx_dma;
         let usart_dma_chan = cx.resources.usart_dma_tx_channel;
         let usart_dma_cons = cx.resources.dma_rx_cons;
+        let dma_rx_spare   = cx.resources.usart_dma_rx_spare;
+        let usart_err_cons = cx.resources.usart_err_cons;
+        let usart_addr_cons = cx.resources.usart_addr_cons;
 
         let mut usart_rx_int = cx.resources.usart_rx_int;
+        let mut usart_dma_rx = cx.resources.usart_dma_rx_transfer;
+        let mut green        = cx.resources.green;
+        let mut red          = cx.resources.red;
+        let mut pin_echo     = cx.resources.pin_echo;
+        let mut addr_filter  = cx.resources.usart_addr_filter;
 
         let mut buf = [0; 256];
 
+        let mut benchmark: Option<(UsartMode, Benchmark, systick::Saved)> =
+            None;
+
+        let mut dma_line_config = LineConfig::default();
+        let mut dma_framing: Option<(FrameDetector, systick::Saved)> = None;
+
+        let mut lin: Option<LinSlave> = None;
+
+        // The address that received data from the filter was sent to
+        let mut addressed_to = None;
+
+        // The SPI, while in slave mode, and the data it has received
+        let mut spi_slave:
+            Option<(SPI<SPI0, Enabled<spi::Slave>>, SpiTransfer)> = None;
+
+        // The configuration of the SPI, which has to be restored, whenever
+        // it's enabled in another mode.
+        let mut spi_config = SpiConfig::default();
+
         loop {
+            // While receiving for a benchmark, data is counted instead of
+            // forwarded to the host, which wouldn't be able to keep up.
+            if let Some((mode, benchmark, _)) = &mut benchmark {
+                let mut bytes = 0;
+                match mode {
+                    UsartMode::Dma => {
+                        while let Some(_) = usart_dma_cons.dequeue() {
+                            bytes += 1;
+                        }
+                    }
+                    _ => {
+                        while let Some(_) = usart_rx.queue.dequeue() {
+                            bytes += 1;
+                        }
+                    }
+                }
+
+                match bytes {
+                    0     => benchmark.update(systick::now()),
+                    bytes => benchmark.add(bytes, systick::now()),
+                }
+            }
+
+            let done = benchmark
+                .as_ref()
+                .map_or(false, |(_, benchmark, _)| benchmark.is_done());
+            if done {
+                let (_, finished, saved) = benchmark.take().unwrap();
+                systick::restore(systick, saved);
+                host_tx
+                    .send_message(
+                        &TargetToHost::UsartBenchmarkResult(
+                            finished.result()
+                        ),
+                        &mut buf,
+                    )
+                    .unwrap();
+            }
+
+            // Breaks need to be processed before the data that follows them.
+            while let Some(error) = usart_err_cons.dequeue() {
+                if let (Some(lin), RxError::Break) = (&mut lin, error) {
+                    lin.on_break();
+                    continue;
+                }
+
+                host_tx
+                    .send_message(
+                        &TargetToHost::UsartError {
+                            mode: UsartMode::Regular,
+                            error,
+                        },
+                        &mut buf,
+                    )
+                    .unwrap();
+            }
+
             usart_rx
                 .process_raw(|data| {
+                    let mode = match &mut lin {
+                        Some(lin) => {
+                            // Respond right away. Reporting to the host can
+                            // wait.
+                            for &b in data {
+                                if let Some(response) = lin.receive(b) {
+                                    usart_tx.as_mut().unwrap()
+                                        .send_raw(response)
+                                        .void_unwrap();
+                                }
+                            }
+                            UsartMode::Lin
+                        }
+                        None => {
+                            UsartMode::Regular
+                        }
+                    };
+
                     host_tx.send_message(
-                        &TargetToHost::UsartReceive {
-                            mode: UsartMode::Regular,
-                            data,
-                        },
+                        &TargetToHost::UsartReceive { mode, data },
                         &mut buf,
                     )
                 })
@@ -490,6 +704,60 @@ const APP: () = {
                 })
                 .expect("Error processing USART data (sync)");
 
+            let frame_end = dma_framing
+                .as_mut()
+                .and_then(|(detector, _)| {
+                    detector.update(
+                        dma_rx_received(FRAME_BUFFER_LEN),
+                        systick::now(),
+                    )
+                });
+            if let Some(end) = frame_end {
+                let mut frame = [0; FRAME_BUFFER_LEN];
+
+                let len = usart_dma_rx.lock(|transfer| {
+                    let transfer = transfer.take().unwrap();
+
+                    // If the buffer is full, the transfer has completed
+                    // already. Otherwise, any data received after this would
+                    // end up in the wrong frame.
+                    if end != FrameEnd::BufferFull {
+                        abort_dma_rx();
+                    }
+
+                    let payload = transfer.wait().unwrap();
+                    let len = dma_rx_received(payload.dest.len());
+                    frame[..len].copy_from_slice(&payload.dest[..len]);
+
+                    // A character that arrives before the restart waits in
+                    // the USART's receive register, so nothing is lost, as
+                    // long as we restart within a character time.
+                    *transfer = Some(start_dma_rx(
+                        payload.source,
+                        payload.dest,
+                        payload.channel,
+                        false,
+                    ));
+
+                    len
+                });
+
+                if let Some((detector, _)) = &mut dma_framing {
+                    detector.reset();
+                }
+
+                host_tx
+                    .send_message(
+                        &TargetToHost::UsartFrame {
+                            mode: UsartMode::Dma,
+                            data: &frame[..len],
+                            end,
+                        },
+                        &mut buf,
+                    )
+                    .unwrap();
+            }
+
             while let Some(b) = usart_dma_cons.dequeue() {
                 host_tx
                     .send_message(
@@ -502,6 +770,109 @@ const APP: () = {
                     .unwrap();
             }
 
+            if let Some((slave, transfer)) = &mut spi_slave {
+                if slave.is_slave_select_asserted() {
+                    transfer.start();
+                }
+                if slave.is_ready_to_receive() {
+                    let data = slave.receive().unwrap();
+                    transfer.on_receive(data);
+
+                    // Transmitted while the next byte is received.
+                    block!(slave.transmit(data << 1))
+                        .unwrap();
+                }
+                if slave.is_slave_select_deasserted() {
+                    transfer.finish();
+                }
+
+                if let Some(data) = transfer.take() {
+                    host_tx
+                        .send_message(
+                            &TargetToHost::SpiReceived(&data),
+                            &mut buf,
+                        )
+                        .unwrap();
+                }
+            }
+
+            // Data sent to the same address is reported together, as far as
+            // it has been received already.
+            let mut data: Vec<u8, 32> = Vec::new();
+            loop {
+                let word = usart_addr_cons.dequeue();
+
+                let flush = match word {
+                    Some(Word::Address(_)) | None => true,
+                    Some(Word::Data(_))           => data.is_full(),
+                };
+                if let (true, Some(address)) = (flush, addressed_to) {
+                    if !data.is_empty() {
+                        host_tx
+                            .send_message(
+                                &TargetToHost::UsartAddressed {
+                                    address,
+                                    data: &data,
+                                },
+                                &mut buf,
+                            )
+                            .unwrap();
+                        data.clear();
+                    }
+                }
+
+                match word {
+                    Some(Word::Address(address)) => {
+                        addressed_to = Some(address);
+                    }
+                    Some(Word::Data(b)) if addressed_to.is_some() => {
+                        // Can't fail, as a full buffer has been flushed
+                        // above.
+                        let _ = data.push(b);
+                    }
+                    Some(Word::Data(_)) => {
+                        // The filter only passes on data after an address.
+                        // Nothing to report it with, if that happens anyway.
+                    }
+                    None => {
+                        break;
+                    }
+                }
+            }
+
+            let links: [(Link, &mut RxIdle); 3] = [
+                (Link::Host,                      &mut *host_rx),
+                (Link::Usart(UsartMode::Regular), &mut *usart_rx),
+                (Link::Usart(UsartMode::Sync),    &mut *usart_sync_rx),
+            ];
+            for (link, rx) in links.iter_mut() {
+                let rx_errors = rx.new_errors().and_then(|rx_errors| {
+                    let rx_errors = match link {
+                        // Other errors have been reported individually, using
+                        // `UsartError`.
+                        Link::Usart(UsartMode::Regular) => RxErrorCounts {
+                            queue_full: rx_errors.queue_full,
+                            .. RxErrorCounts::default()
+                        },
+                        _ => rx_errors,
+                    };
+
+                    Some(rx_errors).filter(|errors| !errors.is_empty())
+                });
+
+                if let Some(rx_errors) = rx_errors {
+                    host_tx
+                        .send_message(
+                            &TargetToHost::Diagnostics(Diagnostics {
+                                link: *link,
+                                rx_errors,
+                            }),
+                            &mut buf,
+                        )
+                        .unwrap();
+                }
+            }
+
             host_rx
                 .process_message(|message| {
                     // We're working around two problems here:
@@ -618,6 +989,424 @@ const APP: () = {
                         } => {
                             usart_sync_tx.send_raw(data)
                         }
+                        HostToTarget::SendUsart {
+                            mode: UsartMode::Rs485,
+                            data,
+                        } => {
+                            rprintln!("USART: Sending in RS-485 mode");
+
+                            // Sound, as we only change the driver enable
+                            // configuration, which the HAL doesn't touch.
+                            let usart = unsafe { &*USART1::ptr() };
+
+                            let (rts, rts_pin) = usart_tx_local.usart
+                                .enable_rts(
+                                    usart_rts_local,
+                                    usart_rts_pin_local,
+                                    &mut swm_local,
+                                );
+                            usart_config::set_driver_enable(usart, true);
+
+                            // Give the assistant time to see DE inactive,
+                            // before it's asserted for the transmission.
+                            asm::delay(RS485_GUARD_CYCLES);
+
+                            usart_tx_local.send_raw(data)
+                                .void_unwrap();
+                            wait_for_usart1_idle();
+
+                            // Once RTS is no longer assigned, its pin goes
+                            // back to the level of the pull-up resistor. Give
+                            // the assistant time to see DE released first.
+                            asm::delay(RS485_GUARD_CYCLES);
+
+                            usart_config::set_driver_enable(usart, false);
+                            let (rts, rts_pin) = usart_tx_local.usart
+                                .disable_rts(rts, rts_pin, &mut swm_local);
+                            usart_rts_local = rts;
+                            usart_rts_pin_local = rts_pin;
+
+                            Ok(())
+                        }
+                        HostToTarget::SendUsartSequence {
+                            mode: UsartMode::Regular,
+                            len,
+                        } => {
+                            let mut chunk = [0; 32];
+                            let mut sent  = 0;
+                            while sent < len {
+                                let data = sequence(&mut chunk, sent, len);
+                                usart_tx_local.send_raw(data)
+                                    .void_unwrap();
+                                sent += data.len() as u32;
+                            }
+
+                            Ok(())
+                        }
+                        HostToTarget::SendUsartSequence {
+                            mode: UsartMode::FlowControl,
+                            len,
+                        } => {
+                            rprintln!("USART: Sending sequence ({} bytes)", len);
+
+                            let mut usart = usart_tx_local.usart;
+                            let (rts, rts_pin) = usart.enable_rts(
+                                usart_rts_local,
+                                usart_rts_pin_local,
+                                &mut swm_local,
+                            );
+                            let mut usart = usart.enable_cts_throttling(
+                                usart_cts_local,
+                            );
+
+                            let mut chunk = [0; 32];
+                            let mut sent  = 0;
+                            while sent < len {
+                                let data = sequence(&mut chunk, sent, len);
+                                usart.bwrite_all(data)
+                                    .unwrap();
+                                sent += data.len() as u32;
+                            }
+
+                            let (rts, rts_pin) = usart.disable_rts(
+                                rts,
+                                rts_pin,
+                                &mut swm_local,
+                            );
+                            let (usart, cts) = usart
+                                .disable_cts_throttling();
+                            usart_rts_local = rts;
+                            usart_rts_pin_local = rts_pin;
+                            usart_cts_local = cts;
+                            usart_tx_local.usart = usart;
+
+                            Ok(())
+                        }
+                        HostToTarget::SendUsartSequence { mode, .. } => {
+                            rprintln!("Can't send sequence ({:?})", mode);
+                            Ok(())
+                        }
+                        HostToTarget::ConfigureUsart { mode, config } => {
+                            rprintln!(
+                                "Configuring USART ({:?}): {:?}",
+                                mode,
+                                config,
+                            );
+
+                            // Sound, as we only access the registers to
+                            // change the configuration, which the HAL doesn't
+                            // touch after initialization.
+                            let usart = match mode {
+                                UsartMode::Regular
+                                | UsartMode::FlowControl
+                                | UsartMode::Rs485
+                                | UsartMode::Lin => {
+                                    Ok(unsafe { &*USART1::ptr() })
+                                }
+                                UsartMode::Dma => {
+                                    Ok(unsafe { &*USART2::ptr() })
+                                }
+                                UsartMode::Sync => {
+                                    Err(ConfigError::UnsupportedMode)
+                                }
+                            };
+
+                            let result = usart.and_then(|usart| {
+                                usart_config::configure(
+                                    usart,
+                                    &config,
+                                    usart_config::USART_CLOCK_HZ,
+                                )
+                                .map_err(|err| err.into())
+                            });
+                            let reply = match result {
+                                Ok(()) => {
+                                    if mode == UsartMode::Dma {
+                                        dma_line_config = config;
+                                    }
+                                    TargetToHost::UsartConfigured
+                                }
+                                Err(err) => {
+                                    rprintln!(
+                                        "Error configuring USART: {:?}",
+                                        err,
+                                    );
+                                    TargetToHost::UsartConfigError(err)
+                                }
+                            };
+
+                            host_tx
+                                .send_message(&reply, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::ConfigureUsartFraming {
+                            mode: UsartMode::Dma,
+                            framing,
+                        } => {
+                            rprintln!("USART/DMA: Framing: {:?}", framing);
+
+                            // The detector depends on the line parameters, so
+                            // those need to be configured first.
+                            let detector = FrameDetector::new(
+                                &framing,
+                                &dma_line_config,
+                                FRAME_BUFFER_LEN,
+                                SYSTICK_HZ,
+                                systick::MAX_RELOAD,
+                            );
+
+                            // SysTick is left running while the detector
+                            // uses it, and restored once framing is turned
+                            // off.
+                            let saved = dma_framing
+                                .take()
+                                .map(|(_, saved)| saved);
+                            dma_framing = match (detector, saved) {
+                                (Some(detector), Some(saved)) => {
+                                    Some((detector, saved))
+                                }
+                                (Some(detector), None) => {
+                                    Some((detector, systick::start(systick)))
+                                }
+                                (None, Some(saved)) => {
+                                    systick::restore(systick, saved);
+                                    None
+                                }
+                                (None, None) => {
+                                    None
+                                }
+                            };
+                            let frames = dma_framing.is_some();
+
+                            let buffer_len = match frames {
+                                true  => FRAME_BUFFER_LEN,
+                                false => CHUNK_BUFFER_LEN,
+                            };
+
+                            usart_dma_rx.lock(|transfer| {
+                                let transfer = transfer.take().unwrap();
+                                abort_dma_rx();
+                                let payload = transfer.wait().unwrap();
+
+                                // Data in the previous buffer is discarded.
+                                let buffer =
+                                    match payload.dest.len() == buffer_len {
+                                        true  => payload.dest,
+                                        false => {
+                                            dma_rx_spare
+                                                .replace(payload.dest)
+                                                .unwrap()
+                                        }
+                                    };
+
+                                // In chunk mode, the DMA interrupt handler
+                                // processes completed transfers. In frame
+                                // mode, the idle loop does that.
+                                *transfer = Some(start_dma_rx(
+                                    payload.source,
+                                    buffer,
+                                    payload.channel,
+                                    !frames,
+                                ));
+                            });
+
+                            Ok(())
+                        }
+                        HostToTarget::ConfigureUsartFraming { mode, .. } => {
+                            rprintln!("Can't configure framing ({:?})", mode);
+                            Ok(())
+                        }
+                        HostToTarget::UsartBenchmark {
+                            mode: mode @ UsartMode::Regular,
+                            direction: Direction::Receive,
+                            bytes,
+                        }
+                        | HostToTarget::UsartBenchmark {
+                            mode: mode @ UsartMode::Dma,
+                            direction: Direction::Receive,
+                            bytes,
+                        } => {
+                            let saved = systick::start(systick);
+                            benchmark = Some((
+                                mode,
+                                Benchmark::new(
+                                    Direction::Receive,
+                                    bytes,
+                                    SYSTICK_HZ,
+                                    systick::MAX_RELOAD,
+                                ),
+                                saved,
+                            ));
+
+                            Ok(())
+                        }
+                        HostToTarget::UsartBenchmark {
+                            mode: UsartMode::Regular,
+                            direction: Direction::Send,
+                            bytes,
+                        } => {
+                            let mut sender = Benchmark::new(
+                                Direction::Send,
+                                bytes,
+                                SYSTICK_HZ,
+                                systick::MAX_RELOAD,
+                            );
+
+                            let saved = systick::start(systick);
+                            sender.start(systick::now());
+
+                            let mut remaining = bytes as usize;
+                            while remaining > 0 {
+                                let len = Ord::min(
+                                    remaining,
+                                    benchmark::DATA.len(),
+                                );
+                                usart_tx_local.send_raw(&benchmark::DATA[..len])
+                                    .void_unwrap();
+                                sender.add(len as u32, systick::now());
+                                remaining -= len;
+                            }
+
+                            wait_for_usart1_idle();
+                            sender.add(0, systick::now());
+                            systick::restore(systick, saved);
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::UsartBenchmarkResult(
+                                        sender.result()
+                                    ),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::UsartBenchmark {
+                            mode: UsartMode::Dma,
+                            direction: Direction::Send,
+                            bytes,
+                        } => {
+                            let mut sender = Benchmark::new(
+                                Direction::Send,
+                                bytes,
+                                SYSTICK_HZ,
+                                systick::MAX_RELOAD,
+                            );
+
+                            let saved = systick::start(systick);
+                            sender.start(systick::now());
+
+                            let mut remaining = bytes as usize;
+                            while remaining > 0 {
+                                let len = Ord::min(
+                                    remaining,
+                                    benchmark::DATA.len(),
+                                );
+
+                                let payload = usart_tx_local.usart
+                                    .write_all(
+                                        &benchmark::DATA[..len],
+                                        usart_dma_chan_local,
+                                    )
+                                    .start()
+                                    .wait()
+                                    .unwrap();
+                                usart_dma_chan_local = payload.channel;
+                                usart_tx_local.usart = payload.dest;
+
+                                sender.add(len as u32, systick::now());
+                                remaining -= len;
+                            }
+
+                            wait_for_usart1_idle();
+                            sender.add(0, systick::now());
+                            systick::restore(systick, saved);
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::UsartBenchmarkResult(
+                                        sender.result()
+                                    ),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::UsartBenchmark { mode, .. } => {
+                            rprintln!("Can't run benchmark ({:?})", mode);
+                            Ok(())
+                        }
+                        HostToTarget::StartLinSlave => {
+                            rprintln!("USART: Acting as LIN slave");
+                            lin = Some(LinSlave::new());
+                            Ok(())
+                        }
+                        HostToTarget::StopLinSlave => {
+                            rprintln!("USART: No longer acting as LIN slave");
+                            lin = None;
+                            Ok(())
+                        }
+                        HostToTarget::SetLinResponse { pid, data } => {
+                            let result = match &mut lin {
+                                Some(lin) => {
+                                    lin.set_response(pid, data)
+                                }
+                                None => {
+                                    rprintln!("USART: Not acting as LIN slave");
+                                    Ok(())
+                                }
+                            };
+                            if let Err(err) = result {
+                                rprintln!(
+                                    "Error setting LIN response: {:?}",
+                                    err,
+                                );
+                            }
+
+                            Ok(())
+                        }
+                        HostToTarget::StartAddressFilter { addresses } => {
+                            rprintln!("USART: Filtering {:?}", addresses);
+
+                            // Sound, as we only change the address detection
+                            // mode, which the HAL doesn't touch.
+                            let usart = unsafe { &*USART1::ptr() };
+
+                            match AddressFilter::new(addresses) {
+                                Ok(filter) => {
+                                    addr_filter.lock(|addr_filter| {
+                                        filter.start(usart);
+                                        *addr_filter = Some(filter);
+                                    });
+                                    addressed_to = None;
+                                }
+                                Err(err) => {
+                                    rprintln!(
+                                        "Error starting address filter: {:?}",
+                                        err,
+                                    );
+                                }
+                            }
+
+                            Ok(())
+                        }
+                        HostToTarget::StopAddressFilter => {
+                            // Sound, as we only change the address detection
+                            // mode, which the HAL doesn't touch.
+                            let usart = unsafe { &*USART1::ptr() };
+
+                            addr_filter.lock(|addr_filter| {
+                                if let Some(filter) = addr_filter.take() {
+                                    filter.stop(usart);
+                                }
+                            });
+
+                            Ok(())
+                        }
                         HostToTarget::WaitForAddress(address) => {
                             usart_rx_int.lock(|rx| {
                                 rx.usart.start_address_detection(address);
@@ -630,15 +1419,15 @@ const APP: () = {
                         HostToTarget::SetPin(
                             pin::SetLevel { level: pin::Level::High, .. }
                         ) => {
-                            Ok(green.set_high())
+                            Ok(green.lock(|green| green.set_high()))
                         }
                         HostToTarget::SetPin(
                             pin::SetLevel { level: pin::Level::Low, .. }
                         ) => {
-                            Ok(green.set_low())
+                            Ok(green.lock(|green| green.set_low()))
                         }
                         HostToTarget::ReadPin(pin::ReadLevel { pin: () }) => {
-                            let level = match red.is_high() {
+                            let level = match red.lock(|red| red.is_high()) {
                                 true  => pin::Level::High,
                                 false => pin::Level::Low,
                             };
@@ -658,6 +1447,23 @@ const APP: () = {
 
                             Ok(())
                         }
+                        HostToTarget::ConfigurePin(
+                            pin::Configure { pin: (), config }
+                        ) => {
+                            configure_iocon!(iocon.pio1_2, config);
+                            red.lock(|red| {
+                                pin_config::configure_direction(red, &config)
+                            });
+                            Ok(())
+                        }
+                        HostToTarget::StartPinInterruptEcho => {
+                            pin_echo.lock(|pin_echo| *pin_echo = true);
+                            Ok(())
+                        }
+                        HostToTarget::StopPinInterruptEcho => {
+                            pin_echo.lock(|pin_echo| *pin_echo = false);
+                            Ok(())
+                        }
                         HostToTarget::StartTimerInterrupt { period_ms } => {
                             // By default (and we haven't changed that setting)
                             // the SysTick timer runs at half the system
@@ -683,32 +1489,86 @@ const APP: () = {
                         }
                         HostToTarget::StartI2cTransaction {
                             mode: DmaMode::Regular,
-                            address,
+                            address: I2cAddress::SevenBit(address),
                             data,
                         } => {
                             rprintln!("I2C: Write");
-                            i2c_local.write(address, &[data])
+                            let mut rx_buf = [0u8; 1];
+                            let result = i2c_local.write(address, &[data])
+                                .and_then(|()| {
+                                    rprintln!("I2C: Read");
+                                    i2c_local.read(address, &mut rx_buf)
+                                });
+
+                            let reply = match result {
+                                Ok(()) => {
+                                    rprintln!("I2C: Done");
+                                    TargetToHost::I2cReply(rx_buf[0])
+                                }
+                                Err(err) => {
+                                    // Sound, as we only access the registers
+                                    // while the HAL's API isn't in use.
+                                    let i2c_regs = unsafe { &*I2C0::ptr() };
+
+                                    let err = i2c_master::recover(
+                                        i2c_regs,
+                                        &err,
+                                    );
+                                    rprintln!("I2C: Error: {:?}", err);
+
+                                    TargetToHost::I2cError(err)
+                                }
+                            };
+
+                            host_tx
+                                .send_message(&reply, &mut buf)
                                 .unwrap();
 
-                            rprintln!("I2C: Read");
+                            Ok(())
+                        }
+                        HostToTarget::StartI2cTransaction {
+                            mode: DmaMode::Regular,
+                            address: address @ I2cAddress::TenBit(_),
+                            data,
+                        } => {
+                            rprintln!("I2C: Write to {:?}", address);
+
+                            // Sound, as we only access the registers while
+                            // the HAL's API isn't in use.
+                            let i2c_regs = unsafe { &*I2C0::ptr() };
+
+                            // LPC8xx HAL only supports 7-bit addresses. The
+                            // read follows the write after a repeated start,
+                            // as the I2C specification requires for 10-bit
+                            // addresses.
                             let mut rx_buf = [0u8; 1];
-                            i2c_local.read(address, &mut rx_buf)
-                                .unwrap();
+                            let result = i2c_master::write_read(
+                                i2c_regs,
+                                address,
+                                &[data],
+                                &mut rx_buf,
+                            );
 
-                            rprintln!("I2C: Done");
+                            let reply = match result {
+                                Ok(()) => {
+                                    rprintln!("I2C: Done");
+                                    TargetToHost::I2cReply(rx_buf[0])
+                                }
+                                Err(err) => {
+                                    rprintln!("I2C: Error: {:?}", err);
+                                    TargetToHost::I2cError(err.into())
+                                }
+                            };
 
                             host_tx
-                                .send_message(
-                                    &TargetToHost::I2cReply(rx_buf[0]),
-                                    &mut buf,
-                                )
+                                .send_message(&reply, &mut buf)
                                 .unwrap();
 
                             Ok(())
                         }
                         HostToTarget::StartI2cTransaction {
                             mode: DmaMode::Dma,
-                            address,
+                            address: I2cAddress::SevenBit(address),
                             data,
                         } => {
                             static mut TX_BUF: [u8; 1] = [0; 1];
@@ -756,6 +1616,226 @@ const APP: () = {
 
                             Ok(())
                         }
+                        HostToTarget::StartI2cTransaction {
+                            mode: DmaMode::Dma,
+                            address: address @ I2cAddress::TenBit(_),
+                            ..
+                        } => {
+                            // LPC8xx HAL's DMA API only supports 7-bit
+                            // addresses.
+                            rprintln!("I2C/DMA: Can't address {:?}", address);
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::I2cError(I2cError::Other),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::StartI2cTransfer {
+                            mode: DmaMode::Regular,
+                            address,
+                            write,
+                            read_len,
+                        } => {
+                            rprintln!(
+                                "I2C: Write {} byte(s), read {}",
+                                write.len(),
+                                read_len,
+                            );
+
+                            // Sound, as we only access the registers while
+                            // the HAL's API isn't in use.
+                            let i2c_regs = unsafe { &*I2C0::ptr() };
+
+                            let mut rx_buf = [0u8; I2C_MAX_TRANSFER];
+                            let read = &mut rx_buf[..read_len as usize];
+
+                            // The HAL ends each transfer with a stop
+                            // condition. Only a write followed by a read needs
+                            // the repeated start that the HAL can't do.
+                            let result = match (write.len(), read.len()) {
+                                (_, 0) => {
+                                    i2c_local.write(address, write)
+                                        .map_err(|err| {
+                                            i2c_master::recover(i2c_regs, &err)
+                                        })
+                                }
+                                (0, _) => {
+                                    i2c_local.read(address, read)
+                                        .map_err(|err| {
+                                            i2c_master::recover(i2c_regs, &err)
+                                        })
+                                }
+                                _ => {
+                                    i2c_master::write_read(
+                                        i2c_regs,
+                                        I2cAddress::SevenBit(address),
+                                        write,
+                                        read,
+                                    )
+                                    .map_err(|err| err.into())
+                                }
+                            };
+
+                            let reply = match result {
+                                Ok(()) => {
+                                    TargetToHost::I2cTransferReply(read)
+                                }
+                                Err(err) => {
+                                    rprintln!("I2C: Error: {:?}", err);
+                                    TargetToHost::I2cError(err)
+                                }
+                            };
+
+                            host_tx
+                                .send_message(&reply, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::StartI2cTransfer {
+                            mode: DmaMode::Dma,
+                            address,
+                            write,
+                            read_len,
+                        } => {
+                            static mut TX_BUF: [u8; I2C_MAX_TRANSFER] =
+                                [0; I2C_MAX_TRANSFER];
+                            static mut RX_BUF: [u8; I2C_MAX_TRANSFER] =
+                                [0; I2C_MAX_TRANSFER];
+
+                            // Sound, as we have exclusive access to these
+                            // statics here.
+                            let tx_buf = unsafe { &mut TX_BUF[..write.len()] };
+                            let mut rx_buf =
+                                unsafe { &mut RX_BUF[..read_len as usize] };
+
+                            let result = if write.is_empty() {
+                                Ok(())
+                            }
+                            else if rx_buf.is_empty() {
+                                tx_buf.copy_from_slice(write);
+
+                                let payload = i2c_local
+                                    .write_all(address, tx_buf, i2c_dma_local)
+                                    .unwrap()
+                                    .start()
+                                    .wait()
+                                    .unwrap();
+
+                                i2c_dma_local = payload.channel;
+                                i2c_local = payload.dest;
+
+                                Ok(())
+                            }
+                            else {
+                                // The HAL ends each DMA transfer with a stop
+                                // condition. Write without DMA, so the read
+                                // follows after a repeated start.
+                                //
+                                // Sound, as we only access the registers
+                                // while the HAL's API isn't in use.
+                                let i2c_regs = unsafe { &*I2C0::ptr() };
+
+                                i2c_master::write_without_stop(
+                                    i2c_regs,
+                                    I2cAddress::SevenBit(address),
+                                    write,
+                                )
+                            };
+
+                            let reply = match result {
+                                Ok(()) => {
+                                    if !rx_buf.is_empty() {
+                                        let payload = i2c_local
+                                            .read_all(
+                                                address,
+                                                rx_buf,
+                                                i2c_dma_local,
+                                            )
+                                            .unwrap()
+                                            .start()
+                                            .wait()
+                                            .unwrap();
+
+                                        i2c_dma_local = payload.channel;
+                                        i2c_local = payload.source;
+                                        rx_buf = payload.dest;
+                                    }
+
+                                    TargetToHost::I2cTransferReply(rx_buf)
+                                }
+                                Err(err) => {
+                                    rprintln!("I2C/DMA: Error: {:?}", err);
+                                    TargetToHost::I2cError(err.into())
+                                }
+                            };
+
+                            host_tx
+                                .send_message(&reply, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::ScanI2cBus => {
+                            rprintln!("I2C: Scan bus");
+
+                            // Sound, as we only access the registers while
+                            // the HAL's API isn't in use.
+                            let i2c_regs = unsafe { &*I2C0::ptr() };
+
+                            // Enough for every address that is scanned.
+                            let mut found: Vec<u8, 128> = Vec::new();
+
+                            let result = (I2C_SCAN_FIRST ..= I2C_SCAN_LAST)
+                                .try_for_each(|address| {
+                                    let acked = i2c_master::probe(
+                                        i2c_regs,
+                                        I2cAddress::SevenBit(address),
+                                    )?;
+                                    if acked {
+                                        found.push(address).unwrap();
+                                    }
+                                    Ok(())
+                                });
+
+                            let reply = match result {
+                                Ok(()) => {
+                                    rprintln!("I2C: Found {:?}", found);
+                                    TargetToHost::I2cScanResult(&found)
+                                }
+                                Err(err) => {
+                                    rprintln!("I2C: Error: {:?}", err);
+                                    TargetToHost::I2cError(err.into())
+                                }
+                            };
+
+                            host_tx
+                                .send_message(&reply, &mut buf)
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::StartSpiTransaction { .. }
+                        | HostToTarget::StartSpiTransfer { .. }
+                            if spi_slave.is_some() =>
+                        {
+                            // SPI0 is configured as a slave. Driving it
+                            // through the master API would hang.
+                            rprintln!("SPI: Refusing request in slave mode");
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::SpiSlaveActive,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
                         HostToTarget::StartSpiTransaction {
                             mode: DmaMode::Regular,
                             data,
@@ -841,6 +1921,131 @@ const APP: () = {
 
                             Ok(())
                         }
+                        HostToTarget::ConfigureSpi(config) => {
+                            rprintln!("SPI: Configure {:?}", config);
+
+                            // Sound, as we only access the registers while
+                            // the HAL's API isn't in use.
+                            let spi_regs = unsafe { &*SPI0::ptr() };
+                            spi_config::configure(spi_regs, &config);
+                            spi_config = config;
+
+                            Ok(())
+                        }
+                        HostToTarget::StartSpiSlave => {
+                            rprintln!("SPI: Start slave mode");
+
+                            let slave = enable_spi_slave(ssel, &spi_config);
+                            spi_slave = Some((slave, SpiTransfer::new()));
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::SpiSlaveStarted,
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::StopSpiSlave => {
+                            rprintln!("SPI: Stop slave mode");
+
+                            if let Some((slave, _)) = spi_slave.take() {
+                                spi_local = enable_spi_master(
+                                    slave,
+                                    ssel,
+                                    &spi_config,
+                                );
+                            }
+
+                            Ok(())
+                        }
+                        HostToTarget::StartSpiTransfer {
+                            mode: DmaMode::Regular,
+                            data,
+                        } => {
+                            rprintln!("SPI: Transfer {} byte(s)", data.len());
+
+                            let mut rx_buf = [0u8; SPI_MAX_TRANSFER];
+                            let reply = &mut rx_buf[..data.len()];
+
+                            ssel.set_low();
+
+                            // Clear receive buffer, as for single-byte
+                            // transactions.
+                            loop {
+                                if let Err(nb::Error::WouldBlock) =
+                                    spi_local.read()
+                                {
+                                    break;
+                                }
+                            }
+
+                            for (tx, rx) in data.iter().zip(reply.iter_mut()) {
+                                block!(spi_local.send(*tx))
+                                    .unwrap();
+                                *rx = block!(spi_local.read())
+                                    .unwrap();
+                            }
+
+                            ssel.set_high();
+                            rprintln!("SPI: Done");
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::SpiTransferReply(reply),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
+                        HostToTarget::StartSpiTransfer {
+                            mode: DmaMode::Dma,
+                            data,
+                        } => {
+                            static mut SPI_BUF: [u8; SPI_MAX_TRANSFER] =
+                                [0; SPI_MAX_TRANSFER];
+
+                            // Sound, as we have exclusive access to the static
+                            // here.
+                            let mut spi_buf =
+                                unsafe { &mut SPI_BUF[..data.len()] };
+
+                            rprintln!(
+                                "SPI/DMA: Transfer {} byte(s)",
+                                data.len(),
+                            );
+
+                            spi_buf.copy_from_slice(data);
+
+                            ssel.set_low();
+
+                            let payload = spi_local
+                                .transfer_all(
+                                    spi_buf,
+                                    spi_rx_dma_local,
+                                    spi_tx_dma_local,
+                                )
+                                .start()
+                                .wait();
+
+                            ssel.set_high();
+
+                            spi_local        = payload.0;
+                            spi_buf          = payload.1;
+                            spi_rx_dma_local = payload.2;
+                            spi_tx_dma_local = payload.3;
+
+                            host_tx
+                                .send_message(
+                                    &TargetToHost::SpiTransferReply(spi_buf),
+                                    &mut buf,
+                                )
+                                .unwrap();
+
+                            Ok(())
+                        }
                         message => {
                             panic!("Unsupported message: {:?}", message)
                         }
@@ -875,7 +2080,13 @@ const APP: () = {
             // us up before the test suite times out. But it could also lead to
             // spurious test failures.
             interrupt::free(|_| {
-                if !host_rx.can_process() && !usart_rx.can_process() {
+                // Frames are detected by polling, so we can't sleep while
+                // that's going on.
+                if !host_rx.can_process()
+                    && !usart_rx.can_process()
+                    && !usart_addr_cons.ready()
+                    && dma_framing.is_none()
+                {
                     // On LPC84x MCUs, debug mode is not supported when
                     // sleeping. This interferes with RTT communication. Only
                     // sleep, if the user enables this through a compile-time
@@ -889,20 +2100,94 @@ const APP: () = {
 
     #[task(binds = USART0, resources = [host_rx_int])]
     fn usart0(cx: usart0::Context) {
-        cx.resources.host_rx_int.receive()
-            .expect("Error receiving from USART0");
+        // Errors are counted, and reported to the host from the idle loop.
+        let _ = cx.resources.host_rx_int.receive();
     }
 
-    #[task(binds = USART1, resources = [usart_rx_int])]
+    #[task(
+        binds = USART1,
+        resources = [
+            usart_rx_int,
+            usart_err_prod,
+            usart_addr_filter,
+            usart_addr_prod,
+        ]
+    )]
     fn usart1(cx: usart1::Context) {
-        cx.resources.usart_rx_int.receive()
-            .expect("Error receiving from USART1");
+        let rx_int = cx.resources.usart_rx_int;
+        let errors = cx.resources.usart_err_prod;
+        let filter = cx.resources.usart_addr_filter;
+        let words  = cx.resources.usart_addr_prod;
+
+        // Sound, as we only read the status and clear a flag that the HAL
+        // doesn't use.
+        let usart = unsafe { &*USART1::ptr() };
+
+        let stat = usart.stat.read();
+        if stat.deltarxbrk().bit_is_set() {
+            usart.stat.write(|w| w.deltarxbrk().set_bit());
+
+            // This flag is set at the beginning and the end of a break. Only
+            // report the beginning.
+            if stat.rxbrk().bit_is_set() {
+                // If the queue is full, the idle loop hasn't caught up with
+                // previous errors. Nothing we can do about it here.
+                let _ = errors.enqueue(RxError::Break);
+            }
+        }
+
+        // While filtering, the HAL can't receive, as it would drop the address
+        // bit.
+        if let Some(filter) = filter {
+            while let Some(word) = filter.receive(usart) {
+                // If the queue is full, the idle loop hasn't caught up. Nothing
+                // we can do about it here.
+                let _ = words.enqueue(word);
+            }
+        }
+
+        // If there's more data after an error, the interrupt fires again.
+        // Errors are also counted, and reported to the host from the idle
+        // loop. Queue overflows are only reported that way.
+        if let Err(err) = rx_int.receive() {
+            if let Some(error) = err.rx_error() {
+                let _ = errors.enqueue(error);
+            }
+        }
     }
 
     #[task(binds = PIN_INT6_USART3, resources = [usart_sync_rx_int])]
     fn usart3(cx: usart3::Context) {
-        cx.resources.usart_sync_rx_int.receive()
-            .expect("Error receiving from USART3");
+        // Errors are counted, and reported to the host from the idle loop.
+        let _ = cx.resources.usart_sync_rx_int.receive();
+    }
+
+    #[task(binds = I2C0, resources = [i2c_slave, i2c_device])]
+    fn i2c0(context: i2c0::Context) {
+        let device = context.resources.i2c_device;
+
+        match context.resources.i2c_slave.wait() {
+            Ok(i2c::slave::State::AddressMatched(i2c)) => {
+                device.on_address();
+                i2c.ack().unwrap();
+            }
+            Ok(i2c::slave::State::RxReady(i2c)) => {
+                device.on_write(i2c.read().unwrap());
+                i2c.ack().unwrap();
+            }
+            Ok(i2c::slave::State::TxReady(i2c)) => {
+                // The slave stretches the clock until it transmits something,
+                // so send a filler byte, if the device has no data.
+                let data = device.on_read().unwrap_or(0xff);
+                i2c.transmit(data).unwrap();
+            }
+            Err(nb::Error::WouldBlock) => {
+                // I2C not ready; nothing to do
+            }
+            Err(err) => {
+                panic!("I2C error: {:?}", err);
+            }
+        }
     }
 
     #[task(binds = SysTick, resources = [blue])]
@@ -910,9 +2195,20 @@ const APP: () = {
         cx.resources.blue.toggle();
     }
 
-    #[task(binds = PIN_INT0, resources = [red_int])]
+    #[task(binds = PIN_INT0, resources = [red_int, red, green, pin_echo])]
     fn pinint0(context: pinint0::Context) {
-        let red_int = context.resources.red_int;
+        let red_int  = context.resources.red_int;
+        let red      = context.resources.red;
+        let green    = context.resources.green;
+        let pin_echo = context.resources.pin_echo;
+
+        // Do this first, as the assistant measures how long it takes.
+        if *pin_echo {
+            match red.is_high() {
+                true  => green.set_high(),
+                false => green.set_low(),
+            }
+        }
 
         red_int.clear_rising_edge_flag();
         red_int.clear_falling_edge_flag();
@@ -929,6 +2225,12 @@ const APP: () = {
         let transfer = context.resources.usart_dma_rx_transfer;
         let queue    = context.resources.dma_rx_prod;
 
+        // The idle loop might have restarted the transfer, after it completed
+        // but before we got here. Nothing to do in that case.
+        if !dma_rx_completed() {
+            return;
+        }
+
         // Process completed transfer.
         let payload = transfer
             .take()
@@ -946,8 +2248,225 @@ const APP: () = {
         }
 
         // Restart transfer.
-        let mut transfer_ready = usart.read_all(buffer, channel);
-        transfer_ready.set_a_when_complete();
-        *transfer = Some(transfer_ready.start());
+        *transfer = Some(start_dma_rx(usart, buffer, channel, true));
     }
 };
+
+
+/// Switches SPI0 from master to slave mode
+///
+/// LPC8xx HAL can't switch an enabled SPI to another mode, and the SWM
+/// functions needed to enable it again went to the master in `init`. This
+/// takes new instances of both. The master must not be used, until
+/// `enable_spi_master` has replaced it.
+fn enable_spi_slave(
+    ssel:   &mut GpioPin<PIO0_19, Dynamic>,
+    config: &SpiConfig,
+)
+    -> SPI<SPI0, Enabled<spi::Slave>>
+{
+    // Sound, as only SPI0, and the SWM functions and pins it already uses, are
+    // taken from this, and the caller doesn't use the master anymore.
+    let p = unsafe { Peripherals::steal() };
+
+    let mut syscon     = p.SYSCON.split();
+    let     swm        = p.SWM.split();
+    let mut swm_handle = swm.handle.enable(&mut syscon.handle);
+
+    // Driven by the master from now on.
+    ssel.switch_to_input();
+
+    let (sck, _) = swm.movable_functions.spi0_sck
+        .assign(p.pins.pio0_16.into_swm_pin(), &mut swm_handle);
+    let (mosi, _) = swm.movable_functions.spi0_mosi
+        .assign(p.pins.pio0_17.into_swm_pin(), &mut swm_handle);
+    let (miso, _) = swm.movable_functions.spi0_miso
+        .assign(p.pins.pio0_18.into_swm_pin(), &mut swm_handle);
+    let (ssel0, _) = swm.movable_functions.spi0_ssel0
+        .assign(p.pins.pio0_19.into_swm_pin(), &mut swm_handle);
+
+    let slave = p.SPI0.enable_as_slave(
+        &syscon.iosc,
+        &mut syscon.handle,
+        spi::MODE_0,
+        sck,
+        mosi,
+        miso,
+        ssel0,
+    );
+
+    // The HAL only supports the mode when enabling the SPI. Sound, as we only
+    // access the registers while the HAL's API isn't in use.
+    spi_config::configure(unsafe { &*SPI0::ptr() }, config);
+
+    slave
+}
+
+/// Switches SPI0 from slave back to master mode
+///
+/// See `enable_spi_slave`.
+fn enable_spi_master(
+    slave:  SPI<SPI0, Enabled<spi::Slave>>,
+    ssel:   &mut GpioPin<PIO0_19, Dynamic>,
+    config: &SpiConfig,
+)
+    -> SPI<SPI0, Enabled<spi::Master>>
+{
+    // Sound, for the same reasons as in `enable_spi_slave`. The slave has been
+    // passed in, so it can't be used anymore either.
+    let p = unsafe { Peripherals::steal() };
+
+    let mut syscon     = p.SYSCON.split();
+    let     swm        = p.SWM.split();
+    let mut swm_handle = swm.handle.enable(&mut syscon.handle);
+
+    let (sck, _) = swm.movable_functions.spi0_sck
+        .assign(p.pins.pio0_16.into_swm_pin(), &mut swm_handle);
+    let (mosi, _) = swm.movable_functions.spi0_mosi
+        .assign(p.pins.pio0_17.into_swm_pin(), &mut swm_handle);
+    let (miso, _) = swm.movable_functions.spi0_miso
+        .assign(p.pins.pio0_18.into_swm_pin(), &mut swm_handle);
+
+    // The master drives slave select using GPIO, so SSEL0 must not be
+    // assigned to the pin.
+    let (ssel0, pin) = swm.movable_functions.spi0_ssel0
+        .assign(p.pins.pio0_19.into_swm_pin(), &mut swm_handle);
+    ssel0.unassign(pin, &mut swm_handle);
+    ssel.switch_to_output(Level::High);
+
+    let master = slave
+        .disable(&mut syscon.handle)
+        .enable_as_master(
+            &spi::Clock::new(&syscon.iosc, 0x0fff),
+            &mut syscon.handle,
+            spi::MODE_0,
+            sck,
+            mosi,
+            miso,
+        );
+
+    // Sound, see above.
+    spi_config::configure(unsafe { &*SPI0::ptr() }, config);
+
+    master
+}
+
+/// Waits until USART1 has sent all data
+///
+/// Writing data only means it has been handed over to the USART. Benchmarks
+/// need to know when it has actually been sent.
+fn wait_for_usart1_idle() {
+    // Sound, as we're only reading the status register.
+    let usart = unsafe { &*USART1::ptr() };
+    while usart.stat.read().txidle().bit_is_clear() {}
+}
+
+/// Fills `buf` with the next part of a `SendUsartSequence` sequence
+///
+/// `offset` is the number of bytes that have already been sent, `len` the
+/// length of the whole sequence. Returns the filled part of `buf`.
+fn sequence(buf: &mut [u8], offset: u32, len: u32) -> &[u8] {
+    let n = Ord::min(buf.len() as u32, len - offset) as usize;
+
+    for (i, b) in buf[..n].iter_mut().enumerate() {
+        *b = ((offset + i as u32) % 251) as u8;
+    }
+
+    &buf[..n]
+}
+
+
+/// Starts a DMA transfer that receives from USART2
+///
+/// If `notify` is `true`, the DMA interrupt fires once the buffer is full.
+fn start_dma_rx(
+    usart:   DmaRx,
+    buffer:  &'static mut [u8],
+    channel: dma::Channel<dma::Channel4, Enabled>,
+    notify:  bool,
+)
+    -> DmaRxTransfer
+{
+    let mut transfer = usart.read_all(buffer, channel);
+    if notify {
+        transfer.set_a_when_complete();
+    }
+    transfer.start()
+}
+
+/// Returns the number of bytes the USART2 DMA receive transfer has written
+///
+/// `buffer_len` is the length of the transfer's buffer. The HAL doesn't
+/// provide this information, so we need to read the DMA registers directly.
+fn dma_rx_received(buffer_len: usize) -> usize {
+    // Sound, as we're only reading a register.
+    let dma = unsafe { &*DMA0::ptr() };
+
+    // XFERCOUNT is the number of remaining transfers, minus one. Once the
+    // transfer has completed, it wraps around to 0x3ff.
+    let count = dma.channel[DMA_RX_CHANNEL].xfercfg.read().xfercount().bits();
+    let remaining = (count as usize + 1) & 0x3ff;
+
+    buffer_len - remaining
+}
+
+/// Indicates whether the USART2 DMA receive transfer has notified completion
+fn dma_rx_completed() -> bool {
+    // Sound, as we're only reading a register.
+    let dma = unsafe { &*DMA0::ptr() };
+    dma.inta0.read().bits() & (1 << DMA_RX_CHANNEL) != 0
+}
+
+/// Aborts the USART2 DMA receive transfer
+///
+/// The HAL doesn't support aborting transfers, so this follows the procedure
+/// from the user manual, accessing the DMA registers directly. Afterwards, the
+/// transfer is no longer active, and waiting for it returns immediately.
+fn abort_dma_rx() {
+    // Sound, as all these registers only affect the channels whose bits are
+    // set, and this channel belongs to the transfer we're aborting.
+    let dma = unsafe { &*DMA0::ptr() };
+    let channel = 1 << DMA_RX_CHANNEL;
+
+    dma.enableclr0.write(|w| unsafe { w.bits(channel) });
+    while dma.busy0.read().bits() & channel != 0 {}
+    dma.abort0.write(|w| unsafe { w.bits(channel) });
+
+    // The HAL only enables channels once, not for every transfer.
+    dma.enableset0.write(|w| unsafe { w.bits(channel) });
+}
+
+
+type DmaRx = usart::Rx<USART2, usart::state::Enabled<u8, AsyncMode>>;
+type DmaRxTransfer = dma::Transfer<
+    Started,
+    dma::Channel4,
+    DmaRx,
+    &'static mut [u8],
+>;
+
+// The DMA channel that receives from USART2
+const DMA_RX_CHANNEL: usize = 4;
+
+// The size of the DMA receive buffer in chunk mode. Received data is processed
+// whenever the buffer is full.
+const CHUNK_BUFFER_LEN: usize = 13;
+
+// The size of the DMA receive buffer in frame mode. Longer frames are split.
+const FRAME_BUFFER_LEN: usize = 64;
+
+
+// The SysTick timer runs at half the system frequency, which is 12 MHz. See
+// `StartTimerInterrupt`.
+const SYSTICK_HZ: u32 = 6_000_000;
+
+// How long to keep DE inactive before and after an RS-485 transmission. 1 ms
+// at 12 MHz.
+const RS485_GUARD_CYCLES: u32 = 12_000;
+
+// The address of the target's I2C slave, which echoes what it receives
+const I2C_SLAVE_ADDRESS: u8 = 0x24;
+
+// The I2C timeout, in I2C function clock cycles. A slave that holds SCL low
+// for longer than this causes an error, instead of blocking forever.
+const I2C_TIMEOUT_CLOCKS: u32 = 12_000;
@@ -6,6 +6,8 @@ use host_lib::assistant::AssistantError;
 use crate::{
     target::{
         ReadAdcError,
+        TargetConfigurePinError,
+        TargetConfigureUsartError,
         TargetI2cError,
         TargetPinReadError,
         TargetSetPinHighError,
@@ -29,6 +31,8 @@ pub type Result<T = ()> = std::result::Result<T, Error>;
 pub enum Error {
     Assistant(AssistantError),
     ReadAdc(ReadAdcError),
+    TargetConfigurePin(TargetConfigurePinError),
+    TargetConfigureUsart(TargetConfigureUsartError),
     TargetI2c(TargetI2cError),
     TargetPinRead(TargetPinReadError),
     TargetSetPinHigh(TargetSetPinHighError),
@@ -53,6 +57,18 @@ impl From<ReadAdcError> for Error {
     }
 }
 
+impl From<TargetConfigurePinError> for Error {
+    fn from(err: TargetConfigurePinError) -> Self {
+        Self::TargetConfigurePin(err)
+    }
+}
+
+impl From<TargetConfigureUsartError> for Error {
+    fn from(err: TargetConfigureUsartError) -> Self {
+        Self::TargetConfigureUsart(err)
+    }
+}
+
 impl From<TargetI2cError> for Error {
     fn from(err: TargetI2cError) -> Self {
         Self::TargetI2c(err)
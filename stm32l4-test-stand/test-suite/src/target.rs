@@ -8,21 +8,27 @@ use std::{
 
 use host_lib::{
     conn::{
+        self,
         Conn,
         ConnReceiveError,
         ConnSendError,
+        Route,
     },
     pin::{
         Pin,
         ReadLevelError,
     },
+    usart::WaitForErrorError,
 };
 use lpc845_messages::{
     DmaMode,
     HostToTarget,
     TargetToHost,
     UsartMode,
+    i2c,
     pin,
+    spi,
+    usart,
 };
 
 
@@ -33,7 +39,9 @@ pub struct Target {
 }
 
 impl Target {
-    pub(crate) fn new(conn: Conn) -> Self {
+    pub(crate) fn new(mut conn: Conn) -> Self {
+        conn.set_router(route);
+
         Self {
             conn,
             pin: Pin::new(()),
@@ -60,6 +68,21 @@ impl Target {
             .map_err(|err| TargetSetPinLowError(err))
     }
 
+    /// Instruct the target to change the configuration of its input pin
+    ///
+    /// The input pin is the pin whose level is read by [`pin_is_high`] and
+    /// [`pin_is_low`].
+    ///
+    /// [`pin_is_high`]: #method.pin_is_high
+    /// [`pin_is_low`]: #method.pin_is_low
+    pub fn configure_pin(&mut self, config: pin::Config)
+        -> Result<(), TargetConfigurePinError>
+    {
+        self.pin
+            .configure::<HostToTarget>(config, &mut self.conn)
+            .map_err(|err| TargetConfigurePinError(err))
+    }
+
     /// Indicates whether the input pin is set high
     ///
     /// Uses `pin_state` internally.
@@ -82,6 +105,44 @@ impl Target {
         Ok(pin_state.0 == pin::Level::Low)
     }
 
+    /// Instruct the target to change the line parameters of a USART
+    ///
+    /// `mode` selects the USART that is used in that mode. Waits for the target
+    /// to confirm the new configuration.
+    pub fn configure_usart(&mut self,
+        mode:   UsartMode,
+        config: usart::LineConfig,
+    )
+        -> Result<(), TargetConfigureUsartError>
+    {
+        self.conn
+            .send(&HostToTarget::ConfigureUsart { mode, config })
+            .map_err(|err| TargetConfigureUsartError::Send(err))?;
+
+        let timeout = Duration::from_millis(50);
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_from::<TargetToHost>(Route::Other, timeout, &mut tmp)
+            .map_err(|err| TargetConfigureUsartError::Receive(err))?;
+
+        match message {
+            TargetToHost::UsartConfigured => {
+                Ok(())
+            }
+            TargetToHost::UsartConfigError(err) => {
+                Err(TargetConfigureUsartError::Config(err))
+            }
+            message => {
+                Err(
+                    TargetConfigureUsartError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
     /// Instruct the target to send this message via USART
     pub fn send_usart(&mut self, data: &[u8])
         -> Result<(), TargetUsartSendError>
@@ -112,6 +173,21 @@ impl Target {
             .map_err(|err| TargetUsartSendError(err))
     }
 
+    /// Instruct the target to send this message via synchronous USART
+    ///
+    /// The target is the master, and only provides a clock while sending. It
+    /// reports the data it received during that time, which can be waited for
+    /// using [`wait_for_usart_rx_sync`].
+    ///
+    /// [`wait_for_usart_rx_sync`]: #method.wait_for_usart_rx_sync
+    pub fn send_usart_sync(&mut self, data: &[u8])
+        -> Result<(), TargetUsartSendError>
+    {
+        self.conn
+            .send(&HostToTarget::SendUsart { mode: UsartMode::Sync, data })
+            .map_err(|err| TargetUsartSendError(err))
+    }
+
     /// Wait to receive the provided data via USART
     ///
     /// Returns the receive buffer, once the data was received. Returns an
@@ -132,6 +208,69 @@ impl Target {
         self.wait_for_usart_rx_inner(data, timeout, UsartMode::Dma)
     }
 
+    /// Wait to receive the provided data via synchronous USART
+    ///
+    /// Returns the receive buffer, once the data was received. Returns an
+    /// error, if it times out before that, or an I/O error occurs.
+    pub fn wait_for_usart_rx_sync(&mut self, data: &[u8], timeout: Duration)
+        -> Result<Vec<u8>, TargetUsartWaitError>
+    {
+        self.wait_for_usart_rx_inner(data, timeout, UsartMode::Sync)
+    }
+
+    /// Wait for the target to report the provided USART receive error
+    ///
+    /// Returns all errors that the target reported until then, including the
+    /// expected one. Received data is ignored. Returns an error, if it times
+    /// out before that, or an I/O error occurs.
+    ///
+    /// Once the expected error has been reported, keeps receiving until the
+    /// target has been quiet for a moment, so anything else caused by the
+    /// fault doesn't affect later calls.
+    pub fn wait_for_usart_error(&mut self,
+        error:   usart::RxError,
+        timeout: Duration,
+    )
+        -> Result<Vec<usart::RxError>, TargetUsartWaitError>
+    {
+        host_lib::usart::wait_for_error(
+            error,
+            timeout,
+            Route::Usart(UsartMode::Regular),
+            &mut self.conn,
+            |message| {
+                match message {
+                    TargetToHost::UsartError {
+                        mode: UsartMode::Regular,
+                        error,
+                    } => {
+                        Ok(Some(error))
+                    }
+                    TargetToHost::UsartReceive {
+                        mode: UsartMode::Regular,
+                        data: _,
+                    } => {
+                        Ok(None)
+                    }
+                    message => {
+                        Err(message)
+                    }
+                }
+            },
+        )
+        .map_err(|err| match err {
+            WaitForErrorError::Receive(err) => {
+                TargetUsartWaitError::Receive(err)
+            }
+            WaitForErrorError::Timeout => {
+                TargetUsartWaitError::Timeout
+            }
+            WaitForErrorError::UnexpectedMessage(message) => {
+                TargetUsartWaitError::UnexpectedMessage(message)
+            }
+        })
+    }
+
     fn wait_for_usart_rx_inner(&mut self,
         data:          &[u8],
         timeout:       Duration,
@@ -152,7 +291,11 @@ impl Target {
 
             let mut tmp = Vec::new();
             let message = self.conn
-                .receive::<TargetToHost>(timeout, &mut tmp)
+                .receive_from::<TargetToHost>(
+                    Route::Usart(expected_mode),
+                    timeout,
+                    &mut tmp,
+                )
                 .map_err(|err| TargetUsartWaitError::Receive(err))?;
 
             match message {
@@ -184,7 +327,8 @@ impl Target {
             .map_err(|err| ReadAdcError::Send(err))?;
 
         let mut buf = Vec::new();
-        let reply = self.conn.receive::<TargetToHost>(timeout, &mut buf)
+        let reply = self.conn
+            .receive_from::<TargetToHost>(Route::Other, timeout, &mut buf)
             .map_err(|err| ReadAdcError::Receive(err))?;
 
         match reply {
@@ -207,26 +351,129 @@ impl Target {
     pub fn start_i2c_transaction(&mut self, data: u8, timeout: Duration)
         -> Result<u8, TargetI2cError>
     {
-        let address = 0x48;
+        self.start_i2c_transaction_inner(data, timeout, DmaMode::Regular)
+    }
+
+    /// Start an I2C/DMA transaction
+    ///
+    /// Sends the provided `data` and returns the reply.
+    pub fn start_i2c_transaction_dma(&mut self, data: u8, timeout: Duration)
+        -> Result<u8, TargetI2cError>
+    {
+        self.start_i2c_transaction_inner(data, timeout, DmaMode::Dma)
+    }
+
+    fn start_i2c_transaction_inner(&mut self,
+        data:    u8,
+        timeout: Duration,
+        mode:    DmaMode,
+    )
+        -> Result<u8, TargetI2cError>
+    {
+        let address = i2c::Address::SevenBit(0x48);
+
+        self.conn
+            .send(&HostToTarget::StartI2cTransaction { mode, address, data })
+            .map_err(|err| TargetI2cError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_from::<TargetToHost>(Route::Bus, timeout, &mut tmp)
+            .map_err(|err| TargetI2cError::Receive(err))?;
+
+        match message {
+            TargetToHost::I2cReply(reply) => {
+                Ok(reply)
+            }
+            TargetToHost::I2cError(err) => {
+                Err(TargetI2cError::I2c(err))
+            }
+            message => {
+                Err(
+                    TargetI2cError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Write to the I2C slave at `address`
+    pub fn i2c_write(&mut self,
+        mode:    DmaMode,
+        address: u8,
+        data:    &[u8],
+        timeout: Duration,
+    )
+        -> Result<(), TargetI2cError>
+    {
+        self.i2c_transfer(mode, address, data, 0, timeout)?;
+        Ok(())
+    }
+
+    /// Read `len` bytes from the I2C slave at `address`
+    pub fn i2c_read(&mut self,
+        mode:    DmaMode,
+        address: u8,
+        len:     usize,
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, TargetI2cError>
+    {
+        self.i2c_transfer(mode, address, &[], len, timeout)
+    }
+
+    /// Write to the I2C slave at `address`, then read `len` bytes from it
+    ///
+    /// The read follows the write after a repeated start, in both modes.
+    pub fn i2c_write_read(&mut self,
+        mode:    DmaMode,
+        address: u8,
+        data:    &[u8],
+        len:     usize,
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, TargetI2cError>
+    {
+        self.i2c_transfer(mode, address, data, len, timeout)
+    }
+
+    fn i2c_transfer(&mut self,
+        mode:     DmaMode,
+        address:  u8,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, TargetI2cError>
+    {
+        let too_long = Ord::max(write.len(), read_len);
+        if too_long > i2c::MAX_TRANSFER {
+            return Err(TargetI2cError::TransferTooLong(too_long));
+        }
 
         self.conn
             .send(
-                &HostToTarget::StartI2cTransaction {
-                    mode: DmaMode::Regular,
+                &HostToTarget::StartI2cTransfer {
+                    mode,
                     address,
-                    data,
+                    write,
+                    read_len: read_len as u8,
                 }
             )
             .map_err(|err| TargetI2cError::Send(err))?;
 
         let mut tmp = Vec::new();
         let message = self.conn
-            .receive::<TargetToHost>(timeout, &mut tmp)
+            .receive_from::<TargetToHost>(Route::Bus, timeout, &mut tmp)
             .map_err(|err| TargetI2cError::Receive(err))?;
 
         match message {
-            TargetToHost::I2cReply(reply) => {
-                Ok(reply)
+            TargetToHost::I2cTransferReply(data) if data.len() == read_len => {
+                Ok(data.to_vec())
+            }
+            TargetToHost::I2cError(err) => {
+                Err(TargetI2cError::I2c(err))
             }
             message => {
                 Err(
@@ -243,18 +490,33 @@ impl Target {
     /// Sends the provided `data` and returns the reply.
     pub fn start_spi_transaction(&mut self, data: u8, timeout: Duration)
         -> Result<u8, TargetSpiError>
+    {
+        self.start_spi_transaction_inner(data, timeout, DmaMode::Regular)
+    }
+
+    /// Start an SPI/DMA transaction
+    ///
+    /// Sends the provided `data` and returns the reply.
+    pub fn start_spi_transaction_dma(&mut self, data: u8, timeout: Duration)
+        -> Result<u8, TargetSpiError>
+    {
+        self.start_spi_transaction_inner(data, timeout, DmaMode::Dma)
+    }
+
+    fn start_spi_transaction_inner(&mut self,
+        data:    u8,
+        timeout: Duration,
+        mode:    DmaMode,
+    )
+        -> Result<u8, TargetSpiError>
     {
         self.conn
-            .send(
-                &HostToTarget::StartSpiTransaction {
-                    mode: DmaMode::Regular,
-                    data,
-                }
-            )
+            .send(&HostToTarget::StartSpiTransaction { mode, data })
             .map_err(|err| TargetSpiError::Send(err))?;
 
         let mut tmp = Vec::new();
-        let message = self.conn.receive::<TargetToHost>(timeout, &mut tmp)
+        let message = self.conn
+            .receive_from::<TargetToHost>(Route::Bus, timeout, &mut tmp)
             .map_err(|err| TargetSpiError::Receive(err))?;
 
         match message {
@@ -271,6 +533,46 @@ impl Target {
         }
     }
 
+    /// Start a multi-byte SPI transfer
+    ///
+    /// Sends the provided `data` and returns the data received at the same
+    /// time, which has the same length.
+    pub fn spi_transfer(&mut self,
+        mode:    DmaMode,
+        data:    &[u8],
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, TargetSpiError>
+    {
+        if data.len() > spi::MAX_TRANSFER {
+            return Err(TargetSpiError::TransferTooLong(data.len()));
+        }
+
+        self.conn
+            .send(&HostToTarget::StartSpiTransfer { mode, data })
+            .map_err(|err| TargetSpiError::Send(err))?;
+
+        let mut tmp = Vec::new();
+        let message = self.conn
+            .receive_from::<TargetToHost>(Route::Bus, timeout, &mut tmp)
+            .map_err(|err| TargetSpiError::Receive(err))?;
+
+        match message {
+            TargetToHost::SpiTransferReply(reply)
+                if reply.len() == data.len() =>
+            {
+                Ok(reply.to_vec())
+            }
+            message => {
+                Err(
+                    TargetSpiError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
     /// Start a timer interrupt with the given period in milliseconds
     pub fn start_timer_interrupt(&mut self, period_ms: u32)
         -> Result<TimerInterrupt, TargetStartTimerInterruptError>
@@ -295,6 +597,40 @@ impl Target {
 }
 
 
+/// Decides where messages from the target are queued
+///
+/// See [`Conn::set_router`].
+///
+/// [`Conn::set_router`]: ../../host_lib/conn/struct.Conn.html#method.set_router
+fn route(frame: &[u8]) -> Route {
+    let message = conn::decode::<TargetToHost>(frame);
+
+    match message {
+        Some(TargetToHost::UsartReceive { mode, .. })
+        | Some(TargetToHost::UsartFrame { mode, .. })
+        | Some(TargetToHost::UsartError { mode, .. }) => {
+            Route::Usart(mode)
+        }
+        Some(TargetToHost::Diagnostics(diagnostics)) => {
+            Route::Diagnostics(diagnostics)
+        }
+        Some(TargetToHost::ReadPinResult(_)) => {
+            Route::Pin
+        }
+        Some(TargetToHost::I2cReply(_))
+        | Some(TargetToHost::I2cTransferReply(_))
+        | Some(TargetToHost::I2cError(_))
+        | Some(TargetToHost::SpiReply(_))
+        | Some(TargetToHost::SpiTransferReply(_)) => {
+            Route::Bus
+        }
+        _ => {
+            Route::Other
+        }
+    }
+}
+
+
 /// Represent a timer interrupt that's currently configured on the target
 ///
 /// This timer interrupt will be stopped when this struct is dropped.
@@ -326,6 +662,19 @@ pub struct TargetSetPinHighError(ConnSendError);
 #[derive(Debug)]
 pub struct TargetSetPinLowError(ConnSendError);
 
+#[derive(Debug)]
+pub struct TargetConfigurePinError(ConnSendError);
+
+#[derive(Debug)]
+pub enum TargetConfigureUsartError {
+    /// The target can't apply the configuration
+    Config(usart::ConfigError),
+
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
 #[derive(Debug)]
 pub struct TargetPinReadError(ReadLevelError);
 
@@ -342,6 +691,7 @@ pub struct TargetUsartSendError(ConnSendError);
 #[derive(Debug)]
 pub enum TargetUsartWaitError {
     Receive(ConnReceiveError),
+
     Timeout,
     UnexpectedMessage(String),
 }
@@ -357,6 +707,8 @@ pub enum ReadAdcError {
 pub enum TargetI2cError {
     Send(ConnSendError),
     Receive(ConnReceiveError),
+    I2c(i2c::Error),
+    TransferTooLong(usize),
     UnexpectedMessage(String),
 }
 
@@ -364,6 +716,7 @@ pub enum TargetI2cError {
 pub enum TargetSpiError {
     Send(ConnSendError),
     Receive(ConnReceiveError),
+    TransferTooLong(usize),
     UnexpectedMessage(String),
 }
 
@@ -1,6 +1,10 @@
-use std::sync::{
-    LockResult,
-    MutexGuard,
+use std::{
+    sync::{
+        LockResult,
+        MutexGuard,
+    },
+    thread::sleep,
+    time::Duration,
 };
 
 use host_lib::{
@@ -8,6 +12,11 @@ use host_lib::{
     test_stand::NotConfiguredError,
 };
 
+use lpc845_messages::{
+    UsartMode,
+    usart,
+};
+
 use crate::target::Target;
 
 
@@ -38,6 +47,24 @@ impl TestStand {
             }
         )
     }
+
+    /// Changes the line parameters of a USART on both target and assistant
+    ///
+    /// Configures both ends of the connection that is used in `mode`, then
+    /// waits a bit, to give target and assistant time to apply the change.
+    pub fn configure_usart(&mut self,
+        mode:   UsartMode,
+        config: usart::LineConfig,
+    )
+        -> crate::Result
+    {
+        self.target.configure_usart(mode, config)?;
+        self.assistant.configure_usart(mode, config)?;
+
+        sleep(Duration::from_millis(10));
+
+        Ok(())
+    }
 }
 
 
@@ -4,6 +4,7 @@
 //! wiring instructions.
 
 
+use lpc845_messages::pin;
 use stm32l4_test_suite::{
     Result,
     TestStand,
@@ -35,3 +36,53 @@ fn it_should_read_input_level() -> Result {
 
     Ok(())
 }
+
+#[test]
+fn it_should_enable_pull_resistors_on_the_input_pin() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant.configure_pin("red", pin::Config::input())?;
+
+    test_stand.target
+        .configure_pin(pin::Config::input_with_pull(pin::Pull::Up))?;
+    assert!(test_stand.target.pin_is_high()?);
+
+    test_stand.target
+        .configure_pin(pin::Config::input_with_pull(pin::Pull::Down))?;
+    assert!(test_stand.target.pin_is_low()?);
+
+    test_stand.target.configure_pin(pin::Config::input())?;
+    test_stand.assistant.configure_pin(
+        "red",
+        pin::Config::output(pin::Level::High),
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn it_should_read_an_open_drain_output() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.target
+        .configure_pin(pin::Config::input_with_pull(pin::Pull::Up))?;
+
+    test_stand.assistant.configure_pin(
+        "red",
+        pin::Config::open_drain(pin::Level::Low, pin::Pull::None),
+    )?;
+    assert!(test_stand.target.pin_is_low()?);
+
+    // The open-drain output releases the line, so it's pulled up by the
+    // target.
+    test_stand.assistant.set_pin_high()?;
+    assert!(test_stand.target.pin_is_high()?);
+
+    test_stand.target.configure_pin(pin::Config::input())?;
+    test_stand.assistant.configure_pin(
+        "red",
+        pin::Config::output(pin::Level::High),
+    )?;
+
+    Ok(())
+}
@@ -4,14 +4,33 @@
 //! wiring instructions.
 
 
-use std::time::Duration;
+use std::{
+    fmt::Debug,
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
 
+use lpc845_messages::{
+    DmaMode,
+    i2c::{
+        self,
+        Fault,
+    },
+};
 use stm32l4_test_suite::{
     Result,
     TestStand,
+    target::TargetI2cError,
 };
 
 
+// The address of the assistant's I2C slave
+const ECHO: u8 = 0x48;
+
+
 #[test]
 fn it_should_start_a_transaction() -> Result {
     let mut test_stand = TestStand::new()?;
@@ -24,3 +43,189 @@ fn it_should_start_a_transaction() -> Result {
 
     Ok(())
 }
+
+#[test]
+fn it_should_start_a_transaction_using_dma() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let data = 0x22;
+    let timeout = Duration::from_millis(50);
+    let reply = test_stand.target.start_i2c_transaction_dma(data, timeout)?;
+
+    assert_eq!(reply, data << 1);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_write_and_read_multiple_bytes() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let data = [0x11, 0x22, 0x33, 0x44];
+    let timeout = Duration::from_millis(50);
+    test_stand.target.i2c_write(DmaMode::Regular, ECHO, &data, timeout)?;
+    let reply = test_stand.target
+        .i2c_read(DmaMode::Regular, ECHO, 4, timeout)?;
+
+    let expected: Vec<_> = data.iter().map(|b| b << 1).collect();
+    assert_eq!(reply, expected);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_write_and_read_multiple_bytes_using_dma() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let data = [0x11, 0x22, 0x33, 0x44];
+    let timeout = Duration::from_millis(50);
+    test_stand.target.i2c_write(DmaMode::Dma, ECHO, &data, timeout)?;
+    let reply = test_stand.target
+        .i2c_read(DmaMode::Dma, ECHO, 4, timeout)?;
+
+    let expected: Vec<_> = data.iter().map(|b| b << 1).collect();
+    assert_eq!(reply, expected);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_read_after_a_repeated_start() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // Like a register address, followed by a value.
+    let data = [0x05, 0x3c];
+    let timeout = Duration::from_millis(50);
+    let reply = test_stand.target
+        .i2c_write_read(DmaMode::Regular, ECHO, &data, 3, timeout)?;
+
+    // The slave repeats the written data, if more is read.
+    assert_eq!(reply, [0x05 << 1, 0x3c << 1, 0x05 << 1]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_read_after_a_repeated_start_using_dma() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let data = [0x05, 0x3c];
+    let timeout = Duration::from_millis(50);
+    let reply = test_stand.target
+        .i2c_write_read(DmaMode::Dma, ECHO, &data, 3, timeout)?;
+
+    assert_eq!(reply, [0x05 << 1, 0x3c << 1, 0x05 << 1]);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_report_a_nacked_address() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant.inject_i2c_fault(Fault::NackAddress)?;
+
+    let timeout = Duration::from_millis(50);
+    let result = test_stand.target.start_i2c_transaction(0x22, timeout);
+
+    expect_i2c_error(result, i2c::Error::Nack);
+    Ok(())
+}
+
+#[test]
+fn it_should_report_a_nacked_data_byte() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant.inject_i2c_fault(Fault::NackData { index: 0 })?;
+
+    // The HAL doesn't distinguish between address and data NACKs.
+    let timeout = Duration::from_millis(50);
+    let result = test_stand.target.start_i2c_transaction(0x22, timeout);
+
+    expect_i2c_error(result, i2c::Error::Nack);
+    Ok(())
+}
+
+#[test]
+fn it_should_report_a_nacked_data_byte_using_dma() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant.inject_i2c_fault(Fault::NackData { index: 0 })?;
+
+    let timeout = Duration::from_millis(50);
+    let result = test_stand.target.start_i2c_transaction_dma(0x22, timeout);
+
+    expect_i2c_error(result, i2c::Error::Nack);
+    Ok(())
+}
+
+#[test]
+fn it_should_tolerate_clock_stretching() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant
+        .inject_i2c_fault(Fault::StretchClock { duration_us: 100 })?;
+
+    let data = 0x22;
+    let timeout = Duration::from_millis(50);
+    let reply = test_stand.target.start_i2c_transaction(data, timeout)?;
+
+    assert_eq!(reply, data << 1);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_time_out_on_long_clock_stretching_using_dma() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // The HAL has no timeout, so only the DMA path, which uses the registers
+    // directly, can detect this.
+    test_stand.assistant
+        .inject_i2c_fault(Fault::StretchClock { duration_us: 20_000 })?;
+
+    let timeout = Duration::from_millis(100);
+    let result = test_stand.target.start_i2c_transaction_dma(0x22, timeout);
+
+    expect_i2c_error(result, i2c::Error::Timeout);
+    Ok(())
+}
+
+#[test]
+fn it_should_wait_while_sda_is_held_low() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let hold = Duration::from_millis(20);
+    test_stand.assistant.inject_i2c_fault(
+        Fault::HoldSda { duration_us: hold.as_micros() as u32 }
+    )?;
+
+    // Make sure the assistant has received the message, and SDA is low.
+    let delay = Duration::from_millis(5);
+    thread::sleep(delay);
+
+    // The bus looks busy, so the master waits until SDA is released, then
+    // starts the transaction.
+    let data = 0x22;
+    let timeout = Duration::from_millis(100);
+    let start = Instant::now();
+    let reply = test_stand.target.start_i2c_transaction(data, timeout)?;
+
+    assert_eq!(reply, data << 1);
+    assert!(start.elapsed() >= hold - delay);
+
+    Ok(())
+}
+
+
+fn expect_i2c_error<T: Debug>(
+    result:   std::result::Result<T, TargetI2cError>,
+    expected: i2c::Error,
+) {
+    match result {
+        Err(TargetI2cError::I2c(err)) if err == expected => {}
+        result => {
+            panic!("Expected {:?}, got {:?}", expected, result);
+        }
+    }
+}
@@ -32,3 +32,26 @@ fn it_should_create_a_pwm_signal() -> Result {
 
     Ok(())
 }
+
+#[test]
+fn it_should_create_a_pwm_signal_with_the_configured_duty_cycle() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // The target generates a 50 Hz signal with a 50% duty cycle.
+    let frequency_hz = 50.0;
+    let duty_cycle   = 0.5;
+
+    // When `_signal` is dropped, the PWM signal will be stopped.
+    let _signal = test_stand.target.start_pwm_signal()?;
+
+    let timeout     = Duration::from_millis(200);
+    let measurement = test_stand.assistant.measure_pwm_duty_cycle(5, timeout)?;
+
+    assert_eq!(measurement.cycles, 5);
+    assert!(measurement.frequency_hz >= frequency_hz * 0.9);
+    assert!(measurement.frequency_hz <= frequency_hz * 1.1);
+    assert!(measurement.duty_cycle.min >= duty_cycle - 0.05);
+    assert!(measurement.duty_cycle.max <= duty_cycle + 0.05);
+
+    Ok(())
+}
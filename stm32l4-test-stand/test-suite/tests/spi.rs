@@ -6,6 +6,7 @@
 
 use std::time::Duration;
 
+use lpc845_messages::DmaMode;
 use stm32l4_test_suite::{
     Result,
     TestStand,
@@ -24,3 +25,50 @@ fn it_should_start_a_transaction() -> Result {
 
     Ok(())
 }
+
+#[test]
+fn it_should_start_a_transaction_using_dma() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let data = 0x22;
+    let timeout = Duration::from_millis(50);
+    let reply = test_stand.target.start_spi_transaction_dma(data, timeout)?;
+
+    assert_eq!(reply, data << 1);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_transfer_multiple_bytes() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // The clock is slow, so this takes a while.
+    let data = [0x11, 0x22, 0x33, 0x44];
+    let timeout = Duration::from_millis(100);
+    let reply = test_stand.target
+        .spi_transfer(DmaMode::Regular, &data, timeout)?;
+    let received = test_stand.assistant.receive_from_target_spi(timeout)?;
+
+    // The assistant answers every byte with the one it received before.
+    assert_eq!(reply[1..], [0x22, 0x44, 0x66]);
+    assert_eq!(received, data);
+
+    Ok(())
+}
+
+#[test]
+fn it_should_transfer_multiple_bytes_using_dma() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let data = [0x11, 0x22, 0x33, 0x44];
+    let timeout = Duration::from_millis(100);
+    let reply = test_stand.target
+        .spi_transfer(DmaMode::Dma, &data, timeout)?;
+    let received = test_stand.assistant.receive_from_target_spi(timeout)?;
+
+    assert_eq!(reply[1..], [0x22, 0x44, 0x66]);
+    assert_eq!(received, data);
+
+    Ok(())
+}
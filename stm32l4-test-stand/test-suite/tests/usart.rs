@@ -1,8 +1,22 @@
 //! Test Suite for the USART API in STM32L4xx HAL
 
 
-use std::time::Duration;
+use std::{
+    thread::sleep,
+    time::Duration,
+};
 
+use lpc845_messages::{
+    UsartMode,
+    usart::{
+        DataBits,
+        Fault,
+        LineConfig,
+        Parity,
+        RxError,
+        StopBits,
+    },
+};
 use stm32l4_test_suite::{
     Result,
     TestStand,
@@ -90,3 +104,139 @@ fn it_should_send_using_flow_control() -> Result {
     assert_eq!(received, message);
     Ok(())
 }
+
+#[test]
+fn it_should_send_in_sync_mode() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let message = b"Hello, world!";
+    test_stand.target.send_usart_sync(message)?;
+
+    let timeout  = Duration::from_millis(50);
+    let received = test_stand.assistant
+        .receive_from_target_usart_sync(message, timeout)?;
+
+    assert_eq!(received, message);
+    Ok(())
+}
+
+#[test]
+fn it_should_receive_in_sync_mode() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let message = b"Hello, world!";
+    test_stand.assistant.send_to_target_usart_sync(message)?;
+
+    // The target only provides a clock while sending, so it needs to send
+    // something for the assistant's message to arrive. Give the assistant a
+    // moment to get ready first.
+    sleep(Duration::from_millis(10));
+    test_stand.target.send_usart_sync(&[0; 13])?;
+
+    let timeout  = Duration::from_millis(50);
+    let received = test_stand.target.wait_for_usart_rx_sync(message, timeout)?;
+
+    assert_eq!(received, message);
+    Ok(())
+}
+
+#[test]
+fn it_should_send_and_receive_with_different_line_parameters() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let baud_rates = [9600, 57_600, 115_200, 230_400];
+    let formats = [
+        (DataBits::Eight, Parity::None, StopBits::One),
+        (DataBits::Eight, Parity::Even, StopBits::One),
+        (DataBits::Eight, Parity::Odd,  StopBits::Two),
+        (DataBits::Seven, Parity::None, StopBits::One),
+    ];
+
+    // Only uses 7-bit characters, so it works with all formats.
+    let message = b"Hello, world!";
+    let timeout = Duration::from_millis(100);
+
+    // Collect the result first, so the default is restored on every path.
+    let result = (|| -> Result {
+        for &baud_rate in &baud_rates {
+            for &(data_bits, parity, stop_bits) in &formats {
+                let config = LineConfig {
+                    baud_rate,
+                    data_bits,
+                    parity,
+                    stop_bits,
+                };
+                test_stand.configure_usart(UsartMode::Regular, config)?;
+
+                test_stand.target.send_usart(message)?;
+                let received = test_stand.assistant
+                    .receive_from_target_usart(message, timeout)?;
+                assert_eq!(received, message, "Sending with {:?}", config);
+
+                test_stand.assistant.send_to_target_usart(message)?;
+                let received = test_stand.target
+                    .wait_for_usart_rx(message, timeout)?;
+                assert_eq!(received, message, "Receiving with {:?}", config);
+            }
+        }
+
+        Ok(())
+    })();
+
+    // Restore the default, so other tests aren't affected.
+    test_stand.configure_usart(UsartMode::Regular, LineConfig::default())?;
+
+    result
+}
+
+#[test]
+fn it_should_report_parity_errors() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    let config = LineConfig {
+        parity: Parity::Even,
+        .. LineConfig::default()
+    };
+    test_stand.configure_usart(UsartMode::Regular, config)?;
+
+    test_stand.assistant.inject_usart_fault(Fault::Parity)?;
+
+    let timeout = Duration::from_millis(50);
+    let result = test_stand.target
+        .wait_for_usart_error(RxError::Parity, timeout);
+
+    // Restore the default, so other tests aren't affected.
+    test_stand.configure_usart(UsartMode::Regular, LineConfig::default())?;
+
+    result?;
+    Ok(())
+}
+
+#[test]
+fn it_should_report_framing_errors() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    test_stand.assistant.inject_usart_fault(Fault::Framing)?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.target.wait_for_usart_error(RxError::Framing, timeout)?;
+
+    Ok(())
+}
+
+#[test]
+fn it_should_report_errors_on_baud_rate_mismatch() -> Result {
+    let mut test_stand = TestStand::new()?;
+
+    // At half the expected baud rate, some characters of this message are
+    // guaranteed to produce framing errors.
+    test_stand.assistant.inject_usart_fault(Fault::BaudRate {
+        baud_rate: LineConfig::default().baud_rate / 2,
+        data:      b"Hello, world!",
+    })?;
+
+    let timeout = Duration::from_millis(50);
+    test_stand.target.wait_for_usart_error(RxError::Framing, timeout)?;
+
+    Ok(())
+}
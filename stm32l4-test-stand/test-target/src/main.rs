@@ -8,9 +8,17 @@
 extern crate panic_rtt_target;
 
 
-use cortex_m::peripheral::{
-    SYST,
-    syst::SystClkSource,
+use core::sync::atomic::{
+    Ordering,
+    compiler_fence,
+};
+
+use cortex_m::{
+    asm,
+    peripheral::{
+        SYST,
+        syst::SystClkSource,
+    },
 };
 use embedded_hal::spi;
 use heapless::{
@@ -55,9 +63,15 @@ use stm32l4xx_hal::{
         PC1,
         PC2,
         PC7,
+        PullDown,
+        PullUp,
         PushPull,
+        gpioc,
+    },
+    i2c::{
+        self,
+        I2c,
     },
-    i2c::I2c,
     pac::{
         self,
         I2C1,
@@ -84,7 +98,14 @@ use lpc845_messages::{
     HostToTarget,
     TargetToHost,
     UsartMode,
+    i2c::{
+        Address as I2cAddress,
+        Error as I2cError,
+        MAX_TRANSFER as I2C_MAX_TRANSFER,
+    },
     pin,
+    spi::MAX_TRANSFER as SPI_MAX_TRANSFER,
+    usart,
 };
 
 
@@ -111,6 +132,9 @@ const APP: () = {
         rx_prod_dma: spsc::Producer<'static, u8, 256>,
         rx_cons_dma: spsc::Consumer<'static, u8, 256>,
 
+        rx_err_prod_main: spsc::Producer<'static, usart::RxError, 8>,
+        rx_err_cons_main: spsc::Consumer<'static, usart::RxError, 8>,
+
         dma_tx_main: FrameSender<Box<DmaPool>, dma1::C4, 256>,
         dma_rx_dma: FrameReader<Box<DmaPool>, dma1::C3, 256>,
 
@@ -118,7 +142,8 @@ const APP: () = {
         analog: PC0<Analog>,
 
         gpio_out: PC1<Output<PushPull>>,
-        gpio_in: PC2<Input<Floating>>,
+        gpio_in: Option<GpioIn>,
+        gpioc_regs: GpiocRegs,
 
         i2c: I2c<
             I2C1,
@@ -150,6 +175,8 @@ const APP: () = {
         static mut RX_QUEUE_HOST: spsc::Queue<u8, 256> = spsc::Queue::new();
         static mut RX_QUEUE_MAIN: spsc::Queue<u8, 256> = spsc::Queue::new();
         static mut RX_QUEUE_DMA: spsc::Queue<u8, 256> = spsc::Queue::new();
+        static mut RX_ERRORS_MAIN: spsc::Queue<usart::RxError, 8> =
+            spsc::Queue::new();
 
         // Allocate memory for DMA transfers.
         static mut MEMORY: [u8; 1024] = [0; 1024];
@@ -199,12 +226,22 @@ const APP: () = {
         let tx_pin_dma = gpiob.pb10.into_af7(&mut gpiob.moder, &mut gpiob.afrh);
         let rx_pin_dma = gpiob.pb11.into_af7(&mut gpiob.moder, &mut gpiob.afrh);
 
+        // USART3 doubles as the USART for sync mode. Its clock output is only
+        // active in that mode, while its second receive pin only gets
+        // connected when switching into it (see `set_usart_sync`).
+        gpiob.pb12.into_af7(&mut gpiob.moder, &mut gpiob.afrh);
+        gpioc.pc11
+            .into_af7(&mut gpioc.moder, &mut gpioc.afrh)
+            .into_floating_input(&mut gpioc.moder, &mut gpioc.pupdr);
+
         let analog = gpioc.pc0.into_analog(&mut gpioc.moder, &mut gpioc.pupdr);
 
         let gpio_out = gpioc.pc1
             .into_push_pull_output(&mut gpioc.moder, &mut gpioc.otyper);
-        let gpio_in = gpioc.pc2
-            .into_floating_input(&mut gpioc.moder, &mut gpioc.pupdr);
+        let gpio_in = GpioIn::Floating(
+            gpioc.pc2
+                .into_floating_input(&mut gpioc.moder, &mut gpioc.pupdr)
+        );
 
         let timer_signal = gpioc.pc7
             .into_push_pull_output(&mut gpioc.moder, &mut gpioc.otyper);
@@ -282,6 +319,7 @@ const APP: () = {
         let (rx_prod_main, rx_cons_main) = RX_QUEUE_MAIN.split();
         let (rx_prod_host, rx_cons_host) = RX_QUEUE_HOST.split();
         let (rx_prod_dma, rx_cons_dma) = RX_QUEUE_DMA.split();
+        let (rx_err_prod_main, rx_err_cons_main) = RX_ERRORS_MAIN.split();
 
         let dma1 = p.DMA1.split(&mut rcc.ahb1);
         let dma_tx_main = tx_main.frame_sender(dma1.4);
@@ -309,6 +347,9 @@ const APP: () = {
             rx_prod_dma,
             rx_cons_dma,
 
+            rx_err_prod_main,
+            rx_err_cons_main,
+
             dma_tx_main,
             dma_rx_dma,
 
@@ -316,7 +357,12 @@ const APP: () = {
             analog,
 
             gpio_out,
-            gpio_in,
+            gpio_in: Some(gpio_in),
+            gpioc_regs: GpiocRegs {
+                moder:  gpioc.moder,
+                otyper: gpioc.otyper,
+                pupdr:  gpioc.pupdr,
+            },
 
             i2c,
 
@@ -335,6 +381,7 @@ const APP: () = {
         rx_cons_main,
         rx_cons_host,
         rx_cons_dma,
+        rx_err_cons_main,
         tx_main,
         tx_host,
         dma_tx_main,
@@ -342,6 +389,7 @@ const APP: () = {
         analog,
         gpio_out,
         gpio_in,
+        gpioc_regs,
         i2c,
         ssel,
         spi,
@@ -353,6 +401,7 @@ const APP: () = {
         let rx_main = cx.resources.rx_cons_main;
         let rx_host = cx.resources.rx_cons_host;
         let rx_dma  = cx.resources.rx_cons_dma;
+        let rx_err_main = cx.resources.rx_err_cons_main;
         let tx_main = cx.resources.tx_main;
         let tx_host = cx.resources.tx_host;
         let dma_tx_main = cx.resources.dma_tx_main;
@@ -360,6 +409,7 @@ const APP: () = {
         let analog = cx.resources.analog;
         let gpio_out = cx.resources.gpio_out;
         let gpio_in = cx.resources.gpio_in;
+        let gpioc_regs = cx.resources.gpioc_regs;
         let i2c = cx.resources.i2c;
         let ssel = cx.resources.ssel;
         let spi = cx.resources.spi;
@@ -383,6 +433,11 @@ const APP: () = {
                 UsartMode::Dma,
                 &mut buf_main_rx,
             );
+            handle_usart_errors(
+                rx_err_main,
+                tx_host,
+                UsartMode::Regular,
+            );
 
             if let Some(b) = rx_host.dequeue() {
                 // Requests are COBS-encoded, so we know that `0` means we
@@ -395,6 +450,54 @@ const APP: () = {
                 let message = postcard::from_bytes_cobs(&mut buf_host_rx)
                     .expect("Error decoding message");
                 match message {
+                    HostToTarget::ConfigureUsart { mode, config } => {
+                        rprintln!(
+                            "Configuring USART ({:?}): {:?}",
+                            mode,
+                            config,
+                        );
+
+                        // Sound, as we only access the registers to change the
+                        // configuration, which the HAL doesn't touch after
+                        // initialization.
+                        let result = match mode {
+                            UsartMode::Regular | UsartMode::FlowControl => {
+                                configure_usart(
+                                    unsafe { &*pac::USART1::ptr() },
+                                    &config,
+                                    clocks.pclk2().0,
+                                )
+                            }
+                            UsartMode::Dma => {
+                                configure_usart(
+                                    unsafe { &*pac::USART3::ptr() },
+                                    &config,
+                                    clocks.pclk1().0,
+                                )
+                            }
+                            UsartMode::Sync
+                            | UsartMode::Rs485
+                            | UsartMode::Lin => {
+                                Err(usart::ConfigError::UnsupportedMode)
+                            }
+                        };
+
+                        let message = match result {
+                            Ok(()) => {
+                                TargetToHost::UsartConfigured
+                            }
+                            Err(err) => {
+                                rprintln!("Error configuring USART: {:?}", err);
+                                TargetToHost::UsartConfigError(err)
+                            }
+                        };
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&message)
+                                .expect("Error encoding message to host");
+                        tx_host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
                     HostToTarget::SendUsart {
                         mode: UsartMode::Regular,
                         data,
@@ -440,6 +543,44 @@ const APP: () = {
 
                         rprintln!("Sent data using flow control: {:?}", data);
                     }
+                    HostToTarget::SendUsart {
+                        mode: UsartMode::Sync,
+                        data,
+                    } => {
+                        // The STM32L433 doesn't have enough USARTs either, so
+                        // we switch USART3 into sync mode for the duration of
+                        // the transfer. As the sync master, the target only
+                        // provides a clock while sending, so this is also the
+                        // only time it can receive anything.
+                        //
+                        // Sound, as the HAL doesn't touch the registers after
+                        // initialization, and DMA reception is disabled while
+                        // we read the received data here.
+                        let usart = unsafe { &*pac::USART3::ptr() };
+
+                        let mut rx_buf: Vec<u8, 256> = Vec::new();
+
+                        set_usart_sync(usart, true);
+                        for &b in data {
+                            let received = transfer_usart_sync(usart, b);
+                            rx_buf.push(received)
+                                .expect("Sync receive buffer full");
+                        }
+                        set_usart_sync(usart, false);
+
+                        rprintln!("Sent data in sync mode: {:?}", data);
+
+                        let message = TargetToHost::UsartReceive {
+                            mode: UsartMode::Sync,
+                            data: rx_buf.as_ref(),
+                        };
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&message)
+                                .expect("Error encoding message to host");
+                        tx_host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
                     HostToTarget::ReadAdc => {
                         let value = adc.read(analog).unwrap();
 
@@ -464,7 +605,7 @@ const APP: () = {
                         }
                     }
                     HostToTarget::ReadPin(pin::ReadLevel { pin: () }) => {
-                        let level = match gpio_in.is_high().unwrap() {
+                        let level = match gpio_in.as_ref().unwrap().is_high() {
                             true  => pin::Level::High,
                             false => pin::Level::Low,
                         };
@@ -485,19 +626,145 @@ const APP: () = {
                         tx_host.bwrite_all(buf_host_tx.as_ref())
                             .expect("Error sending message to host");
                     }
+                    HostToTarget::ConfigurePin(
+                        pin::Configure { pin: (), config }
+                    ) => {
+                        let pin = gpio_in.take().unwrap();
+                        *gpio_in = Some(pin.configure(config, gpioc_regs));
+                    }
                     HostToTarget::StartI2cTransaction {
                         mode: DmaMode::Regular,
-                        address,
+                        address: I2cAddress::SevenBit(address),
                         data,
                     } => {
-                        i2c.write(address, &[data])
-                            .unwrap();
+                        let mut rx_buf = [0u8; 1];
+                        let result = i2c.write(address, &[data])
+                            .and_then(|()| i2c.read(address, &mut rx_buf));
+
+                        let message = match result {
+                            Ok(()) => {
+                                TargetToHost::I2cReply(rx_buf[0])
+                            }
+                            Err(err) => {
+                                rprintln!("I2C: Error: {:?}", err);
+                                TargetToHost::I2cError(i2c_error(err))
+                            }
+                        };
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&message)
+                                .expect("Error encoding message to host");
+                        tx_host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
+                    HostToTarget::StartI2cTransaction {
+                        mode: DmaMode::Dma,
+                        address: I2cAddress::SevenBit(address),
+                        data,
+                    } => {
+                        // Sound, as we only access the registers while the
+                        // HAL's API isn't in use, and DMA1 channels 6 and 7
+                        // aren't used by anything else.
+                        let i2c_regs = unsafe { &*pac::I2C1::ptr() };
+                        let dma_regs = unsafe { &*pac::DMA1::ptr() };
 
                         let mut rx_buf = [0u8; 1];
-                        i2c.read(address, &mut rx_buf)
-                            .unwrap();
+                        let result = i2c_write_read_dma(
+                            i2c_regs,
+                            dma_regs,
+                            address,
+                            &[data],
+                            &mut rx_buf,
+                        );
+
+                        let message = match result {
+                            Ok(()) => {
+                                TargetToHost::I2cReply(rx_buf[0])
+                            }
+                            Err(err) => {
+                                rprintln!("I2C/DMA: Error: {:?}", err);
+                                TargetToHost::I2cError(err)
+                            }
+                        };
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&message)
+                                .expect("Error encoding message to host");
+                        tx_host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
+                    HostToTarget::StartI2cTransaction {
+                        address: address @ I2cAddress::TenBit(_),
+                        ..
+                    } => {
+                        // Neither the HAL nor `i2c_write_read_dma` support
+                        // 10-bit addresses.
+                        rprintln!("I2C: Can't address {:?}", address);
+
+                        let message = TargetToHost::I2cError(I2cError::Other);
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&message)
+                                .expect("Error encoding message to host");
+                        tx_host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
+                    HostToTarget::StartI2cTransfer {
+                        mode,
+                        address,
+                        write,
+                        read_len,
+                    } => {
+                        rprintln!(
+                            "I2C ({:?}): Write {} byte(s), read {}",
+                            mode,
+                            write.len(),
+                            read_len,
+                        );
 
-                        let message = TargetToHost::I2cReply(rx_buf[0]);
+                        let mut rx_buf = [0u8; I2C_MAX_TRANSFER];
+                        let read = &mut rx_buf[..read_len as usize];
+
+                        let result = match mode {
+                            DmaMode::Regular => {
+                                let result = match (write.len(), read.len()) {
+                                    (_, 0) => i2c.write(address, write),
+                                    (0, _) => i2c.read(address, read),
+                                    _      => i2c.write_read(
+                                        address,
+                                        write,
+                                        read,
+                                    ),
+                                };
+                                result.map_err(|err| i2c_error(err))
+                            }
+                            DmaMode::Dma => {
+                                // Sound, as we only access the registers
+                                // while the HAL's API isn't in use, and DMA1
+                                // channels 6 and 7 aren't used by anything
+                                // else.
+                                let i2c_regs = unsafe { &*pac::I2C1::ptr() };
+                                let dma_regs = unsafe { &*pac::DMA1::ptr() };
+
+                                i2c_write_read_dma(
+                                    i2c_regs,
+                                    dma_regs,
+                                    address,
+                                    write,
+                                    read,
+                                )
+                            }
+                        };
+
+                        let message = match result {
+                            Ok(()) => {
+                                TargetToHost::I2cTransferReply(read)
+                            }
+                            Err(err) => {
+                                rprintln!("I2C: Error: {:?}", err);
+                                TargetToHost::I2cError(err)
+                            }
+                        };
 
                         let buf_host_tx: Vec<_, 256> =
                             postcard::to_vec_cobs(&message)
@@ -506,14 +773,27 @@ const APP: () = {
                             .expect("Error sending message to host");
                     }
                     HostToTarget::StartSpiTransaction {
-                        mode: DmaMode::Regular,
+                        mode,
                         data,
                     } => {
                         rprintln!("SPI: Set SSEL LOW");
                         ssel.set_low().unwrap();
 
                         let mut data = [data, 0xFF];
-                        spi.transfer(&mut data).unwrap();
+                        match mode {
+                            DmaMode::Regular => {
+                                spi.transfer(&mut data).unwrap();
+                            }
+                            DmaMode::Dma => {
+                                // Sound, as we only access the registers
+                                // while the HAL's API isn't in use. See
+                                // `spi_transfer_dma` regarding DMA1.
+                                let spi_regs = unsafe { &*pac::SPI2::ptr() };
+                                let dma_regs = unsafe { &*pac::DMA1::ptr() };
+
+                                spi_transfer_dma(spi_regs, dma_regs, &mut data);
+                            }
+                        }
                         let reply = data[1];
 
                         rprintln!("SPI: Set SSEL HIGH");
@@ -529,6 +809,45 @@ const APP: () = {
 
                         rprintln!(" done.");
                     }
+                    HostToTarget::StartSpiTransfer {
+                        mode,
+                        data,
+                    } => {
+                        rprintln!(
+                            "SPI ({:?}): Transfer {} byte(s)",
+                            mode,
+                            data.len(),
+                        );
+
+                        let mut spi_buf = [0u8; SPI_MAX_TRANSFER];
+                        let spi_buf = &mut spi_buf[..data.len()];
+                        spi_buf.copy_from_slice(data);
+
+                        ssel.set_low().unwrap();
+                        match mode {
+                            DmaMode::Regular => {
+                                spi.transfer(spi_buf).unwrap();
+                            }
+                            DmaMode::Dma => {
+                                // Sound, as we only access the registers
+                                // while the HAL's API isn't in use. See
+                                // `spi_transfer_dma` regarding DMA1.
+                                let spi_regs = unsafe { &*pac::SPI2::ptr() };
+                                let dma_regs = unsafe { &*pac::DMA1::ptr() };
+
+                                spi_transfer_dma(spi_regs, dma_regs, spi_buf);
+                            }
+                        }
+                        ssel.set_high().unwrap();
+
+                        let message = TargetToHost::SpiTransferReply(spi_buf);
+
+                        let buf_host_tx: Vec<_, 256> =
+                            postcard::to_vec_cobs(&message)
+                                .expect("Error encoding message to host");
+                        tx_host.bwrite_all(buf_host_tx.as_ref())
+                            .expect("Error sending message to host");
+                    }
                     HostToTarget::StartTimerInterrupt { period_ms } => {
                         let reload = clocks.hclk().0 / 1000 * period_ms;
                         systick.set_clock_source(SystClkSource::Core);
@@ -559,15 +878,33 @@ const APP: () = {
         }
     }
 
-    #[task(binds = USART1, resources = [rx_main, rx_prod_main])]
+    #[task(
+        binds = USART1,
+        resources = [
+            rx_main,
+            rx_prod_main,
+            rx_err_prod_main,
+        ]
+    )]
     fn usart1(cx: usart1::Context) {
         let rx = cx.resources.rx_main;
         let queue = cx.resources.rx_prod_main;
+        let errors = cx.resources.rx_err_prod_main;
 
         let b = match rx.read() {
             Ok(b) => b,
             Err(err) => {
                 rprintln!("Error reading from USART1: {:?}", err);
+
+                if let nb::Error::Other(err) = err {
+                    if let Some(error) = rx_error(&err) {
+                        // If the queue is full, the idle loop hasn't caught up
+                        // with previous errors. Nothing we can do about it
+                        // here.
+                        let _ = errors.enqueue(error);
+                    }
+                }
+
                 return;
             }
         };
@@ -662,3 +999,495 @@ fn handle_usart_rx(
         buf.clear();
     }
 }
+
+fn handle_usart_errors(
+    queue: &mut spsc::Consumer<'static, usart::RxError, 8>,
+    tx_host: &mut serial::Tx<USART2>,
+    mode: UsartMode,
+) {
+    while let Some(error) = queue.dequeue() {
+        let message = TargetToHost::UsartError {
+            mode,
+            error,
+        };
+
+        let buf_host_tx: Vec<_, 256> = postcard::to_vec_cobs(&message)
+            .expect("Error encoding message to host");
+        tx_host.bwrite_all(buf_host_tx.as_ref())
+            .expect("Error sending message to host");
+    }
+}
+
+/// Returns the protocol representation of a USART receive error
+fn rx_error(err: &serial::Error) -> Option<usart::RxError> {
+    match err {
+        serial::Error::Framing => Some(usart::RxError::Framing),
+        serial::Error::Noise   => Some(usart::RxError::Noise),
+        serial::Error::Overrun => Some(usart::RxError::Overrun),
+        serial::Error::Parity  => Some(usart::RxError::Parity),
+
+        // The HAL's error type is non-exhaustive.
+        _ => None,
+    }
+}
+
+
+/// The registers of GPIOC that are required to reconfigure its pins
+pub struct GpiocRegs {
+    moder:  gpioc::MODER,
+    otyper: gpioc::OTYPER,
+    pupdr:  gpioc::PUPDR,
+}
+
+
+/// Changes the line parameters of an enabled USART
+///
+/// The HAL only supports configuring the USART when creating the `Serial`
+/// instance, so we need to access the registers directly. Leaves the USART
+/// untouched, if it doesn't support the configuration.
+fn configure_usart(
+    usart:    &pac::usart1::RegisterBlock,
+    config:   &usart::LineConfig,
+    clock_hz: u32,
+)
+    -> Result<(), usart::ConfigError>
+{
+    // The word length includes the parity bit. If we used 7 data bits with a
+    // parity bit, the received bytes would contain the parity bit. 7 or 9 data
+    // bits with parity would need a word length the USART doesn't support.
+    let (m1, m0) = match (config.data_bits, config.parity) {
+        (usart::DataBits::Seven, usart::Parity::None) => (true,  false),
+        (usart::DataBits::Eight, usart::Parity::None) => (false, false),
+        (usart::DataBits::Eight, _)                   => (false, true),
+        (usart::DataBits::Nine,  usart::Parity::None) => (false, true),
+        (usart::DataBits::Seven, _) | (usart::DataBits::Nine, _) => {
+            return Err(usart::ConfigError::UnsupportedFormat);
+        }
+    };
+    let stop = match config.stop_bits {
+        usart::StopBits::One => 0b00,
+        usart::StopBits::Two => 0b10,
+    };
+
+    // With 16x oversampling, BRR is the USART clock divided by the baud rate,
+    // and must be at least 16 (see the reference manual's description of the
+    // USART baud rate generation).
+    let brr = (clock_hz as u64 + config.baud_rate as u64 / 2)
+        .checked_div(config.baud_rate as u64)
+        .filter(|brr| (16 ..= 0xffff).contains(brr))
+        .ok_or(usart::ConfigError::UnsupportedBaudRate(config.baud_rate))?;
+
+    // Wait until the USART has finished sending.
+    while usart.isr.read().tc().bit_is_clear() {}
+
+    usart.cr1.modify(|_, w| w.ue().clear_bit());
+
+    usart.cr1.modify(|_, w| {
+        w
+            .m1().bit(m1)
+            .m0().bit(m0)
+            .pce().bit(config.parity != usart::Parity::None)
+            .ps().bit(config.parity == usart::Parity::Odd)
+    });
+    // Sound, as the values are taken from the reference manual.
+    usart.cr2.modify(|_, w| unsafe { w.stop().bits(stop) });
+    usart.brr.write(|w| unsafe { w.bits(brr as u32) });
+
+    usart.cr1.modify(|_, w| w.ue().set_bit());
+
+    Ok(())
+}
+
+/// Switches USART3 between asynchronous and synchronous mode
+///
+/// In sync mode, USART3 outputs a clock and receives on PC11, instead of PB11,
+/// which is connected to a different USART of the assistant. DMA reception is
+/// disabled, so received data must be read from the receive register.
+fn set_usart_sync(usart: &pac::usart1::RegisterBlock, sync: bool) {
+    // Sound, as we only change the mode of both receive pins, which the HAL
+    // doesn't touch after initialization. Their alternate function is already
+    // configured.
+    let gpiob = unsafe { &*pac::GPIOB::ptr() };
+    let gpioc = unsafe { &*pac::GPIOC::ptr() };
+
+    // Input mode or alternate function mode, according to the reference
+    // manual.
+    let (pb11, pc11) = match sync {
+        true  => (0b00, 0b10),
+        false => (0b10, 0b00),
+    };
+
+    // Wait until the USART has finished sending.
+    while usart.isr.read().tc().bit_is_clear() {}
+
+    usart.cr1.modify(|_, w| w.ue().clear_bit());
+
+    // Sound, as the values are taken from the reference manual.
+    gpiob.moder.modify(|_, w| unsafe { w.moder11().bits(pb11) });
+    gpioc.moder.modify(|_, w| unsafe { w.moder11().bits(pc11) });
+
+    // The assistant samples on the falling edge of the clock, and it needs a
+    // clock pulse for the last data bit too.
+    usart.cr2.modify(|_, w| {
+        w
+            .clken().bit(sync)
+            .cpol().clear_bit()
+            .cpha().set_bit()
+            .lbcl().set_bit()
+    });
+    usart.cr3.modify(|_, w| w.dmar().bit(!sync));
+
+    usart.cr1.modify(|_, w| w.ue().set_bit());
+}
+
+/// Sends a byte via USART in sync mode, returns the byte received meanwhile
+fn transfer_usart_sync(usart: &pac::usart1::RegisterBlock, b: u8) -> u8 {
+    while usart.isr.read().txe().bit_is_clear() {}
+    // Sound, as any 8-bit value fits into the 9-bit field.
+    usart.tdr.write(|w| unsafe { w.tdr().bits(b as u16) });
+
+    while usart.isr.read().rxne().bit_is_clear() {}
+    usart.rdr.read().rdr().bits() as u8
+}
+
+/// Writes to an I2C slave, then reads from it, using DMA
+///
+/// The HAL doesn't support DMA for I2C, so we need to access the registers
+/// directly. Uses DMA1 channel 6 for writing and channel 7 for reading. If
+/// both `write` and `read` are non-empty, the read follows the write after a
+/// repeated start.
+///
+/// Unlike the HAL, this detects a slave that holds SCL low for too long, and
+/// reports a timeout.
+fn i2c_write_read_dma(
+    i2c:     &pac::i2c1::RegisterBlock,
+    dma:     &pac::dma1::RegisterBlock,
+    address: u8,
+    write:   &[u8],
+    read:    &mut [u8],
+)
+    -> Result<(), I2cError>
+{
+    i2c_clear_flags(i2c);
+
+    // Sound, as the timeout fits into the 12-bit field. TIDLE is left at zero,
+    // which means the timeout applies to SCL being low.
+    i2c.timeoutr.write(|w| unsafe {
+        w
+            .timeouta().bits(I2C_TIMEOUT_A)
+            .timouten().set_bit()
+    });
+
+    // Sound, as 3 selects I2C1 for both channels, according to the reference
+    // manual.
+    dma.cselr.modify(|_, w| unsafe { w.c6s().bits(3).c7s().bits(3) });
+
+    let mut result = Ok(());
+
+    if !write.is_empty() {
+        let txdr = &i2c.txdr as *const _ as u32;
+
+        // Sound, as the addresses are valid and the buffer stays borrowed
+        // until the transfer is finished.
+        dma.cpar6.write(|w| unsafe { w.pa().bits(txdr) });
+        dma.cmar6.write(|w| unsafe { w.ma().bits(write.as_ptr() as u32) });
+        dma.cndtr6.write(|w| unsafe { w.ndt().bits(write.len() as u16) });
+        compiler_fence(Ordering::SeqCst);
+        dma.ccr6.write(|w| w.dir().set_bit().minc().set_bit().en().set_bit());
+
+        i2c.cr1.modify(|_, w| w.txdmaen().set_bit());
+
+        // Without a read to follow, the hardware sends a stop condition.
+        // Otherwise we wait until the write is complete, then start the read
+        // right away.
+        let autoend = read.is_empty();
+        i2c_start(i2c, address, false, write.len(), autoend);
+        result = i2c_wait(i2c, |isr| match autoend {
+            true  => isr.stopf().bit_is_set(),
+            false => isr.tc().bit_is_set(),
+        });
+    }
+
+    if result.is_ok() && !read.is_empty() {
+        let rxdr = &i2c.rxdr as *const _ as u32;
+
+        // Sound, as the addresses are valid and the buffer stays borrowed
+        // until the transfer is finished.
+        dma.cpar7.write(|w| unsafe { w.pa().bits(rxdr) });
+        dma.cmar7.write(|w| unsafe { w.ma().bits(read.as_mut_ptr() as u32) });
+        dma.cndtr7.write(|w| unsafe { w.ndt().bits(read.len() as u16) });
+        compiler_fence(Ordering::SeqCst);
+        dma.ccr7.write(|w| w.minc().set_bit().en().set_bit());
+
+        i2c.cr1.modify(|_, w| w.rxdmaen().set_bit());
+
+        i2c_start(i2c, address, true, read.len(), true);
+        result = i2c_wait(i2c, |isr| isr.stopf().bit_is_set());
+
+        if result.is_ok() {
+            while dma.isr.read().tcif7().bit_is_clear() {}
+        }
+    }
+
+    if result.is_err() {
+        // Discard any data that hasn't been sent.
+        i2c.isr.modify(|_, w| w.txe().set_bit());
+    }
+    if result == Err(I2cError::Timeout) {
+        // The transfer is stuck. A software reset returns the I2C to idle and
+        // releases the bus. PE must stay low for at least 3 APB clock cycles,
+        // and the core runs faster than APB1.
+        i2c.cr1.modify(|_, w| w.pe().clear_bit());
+        asm::delay(100);
+        i2c.cr1.modify(|_, w| w.pe().set_bit());
+    }
+
+    i2c.timeoutr.modify(|_, w| w.timouten().clear_bit());
+    i2c.cr1.modify(|_, w| w.txdmaen().clear_bit().rxdmaen().clear_bit());
+    dma.ccr6.modify(|_, w| w.en().clear_bit());
+    dma.ccr7.modify(|_, w| w.en().clear_bit());
+    dma.ifcr.write(|w| w.cgif6().set_bit().cgif7().set_bit());
+    compiler_fence(Ordering::SeqCst);
+
+    i2c_clear_flags(i2c);
+
+    result
+}
+
+/// Starts an I2C transfer of `len` bytes
+fn i2c_start(
+    i2c:     &pac::i2c1::RegisterBlock,
+    address: u8,
+    read:    bool,
+    len:     usize,
+    autoend: bool,
+) {
+    // Sound, as a 7-bit address, shifted left by one, fits into the field, and
+    // `len` never exceeds the maximum transfer size of 255.
+    i2c.cr2.write(|w| unsafe {
+        w
+            .sadd().bits((address as u16) << 1)
+            .rd_wrn().bit(read)
+            .nbytes().bits(len as u8)
+            .autoend().bit(autoend)
+            .start().set_bit()
+    });
+}
+
+/// Waits until `done` returns `true`, or an error occurs
+fn i2c_wait<F>(i2c: &pac::i2c1::RegisterBlock, done: F) -> Result<(), I2cError>
+    where F: Fn(&pac::i2c1::isr::R) -> bool
+{
+    loop {
+        let isr = i2c.isr.read();
+
+        if isr.nackf().bit_is_set() {
+            // The hardware sends a stop condition after a NACK. Wait for it,
+            // so the bus is idle when we return.
+            while i2c.isr.read().stopf().bit_is_clear() {}
+            return Err(I2cError::Nack);
+        }
+        if isr.arlo().bit_is_set() {
+            return Err(I2cError::ArbitrationLoss);
+        }
+        if isr.timeout().bit_is_set() {
+            return Err(I2cError::Timeout);
+        }
+        if isr.berr().bit_is_set() {
+            return Err(I2cError::Other);
+        }
+        if done(&isr) {
+            return Ok(());
+        }
+    }
+}
+
+fn i2c_clear_flags(i2c: &pac::i2c1::RegisterBlock) {
+    i2c.icr.write(|w|
+        w
+            .stopcf().set_bit()
+            .nackcf().set_bit()
+            .berrcf().set_bit()
+            .arlocf().set_bit()
+            .timoutcf().set_bit()
+    );
+}
+
+/// Transfers `data` via SPI using DMA, replacing it with the data received
+///
+/// The HAL's SPI/DMA support would need to own DMA1 channel 4, which is
+/// already owned by the USART1 frame sender. The frame sender only uses the
+/// channel while sending, and we're done with it before returning, so we
+/// borrow it here and restore its configuration afterwards. Channel 5 isn't
+/// used by anything else.
+fn spi_transfer_dma(
+    spi:  &pac::spi1::RegisterBlock,
+    dma:  &pac::dma1::RegisterBlock,
+    data: &mut [u8],
+) {
+    let ccr4  = dma.ccr4.read().bits();
+    let cpar4 = dma.cpar4.read().bits();
+    let cselr = dma.cselr.read().bits();
+
+    // Sound, as 1 selects SPI2 for both channels, according to the reference
+    // manual.
+    dma.cselr.modify(|_, w| unsafe { w.c4s().bits(1).c5s().bits(1) });
+    dma.ifcr.write(|w| w.cgif4().set_bit().cgif5().set_bit());
+
+    let dr  = &spi.dr as *const _ as u32;
+    let buf = data.as_mut_ptr() as u32;
+    let len = data.len() as u16;
+
+    // Both channels use the same buffer. That's fine, as every byte has been
+    // sent before the byte that replaces it is received.
+    //
+    // Sound, as the addresses are valid and the buffer stays borrowed until
+    // the transfer is finished.
+    dma.cpar4.write(|w| unsafe { w.pa().bits(dr) });
+    dma.cmar4.write(|w| unsafe { w.ma().bits(buf) });
+    dma.cndtr4.write(|w| unsafe { w.ndt().bits(len) });
+    dma.cpar5.write(|w| unsafe { w.pa().bits(dr) });
+    dma.cmar5.write(|w| unsafe { w.ma().bits(buf) });
+    dma.cndtr5.write(|w| unsafe { w.ndt().bits(len) });
+    compiler_fence(Ordering::SeqCst);
+
+    // The reference manual requires this order.
+    spi.cr2.modify(|_, w| w.rxdmaen().set_bit());
+    dma.ccr4.write(|w| w.minc().set_bit().en().set_bit());
+    dma.ccr5.write(|w| w.dir().set_bit().minc().set_bit().en().set_bit());
+    spi.cr2.modify(|_, w| w.txdmaen().set_bit());
+
+    while dma.isr.read().tcif4().bit_is_clear() {}
+    while spi.sr.read().bsy().bit_is_set() {}
+    compiler_fence(Ordering::SeqCst);
+
+    spi.cr2.modify(|_, w| w.txdmaen().clear_bit().rxdmaen().clear_bit());
+    dma.ccr5.modify(|_, w| w.en().clear_bit());
+    dma.ifcr.write(|w| w.cgif4().set_bit().cgif5().set_bit());
+
+    // Sound, as we're restoring values that were read from the registers.
+    dma.ccr4.write(|w| unsafe { w.bits(ccr4) });
+    dma.cpar4.write(|w| unsafe { w.bits(cpar4) });
+    dma.cselr.write(|w| unsafe { w.bits(cselr) });
+}
+
+/// The pin that is connected to the assistant's output pin
+///
+/// It's a floating input by default, but the host can reconfigure it.
+pub enum GpioIn {
+    Floating(PC2<Input<Floating>>),
+    PullUp(PC2<Input<PullUp>>),
+    PullDown(PC2<Input<PullDown>>),
+    PushPull(PC2<Output<PushPull>>),
+    OpenDrain(PC2<Output<OpenDrain>>),
+}
+
+impl GpioIn {
+    fn configure(self, config: pin::Config, regs: &mut GpiocRegs) -> Self {
+        let pin = match self {
+            GpioIn::Floating(pin)  => pin,
+            GpioIn::PullUp(pin)    => pin.into_floating_input(
+                &mut regs.moder,
+                &mut regs.pupdr,
+            ),
+            GpioIn::PullDown(pin)  => pin.into_floating_input(
+                &mut regs.moder,
+                &mut regs.pupdr,
+            ),
+            GpioIn::PushPull(pin)  => pin.into_floating_input(
+                &mut regs.moder,
+                &mut regs.pupdr,
+            ),
+            GpioIn::OpenDrain(pin) => pin.into_floating_input(
+                &mut regs.moder,
+                &mut regs.pupdr,
+            ),
+        };
+
+        let level = match config.direction {
+            pin::Direction::Input => {
+                return match config.pull {
+                    pin::Pull::None => {
+                        GpioIn::Floating(pin)
+                    }
+                    pin::Pull::Up => {
+                        GpioIn::PullUp(pin.into_pull_up_input(
+                            &mut regs.moder,
+                            &mut regs.pupdr,
+                        ))
+                    }
+                    pin::Pull::Down => {
+                        GpioIn::PullDown(pin.into_pull_down_input(
+                            &mut regs.moder,
+                            &mut regs.pupdr,
+                        ))
+                    }
+                };
+            }
+            pin::Direction::Output(level) => {
+                level
+            }
+        };
+
+        match config.output_mode {
+            pin::OutputMode::PushPull => {
+                let mut pin = pin.into_push_pull_output(
+                    &mut regs.moder,
+                    &mut regs.otyper,
+                );
+                set_level(&mut pin, level);
+                GpioIn::PushPull(pin)
+            }
+            pin::OutputMode::OpenDrain => {
+                if config.pull == pin::Pull::Down {
+                    rprintln!("Pull-down not supported in open-drain mode");
+                }
+
+                let mut pin = pin
+                    .into_open_drain_output(&mut regs.moder, &mut regs.otyper)
+                    .internal_pull_up(
+                        &mut regs.pupdr,
+                        config.pull == pin::Pull::Up,
+                    );
+                set_level(&mut pin, level);
+                GpioIn::OpenDrain(pin)
+            }
+        }
+    }
+
+    fn is_high(&self) -> bool {
+        match self {
+            GpioIn::Floating(pin)  => pin.is_high().unwrap(),
+            GpioIn::PullUp(pin)    => pin.is_high().unwrap(),
+            GpioIn::PullDown(pin)  => pin.is_high().unwrap(),
+            GpioIn::PushPull(pin)  => pin.is_set_high().unwrap(),
+            GpioIn::OpenDrain(pin) => pin.is_high().unwrap(),
+        }
+    }
+}
+
+
+fn set_level<P>(pin: &mut P, level: pin::Level)
+    where P: embedded_hal::digital::v2::OutputPin,
+          P::Error: core::fmt::Debug,
+{
+    match level {
+        pin::Level::High => pin.set_high().unwrap(),
+        pin::Level::Low  => pin.set_low().unwrap(),
+    }
+}
+
+
+fn i2c_error(err: i2c::Error) -> I2cError {
+    match err {
+        i2c::Error::Nack        => I2cError::Nack,
+        i2c::Error::Arbitration => I2cError::ArbitrationLoss,
+        _                       => I2cError::Other,
+    }
+}
+
+
+// The I2C bus timeout used by `i2c_write_read_dma`. The timeout is
+// `(TIMEOUTA + 1) * 2048` cycles of the I2C clock, which is PCLK1 at 2 MHz,
+// so this is about 1 ms.
+const I2C_TIMEOUT_A: u16 = 0;
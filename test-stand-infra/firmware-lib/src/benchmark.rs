@@ -0,0 +1,157 @@
+//! USART throughput benchmarks
+
+
+use protocol::usart::{
+    BenchmarkResult,
+    Direction,
+};
+
+use crate::systick;
+
+
+/// Measures how long it takes to transfer a number of bytes
+///
+/// Timing is based on a free-running timer that counts up from zero to
+/// `timer_max`, then wraps around. The caller passes the current timer value
+/// whenever it transferred bytes, and regularly in between. This has to happen
+/// at least once per timer period, or elapsed periods will be missed.
+///
+/// The measurement starts with the first call to [`start`] or [`add`]. A sender
+/// should call [`start`] before sending the first byte. A receiver should only
+/// call [`add`], so the measurement starts when the first byte arrives. Until
+/// then, a receiver should call [`update`] regularly, so the benchmark can end
+/// if no bytes arrive at all.
+///
+/// [`start`]: #method.start
+/// [`add`]: #method.add
+/// [`update`]: #method.update
+pub struct Benchmark {
+    direction:        Direction,
+    bytes:            u32,
+    ticks_per_second: u32,
+    timer_max:        u32,
+
+    transferred: u32,
+    elapsed:     u32,
+    ticks:       u32,
+    idle:        u32,
+    last_timer:  Option<u32>,
+    started:     bool,
+}
+
+impl Benchmark {
+    /// Create a new instance of `Benchmark`
+    ///
+    /// `bytes` is the number of bytes to transfer. `ticks_per_second` is the
+    /// frequency of the timer, `timer_max` the highest value it reaches before
+    /// wrapping around.
+    pub fn new(
+        direction:        Direction,
+        bytes:            u32,
+        ticks_per_second: u32,
+        timer_max:        u32,
+    )
+        -> Self
+    {
+        Self {
+            direction,
+            bytes,
+            ticks_per_second,
+            timer_max,
+
+            transferred: 0,
+            elapsed:     0,
+            ticks:       0,
+            idle:        0,
+            last_timer:  None,
+            started:     false,
+        }
+    }
+
+    /// Starts the measurement
+    pub fn start(&mut self, timer: u32) {
+        self.elapsed    = 0;
+        self.idle       = 0;
+        self.last_timer = Some(timer);
+        self.started    = true;
+    }
+
+    /// Adds transferred bytes to the measurement
+    ///
+    /// Starts the measurement, if it hasn't been started yet. Bytes passed to
+    /// that first call are counted, but no time has elapsed for them.
+    pub fn add(&mut self, bytes: u32, timer: u32) {
+        if !self.started {
+            self.start(timer);
+        }
+
+        self.update(timer);
+
+        self.transferred += bytes;
+        self.ticks        = self.elapsed;
+        self.idle         = 0;
+    }
+
+    /// Advances the elapsed time, without adding any bytes
+    ///
+    /// If the measurement hasn't been started yet, only the time spent waiting
+    /// for it to start is tracked.
+    pub fn update(&mut self, timer: u32) {
+        let last = match self.last_timer {
+            Some(last) => last,
+            None       => {
+                self.last_timer = Some(timer);
+                return;
+            }
+        };
+
+        let delta = systick::delta(last, timer, self.timer_max);
+
+        if self.started {
+            self.elapsed += delta;
+        }
+        self.idle       += delta;
+        self.last_timer  = Some(timer);
+    }
+
+    /// Indicates whether the benchmark is done
+    ///
+    /// This is the case, if all bytes have been transferred, or no bytes have
+    /// been transferred for [`IDLE_TIMEOUT_MS`] after the measurement has
+    /// started. The latter means that bytes have been lost. If the measurement
+    /// doesn't start within [`START_TIMEOUT_MS`], the benchmark is done too,
+    /// without any bytes transferred.
+    ///
+    /// [`IDLE_TIMEOUT_MS`]: constant.IDLE_TIMEOUT_MS.html
+    /// [`START_TIMEOUT_MS`]: constant.START_TIMEOUT_MS.html
+    pub fn is_done(&self) -> bool {
+        let timeout_ms = match self.started {
+            true  => IDLE_TIMEOUT_MS,
+            false => START_TIMEOUT_MS,
+        };
+        let timeout = self.ticks_per_second / 1000 * timeout_ms;
+
+        self.transferred >= self.bytes || self.idle > timeout
+    }
+
+    /// Returns the result of the benchmark
+    pub fn result(&self) -> BenchmarkResult {
+        BenchmarkResult {
+            direction:        self.direction,
+            bytes:            self.transferred,
+            ticks:            self.ticks,
+            ticks_per_second: self.ticks_per_second,
+        }
+    }
+}
+
+
+/// How long a benchmark waits for the next byte, before giving up
+pub const IDLE_TIMEOUT_MS: u32 = 100;
+
+/// How long a receiving benchmark waits for the first byte, before giving up
+pub const START_TIMEOUT_MS: u32 = 250;
+
+
+/// Data that is sent during benchmarks
+pub static DATA: [u8; 16] = *b"0123456789abcdef";
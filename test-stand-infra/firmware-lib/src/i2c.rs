@@ -0,0 +1,6 @@
+//! I2C support, beyond what LPC8xx HAL provides
+
+
+pub mod fault;
+pub mod master;
+pub mod slave;
@@ -0,0 +1,134 @@
+//! Injection of faults into an I2C bus, as slave
+//!
+//! Most faults are injected by the slave's interrupt handler, which asks an
+//! [`Injector`] how to respond to the master. Holding SDA low requires taking
+//! the pin away from the I2C, which this module does by accessing the switch
+//! matrix and GPIO registers directly.
+//!
+//! [`Injector`]: struct.Injector.html
+
+
+use lpc8xx_hal::{
+    cortex_m::asm,
+    pac::{
+        gpio::RegisterBlock as Gpio,
+        swm0::RegisterBlock as Swm,
+    },
+};
+use protocol::i2c::Fault;
+
+
+/// Holds SDA low for the given number of core clock cycles
+///
+/// `pin` is the number of the port 0 pin that is assigned the I2C0_SDA
+/// function. The GPIO peripheral must be enabled. Blocks until the function
+/// has been assigned to the pin again.
+pub fn hold_sda_low(swm: &Swm, gpio: &Gpio, pin: u8, cycles: u32) {
+    let mask = 0x1 << pin;
+
+    // Disabling the fixed function turns the pin into a GPIO input. Set its
+    // output low before making it an output, so it doesn't glitch high.
+    swm.pinenable0.modify(|_, w| w.i2c0_sda().set_bit());
+
+    // Sound, as writing ones only affects our pin, zeros are ignored.
+    gpio.clr[0].write(|w| unsafe { w.clrp().bits(mask) });
+    gpio.dirset[0].write(|w| unsafe { w.dirsetp().bits(mask) });
+
+    asm::delay(cycles);
+
+    // Sound, see above.
+    gpio.dirclr[0].write(|w| unsafe { w.dirclrp().bits(mask) });
+    swm.pinenable0.modify(|_, w| w.i2c0_sda().clear_bit());
+}
+
+
+/// How the slave should respond to the master
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    /// Acknowledge, as usual
+    Ack,
+
+    /// Don't acknowledge
+    Nack,
+}
+
+
+/// Decides when to inject a fault
+///
+/// The slave's interrupt handler calls [`on_address`] and [`on_write`], and
+/// responds as instructed. Faults are injected once, then cleared.
+///
+/// [`on_address`]: #method.on_address
+/// [`on_write`]: #method.on_write
+pub struct Injector {
+    fault:         Option<Fault>,
+    index:         u8,
+    core_clock_hz: u32,
+}
+
+impl Injector {
+    /// Create a new instance of `Injector`, with no fault armed
+    ///
+    /// `core_clock_hz` is used to convert the duration of clock stretching.
+    pub fn new(core_clock_hz: u32) -> Self {
+        Self {
+            fault: None,
+            index: 0,
+            core_clock_hz,
+        }
+    }
+
+    /// Inject the fault into the next transaction
+    ///
+    /// Replaces any fault that hasn't been injected yet. `Fault::HoldSda`
+    /// isn't injected by the slave, see [`hold_sda_low`].
+    ///
+    /// [`hold_sda_low`]: fn.hold_sda_low.html
+    pub fn arm(&mut self, fault: Fault) {
+        self.fault = Some(fault);
+    }
+
+    /// Decides how to respond to the slave's address being matched
+    ///
+    /// Must be called before the slave responds, as the slave holds SCL low
+    /// until then. If the clock is to be stretched, blocks for that long.
+    pub fn on_address(&mut self) -> Action {
+        self.index = 0;
+
+        match self.fault {
+            Some(Fault::NackAddress) => {
+                self.fault = None;
+                Action::Nack
+            }
+            Some(Fault::StretchClock { duration_us }) => {
+                self.fault = None;
+
+                let cycles = duration_us as u64
+                    * self.core_clock_hz as u64
+                    / 1_000_000;
+                asm::delay(cycles as u32);
+
+                Action::Ack
+            }
+            _ => {
+                Action::Ack
+            }
+        }
+    }
+
+    /// Decides how to respond to a data byte written by the master
+    pub fn on_write(&mut self) -> Action {
+        let index = self.index;
+        self.index = self.index.saturating_add(1);
+
+        match self.fault {
+            Some(Fault::NackData { index: nack }) if nack == index => {
+                self.fault = None;
+                Action::Nack
+            }
+            _ => {
+                Action::Ack
+            }
+        }
+    }
+}
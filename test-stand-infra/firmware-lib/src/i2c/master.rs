@@ -0,0 +1,275 @@
+//! Multi-byte I2C master transactions
+//!
+//! LPC8xx HAL's blocking I2C API ends every write and every read with a stop
+//! condition. This module accesses the I2C's registers directly, like
+//! [`usart::config`], to support a write followed by a read after a repeated
+//! start, and 10-bit addresses.
+//!
+//! It also helps with errors that the HAL's API reports, which leave the I2C
+//! in the middle of a transaction.
+//!
+//! [`usart::config`]: ../../usart/config/index.html
+
+
+use lpc8xx_hal::{
+    i2c,
+    pac::i2c0::RegisterBlock,
+};
+use protocol::i2c::{
+    Address,
+    Error,
+};
+
+
+// Values of the MSTSTATE field, as listed in the user manual, section 19.6.3.
+const IDLE:           u8 = 0x0;
+const RECEIVE_READY:  u8 = 0x1;
+const TRANSMIT_READY: u8 = 0x2;
+const NACK_ADDRESS:   u8 = 0x3;
+const NACK_DATA:      u8 = 0x4;
+
+
+/// Enables the timeout of an I2C
+///
+/// `clocks` is the timeout in cycles of the I2C's function clock. It is
+/// rounded down to a multiple of 16, and limited to what the hardware
+/// supports. Once enabled, a slave that stretches the clock for longer causes
+/// a timeout error, instead of blocking the master.
+pub fn enable_timeout(i2c: &RegisterBlock, clocks: u32) {
+    // The TO field counts in units of 16 clocks, minus one, and is 12 bits
+    // wide.
+    let to = Ord::min(clocks / 16, 0x1000).saturating_sub(1) as u16;
+
+    // Sound, as we made sure the value fits into the field.
+    i2c.timeout.write(|w| unsafe { w.to().bits(to) });
+    i2c.cfg.modify(|_, w| w.timeouten().set_bit());
+}
+
+/// Maps an error reported by LPC8xx HAL, and returns the I2C to idle
+///
+/// The HAL leaves the I2C as it was, when the error occurred. This ends the
+/// transaction, so the I2C can be used again.
+pub fn recover(i2c: &RegisterBlock, err: &i2c::Error) -> Error {
+    // The HAL reports NACKs as unexpected states. The state is still there.
+    let error = match (i2c.stat.read().mststate().bits(), err) {
+        (NACK_ADDRESS, _) => {
+            Error::AddressNack
+        }
+        (NACK_DATA, _) => {
+            Error::DataNack
+        }
+        (_, i2c::Error::EventTimeout) | (_, i2c::Error::SclTimeout) => {
+            Error::Timeout
+        }
+        (_, i2c::Error::MasterArbitrationLoss) => {
+            Error::ArbitrationLoss
+        }
+        _ => {
+            Error::Other
+        }
+    };
+
+    end_transaction(i2c);
+
+    error
+}
+
+/// Writes `write` to the slave, then reads into `read`
+///
+/// If neither is empty, the read follows the write after a repeated start.
+/// If only `read` is empty, this is a plain write. If only `write` is empty,
+/// it is a plain read. The I2C must be enabled in master mode and idle.
+///
+/// A read from a 10-bit address always follows a write of the address, as
+/// required by the I2C specification, even if `write` is empty.
+///
+/// Sends a stop condition at the end, or as soon as an error occurs.
+pub fn write_read(
+    i2c:     &RegisterBlock,
+    address: Address,
+    write:   &[u8],
+    read:    &mut [u8],
+)
+    -> Result<(), TransferError>
+{
+    let result = transfer(i2c, address, write, read);
+
+    if result.is_err() {
+        end_transaction(i2c);
+        return result;
+    }
+
+    i2c.mstctl.write(|w| w.mststop().set_bit());
+    wait_for(i2c, IDLE)
+}
+
+/// Writes `write` to the slave, without ending the transaction
+///
+/// Unlike [`write_read`], this doesn't send a stop condition at the end. A
+/// read that follows, using LPC8xx HAL's DMA API for example, starts with a
+/// repeated start. The I2C must be enabled in master mode and idle.
+///
+/// Sends a stop condition, as soon as an error occurs.
+///
+/// [`write_read`]: fn.write_read.html
+pub fn write_without_stop(i2c: &RegisterBlock, address: Address, write: &[u8])
+    -> Result<(), TransferError>
+{
+    let result = transfer(i2c, address, write, &mut []);
+
+    if result.is_err() {
+        end_transaction(i2c);
+    }
+
+    result
+}
+
+/// Checks whether a slave acknowledges `address`
+///
+/// Addresses the slave for a write, without writing any data. Returns `false`
+/// if the address isn't acknowledged, or an error if something else goes
+/// wrong.
+pub fn probe(i2c: &RegisterBlock, address: Address)
+    -> Result<bool, TransferError>
+{
+    match write_read(i2c, address, &[], &mut []) {
+        Ok(())                          => Ok(true),
+        Err(TransferError::AddressNack) => Ok(false),
+        Err(err)                        => Err(err),
+    }
+}
+
+fn transfer(
+    i2c:     &RegisterBlock,
+    address: Address,
+    write:   &[u8],
+    read:    &mut [u8],
+)
+    -> Result<(), TransferError>
+{
+    let (first, second) = address.bytes();
+
+    if !write.is_empty() || read.is_empty() || second.is_some() {
+        start(i2c, first << 1);
+        wait_for(i2c, TRANSMIT_READY)?;
+
+        if let Some(b) = second {
+            // The slave doesn't acknowledge the second byte, if it doesn't
+            // match the rest of its address.
+            send(i2c, b).map_err(|err| match err {
+                TransferError::DataNack => TransferError::AddressNack,
+                err                     => err,
+            })?;
+        }
+        for &b in write {
+            send(i2c, b)?;
+        }
+    }
+
+    if !read.is_empty() {
+        // If we've written before, this is a repeated start. For a 10-bit
+        // address, the slave remembers the second byte from the write.
+        start(i2c, first << 1 | 0x1);
+        wait_for(i2c, RECEIVE_READY)?;
+
+        for (i, b) in read.iter_mut().enumerate() {
+            if i > 0 {
+                i2c.mstctl.write(|w| w.mstcontinue().set_bit());
+                wait_for(i2c, RECEIVE_READY)?;
+            }
+
+            *b = i2c.mstdat.read().data().bits();
+        }
+    }
+
+    Ok(())
+}
+
+fn start(i2c: &RegisterBlock, address_rw: u8) {
+    // Sound, as all 8-bit values are valid addresses, including the R/W bit.
+    i2c.mstdat.write(|w| unsafe { w.data().bits(address_rw) });
+    i2c.mstctl.write(|w| w.mststart().set_bit());
+}
+
+fn send(i2c: &RegisterBlock, b: u8) -> Result<(), TransferError> {
+    // Sound, as all 8-bit values are valid data.
+    i2c.mstdat.write(|w| unsafe { w.data().bits(b) });
+    i2c.mstctl.write(|w| w.mstcontinue().set_bit());
+    wait_for(i2c, TRANSMIT_READY)
+}
+
+fn wait_for(i2c: &RegisterBlock, expected: u8) -> Result<(), TransferError> {
+    loop {
+        let stat = i2c.stat.read();
+
+        if stat.eventtimeout().bit_is_set() || stat.scltimeout().bit_is_set() {
+            return Err(TransferError::Timeout);
+        }
+        if stat.mstarbloss().bit_is_set() {
+            return Err(TransferError::ArbitrationLoss);
+        }
+        if stat.mstpending().bit_is_set() {
+            break;
+        }
+    }
+
+    match i2c.stat.read().mststate().bits() {
+        state if state == expected => Ok(()),
+        NACK_ADDRESS => Err(TransferError::AddressNack),
+        NACK_DATA    => Err(TransferError::DataNack),
+        state        => Err(TransferError::UnexpectedState(state)),
+    }
+}
+
+
+fn end_transaction(i2c: &RegisterBlock) {
+    // Clear the error flags. Writing ones clears them, zeros are ignored.
+    i2c.stat.write(|w|
+        w
+            .eventtimeout().set_bit()
+            .scltimeout().set_bit()
+            .mstarbloss().set_bit()
+            .mstststperr().set_bit()
+    );
+
+    // A stop condition is only possible, while the master is still part of a
+    // transaction. After losing arbitration, it's idle already.
+    let state = i2c.stat.read().mststate().bits();
+    if i2c.stat.read().mstpending().bit_is_set() && state != IDLE {
+        i2c.mstctl.write(|w| w.mststop().set_bit());
+    }
+}
+
+
+/// An error that occurred during [`write_read`]
+///
+/// [`write_read`]: fn.write_read.html
+#[derive(Debug, Eq, PartialEq)]
+pub enum TransferError {
+    /// The slave didn't acknowledge its address
+    AddressNack,
+
+    /// The slave didn't acknowledge a data byte
+    DataNack,
+
+    /// The bus didn't change state before the I2C's timeout expired
+    Timeout,
+
+    /// Another device drove the bus, while the master was sending
+    ArbitrationLoss,
+
+    /// The I2C was in an unexpected state (value of the MSTSTATE field)
+    UnexpectedState(u8),
+}
+
+impl From<TransferError> for Error {
+    fn from(err: TransferError) -> Self {
+        match err {
+            TransferError::AddressNack        => Error::AddressNack,
+            TransferError::DataNack           => Error::DataNack,
+            TransferError::Timeout            => Error::Timeout,
+            TransferError::ArbitrationLoss    => Error::ArbitrationLoss,
+            TransferError::UnexpectedState(_) => Error::Other,
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Behavior of an I2C slave
+//!
+//! The interrupt handler passes the events it gets from LPC8xx HAL's slave API
+//! to a [`Device`], which decides what to transmit. [`Addressing`] decides
+//! which of those events concern the device, if it has a 10-bit address.
+//!
+//! [`Device`]: enum.Device.html
+//! [`Addressing`]: struct.Addressing.html
+
+
+use heapless::Vec;
+use lpc8xx_hal::pac::i2c0::RegisterBlock;
+use protocol::i2c::{
+    Address,
+    MAX_TRANSFER,
+    REGISTERS,
+    SlaveConfig,
+};
+
+
+/// Changes the address of an enabled I2C slave
+///
+/// LPC8xx HAL only sets the address when enabling slave mode. This accesses
+/// the I2C's registers directly, like [`master`].
+///
+/// The hardware only matches 7-bit addresses. For a 10-bit address, this sets
+/// the first byte of the address, and [`Addressing`] needs to check the
+/// second one.
+///
+/// [`master`]: ../master/index.html
+/// [`Addressing`]: struct.Addressing.html
+pub fn set_address(i2c: &RegisterBlock, address: Address) {
+    let (first, _) = address.bytes();
+
+    // Sound, as every 7-bit value is a valid address. Also enables the
+    // address, by leaving SADISABLE cleared.
+    i2c.slvadr[0].write(|w| unsafe { w.slvadr().bits(first) });
+}
+
+/// Indicates whether the master has addressed the slave for a read
+///
+/// Only valid while the slave's address has been matched, before it has been
+/// acknowledged.
+pub fn is_read(i2c: &RegisterBlock) -> bool {
+    // In the slave address state, SLVDAT contains the address byte, including
+    // the R/W bit.
+    i2c.slvdat.read().data().bits() & 0x1 == 0x1
+}
+
+
+/// Keeps track of whether a slave with a 10-bit address has been addressed
+///
+/// The interrupt handler passes address matches and received bytes to this,
+/// before passing them on to the [`Device`]. For a 7-bit address, every
+/// address match is complete, and all received bytes are data.
+///
+/// A 10-bit address is complete, once the second address byte of a write has
+/// matched. A read is only acknowledged, if it follows such a write after a
+/// repeated start. The interrupt handler must call [`on_deselect`], once the
+/// slave has been deselected by a stop condition or otherwise, so a read after
+/// a stop isn't acknowledged.
+///
+/// It also counts how often the slave has been deselected, which tells the
+/// host whether there was a stop condition within a transfer.
+///
+/// [`Device`]: enum.Device.html
+/// [`on_deselect`]: #method.on_deselect
+pub struct Addressing {
+    address:      Address,
+    state:        TenBitState,
+    deselections: u32,
+}
+
+impl Addressing {
+    /// Create a new instance of `Addressing`, for the given address
+    ///
+    /// The address must have been set using [`set_address`] already.
+    ///
+    /// [`set_address`]: fn.set_address.html
+    pub fn new(address: Address) -> Self {
+        Self {
+            address,
+            state:        TenBitState::Idle,
+            deselections: 0,
+        }
+    }
+
+    /// Change the address of the slave
+    pub fn set(&mut self, i2c: &RegisterBlock, address: Address) {
+        set_address(i2c, address);
+        *self = Self::new(address);
+    }
+
+    /// Notify this that the (first byte of the) address has been matched
+    pub fn on_address(&mut self, read: bool) -> AddressMatch {
+        if let (_, None) = self.address.bytes() {
+            return AddressMatch::Complete;
+        }
+
+        match (read, self.state) {
+            (false, _) => {
+                self.state = TenBitState::ExpectSecondByte;
+                AddressMatch::Partial
+            }
+            (true, TenBitState::Matched) => {
+                AddressMatch::Complete
+            }
+            (true, _) => {
+                AddressMatch::Mismatch
+            }
+        }
+    }
+
+    /// Notify this that the master has written a byte
+    ///
+    /// Returns `None`, if the byte is data for the device. Otherwise, it was
+    /// the second byte of a 10-bit address.
+    pub fn on_write(&mut self, b: u8) -> Option<AddressMatch> {
+        if self.state != TenBitState::ExpectSecondByte {
+            return None;
+        }
+
+        let (_, second) = self.address.bytes();
+        if second == Some(b) {
+            self.state = TenBitState::Matched;
+            Some(AddressMatch::Complete)
+        }
+        else {
+            self.state = TenBitState::Idle;
+            Some(AddressMatch::Mismatch)
+        }
+    }
+
+    /// Notify this that the slave has been deselected
+    ///
+    /// This happens on a stop condition, or when the master addresses another
+    /// slave.
+    pub fn on_deselect(&mut self) {
+        self.state = TenBitState::Idle;
+        self.deselections = self.deselections.wrapping_add(1);
+    }
+
+    /// Returns how often the slave has been deselected since the last call
+    pub fn take_deselections(&mut self) -> u32 {
+        core::mem::replace(&mut self.deselections, 0)
+    }
+}
+
+
+/// Whether an address byte matched the slave's address
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AddressMatch {
+    /// The slave has been addressed, and the device should be notified
+    Complete,
+
+    /// The first byte of a 10-bit address matched, the second is yet to come
+    Partial,
+
+    /// The slave hasn't been addressed, after all
+    Mismatch,
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum TenBitState {
+    Idle,
+    ExpectSecondByte,
+    Matched,
+}
+
+
+/// An I2C slave device
+pub enum Device {
+    Echo(Echo),
+    Registers(RegisterMap),
+}
+
+impl Device {
+    /// Notify the device that its address has been matched
+    pub fn on_address(&mut self) {
+        match self {
+            Device::Echo(echo)           => echo.on_address(),
+            Device::Registers(registers) => registers.on_address(),
+        }
+    }
+
+    /// Notify the device that the master has written a byte
+    pub fn on_write(&mut self, b: u8) {
+        match self {
+            Device::Echo(echo)           => echo.on_write(b),
+            Device::Registers(registers) => registers.on_write(b),
+        }
+    }
+
+    /// Ask the device for the next byte the master reads
+    ///
+    /// Returns `None`, if the device has nothing to transmit.
+    pub fn on_read(&mut self) -> Option<u8> {
+        match self {
+            Device::Echo(echo)           => echo.on_read(),
+            Device::Registers(registers) => Some(registers.on_read()),
+        }
+    }
+}
+
+
+/// Answers reads with the data of the last write, shifted left by one bit
+///
+/// If more bytes are read than were written, the data is repeated.
+pub struct Echo {
+    data:      Vec<u8, MAX_TRANSFER>,
+    new_write: bool,
+    read_pos:  usize,
+}
+
+impl Echo {
+    /// Create a new instance of `Echo`, which hasn't received any data
+    pub fn new() -> Self {
+        Self {
+            data:      Vec::new(),
+            new_write: false,
+            read_pos:  0,
+        }
+    }
+
+    fn on_address(&mut self) {
+        // We don't know the direction yet. This is either a write, which
+        // replaces the data, or a read, which starts from the beginning.
+        self.new_write = true;
+        self.read_pos  = 0;
+    }
+
+    fn on_write(&mut self, b: u8) {
+        if self.new_write {
+            self.data.clear();
+            self.new_write = false;
+        }
+
+        // The host doesn't write more than `MAX_TRANSFER` bytes. If anything
+        // else does, the excess is ignored.
+        let _ = self.data.push(b);
+    }
+
+    fn on_read(&mut self) -> Option<u8> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let b = self.data[self.read_pos % self.data.len()];
+        self.read_pos += 1;
+
+        Some(b << 1)
+    }
+}
+
+
+/// Emulates a device with a register map, like a sensor or an EEPROM
+///
+/// The first byte of every write sets the register pointer. Further bytes are
+/// written to the registers, starting at the pointer. Reads start at the
+/// pointer too, so a write of just the pointer, followed by a read, reads
+/// from a specific register.
+pub struct RegisterMap {
+    config:         SlaveConfig,
+    registers:      [u8; REGISTERS],
+    read_only:      [bool; REGISTERS],
+    pointer:        u8,
+    expect_pointer: bool,
+}
+
+impl RegisterMap {
+    /// Create a new register map, with all registers zero and writable
+    pub fn new(config: SlaveConfig) -> Self {
+        Self {
+            config,
+            registers:      [0; REGISTERS],
+            read_only:      [false; REGISTERS],
+            pointer:        0,
+            expect_pointer: false,
+        }
+    }
+
+    /// The configuration of the device
+    pub fn config(&self) -> &SlaveConfig {
+        &self.config
+    }
+
+    /// Sets registers, starting at `offset`
+    ///
+    /// If `read_only` is `true`, the master can't change the registers.
+    /// Returns an error, if the registers are out of range.
+    pub fn set(&mut self, offset: u8, data: &[u8], read_only: bool)
+        -> Result<(), OutOfRange>
+    {
+        let range = range(offset, data.len())?;
+
+        self.registers[range.clone()].copy_from_slice(data);
+        for r in &mut self.read_only[range] {
+            *r = read_only;
+        }
+
+        Ok(())
+    }
+
+    /// Returns `len` registers, starting at `offset`
+    ///
+    /// Returns an error, if the registers are out of range.
+    pub fn get(&self, offset: u8, len: usize) -> Result<&[u8], OutOfRange> {
+        Ok(&self.registers[range(offset, len)?])
+    }
+
+    fn on_address(&mut self) {
+        self.expect_pointer = true;
+    }
+
+    fn on_write(&mut self, b: u8) {
+        if self.expect_pointer {
+            self.pointer        = b;
+            self.expect_pointer = false;
+            return;
+        }
+
+        let i = self.pointer as usize;
+        if !self.read_only[i] {
+            self.registers[i] = b;
+        }
+
+        self.advance();
+    }
+
+    fn on_read(&mut self) -> u8 {
+        let b = self.registers[self.pointer as usize];
+        self.advance();
+
+        b
+    }
+
+    fn advance(&mut self) {
+        if self.config.auto_increment {
+            self.pointer = self.pointer.wrapping_add(1);
+        }
+    }
+}
+
+fn range(offset: u8, len: usize)
+    -> Result<core::ops::Range<usize>, OutOfRange>
+{
+    let start = offset as usize;
+    let end   = start + len;
+
+    if end > REGISTERS {
+        return Err(OutOfRange { offset, len });
+    }
+
+    Ok(start .. end)
+}
+
+
+/// Registers beyond the end of a [`RegisterMap`] were accessed
+///
+/// [`RegisterMap`]: struct.RegisterMap.html
+#[derive(Debug)]
+pub struct OutOfRange {
+    pub offset: u8,
+    pub len:    usize,
+}
@@ -0,0 +1,125 @@
+//! Measurement of interrupt latency
+
+
+use protocol::pin::{
+    Histogram,
+    Level,
+    MeasureLatencyResult,
+    Statistics,
+};
+
+
+/// Measures how fast a pin follows the level changes of another pin
+///
+/// The caller is responsible for toggling the output pin and timing how long it
+/// takes for the input pin to follow. This struct decides which level to set
+/// next, and collects the results.
+pub struct LatencyMeasurement {
+    samples:          u32,
+    ticks_per_second: u32,
+
+    level:      Level,
+    measured:   u32,
+    timeouts:   u32,
+    latency_ns: Option<Statistics>,
+    histogram:  Histogram,
+}
+
+impl LatencyMeasurement {
+    /// Create a new instance of `LatencyMeasurement`
+    ///
+    /// `samples` is the number of edges to measure. `bin_width_ns` is the width
+    /// of the histogram bins in nanoseconds. `ticks_per_second` is the
+    /// frequency of the timer that measures the latencies passed to [`add`].
+    ///
+    /// [`add`]: #method.add
+    pub fn new(samples: u32, bin_width_ns: u32, ticks_per_second: u32)
+        -> Self
+    {
+        Self {
+            samples,
+            ticks_per_second,
+
+            level:      Level::Low,
+            measured:   0,
+            timeouts:   0,
+            latency_ns: None,
+            histogram:  Histogram::new(Ord::max(bin_width_ns, 1)),
+        }
+    }
+
+    /// Returns the level that the output pin should be set to next
+    ///
+    /// Alternates between high and low, so both rising and falling edges are
+    /// measured.
+    pub fn next_level(&mut self) -> Level {
+        self.level = match self.level {
+            Level::High => Level::Low,
+            Level::Low  => Level::High,
+        };
+
+        self.level
+    }
+
+    /// Add the result of a single edge to the measurement
+    ///
+    /// `latency` is the number of timer ticks until the input pin followed the
+    /// output pin, or `None`, if it didn't follow in time.
+    pub fn add(&mut self, latency: Option<u32>) {
+        if self.is_done() {
+            return;
+        }
+
+        let latency_ns = match latency {
+            Some(ticks) => self.ticks_to_ns(ticks),
+            None        => {
+                self.timeouts += 1;
+                return;
+            }
+        };
+
+        self.measured += 1;
+        self.histogram.add(latency_ns);
+
+        match &mut self.latency_ns {
+            Some(statistics) => {
+                statistics.add(latency_ns);
+            }
+            None => {
+                self.latency_ns = Some(Statistics::new(latency_ns));
+            }
+        }
+    }
+
+    /// Indicates whether the requested number of edges has been measured
+    ///
+    /// Edges for which the input pin did not follow in time count towards the
+    /// number of samples.
+    pub fn is_done(&self) -> bool {
+        self.measured + self.timeouts >= self.samples
+    }
+
+    /// Returns the result of the measurement, if it is done
+    pub fn result<Id>(&self, output: Id, input: Id)
+        -> Option<MeasureLatencyResult<Id>>
+    {
+        if !self.is_done() {
+            return None;
+        }
+
+        Some(
+            MeasureLatencyResult {
+                output,
+                input,
+                samples:    self.measured,
+                timeouts:   self.timeouts,
+                latency_ns: self.latency_ns,
+                histogram:  self.histogram.clone(),
+            }
+        )
+    }
+
+    fn ticks_to_ns(&self, ticks: u32) -> u32 {
+        (ticks as u64 * 1_000_000_000 / self.ticks_per_second as u64) as u32
+    }
+}
@@ -4,5 +4,14 @@
 #![no_std]
 
 
+pub mod benchmark;
+pub mod i2c;
+pub mod latency;
+pub mod pin_config;
 pub mod pin_interrupt;
+pub mod rs485;
+pub mod signal;
+pub mod spi;
+pub mod systick;
 pub mod usart;
+pub mod waveform;
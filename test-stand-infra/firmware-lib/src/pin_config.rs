@@ -0,0 +1,77 @@
+//! Runtime pin configuration
+//!
+//! LPC8xx HAL doesn't provide an API for the pin configuration in the IOCON
+//! peripheral (pull resistors, open-drain mode), so this module provides the
+//! required pieces to access it directly.
+
+
+use lpc8xx_hal::{
+    gpio::{
+        self,
+        GpioPin,
+        direction::Dynamic,
+    },
+    pins,
+};
+use protocol::pin::{
+    Config,
+    Direction,
+    Level,
+    OutputMode,
+    Pull,
+};
+
+
+/// Configures a pin's IOCON register according to the provided configuration
+///
+/// `$register` must be the pin's register in the IOCON peripheral, for example
+/// `iocon.pio1_2`. Only the pull resistor and open-drain settings are changed.
+/// Switching the pin's direction is handled by the GPIO API.
+#[macro_export]
+macro_rules! configure_iocon {
+    ($register:expr, $config:expr) => {
+        $register.modify(|_, w| {
+            // Sound, as `iocon_mode` only returns valid values for this field.
+            unsafe {
+                w
+                    .mode().bits($crate::pin_config::iocon_mode(&$config))
+                    .od().bit($crate::pin_config::is_open_drain(&$config))
+            }
+        })
+    };
+}
+
+
+/// Returns the value of the IOCON `MODE` field for the given configuration
+///
+/// See user manual, section 8.5.
+pub fn iocon_mode(config: &Config) -> u8 {
+    match config.pull {
+        Pull::None => 0x0,
+        Pull::Down => 0x1,
+        Pull::Up   => 0x2,
+    }
+}
+
+/// Indicates whether the given configuration requires open-drain mode
+pub fn is_open_drain(config: &Config) -> bool {
+    config.output_mode == OutputMode::OpenDrain
+}
+
+
+/// Switches a dynamic GPIO pin to the direction from the configuration
+pub fn configure_direction<T>(pin: &mut GpioPin<T, Dynamic>, config: &Config)
+    where T: pins::Trait
+{
+    match config.direction {
+        Direction::Input => {
+            pin.switch_to_input();
+        }
+        Direction::Output(Level::High) => {
+            pin.switch_to_output(gpio::Level::High);
+        }
+        Direction::Output(Level::Low) => {
+            pin.switch_to_output(gpio::Level::Low);
+        }
+    }
+}
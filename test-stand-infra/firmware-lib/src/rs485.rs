@@ -0,0 +1,131 @@
+//! Monitoring of RS-485 driver enable timing
+
+
+use protocol::usart::Rs485Timing;
+
+use crate::systick;
+
+
+/// Monitors the driver enable signal and data line of an RS-485 transmission
+///
+/// The caller samples both signals in a tight loop and passes the levels to
+/// [`sample`], together with the current value of a free-running timer. That
+/// timer counts up from zero to `timer_max`, then wraps around. The resolution
+/// of the measurement depends on how fast the caller samples.
+///
+/// The driver enable signal is expected to be active high. Monitoring starts,
+/// once it has been seen inactive, and ends when it has been released after
+/// being asserted, or when the timeout expires.
+///
+/// [`sample`]: #method.sample
+pub struct Rs485Monitor {
+    ticks_per_second: u32,
+    timer_max:        u32,
+    timeout:          u32,
+
+    state:      State,
+    elapsed:    u32,
+    last_timer: Option<u32>,
+    reference:  u32,
+    data:       bool,
+    timing:     Rs485Timing,
+}
+
+impl Rs485Monitor {
+    /// Create a new instance of `Rs485Monitor`
+    ///
+    /// `ticks_per_second` is the frequency of the timer, `timer_max` the
+    /// highest value it reaches before wrapping around.
+    pub fn new(timeout_ms: u32, ticks_per_second: u32, timer_max: u32)
+        -> Self
+    {
+        let timeout = timeout_ms as u64 * ticks_per_second as u64 / 1000;
+
+        Self {
+            ticks_per_second,
+            timer_max,
+            timeout: timeout as u32,
+
+            state:      State::WaitingForIdle,
+            elapsed:    0,
+            last_timer: None,
+            reference:  0,
+            data:       true,
+            timing:     Rs485Timing {
+                de_asserted_ns: None,
+                first_start_ns: None,
+                de_released_ns: None,
+            },
+        }
+    }
+
+    /// Process a sample of both signals
+    ///
+    /// `de` is the level of the driver enable signal, `data` the level of the
+    /// data line. `true` means high.
+    pub fn sample(&mut self, de: bool, data: bool, timer: u32) {
+        if let Some(last) = self.last_timer {
+            let delta = systick::delta(last, timer, self.timer_max);
+            self.elapsed += delta;
+        }
+        self.last_timer = Some(timer);
+
+        let now = Some(self.ticks_to_ns(self.elapsed - self.reference));
+
+        match self.state {
+            State::WaitingForIdle => {
+                if !de {
+                    // Times are relative to this moment.
+                    self.reference = self.elapsed;
+                    self.state     = State::Idle;
+                }
+            }
+            State::Idle | State::Enabled => {
+                if self.data && !data && self.timing.first_start_ns.is_none() {
+                    self.timing.first_start_ns = now;
+                }
+
+                match (self.state, de) {
+                    (State::Idle, true) => {
+                        self.timing.de_asserted_ns = now;
+                        self.state = State::Enabled;
+                    }
+                    (State::Enabled, false) => {
+                        self.timing.de_released_ns = now;
+                        self.state = State::Done;
+                    }
+                    _ => {}
+                }
+            }
+            State::Done => {}
+        }
+
+        self.data = data;
+    }
+
+    /// Indicates whether monitoring is done
+    ///
+    /// This is the case, if the driver enable signal has been released, or
+    /// the timeout has expired.
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done || self.elapsed > self.timeout
+    }
+
+    /// Returns the timing that has been observed so far
+    pub fn timing(&self) -> Rs485Timing {
+        self.timing
+    }
+
+    fn ticks_to_ns(&self, ticks: u32) -> u32 {
+        (ticks as u64 * 1_000_000_000 / self.ticks_per_second as u64) as u32
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    WaitingForIdle,
+    Idle,
+    Enabled,
+    Done,
+}
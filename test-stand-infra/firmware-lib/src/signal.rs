@@ -0,0 +1,148 @@
+//! Measurement of periodic signals
+
+
+use protocol::pin::{
+    Level,
+    MeasureSignalResult,
+    Statistics,
+};
+
+
+/// Measures a periodic signal on a pin
+///
+/// Is fed with the level changes of a pin, for example the events produced by
+/// the [pin interrupt API]. Collects statistics about the high and low phases
+/// of the signal, until the requested number of cycles has been measured.
+///
+/// A cycle starts with a rising edge, and ends with the next rising edge. Any
+/// level changes before the first rising edge are ignored, as the duration of
+/// the phase they end is unknown.
+///
+/// [pin interrupt API]: ../pin_interrupt/index.html
+pub struct Measurement {
+    cycles:           u32,
+    ticks_per_second: u32,
+
+    started: bool,
+    high_us: Option<u32>,
+    result:  Option<Totals>,
+}
+
+impl Measurement {
+    /// Create a new instance of `Measurement`
+    ///
+    /// `cycles` is the number of cycles to measure. `ticks_per_second` is the
+    /// frequency of the timer that measures the periods passed to [`add`].
+    ///
+    /// [`add`]: #method.add
+    pub fn new(cycles: u32, ticks_per_second: u32) -> Self {
+        Self {
+            cycles,
+            ticks_per_second,
+
+            started: false,
+            high_us: None,
+            result:  None,
+        }
+    }
+
+    /// Add a level change to the measurement
+    ///
+    /// `level` is the level of the pin after the change, `period` is the number
+    /// of timer ticks since the previous change, if available. If `period` is
+    /// not available, the current cycle is discarded.
+    pub fn add(&mut self, level: Level, period: Option<u32>) {
+        if self.is_done() {
+            return;
+        }
+
+        let period_us = period.map(|ticks| self.ticks_to_us(ticks));
+
+        match (level, period_us) {
+            (Level::High, Some(low_us)) if self.started => {
+                if let Some(high_us) = self.high_us.take() {
+                    self.add_cycle(high_us, low_us);
+                }
+            }
+            (Level::High, _) => {
+                self.started = true;
+                self.high_us = None;
+            }
+            (Level::Low, Some(high_us)) if self.started => {
+                self.high_us = Some(high_us);
+            }
+            (Level::Low, _) => {
+                self.high_us = None;
+            }
+        }
+    }
+
+    /// Indicates whether the requested number of cycles has been measured
+    pub fn is_done(&self) -> bool {
+        match &self.result {
+            Some(result) => result.cycles >= self.cycles,
+            None         => self.cycles == 0,
+        }
+    }
+
+    /// Returns the result of the measurement, if it is done
+    pub fn result<Id>(&self, pin: Id) -> Option<MeasureSignalResult<Id>> {
+        if !self.is_done() {
+            return None;
+        }
+
+        self.result.as_ref().map(|result| {
+            MeasureSignalResult {
+                pin,
+                cycles:              result.cycles,
+                high_us:             result.high_us,
+                low_us:              result.low_us,
+                period_us:           result.period_us,
+                duty_cycle_permille: result.duty_cycle_permille,
+            }
+        })
+    }
+
+    fn add_cycle(&mut self, high_us: u32, low_us: u32) {
+        let period_us = high_us.saturating_add(low_us);
+        let duty_cycle_permille = match period_us {
+            0         => 0,
+            period_us => (high_us as u64 * 1000 / period_us as u64) as u32,
+        };
+
+        match &mut self.result {
+            Some(result) => {
+                result.cycles += 1;
+                result.high_us.add(high_us);
+                result.low_us.add(low_us);
+                result.period_us.add(period_us);
+                result.duty_cycle_permille.add(duty_cycle_permille);
+            }
+            None => {
+                self.result = Some(
+                    Totals {
+                        cycles:              1,
+                        high_us:             Statistics::new(high_us),
+                        low_us:              Statistics::new(low_us),
+                        period_us:           Statistics::new(period_us),
+                        duty_cycle_permille:
+                            Statistics::new(duty_cycle_permille),
+                    }
+                );
+            }
+        }
+    }
+
+    fn ticks_to_us(&self, ticks: u32) -> u32 {
+        (ticks as u64 * 1_000_000 / self.ticks_per_second as u64) as u32
+    }
+}
+
+
+struct Totals {
+    cycles:              u32,
+    high_us:             Statistics,
+    low_us:              Statistics,
+    period_us:           Statistics,
+    duty_cycle_permille: Statistics,
+}
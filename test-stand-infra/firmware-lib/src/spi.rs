@@ -0,0 +1,7 @@
+//! SPI support, beyond what LPC8xx HAL provides
+
+
+pub mod config;
+pub mod master;
+pub mod slave;
+pub mod timing;
@@ -0,0 +1,53 @@
+//! Runtime configuration of SPI mode, bit order, and clock
+//!
+//! LPC8xx HAL configures an SPI once, when enabling it. This module changes
+//! the configuration of an already enabled SPI, by accessing its registers
+//! directly, like [`usart::config`].
+//!
+//! [`usart::config`]: ../../usart/config/index.html
+
+
+use lpc8xx_hal::pac::spi0::RegisterBlock;
+use protocol::spi::{
+    BitOrder,
+    Config,
+};
+
+
+/// Changes the configuration of an enabled SPI
+///
+/// Works for both master and slave. The clock divider only affects a master.
+/// Disables the SPI, changes the configuration, and enables it again, so this
+/// must not be called during a transfer. The HAL's API for the SPI keeps
+/// working afterwards.
+pub fn configure(spi: &RegisterBlock, config: &Config) {
+    let lsb_first = config.bit_order == BitOrder::LsbFirst;
+
+    spi.cfg.modify(|_, w| w.enable().bit(false));
+
+    spi.cfg.modify(|_, w|
+        w
+            .cpol().bit(config.mode.idle_high())
+            .cpha().bit(config.mode.capture_on_second_edge())
+            .lsbf().bit(lsb_first)
+    );
+
+    // Sound, as DIVVAL is 16 bits wide, and every value is valid.
+    spi.div.write(|w| unsafe { w.divval().bits(config.clock_divider) });
+
+    spi.cfg.modify(|_, w| w.enable().bit(true));
+}
+
+
+/// Switches an enabled SPI between master and slave mode
+///
+/// Disables the SPI while switching, so this must not be called during a
+/// transfer. The HAL's API for the SPI only works in the mode the SPI was
+/// enabled in. For a slave that acts as master temporarily, use [`master`].
+///
+/// [`master`]: ../master/index.html
+pub fn set_master(spi: &RegisterBlock, master: bool) {
+    spi.cfg.modify(|_, w| w.enable().bit(false));
+    spi.cfg.modify(|_, w| w.master().bit(master));
+    spi.cfg.modify(|_, w| w.enable().bit(true));
+}
@@ -0,0 +1,53 @@
+//! SPI master transfers, for an SPI that was enabled as slave
+//!
+//! LPC8xx HAL's SPI API only works in the mode the SPI was enabled in. This
+//! module accesses the SPI's registers directly, to transfer data after the
+//! SPI has been switched to master mode using [`config::set_master`].
+//!
+//! [`config::set_master`]: ../config/fn.set_master.html
+
+
+use lpc8xx_hal::pac::spi0::RegisterBlock;
+
+
+/// Transfers `data` to the slave on SSEL0, replacing it with the reply
+///
+/// Asserts SSEL0 for the whole transfer, and deasserts it after the last byte.
+/// Uses the clock divider, mode, and bit order the SPI is configured with.
+pub fn transfer(spi: &RegisterBlock, data: &mut [u8]) {
+    let len = data.len();
+
+    // Drop anything the SPI might have received as slave.
+    while spi.stat.read().rxrdy().bit_is_set() {
+        let _ = spi.rxdat.read();
+    }
+
+    for (i, b) in data.iter_mut().enumerate() {
+        let last = i + 1 == len;
+
+        while spi.stat.read().txrdy().bit_is_clear() {}
+
+        // Sound, as a length of 7 selects 8-bit transfers, which is what we
+        // want. Slave selects are active low, so only SSEL0 is asserted.
+        spi.txdatctl.write(|w| unsafe {
+            w
+                .txdat().bits(*b as u16)
+                .txssel0_n().clear_bit()
+                .txssel1_n().set_bit()
+                .txssel2_n().set_bit()
+                .txssel3_n().set_bit()
+                .eot().bit(last)
+                .len().bits(7)
+        });
+
+        while spi.stat.read().rxrdy().bit_is_clear() {}
+        *b = spi.rxdat.read().rxdat().bits() as u8;
+    }
+
+    while spi.stat.read().mstidle().bit_is_clear() {}
+
+    // The transfer has asserted and deasserted SSEL0. Clear the flags, so an
+    // interrupt handler for the slave doesn't mistake this for a transfer it
+    // should take part in.
+    spi.stat.write(|w| w.ssa().set_bit().ssd().set_bit());
+}
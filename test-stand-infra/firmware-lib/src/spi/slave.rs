@@ -0,0 +1,367 @@
+//! Behavior of an SPI slave
+//!
+//! The interrupt handler passes the bytes it receives to a [`Device`], which
+//! decides what to transmit next. Unless an SPI flash is emulated, it also
+//! passes them to a [`Transfer`], which records them until slave select is
+//! deasserted. Idle code then takes the received data and reports it to the
+//! host.
+//!
+//! [`Device`]: struct.Device.html
+//! [`Transfer`]: struct.Transfer.html
+
+
+use heapless::Vec;
+use protocol::spi::{
+    FLASH_PAGE_SIZE,
+    FLASH_SIZE,
+    FlashConfig,
+    MAX_TRANSFER,
+    instruction,
+};
+
+
+/// An SPI slave device
+///
+/// Answers every byte with the byte received before it, shifted left by one
+/// bit, unless a flash is emulated. The emulated flash's memory is part of the
+/// device, whether it's emulated or not, so switching between the two doesn't
+/// require moving it around.
+pub struct Device {
+    flash:          Flash,
+    flash_emulated: bool,
+}
+
+impl Device {
+    /// Create a new instance of `Device`, which doesn't emulate a flash
+    pub fn new() -> Self {
+        Self {
+            flash:          Flash::new(),
+            flash_emulated: false,
+        }
+    }
+
+    /// Start emulating an SPI flash
+    ///
+    /// Erases the flash's memory.
+    pub fn start_flash(&mut self, config: FlashConfig) {
+        self.flash.reset(config);
+        self.flash_emulated = true;
+    }
+
+    /// Stop emulating an SPI flash
+    pub fn stop_flash(&mut self) {
+        self.flash_emulated = false;
+    }
+
+    /// Access the emulated flash
+    ///
+    /// Returns `None`, if no flash is emulated.
+    pub fn flash(&mut self) -> Option<&mut Flash> {
+        match self.flash_emulated {
+            true  => Some(&mut self.flash),
+            false => None,
+        }
+    }
+
+    /// Notify the device that slave select has been asserted
+    pub fn on_select(&mut self) {
+        if self.flash_emulated {
+            self.flash.on_select();
+        }
+    }
+
+    /// Notify the device that a byte has been received
+    ///
+    /// Returns the byte to transmit while receiving the next one.
+    pub fn on_receive(&mut self, b: u8) -> u8 {
+        match self.flash_emulated {
+            true  => self.flash.on_receive(b),
+            false => b << 1,
+        }
+    }
+
+    /// Notify the device that slave select has been deasserted
+    pub fn on_deselect(&mut self) {
+        if self.flash_emulated {
+            self.flash.on_deselect();
+        }
+    }
+}
+
+
+/// Emulates an SPI NOR flash, backed by RAM
+///
+/// Supports the instructions in [`protocol::spi::instruction`]. Every
+/// instruction starts when slave select is asserted, and ends when it is
+/// deasserted. Programming takes effect immediately, so the flash is never
+/// busy.
+///
+/// [`protocol::spi::instruction`]: ../../../protocol/spi/instruction/index.html
+pub struct Flash {
+    config:        FlashConfig,
+    memory:        [u8; FLASH_SIZE],
+    write_enabled: bool,
+    state:         State,
+}
+
+impl Flash {
+    fn new() -> Self {
+        Self {
+            config:        FlashConfig { jedec_id: [0xff; 3] },
+            memory:        [0xff; FLASH_SIZE],
+            write_enabled: false,
+            state:         State::Ignore,
+        }
+    }
+
+    fn reset(&mut self, config: FlashConfig) {
+        self.config        = config;
+        self.memory        = [0xff; FLASH_SIZE];
+        self.write_enabled = false;
+        self.state         = State::Ignore;
+    }
+
+    /// The configuration of the flash
+    pub fn config(&self) -> &FlashConfig {
+        &self.config
+    }
+
+    /// Sets the memory, starting at `address`
+    ///
+    /// Unlike programming via SPI, this can set bits too. Returns an error, if
+    /// the memory is out of range.
+    pub fn set(&mut self, address: u32, data: &[u8])
+        -> Result<(), OutOfRange>
+    {
+        let range = range(address, data.len())?;
+        self.memory[range].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Returns `len` bytes of memory, starting at `address`
+    ///
+    /// Returns an error, if the memory is out of range.
+    pub fn get(&self, address: u32, len: usize) -> Result<&[u8], OutOfRange> {
+        Ok(&self.memory[range(address, len)?])
+    }
+
+    fn status(&self) -> u8 {
+        match self.write_enabled {
+            true  => instruction::STATUS_WEL,
+            false => 0,
+        }
+    }
+
+    fn on_select(&mut self) {
+        self.state = State::Instruction;
+    }
+
+    fn on_receive(&mut self, b: u8) -> u8 {
+        match self.state {
+            State::Instruction => {
+                self.on_instruction(b)
+            }
+            State::Address { instruction, address, remaining } => {
+                let address = address << 8 | b as u32;
+
+                match remaining {
+                    1 => {
+                        self.on_address(instruction, address)
+                    }
+                    _ => {
+                        self.state = State::Address {
+                            instruction,
+                            address,
+                            remaining: remaining - 1,
+                        };
+                        0xff
+                    }
+                }
+            }
+            State::JedecId { index } => {
+                self.state = State::JedecId { index: index + 1 };
+                self.config.jedec_id.get(index).copied().unwrap_or(0xff)
+            }
+            State::Status => {
+                self.status()
+            }
+            State::Read { address } => {
+                self.state = State::Read { address: address + 1 };
+                self.memory[address % FLASH_SIZE]
+            }
+            State::Program { address } => {
+                // Only the address within the page advances.
+                let page   = address - address % FLASH_PAGE_SIZE;
+                let offset = (address + 1) % FLASH_PAGE_SIZE;
+                self.state = State::Program { address: page + offset };
+
+                self.memory[address % FLASH_SIZE] &= b;
+                0xff
+            }
+            State::Ignore => {
+                0xff
+            }
+        }
+    }
+
+    fn on_instruction(&mut self, b: u8) -> u8 {
+        match b {
+            instruction::READ_JEDEC_ID => {
+                self.state = State::JedecId { index: 1 };
+                self.config.jedec_id[0]
+            }
+            instruction::READ_STATUS => {
+                self.state = State::Status;
+                self.status()
+            }
+            instruction::WRITE_ENABLE => {
+                self.write_enabled = true;
+                self.state = State::Ignore;
+                0xff
+            }
+            instruction::WRITE_DISABLE => {
+                self.write_enabled = false;
+                self.state = State::Ignore;
+                0xff
+            }
+            instruction::READ_DATA | instruction::PAGE_PROGRAM => {
+                self.state = State::Address {
+                    instruction: b,
+                    address:     0,
+                    remaining:   3,
+                };
+                0xff
+            }
+            _ => {
+                self.state = State::Ignore;
+                0xff
+            }
+        }
+    }
+
+    fn on_address(&mut self, instruction: u8, address: u32) -> u8 {
+        let address = address as usize % FLASH_SIZE;
+
+        match instruction {
+            instruction::READ_DATA => {
+                self.state = State::Read { address: address + 1 };
+                self.memory[address]
+            }
+            _ if self.write_enabled => {
+                self.state = State::Program { address };
+                0xff
+            }
+            _ => {
+                // Page program without write enable is ignored.
+                self.state = State::Ignore;
+                0xff
+            }
+        }
+    }
+
+    fn on_deselect(&mut self) {
+        // Like on a real flash, completing a page program clears the write
+        // enable latch.
+        if let State::Program { .. } = self.state {
+            self.write_enabled = false;
+        }
+
+        self.state = State::Ignore;
+    }
+}
+
+
+/// What the emulated flash expects next
+#[derive(Clone, Copy)]
+enum State {
+    Instruction,
+    Address {
+        instruction: u8,
+        address:     u32,
+        remaining:   u8,
+    },
+    JedecId {
+        index: usize,
+    },
+    Status,
+    Read {
+        address: usize,
+    },
+    Program {
+        address: usize,
+    },
+    Ignore,
+}
+
+
+fn range(address: u32, len: usize)
+    -> Result<core::ops::Range<usize>, OutOfRange>
+{
+    let start = address as usize;
+
+    // `address` comes from the host, so the sum might overflow.
+    match start.checked_add(len) {
+        Some(end) if end <= FLASH_SIZE => Ok(start .. end),
+        _                              => Err(OutOfRange { address, len }),
+    }
+}
+
+
+/// Memory beyond the end of a [`Flash`] was accessed
+///
+/// [`Flash`]: struct.Flash.html
+#[derive(Debug)]
+pub struct OutOfRange {
+    pub address: u32,
+    pub len:     usize,
+}
+
+
+/// Records the data an SPI slave receives during a transfer
+pub struct Transfer {
+    received: Vec<u8, MAX_TRANSFER>,
+    complete: bool,
+}
+
+impl Transfer {
+    /// Create a new instance of `Transfer`, which hasn't received any data
+    pub fn new() -> Self {
+        Self {
+            received: Vec::new(),
+            complete: false,
+        }
+    }
+
+    /// Notify the transfer that slave select has been asserted
+    ///
+    /// Discards data from a previous transfer that hasn't been taken yet.
+    pub fn start(&mut self) {
+        self.received.clear();
+        self.complete = false;
+    }
+
+    /// Notify the transfer that a byte has been received
+    pub fn on_receive(&mut self, b: u8) {
+        // The host doesn't send more than `MAX_TRANSFER` bytes. If anything
+        // else does, the excess is ignored.
+        let _ = self.received.push(b);
+    }
+
+    /// Notify the transfer that slave select has been deasserted
+    pub fn finish(&mut self) {
+        self.complete = true;
+    }
+
+    /// Take the received data, if the transfer is complete
+    ///
+    /// Returns `None`, if there is no complete transfer, or its data has
+    /// already been taken.
+    pub fn take(&mut self) -> Option<Vec<u8, MAX_TRANSFER>> {
+        if !self.complete {
+            return None;
+        }
+
+        self.complete = false;
+        Some(core::mem::replace(&mut self.received, Vec::new()))
+    }
+}
@@ -0,0 +1,168 @@
+//! Monitoring of SPI slave select and clock timing
+
+
+use protocol::spi::Timing;
+
+use crate::systick;
+
+
+/// Monitors the slave select and clock signals of an SPI transfer
+///
+/// Works like [`Rs485Monitor`]: The caller samples both signals in a tight
+/// loop and passes the levels to [`sample`], together with the current value
+/// of a free-running timer. That timer counts up from zero to `timer_max`,
+/// then wraps around. The resolution of the measurement depends on how fast
+/// the caller samples.
+///
+/// Slave select is expected to be active low. Monitoring starts, once it has
+/// been seen inactive, and ends when it has been released after being
+/// asserted, or when the timeout expires. Any change of the clock counts as an
+/// edge, so this works regardless of the SPI mode.
+///
+/// [`Rs485Monitor`]: ../../rs485/struct.Rs485Monitor.html
+/// [`sample`]: #method.sample
+pub struct Monitor {
+    ticks_per_second: u32,
+    timer_max:        u32,
+    timeout:          u32,
+
+    state:      State,
+    elapsed:    u32,
+    last_timer: Option<u32>,
+    reference:  u32,
+    sck:        bool,
+    edges:      u32,
+    byte_end:   u32,
+    timing:     Timing,
+}
+
+impl Monitor {
+    /// Create a new instance of `Monitor`
+    ///
+    /// `ticks_per_second` is the frequency of the timer, `timer_max` the
+    /// highest value it reaches before wrapping around.
+    pub fn new(timeout_ms: u32, ticks_per_second: u32, timer_max: u32)
+        -> Self
+    {
+        let timeout = timeout_ms as u64 * ticks_per_second as u64 / 1000;
+
+        Self {
+            ticks_per_second,
+            timer_max,
+            timeout: timeout as u32,
+
+            state:      State::WaitingForIdle,
+            elapsed:    0,
+            last_timer: None,
+            reference:  0,
+            sck:        false,
+            edges:      0,
+            byte_end:   0,
+            timing:     Timing {
+                ssel_asserted_ns: None,
+                first_clock_ns:   None,
+                last_clock_ns:    None,
+                ssel_released_ns: None,
+                bytes:            0,
+                min_gap_ns:       None,
+                max_gap_ns:       None,
+            },
+        }
+    }
+
+    /// Process a sample of both signals
+    ///
+    /// `ssel` is the level of slave select, `sck` the level of the clock.
+    /// `true` means high.
+    pub fn sample(&mut self, ssel: bool, sck: bool, timer: u32) {
+        if let Some(last) = self.last_timer {
+            let delta = systick::delta(last, timer, self.timer_max);
+            self.elapsed += delta;
+        }
+        self.last_timer = Some(timer);
+
+        let now = self.ticks_to_ns(self.elapsed - self.reference);
+
+        match self.state {
+            State::WaitingForIdle => {
+                if ssel {
+                    // Times are relative to this moment.
+                    self.reference = self.elapsed;
+                    self.state     = State::Idle;
+                }
+            }
+            State::Idle => {
+                if !ssel {
+                    self.timing.ssel_asserted_ns = Some(now);
+                    self.state = State::Selected;
+                }
+            }
+            State::Selected => {
+                if sck != self.sck {
+                    self.on_edge(now);
+                }
+                if ssel {
+                    self.timing.ssel_released_ns = Some(now);
+                    self.state = State::Done;
+                }
+            }
+            State::Done => {}
+        }
+
+        self.sck = sck;
+    }
+
+    /// Indicates whether monitoring is done
+    ///
+    /// This is the case, if slave select has been released, or the timeout
+    /// has expired.
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done || self.elapsed > self.timeout
+    }
+
+    /// Returns the timing that has been observed so far
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    fn on_edge(&mut self, now: u32) {
+        if self.timing.first_clock_ns.is_none() {
+            self.timing.first_clock_ns = Some(now);
+        }
+
+        // The first edge of every byte but the first one ends a gap.
+        if self.edges > 0 && self.edges % EDGES_PER_BYTE == 0 {
+            let gap = now - self.byte_end;
+
+            let min = self.timing.min_gap_ns.map_or(gap, |min| min.min(gap));
+            let max = self.timing.max_gap_ns.map_or(gap, |max| max.max(gap));
+
+            self.timing.min_gap_ns = Some(min);
+            self.timing.max_gap_ns = Some(max);
+        }
+
+        self.edges += 1;
+        self.timing.last_clock_ns = Some(now);
+
+        if self.edges % EDGES_PER_BYTE == 0 {
+            self.timing.bytes += 1;
+            self.byte_end      = now;
+        }
+    }
+
+    fn ticks_to_ns(&self, ticks: u32) -> u32 {
+        (ticks as u64 * 1_000_000_000 / self.ticks_per_second as u64) as u32
+    }
+}
+
+
+const EDGES_PER_BYTE: u32 = 16;
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    WaitingForIdle,
+    Idle,
+    Selected,
+    Done,
+}
@@ -0,0 +1,97 @@
+//! SysTick as a free-running timer
+//!
+//! Benchmarks and other measurements use the SysTick timer as a free-running
+//! timer. As other code might use SysTick for other purposes, its previous
+//! configuration is saved when starting it, so it can be restored afterwards.
+
+
+use lpc8xx_hal::cortex_m::peripheral::SYST;
+
+
+/// The highest value of the SysTick timer
+///
+/// The SysTick reload register is 24 bits wide.
+pub const MAX_RELOAD: u32 = 0x00ff_ffff;
+
+
+// ENABLE and TICKINT bits in the SysTick control and status register
+const CSR_ENABLE:  u32 = 0x1;
+const CSR_TICKINT: u32 = 0x2;
+
+
+/// Starts SysTick as a free-running timer
+///
+/// Returns the previous configuration, which can be passed to [`restore`]. If
+/// SysTick already runs as a free-running timer, it is left alone, so anyone
+/// else using it as such isn't disturbed.
+///
+/// [`restore`]: fn.restore.html
+pub fn start(systick: &mut SYST) -> Saved {
+    let saved = Saved {
+        csr: systick.csr.read(),
+        rvr: systick.rvr.read(),
+    };
+
+    if !saved.is_free_running() {
+        systick.disable_interrupt();
+        systick.set_reload(MAX_RELOAD);
+        systick.clear_current();
+        systick.enable_counter();
+    }
+
+    saved
+}
+
+/// Restores the configuration of SysTick from before [`start`]
+///
+/// [`start`]: fn.start.html
+pub fn restore(systick: &mut SYST, saved: Saved) {
+    if saved.is_free_running() {
+        return;
+    }
+
+    systick.disable_counter();
+
+    // Sound, as we're writing back values that were read from the same
+    // registers.
+    unsafe { systick.rvr.write(saved.rvr) };
+    systick.clear_current();
+    unsafe { systick.csr.write(saved.csr) };
+}
+
+/// Returns the ticks a timer counting up advanced from `last` to `now`
+///
+/// `max` is the highest value the timer reaches before wrapping around to
+/// zero. Assumes the timer has wrapped around at most once in between.
+pub fn delta(last: u32, now: u32, max: u32) -> u32 {
+    match now >= last {
+        true  => now - last,
+        false => max - last + now + 1,
+    }
+}
+
+/// Returns the current value of the free-running SysTick timer, counting up
+pub fn now() -> u32 {
+    // The counter only loads the reload value on its first tick, so it might
+    // still be zero here.
+    match SYST::get_current() {
+        0       => 0,
+        current => MAX_RELOAD - current,
+    }
+}
+
+
+/// The configuration of SysTick before [`start`]
+///
+/// [`start`]: fn.start.html
+pub struct Saved {
+    csr: u32,
+    rvr: u32,
+}
+
+impl Saved {
+    fn is_free_running(&self) -> bool {
+        self.csr & (CSR_ENABLE | CSR_TICKINT) == CSR_ENABLE
+            && self.rvr == MAX_RELOAD
+    }
+}
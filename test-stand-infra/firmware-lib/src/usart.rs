@@ -1,12 +1,19 @@
 //! Interrupt-enabled USART API
 
 
+pub mod address;
+pub mod config;
+pub mod fault;
+pub mod frame;
+pub mod lin;
 pub mod rx;
+pub mod soak;
 pub mod tx;
 
 
 pub use self::{
     rx::{
+        ErrorCounters,
         RxIdle,
         RxInt,
     },
@@ -22,6 +29,7 @@ use lpc8xx_hal::{
     USART,
     usart::state::Enabled,
 };
+use protocol::usart::RxErrorCounts;
 
 
 /// Interrupt-enabled USART wrapper
@@ -38,14 +46,16 @@ use lpc8xx_hal::{
 /// [`RxIdle`]: rx/struct.RxIdle.html
 /// [`Tx`]: tx/struct.Tx.html
 pub struct Usart {
-    queue: spsc::Queue<u8, QUEUE_CAP>,
+    queue:  spsc::Queue<u8, QUEUE_CAP>,
+    errors: ErrorCounters,
 }
 
 impl Usart {
     /// Creates a new instance of `Usart`
     pub const fn new() -> Self {
         Self {
-            queue: spsc::Queue::new(),
+            queue:  spsc::Queue::new(),
+            errors: ErrorCounters::new(),
         }
     }
 
@@ -63,12 +73,15 @@ impl Usart {
         let (prod, cons) = self.queue.split();
 
         let rx_int = RxInt {
-            usart: usart.rx,
-            queue: prod,
+            usart:  usart.rx,
+            queue:  prod,
+            errors: &self.errors,
         };
         let rx_idle = RxIdle {
-            queue: cons,
-            buf:   Vec::new(),
+            queue:    cons,
+            buf:      Vec::new(),
+            errors:   &self.errors,
+            reported: RxErrorCounts::default(),
         };
         let tx = Tx {
             usart: usart.tx,
@@ -0,0 +1,122 @@
+//! Multiprocessor (9-bit) mode
+//!
+//! With 9 data bits configured, the most significant bit of a character marks
+//! it as an address. The HAL's USART API only handles 8-bit words, so this
+//! module accesses the USART's registers directly, like [`config`].
+//!
+//! [`config`]: ../config/index.html
+
+
+use heapless::Vec;
+use lpc8xx_hal::pac::usart0::RegisterBlock;
+
+
+/// The bit that marks a character as an address
+pub const ADDRESS_BIT: u16 = 0x100;
+
+/// The maximum number of addresses an [`AddressFilter`] can match
+///
+/// [`AddressFilter`]: struct.AddressFilter.html
+pub const MAX_ADDRESSES: usize = 8;
+
+
+/// Sends an address, followed by data
+///
+/// The USART must be configured for 9 data bits. Blocks until all characters
+/// have been written to the transmitter.
+pub fn send(usart: &RegisterBlock, address: u8, data: &[u8]) {
+    write(usart, address as u16 | ADDRESS_BIT);
+    for &b in data {
+        write(usart, b as u16);
+    }
+}
+
+fn write(usart: &RegisterBlock, word: u16) {
+    while usart.stat.read().txrdy().bit_is_clear() {}
+
+    // Sound, as the USART accepts any 9-bit value.
+    usart.txdat.write(|w| unsafe { w.txdat().bits(word) });
+}
+
+
+/// A character received in multiprocessor mode
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Word {
+    Address(u8),
+    Data(u8),
+}
+
+
+/// Receives data sent to one of multiple addresses
+///
+/// The USART can only match a single address in hardware. This filter uses
+/// address detection mode, which makes the USART ignore data until the filter
+/// clears it, after receiving a matching address.
+///
+/// Errors that occur while receiving are ignored.
+pub struct AddressFilter {
+    addresses: Vec<u8, MAX_ADDRESSES>,
+}
+
+impl AddressFilter {
+    /// Create a new filter for the given addresses
+    ///
+    /// Returns an error, if there are more than [`MAX_ADDRESSES`].
+    ///
+    /// [`MAX_ADDRESSES`]: constant.MAX_ADDRESSES.html
+    pub fn new(addresses: &[u8]) -> Result<Self, TooManyAddresses> {
+        let addresses = Vec::from_slice(addresses)
+            .map_err(|()| TooManyAddresses(addresses.len()))?;
+
+        Ok(Self { addresses })
+    }
+
+    /// Start filtering
+    ///
+    /// Received data is ignored, until a matching address is received.
+    pub fn start(&self, usart: &RegisterBlock) {
+        usart.ctl.modify(|_, w| w.addrdet().set_bit());
+    }
+
+    /// Stop filtering
+    ///
+    /// All received data is passed on again.
+    pub fn stop(&self, usart: &RegisterBlock) {
+        usart.ctl.modify(|_, w| w.addrdet().clear_bit());
+    }
+
+    /// Receive a character, if available
+    ///
+    /// Should be called from the interrupt handler, until it returns `None`.
+    /// Only returns matching addresses, and data sent to them.
+    pub fn receive(&self, usart: &RegisterBlock) -> Option<Word> {
+        while usart.stat.read().rxrdy().bit_is_set() {
+            let word = usart.rxdatstat.read().rxdat().bits();
+
+            if word & ADDRESS_BIT == 0 {
+                return Some(Word::Data(word as u8));
+            }
+
+            let address = word as u8;
+            match self.addresses.contains(&address) {
+                true => {
+                    usart.ctl.modify(|_, w| w.addrdet().clear_bit());
+                    return Some(Word::Address(address));
+                }
+                false => {
+                    // Ignore data until the next matching address.
+                    usart.ctl.modify(|_, w| w.addrdet().set_bit());
+                }
+            }
+        }
+
+        None
+    }
+}
+
+
+/// More addresses were passed to [`AddressFilter::new`] than it can match
+///
+/// [`AddressFilter::new`]: struct.AddressFilter.html#method.new
+#[derive(Debug)]
+pub struct TooManyAddresses(pub usize);
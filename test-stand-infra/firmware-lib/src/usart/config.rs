@@ -0,0 +1,146 @@
+//! Runtime configuration of USART line parameters
+//!
+//! LPC8xx HAL configures the line parameters of a USART once, when enabling
+//! it. This module changes them on an already enabled USART, by accessing its
+//! registers directly.
+
+
+use lpc8xx_hal::pac::usart0::RegisterBlock;
+use protocol::usart::{
+    ConfigError,
+    DataBits,
+    LineConfig,
+    Parity,
+    StopBits,
+};
+
+
+/// The multiplier that the firmwares configure FRG0 with
+pub const FRG0_MULT: u8 = 22;
+
+/// The divider that the firmwares configure FRG0 with
+pub const FRG0_DIV: u8 = 0xff;
+
+/// The frequency of FRG0, which clocks all asynchronous USARTs
+///
+/// FRO runs at 12 MHz, FRG0 divides that by `1 + MULT / (DIV + 1)`. Target and
+/// assistant both use this for the baud rate calculation, so their line
+/// configurations match.
+pub const USART_CLOCK_HZ: u32 =
+    12_000_000 * (FRG0_DIV as u32 + 1)
+        / (FRG0_DIV as u32 + 1 + FRG0_MULT as u32);
+
+
+/// Changes the line parameters of an enabled USART
+///
+/// `clock_hz` is the frequency of the USART's function clock. Waits until the
+/// USART has finished sending, then disables it, changes the configuration,
+/// and enables it again. The HAL's API for the USART keeps working afterwards.
+///
+/// Returns an error, if the baud rate can't be derived from `clock_hz`.
+pub fn configure(usart: &RegisterBlock, config: &LineConfig, clock_hz: u32)
+    -> Result<(), UnsupportedBaudRate>
+{
+    let (osrval, brgval) = divider(clock_hz, config.baud_rate)
+        .ok_or(UnsupportedBaudRate(config.baud_rate))?;
+
+    let datalen = match config.data_bits {
+        DataBits::Seven => 0x0,
+        DataBits::Eight => 0x1,
+        DataBits::Nine  => 0x2,
+    };
+    let paritysel = match config.parity {
+        Parity::None => 0x0,
+        Parity::Even => 0x2,
+        Parity::Odd  => 0x3,
+    };
+    let two_stop_bits = config.stop_bits == StopBits::Two;
+
+    while usart.stat.read().txidle().bit_is_clear() {}
+
+    usart.cfg.modify(|_, w| w.enable().bit(false));
+
+    // Sound, as all values have been selected from the ones listed in the
+    // user manual, section 17.6.
+    usart.cfg.modify(|_, w| unsafe {
+        w
+            .datalen().bits(datalen)
+            .paritysel().bits(paritysel)
+            .stoplen().bit(two_stop_bits)
+    });
+    usart.osr.write(|w| unsafe { w.osrval().bits(osrval) });
+    usart.brg.write(|w| unsafe { w.brgval().bits(brgval) });
+
+    usart.cfg.modify(|_, w| w.enable().bit(true));
+
+    Ok(())
+}
+
+
+/// Enables or disables RS-485 driver enable control on an enabled USART
+///
+/// While enabled, the USART drives its RTS output high for as long as it's
+/// sending, instead of using it for flow control. That's what the driver
+/// enable (DE) input of an RS-485 transceiver expects. RTS must be assigned to
+/// a pin, for this to have any effect.
+///
+/// Waits until the USART has finished sending before changing anything, like
+/// [`configure`].
+///
+/// [`configure`]: fn.configure.html
+pub fn set_driver_enable(usart: &RegisterBlock, enable: bool) {
+    while usart.stat.read().txidle().bit_is_clear() {}
+
+    usart.cfg.modify(|_, w| w.enable().bit(false));
+
+    // DE is released right after the last stop bit, as the turnaround delay
+    // (`OETA`) stays disabled.
+    usart.cfg.modify(|_, w|
+        w
+            .oesel().bit(enable)
+            .oepol().bit(enable)
+    );
+
+    usart.cfg.modify(|_, w| w.enable().bit(true));
+}
+
+
+/// Computes oversampling rate and baud rate divider for the given baud rate
+///
+/// Returns the values for the `OSRVAL` and `BRGVAL` fields that get closest to
+/// the requested baud rate, or `None`, if no combination is within 2% of it.
+pub fn divider(clock_hz: u32, baud_rate: u32) -> Option<(u8, u16)> {
+    let mut best: Option<(u32, u8, u16)> = None;
+
+    // The user manual allows oversampling rates from 5 to 16.
+    for osr in (5 ..= 16).rev() {
+        let sample_rate = clock_hz / osr as u32;
+        let brg = (sample_rate + baud_rate / 2) / baud_rate;
+
+        if brg == 0 || brg > 0x1_0000 {
+            continue;
+        }
+
+        let actual = sample_rate / brg;
+        let error  = Ord::max(actual, baud_rate) - Ord::min(actual, baud_rate);
+
+        if best.map(|(best_error, _, _)| error < best_error).unwrap_or(true) {
+            best = Some((error, osr - 1, (brg - 1) as u16));
+        }
+    }
+
+    best
+        .filter(|&(error, _, _)| error as u64 * 50 <= baud_rate as u64)
+        .map(|(_, osr, brg)| (osr, brg))
+}
+
+
+/// The requested baud rate can't be derived from the USART's clock
+#[derive(Debug)]
+pub struct UnsupportedBaudRate(pub u32);
+
+impl From<UnsupportedBaudRate> for ConfigError {
+    fn from(UnsupportedBaudRate(baud_rate): UnsupportedBaudRate) -> Self {
+        Self::UnsupportedBaudRate(baud_rate)
+    }
+}
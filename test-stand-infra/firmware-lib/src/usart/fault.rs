@@ -0,0 +1,134 @@
+//! Injection of faults into a USART connection
+//!
+//! Most faults are produced by temporarily changing the line parameters of the
+//! sending USART, so the receiver sees characters it doesn't expect. Like
+//! [`config`], this module accesses the USART's registers directly.
+//!
+//! [`config`]: ../config/index.html
+
+
+use lpc8xx_hal::{
+    cortex_m::asm,
+    pac::usart0::RegisterBlock,
+    usart,
+};
+use protocol::usart::{
+    DataBits,
+    Fault,
+    LineConfig,
+    Parity,
+    StopBits,
+};
+use void::ResultVoidExt;
+
+use super::{
+    Tx,
+    config::{
+        self,
+        UnsupportedBaudRate,
+    },
+};
+
+
+/// Injects a fault into the connection of an enabled USART
+///
+/// `config` are the line parameters that the receiver expects. `tx` must be
+/// the transmitter of the USART that `usart` points to. `clock_hz` is the
+/// frequency of the USART's function clock, `core_clock_hz` the frequency of
+/// the core clock, which is used to time breaks.
+///
+/// Blocks until the fault has been injected. The USART is configured according
+/// to `config` afterwards.
+pub fn inject<I, Mode>(
+    tx:            &mut Tx<I, Mode>,
+    usart:         &RegisterBlock,
+    config:        &LineConfig,
+    fault:         &Fault,
+    clock_hz:      u32,
+    core_clock_hz: u32,
+)
+    -> Result<(), InjectError>
+    where I: usart::Instance
+{
+    match *fault {
+        Fault::Parity => {
+            let parity = match config.parity {
+                Parity::Even => Parity::Odd,
+                Parity::Odd  => Parity::Even,
+                Parity::None => return Err(InjectError::NoParity),
+            };
+
+            let faulty = LineConfig { parity, .. *config };
+            send_with(tx, usart, &faulty, config, &[0x55], clock_hz)?;
+        }
+        Fault::Framing => {
+            // At half the baud rate, the receiver samples every bit twice. The
+            // start bit and the five least significant data bits of this
+            // character are low, so the line is still low when the receiver
+            // expects a stop bit, regardless of its data length or parity.
+            // That's 12 bit times, not long enough to be detected as a break.
+            let faulty = LineConfig {
+                baud_rate: config.baud_rate / 2,
+                data_bits: DataBits::Eight,
+                parity:    Parity::None,
+                stop_bits: StopBits::One,
+            };
+            send_with(tx, usart, &faulty, config, &[0xe0], clock_hz)?;
+        }
+        Fault::Break { duration_us } => {
+            // Sending a break while a character is being sent would corrupt
+            // it.
+            while usart.stat.read().txidle().bit_is_clear() {}
+
+            usart.ctl.modify(|_, w| w.txbrken().set_bit());
+            asm::delay(
+                (duration_us as u64 * core_clock_hz as u64 / 1_000_000) as u32
+            );
+            usart.ctl.modify(|_, w| w.txbrken().clear_bit());
+        }
+        Fault::BaudRate { baud_rate, data } => {
+            let faulty = LineConfig { baud_rate, .. *config };
+            send_with(tx, usart, &faulty, config, data, clock_hz)?;
+        }
+    }
+
+    Ok(())
+}
+
+
+fn send_with<I, Mode>(
+    tx:       &mut Tx<I, Mode>,
+    usart:    &RegisterBlock,
+    faulty:   &LineConfig,
+    config:   &LineConfig,
+    data:     &[u8],
+    clock_hz: u32,
+)
+    -> Result<(), UnsupportedBaudRate>
+    where I: usart::Instance
+{
+    config::configure(usart, faulty, clock_hz)?;
+    tx.send_raw(data)
+        .void_unwrap();
+
+    // `configure` waits until all data has been sent, before restoring the
+    // original line parameters.
+    config::configure(usart, config, clock_hz)
+}
+
+
+/// Error injecting a fault
+#[derive(Debug)]
+pub enum InjectError {
+    /// A parity fault was requested, but the receiver expects no parity bit
+    NoParity,
+
+    /// The baud rate required for the fault is not supported
+    UnsupportedBaudRate(UnsupportedBaudRate),
+}
+
+impl From<UnsupportedBaudRate> for InjectError {
+    fn from(err: UnsupportedBaudRate) -> Self {
+        Self::UnsupportedBaudRate(err)
+    }
+}
@@ -0,0 +1,144 @@
+//! Splitting of received data into frames
+//!
+//! The LPC8xx USARTs can't detect an idle line in hardware, so this module
+//! detects the end of a frame by watching how much data has been received, and
+//! for how long that amount hasn't changed.
+
+
+use protocol::usart::{
+    FrameEnd,
+    LineConfig,
+    RxFraming,
+};
+
+use crate::systick;
+
+
+/// The number of character times the line must be idle, to end a frame
+///
+/// Characters that are sent back-to-back follow each other within one
+/// character time. Since the caller polls the amount of received data, it
+/// might notice one character late and the next one early, so the timeout
+/// needs some margin.
+const IDLE_CHARS: u64 = 2;
+
+
+/// Detects the end of a frame that is being received
+///
+/// Timing is based on a free-running timer, like in [`Benchmark`]. The caller
+/// regularly passes the current timer value and the number of bytes received
+/// since the start of the frame. This has to happen at least once per timer
+/// period, or elapsed periods will be missed.
+///
+/// If the caller is slow to notice new data, a frame might end later than it
+/// should. It never ends before the timeout has actually expired.
+///
+/// [`Benchmark`]: ../../benchmark/struct.Benchmark.html
+pub struct FrameDetector {
+    capacity:  usize,
+    timeout:   u32,
+    end:       FrameEnd,
+    timer_max: u32,
+
+    received:   usize,
+    idle:       u32,
+    last_timer: Option<u32>,
+}
+
+impl FrameDetector {
+    /// Create a new instance of `FrameDetector`
+    ///
+    /// `config` are the line parameters of the receiving USART, `capacity` the
+    /// size of the receive buffer. `ticks_per_second` is the frequency of the
+    /// timer, `timer_max` the highest value it reaches before wrapping around.
+    ///
+    /// Returns `None`, if `framing` doesn't split data into frames.
+    pub fn new(
+        framing:          &RxFraming,
+        config:           &LineConfig,
+        capacity:         usize,
+        ticks_per_second: u32,
+        timer_max:        u32,
+    )
+        -> Option<Self>
+    {
+        let (timeout, end) = match *framing {
+            RxFraming::Chunks => {
+                return None;
+            }
+            RxFraming::Idle => {
+                let timeout = IDLE_CHARS
+                    * config.bits_per_char() as u64
+                    * ticks_per_second as u64
+                    / config.baud_rate as u64;
+                (timeout, FrameEnd::Idle)
+            }
+            RxFraming::CharTimeout { timeout_us } => {
+                let timeout = timeout_us as u64
+                    * ticks_per_second as u64
+                    / 1_000_000;
+                (timeout, FrameEnd::Timeout)
+            }
+        };
+
+        // A timeout that doesn't fit is longer than anything a test waits for,
+        // so saturating it doesn't change the result.
+        let timeout = Ord::min(timeout, u32::MAX as u64) as u32;
+
+        Some(
+            Self {
+                capacity,
+                timeout,
+                end,
+                timer_max,
+
+                received:   0,
+                idle:       0,
+                last_timer: None,
+            }
+        )
+    }
+
+    /// Checks whether the current frame has ended
+    ///
+    /// `received` is the number of bytes received since the start of the
+    /// frame. Returns the reason the frame ended, if it has. The caller must
+    /// call [`reset`] before starting to receive the next frame.
+    ///
+    /// [`reset`]: #method.reset
+    pub fn update(&mut self, received: usize, timer: u32) -> Option<FrameEnd> {
+        if received >= self.capacity {
+            return Some(FrameEnd::BufferFull);
+        }
+
+        if received != self.received {
+            self.received   = received;
+            self.idle       = 0;
+            self.last_timer = Some(timer);
+            return None;
+        }
+
+        // Nothing received yet, so there's no frame that could end.
+        let last = match self.last_timer {
+            Some(last) => last,
+            None       => return None,
+        };
+
+        let delta = systick::delta(last, timer, self.timer_max);
+
+        self.idle       = self.idle.saturating_add(delta);
+        self.last_timer = Some(timer);
+
+        match self.idle > self.timeout {
+            true  => Some(self.end),
+            false => None,
+        }
+    }
+
+    /// Prepares for receiving the next frame
+    pub fn reset(&mut self) {
+        self.received   = 0;
+        self.idle       = 0;
+        self.last_timer = None;
+    }
+}
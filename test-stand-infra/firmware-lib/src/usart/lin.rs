@@ -0,0 +1,143 @@
+//! LIN support
+//!
+//! A LIN frame starts with a break, followed by regular characters: the sync
+//! byte, the protected identifier (PID), and, after a short pause, the response
+//! consisting of data bytes and a checksum. The firmware doesn't compute PIDs
+//! or checksums. The host does that, and passes the finished bytes.
+
+
+use heapless::Vec;
+use lpc8xx_hal::{
+    cortex_m::asm,
+    pac::usart0::RegisterBlock,
+};
+use protocol::usart::{
+    LIN_SYNC,
+    LineConfig,
+};
+
+
+/// The length of the break, in bit times
+///
+/// The LIN specification requires at least 13.
+pub const BREAK_BITS: u32 = 13;
+
+/// The maximum length of a response
+///
+/// A response consists of up to 8 data bytes and the checksum.
+pub const MAX_RESPONSE: usize = 9;
+
+
+/// Sends a LIN break on an enabled USART
+///
+/// `config` are the line parameters of the USART, `core_clock_hz` the
+/// frequency of the core clock, which is used to time the break. Also waits
+/// for the break delimiter, so data can be sent right away.
+pub fn send_break(
+    usart:         &RegisterBlock,
+    config:        &LineConfig,
+    core_clock_hz: u32,
+) {
+    let bit_cycles = core_clock_hz / config.baud_rate;
+
+    // Sending a break while a character is being sent would corrupt it.
+    while usart.stat.read().txidle().bit_is_clear() {}
+
+    usart.ctl.modify(|_, w| w.txbrken().set_bit());
+    asm::delay(BREAK_BITS * bit_cycles);
+    usart.ctl.modify(|_, w| w.txbrken().clear_bit());
+
+    // The break delimiter must be at least one bit long.
+    asm::delay(bit_cycles);
+}
+
+
+/// Responds to LIN headers, as a slave
+///
+/// The caller passes breaks to [`on_break`] and received characters to
+/// [`receive`], in the order they were received.
+///
+/// [`on_break`]: #method.on_break
+/// [`receive`]: #method.receive
+pub struct LinSlave {
+    state:    State,
+    response: Option<(u8, Vec<u8, MAX_RESPONSE>)>,
+}
+
+impl LinSlave {
+    /// Create a new instance of `LinSlave`, which doesn't respond to anything
+    pub fn new() -> Self {
+        Self {
+            state:    State::WaitingForBreak,
+            response: None,
+        }
+    }
+
+    /// Sets the response to headers with the given protected identifier
+    ///
+    /// `data` must include the checksum. Replaces any previous response. If
+    /// `data` is empty, the slave no longer responds.
+    pub fn set_response(&mut self, pid: u8, data: &[u8])
+        -> Result<(), ResponseTooLong>
+    {
+        if data.is_empty() {
+            self.response = None;
+            return Ok(());
+        }
+
+        let data = Vec::from_slice(data)
+            .map_err(|()| ResponseTooLong(data.len()))?;
+        self.response = Some((pid, data));
+
+        Ok(())
+    }
+
+    /// Notify the slave that a break has been received
+    ///
+    /// Starts a new frame, aborting the current one, if any.
+    pub fn on_break(&mut self) {
+        self.state = State::WaitingForSync;
+    }
+
+    /// Notify the slave that a character has been received
+    ///
+    /// Returns the response that needs to be sent, if this character completed
+    /// a header the slave responds to.
+    pub fn receive(&mut self, b: u8) -> Option<&[u8]> {
+        match self.state {
+            State::WaitingForSync => {
+                self.state = match b {
+                    LIN_SYNC => State::WaitingForPid,
+                    _    => State::WaitingForBreak,
+                };
+                None
+            }
+            State::WaitingForPid => {
+                self.state = State::WaitingForBreak;
+
+                match &self.response {
+                    Some((pid, data)) if *pid == b => Some(data),
+                    _                               => None,
+                }
+            }
+            State::WaitingForBreak => {
+                None
+            }
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    WaitingForBreak,
+    WaitingForSync,
+    WaitingForPid,
+}
+
+
+/// The response is longer than [`MAX_RESPONSE`]
+///
+/// [`MAX_RESPONSE`]: constant.MAX_RESPONSE.html
+#[derive(Debug)]
+pub struct ResponseTooLong(pub usize);
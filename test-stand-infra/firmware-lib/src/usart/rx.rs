@@ -1,6 +1,11 @@
 //! Receiving part of the interrupt-enabled USART API
 
 
+use core::sync::atomic::{
+    AtomicU32,
+    Ordering,
+};
+
 use heapless::{
     Vec,
     spsc,
@@ -12,6 +17,10 @@ use lpc8xx_hal::{
         state::Enabled,
     },
 };
+use protocol::usart::{
+    RxError,
+    RxErrorCounts,
+};
 use serde::Deserialize;
 
 use super::QUEUE_CAP;
@@ -23,8 +32,9 @@ use super::QUEUE_CAP;
 ///
 /// [`Usart::init`]: ../struct.Usart.html#method.init
 pub struct RxInt<'r, I, Mode> {
-    pub usart: usart::Rx<I, Enabled<u8, Mode>>,
-    pub queue: spsc::Producer<'r, u8, QUEUE_CAP>,
+    pub usart:  usart::Rx<I, Enabled<u8, Mode>>,
+    pub queue:  spsc::Producer<'r, u8, QUEUE_CAP>,
+    pub errors: &'r ErrorCounters,
 }
 
 impl<I, Mode> RxInt<'_, I, Mode>
@@ -38,20 +48,30 @@ impl<I, Mode> RxInt<'_, I, Mode>
     /// can be processed by the corresponding [`RxIdle`] instance without any
     /// time pressure.
     ///
+    /// Errors are counted, so they can be picked up by [`RxIdle::new_errors`].
+    /// If the queue is full, the received byte is dropped. Returns on the
+    /// first error. If more data is available, the interrupt will fire again.
+    ///
     /// [`RxIdle`]: struct.RxIdle.html
+    /// [`RxIdle::new_errors`]: struct.RxIdle.html#method.new_errors
     pub fn receive(&mut self) -> Result<(), ReceiveError> {
         loop {
-            match self.usart.read() {
+            let result = match self.usart.read() {
                 Ok(b) => {
                     self.queue.enqueue(b)
-                        .map_err(|_| ReceiveError::QueueFull)?;
+                        .map_err(|_| ReceiveError::QueueFull)
                 }
                 Err(nb::Error::WouldBlock) => {
                     return Ok(());
                 }
                 Err(nb::Error::Other(err)) => {
-                    return Err(ReceiveError::Usart(err));
+                    Err(ReceiveError::Usart(err))
                 }
+            };
+
+            if let Err(err) = result {
+                self.errors.count(&err);
+                return Err(err);
             }
         }
     }
@@ -67,11 +87,30 @@ impl<I, Mode> RxInt<'_, I, Mode>
 ///
 /// [`Usart::init`]: ../struct.Usart.html#method.init
 pub struct RxIdle<'r> {
-    pub queue: spsc::Consumer<'r, u8, QUEUE_CAP>,
-    pub buf:   Vec<u8, QUEUE_CAP>,
+    pub queue:    spsc::Consumer<'r, u8, QUEUE_CAP>,
+    pub buf:      Vec<u8, QUEUE_CAP>,
+    pub errors:   &'r ErrorCounters,
+    pub reported: RxErrorCounts,
 }
 
 impl RxIdle<'_> {
+    /// Returns the errors that occurred since the last call, if any
+    ///
+    /// Errors are counted by the corresponding [`RxInt`] instance.
+    ///
+    /// [`RxInt`]: struct.RxInt.html
+    pub fn new_errors(&mut self) -> Option<RxErrorCounts> {
+        let counts = self.errors.load();
+        let errors = counts.since(&self.reported);
+        self.reported = counts;
+
+        if errors.is_empty() {
+            return None;
+        }
+
+        Some(errors)
+    }
+
     /// Indicates whether data has been received that can be processed
     pub fn can_process(&self) -> bool {
         self.queue.ready()
@@ -152,6 +191,64 @@ impl RxIdle<'_> {
 }
 
 
+/// Counts the errors that occur while receiving
+///
+/// Shared between [`RxInt`], which increments the counters, and [`RxIdle`],
+/// which reads them. Since there's only one writer, plain loads and stores are
+/// sufficient, which is fortunate, as Cortex-M0+ doesn't support atomic
+/// read-modify-write operations.
+///
+/// [`RxInt`]: struct.RxInt.html
+/// [`RxIdle`]: struct.RxIdle.html
+pub struct ErrorCounters {
+    framing:    AtomicU32,
+    noise:      AtomicU32,
+    overrun:    AtomicU32,
+    parity:     AtomicU32,
+    queue_full: AtomicU32,
+}
+
+impl ErrorCounters {
+    /// Creates a new instance of `ErrorCounters`, with all counters at zero
+    pub const fn new() -> Self {
+        Self {
+            framing:    AtomicU32::new(0),
+            noise:      AtomicU32::new(0),
+            overrun:    AtomicU32::new(0),
+            parity:     AtomicU32::new(0),
+            queue_full: AtomicU32::new(0),
+        }
+    }
+
+    /// Returns a snapshot of all counters
+    pub fn load(&self) -> RxErrorCounts {
+        RxErrorCounts {
+            framing:    self.framing.load(Ordering::Relaxed),
+            noise:      self.noise.load(Ordering::Relaxed),
+            overrun:    self.overrun.load(Ordering::Relaxed),
+            parity:     self.parity.load(Ordering::Relaxed),
+            queue_full: self.queue_full.load(Ordering::Relaxed),
+        }
+    }
+
+    fn count(&self, err: &ReceiveError) {
+        let counter = match err.rx_error() {
+            Some(RxError::Framing) => &self.framing,
+            Some(RxError::Noise)   => &self.noise,
+            Some(RxError::Overrun) => &self.overrun,
+            Some(RxError::Parity)  => &self.parity,
+            Some(RxError::Break)   => return,
+            None                   => &self.queue_full,
+        };
+
+        counter.store(
+            counter.load(Ordering::Relaxed).wrapping_add(1),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+
 /// Error receiving data from USART
 #[derive(Debug)]
 pub enum ReceiveError {
@@ -162,6 +259,31 @@ pub enum ReceiveError {
     Usart(usart::Error<u8>),
 }
 
+impl ReceiveError {
+    /// Returns the protocol representation of the error
+    ///
+    /// Returns `None`, if the error was not detected by the USART itself.
+    pub fn rx_error(&self) -> Option<RxError> {
+        match self {
+            Self::QueueFull => {
+                None
+            }
+            Self::Usart(usart::Error::Framing { .. }) => {
+                Some(RxError::Framing)
+            }
+            Self::Usart(usart::Error::Noise { .. }) => {
+                Some(RxError::Noise)
+            }
+            Self::Usart(usart::Error::Overrun { .. }) => {
+                Some(RxError::Overrun)
+            }
+            Self::Usart(usart::Error::Parity { .. }) => {
+                Some(RxError::Parity)
+            }
+        }
+    }
+}
+
 /// Error processing received USART data
 #[derive(Debug)]
 pub enum ProcessError<E> {
@@ -0,0 +1,145 @@
+//! Soak testing of hardware flow control
+//!
+//! The receiving node drives the sender's CTS input. It toggles that signal at
+//! random intervals, and also stops the sender while its receive queue is
+//! nearly full, like a receiver's RTS output would.
+
+
+use protocol::usart::{
+    FlowControlSoak,
+    FlowControlSoakResult,
+};
+
+use crate::systick;
+
+
+/// Decides when to allow the sender to send
+///
+/// Timing is based on a free-running timer, like in [`Benchmark`]. The idle
+/// loop calls [`update`] regularly, the interrupt handler that receives data
+/// calls [`check_queue`]. The caller is responsible for setting the flow
+/// control signal accordingly.
+///
+/// [`Benchmark`]: ../../benchmark/struct.Benchmark.html
+/// [`update`]: #method.update
+/// [`check_queue`]: #method.check_queue
+pub struct Soak {
+    min_ticks:  u32,
+    max_ticks:  u32,
+    timer_max:  u32,
+    high_water: usize,
+    low_water:  usize,
+
+    rng:        u32,
+    remaining:  u32,
+    last_timer: Option<u32>,
+    enabled:    bool,
+    throttled:  bool,
+    result:     FlowControlSoakResult,
+}
+
+impl Soak {
+    /// Create a new instance of `Soak`
+    ///
+    /// The sender is stopped, once the receive queue holds `high_water`
+    /// bytes, until it has been drained to `low_water` bytes.
+    /// `ticks_per_second` is the frequency of the timer, `timer_max` the
+    /// highest value it reaches before wrapping around.
+    pub fn new(
+        soak:             &FlowControlSoak,
+        high_water:       usize,
+        low_water:        usize,
+        ticks_per_second: u32,
+        timer_max:        u32,
+    )
+        -> Self
+    {
+        // Saturates, as with a fast timer, a long interval might not fit.
+        let to_ticks = |us: u32| {
+            let ticks = us as u64 * ticks_per_second as u64 / 1_000_000;
+            Ord::min(ticks, u32::MAX as u64) as u32
+        };
+
+        let mut soak = Self {
+            min_ticks: to_ticks(soak.min_interval_us),
+            max_ticks: to_ticks(soak.max_interval_us),
+            timer_max,
+            high_water,
+            low_water,
+
+            // Xorshift gets stuck on zero.
+            rng:        match soak.seed { 0 => 1, seed => seed },
+            remaining:  0,
+            last_timer: None,
+            enabled:    true,
+            throttled:  false,
+            result:     FlowControlSoakResult {
+                toggles:   0,
+                throttled: 0,
+            },
+        };
+        soak.remaining = soak.next_interval();
+
+        soak
+    }
+
+    /// Checks the fill level of the receive queue
+    ///
+    /// Should be called from the interrupt handler, after receiving. Returns
+    /// `true`, if the sender must be stopped right away.
+    pub fn check_queue(&mut self, queue_len: usize) -> bool {
+        if !self.throttled && queue_len >= self.high_water {
+            self.throttled         = true;
+            self.result.throttled += 1;
+            return true;
+        }
+
+        false
+    }
+
+    /// Advances the random toggling
+    ///
+    /// Returns whether the sender is allowed to send.
+    pub fn update(&mut self, timer: u32, queue_len: usize) -> bool {
+        if self.throttled && queue_len <= self.low_water {
+            self.throttled = false;
+        }
+
+        if let Some(last) = self.last_timer {
+            let mut delta = systick::delta(last, timer, self.timer_max);
+
+            // Several intervals might have passed, if the caller was busy.
+            while delta >= self.remaining {
+                delta -= self.remaining;
+
+                self.enabled         = !self.enabled;
+                self.result.toggles += 1;
+                self.remaining       = self.next_interval();
+            }
+            self.remaining -= delta;
+        }
+        self.last_timer = Some(timer);
+
+        self.enabled && !self.throttled
+    }
+
+    /// Returns the result of the soak test so far
+    pub fn result(&self) -> FlowControlSoakResult {
+        self.result
+    }
+
+    fn next_interval(&mut self) -> u32 {
+        // Xorshift32, as described by George Marsaglia
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+
+        // Computed in `u64`, as the range covers all of `u32`, if `min_ticks`
+        // is zero and `max_ticks` is `u32::MAX`.
+        let range    = self.max_ticks.saturating_sub(self.min_ticks) as u64 + 1;
+        let interval = self.min_ticks + (self.rng as u64 % range) as u32;
+
+        // An interval of zero would toggle forever.
+        Ord::max(interval, 1)
+    }
+}
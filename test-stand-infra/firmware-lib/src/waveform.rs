@@ -0,0 +1,114 @@
+//! Waveform generation API
+
+
+use protocol::pin::{
+    Level,
+    Waveform,
+};
+
+
+/// Plays a waveform, one step at a time
+///
+/// Converts a [`Waveform`] into a sequence of levels, each with a duration in
+/// timer ticks. `Player` doesn't know anything about timers or pins. The caller
+/// is responsible for setting the pin level and waiting for the duration,
+/// before asking for the next step.
+pub struct Player {
+    waveform:         Waveform,
+    ticks_per_second: u32,
+    max_ticks:        u32,
+
+    index:     usize,
+    level:     Level,
+    remaining: u32,
+}
+
+impl Player {
+    /// Create a new instance of `Player`
+    ///
+    /// `ticks_per_second` is the frequency of the timer that is used to time
+    /// the steps. `max_ticks` is the longest duration the timer can handle in
+    /// one go. Longer steps are split into multiple steps of the same level.
+    pub fn new(waveform: Waveform, ticks_per_second: u32, max_ticks: u32)
+        -> Self
+    {
+        Self {
+            waveform,
+            ticks_per_second,
+            max_ticks,
+
+            index:     0,
+            level:     Level::Low,
+            remaining: 0,
+        }
+    }
+
+    /// Returns the next step of the waveform
+    ///
+    /// Returns the level the pin should be set to, and the number of timer
+    /// ticks it should stay at that level. The number of ticks is never zero.
+    ///
+    /// Returns `None`, if the waveform has ended.
+    pub fn next(&mut self) -> Option<(Level, u32)> {
+        if self.remaining == 0 {
+            let (level, ticks) = self.next_step()?;
+
+            self.level     = level;
+            self.remaining = ticks.max(1);
+        }
+
+        let ticks = Ord::min(self.remaining, self.max_ticks);
+        self.remaining -= ticks;
+
+        Some((self.level, ticks))
+    }
+
+    fn next_step(&mut self) -> Option<(Level, u32)> {
+        match &self.waveform {
+            Waveform::Steps { steps, repeat } => {
+                if self.index >= steps.len() {
+                    if !*repeat || steps.is_empty() {
+                        return None;
+                    }
+                    self.index = 0;
+                }
+
+                let step = steps[self.index];
+                self.index += 1;
+
+                let ticks = self.us_to_ticks(step.duration_us);
+                Some((step.level, ticks))
+            }
+            &Waveform::Square { frequency_hz, duty_cycle_percent } => {
+                if frequency_hz == 0 {
+                    return None;
+                }
+
+                let period = self.ticks_per_second / frequency_hz;
+                let duty   = Ord::min(duty_cycle_percent, 100) as u64;
+                let high   = (period as u64 * duty / 100) as u32;
+                let low    = period - high;
+
+                // A duty cycle of 0% or 100% results in a constant level. Don't
+                // toggle the pin for the zero-length phase in that case.
+                if high == 0 {
+                    return Some((Level::Low, period));
+                }
+                if low == 0 {
+                    return Some((Level::High, period));
+                }
+
+                self.index = (self.index + 1) % 2;
+                match self.index {
+                    1 => Some((Level::High, high)),
+                    _ => Some((Level::Low,  low)),
+                }
+            }
+        }
+    }
+
+    fn us_to_ticks(&self, us: u32) -> u32 {
+        let ticks = us as u64 * self.ticks_per_second as u64 / 1_000_000;
+        Ord::min(ticks, u32::MAX as u64) as u32
+    }
+}
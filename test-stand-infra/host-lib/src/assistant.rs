@@ -1,59 +1,135 @@
-use std::time::{
-    Duration,
-    Instant,
+use std::{
+    collections::HashMap,
+    time::{
+        Duration,
+        Instant,
+    },
 };
 
 use protocol::{
     AssistantToHost,
     HostToAssistant,
-    InputPin,
-    OutputPin,
+    PinId,
     UsartMode,
+    i2c,
     pin,
+    spi,
+    usart,
 };
 
 use crate::{
     conn::{
+        self,
         Conn,
         ConnReceiveError,
         ConnSendError,
+        Route,
     },
+    lin,
     pin::{
+        LatencyMeasurement,
+        MeasureLatencyError,
+        MeasureSignalError,
         Pin,
+        PinMap,
         ReadLevelError,
+        SignalMeasurement,
+        UnknownPinError,
     },
 };
 
 
 /// The connection to the test assistant
+///
+/// Pins are resolved by name, whenever they're used. A test stand whose
+/// assistant doesn't provide a pin (or an alias for it) only fails the tests
+/// that use that pin.
 pub struct Assistant {
     conn: Conn,
-    pin5: Pin<OutputPin>,
-    red_led: Pin<OutputPin>,
-    green_led: Pin<InputPin>,
-    blue_led: Pin<InputPin>,
-    pwm: Pin<InputPin>,
-    cts: Pin<OutputPin>,
-    rts: Pin<InputPin>,
+    pins: PinMap,
 }
 
 impl Assistant {
-    pub fn new(conn: Conn) -> Self {
-        Self {
-            conn,
-            pin5: Pin::new(OutputPin::Pin5),
-            red_led: Pin::new(OutputPin::Red),
-            green_led: Pin::new(InputPin::Green),
-            blue_led: Pin::new(InputPin::Blue),
-            pwm: Pin::new(InputPin::Pwm),
-            cts: Pin::new(OutputPin::Cts),
-            rts: Pin::new(InputPin::Rts),
+    /// Connect to the assistant
+    ///
+    /// Performs the handshake, to learn about the pins the assistant provides.
+    /// `aliases` maps the pin names used here to the names the assistant
+    /// reports (see [`Config::assistant_pins`]).
+    ///
+    /// [`Config::assistant_pins`]: ../config/struct.Config.html#structfield.assistant_pins
+    pub fn new(mut conn: Conn, aliases: HashMap<String, String>)
+        -> Result<Self, AssistantInitError>
+    {
+        conn.set_router(route);
+        let pins = Self::handshake(&mut conn, aliases)?;
+
+        Ok(
+            Self {
+                conn,
+                pins,
+            }
+        )
+    }
+
+    fn handshake(conn: &mut Conn, aliases: HashMap<String, String>)
+        -> Result<PinMap, AssistantInitError>
+    {
+        conn.send(&HostToAssistant::Handshake)
+            .map_err(|err| AssistantInitError::Send(err))?;
+
+        let mut pins    = PinMap::new(aliases);
+        let mut pending = None;
+
+        while pending != Some(0) {
+            let mut buf = Vec::new();
+            let message = conn
+                .receive_from::<AssistantToHost>(
+                    Route::Other,
+                    Duration::from_millis(500),
+                    &mut buf,
+                );
+
+            let message = match message {
+                Ok(message) => {
+                    message
+                }
+                // A previous test run might have left some errors behind.
+                Err(err) if err.diagnostics().is_some() => {
+                    continue;
+                }
+                Err(err) => {
+                    return Err(AssistantInitError::Receive(err));
+                }
+            };
+
+            match (message, pending) {
+                (AssistantToHost::HandshakeResult { pins: n }, None) => {
+                    pending = Some(n);
+                }
+                (AssistantToHost::HandshakePin(info), Some(n)) => {
+                    pins.add(info);
+                    pending = Some(n - 1);
+                }
+                // A previous test run might have left some messages behind.
+                (AssistantToHost::UsartReceive { .. }, _) => {
+                    continue;
+                }
+                (message, _) => {
+                    return Err(
+                        AssistantInitError::UnexpectedMessage(
+                            format!("{:?}", message)
+                        )
+                    );
+                }
+            }
         }
+
+        Ok(pins)
     }
 
     /// Instruct the assistant to set pin 5 high
     pub fn set_pin_5_high(&mut self) -> Result<(), AssistantError> {
-        self.pin5
+        self.pins.resolve("pin5")?
             .set_level::<HostToAssistant>(
                 pin::Level::High,
                 &mut self.conn,
@@ -63,7 +139,7 @@ impl Assistant {
 
     /// Instruct the assistant to set pin 5 low
     pub fn set_pin_5_low(&mut self) -> Result<(), AssistantError> {
-        self.pin5
+        self.pins.resolve("pin5")?
             .set_level::<HostToAssistant>(
                 pin::Level::Low,
                 &mut self.conn,
@@ -73,7 +149,7 @@ impl Assistant {
 
     /// Instruct the assistant to set the target's input pin high
     pub fn set_pin_high(&mut self) -> Result<(), AssistantError> {
-        self.red_led
+        self.pins.resolve("red")?
             .set_level::<HostToAssistant>(
                 pin::Level::High,
                 &mut self.conn,
@@ -83,7 +159,7 @@ impl Assistant {
 
     /// Instruct the assistant to set the target's input pin low
     pub fn set_pin_low(&mut self) -> Result<(), AssistantError> {
-        self.red_led
+        self.pins.resolve("red")?
             .set_level::<HostToAssistant>(
                 pin::Level::Low,
                 &mut self.conn,
@@ -93,7 +169,7 @@ impl Assistant {
 
     /// Instruct the assistant to disable CTS
     pub fn disable_cts(&mut self) -> Result<(), AssistantError> {
-        self.cts
+        self.pins.resolve("cts")?
             .set_level::<HostToAssistant>(
                 pin::Level::High,
                 &mut self.conn,
@@ -103,7 +179,7 @@ impl Assistant {
 
     /// Instruct the assistant to enable CTS
     pub fn enable_cts(&mut self) -> Result<(), AssistantError> {
-        self.cts
+        self.pins.resolve("cts")?
             .set_level::<HostToAssistant>(
                 pin::Level::Low,
                 &mut self.conn,
@@ -111,11 +187,105 @@ impl Assistant {
             .map_err(|err| AssistantError::SetPinLow(err))
     }
 
+    /// Instruct the assistant to change the configuration of a pin
+    ///
+    /// `pin` is the name of the pin, as reported by the assistant.
+    pub fn configure_pin(&mut self, pin: &str, config: pin::Config)
+        -> Result<(), AssistantError>
+    {
+        self.pins.resolve(pin)?
+            .configure::<HostToAssistant>(config, &mut self.conn)
+            .map_err(|err| AssistantError::ConfigurePin(err))
+    }
+
+    /// Instruct the assistant to change the line parameters of a USART
+    ///
+    /// `mode` selects the USART that is used to communicate with the target in
+    /// that mode. Waits for the assistant to confirm the new configuration.
+    pub fn configure_usart(&mut self,
+        mode:   UsartMode,
+        config: usart::LineConfig,
+    )
+        -> Result<(), AssistantError>
+    {
+        self.configure_usart_inner(mode, config)
+            .map_err(|err| AssistantError::ConfigureUsart(err))
+    }
+
+    fn configure_usart_inner(&mut self,
+        mode:   UsartMode,
+        config: usart::LineConfig,
+    )
+        -> Result<(), AssistantConfigureUsartError>
+    {
+        self.conn
+            .send(&HostToAssistant::ConfigureUsart { mode, config })
+            .map_err(|err| AssistantConfigureUsartError::Send(err))?;
+
+        let timeout = Duration::from_millis(50);
+
+        let mut buf = Vec::new();
+        let message = self.conn
+            .receive_from::<AssistantToHost>(Route::Other, timeout, &mut buf)
+            .map_err(|err| AssistantConfigureUsartError::Receive(err))?;
+
+        match message {
+            AssistantToHost::UsartConfigured => {
+                Ok(())
+            }
+            AssistantToHost::UsartConfigError(err) => {
+                Err(AssistantConfigureUsartError::Config(err))
+            }
+            message => {
+                Err(
+                    AssistantConfigureUsartError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the assistant to reconfigure its SPI slave
+    ///
+    /// The slave must be configured like the target's SPI master, or the data
+    /// won't be transferred correctly. The clock divider is ignored.
+    pub fn configure_spi(&mut self, config: spi::Config)
+        -> Result<(), AssistantError>
+    {
+        self.conn
+            .send(&HostToAssistant::ConfigureSpi(config))
+            .map_err(|err| AssistantError::ConfigureSpi(err))
+    }
+
+    /// Instruct the assistant to play a waveform on one of its output pins
+    ///
+    /// The waveform keeps playing until it ends, or until [`stop_waveform`] is
+    /// called for the same pin.
+    ///
+    /// [`stop_waveform`]: #method.stop_waveform
+    pub fn generate_waveform(&mut self, pin: &str, waveform: pin::Waveform)
+        -> Result<(), AssistantError>
+    {
+        self.pins.resolve(pin)?
+            .generate_waveform::<HostToAssistant>(waveform, &mut self.conn)
+            .map_err(|err| AssistantError::GenerateWaveform(err))
+    }
+
+    /// Instruct the assistant to stop the waveform on one of its output pins
+    pub fn stop_waveform(&mut self, pin: &str)
+        -> Result<(), AssistantError>
+    {
+        self.pins.resolve(pin)?
+            .stop_waveform::<HostToAssistant>(&mut self.conn)
+            .map_err(|err| AssistantError::StopWaveform(err))
+    }
+
     /// Indicates whether the GPIO pin on the test target is set high
     ///
     /// Uses `pin_state` internally.
     pub fn pin_is_high(&mut self) -> Result<bool, AssistantError> {
-        let pin_state = self.green_led
+        let pin_state = self.pins.resolve("green")?
             .read_level::<HostToAssistant, AssistantToHost>(
                 Duration::from_millis(10),
                 &mut self.conn,
@@ -127,7 +297,7 @@ impl Assistant {
     ///
     /// Uses `pin_state` internally.
     pub fn pin_is_low(&mut self) -> Result<bool, AssistantError> {
-        let pin_state = self.green_led
+        let pin_state = self.pins.resolve("green")?
             .read_level::<HostToAssistant, AssistantToHost>(
                 Duration::from_millis(10),
                 &mut self.conn,
@@ -137,10 +307,11 @@ impl Assistant {
 
     /// Wait for RTS signal to be enabled
     pub fn wait_for_rts(&mut self) -> Result<bool, AssistantError> {
-        let pin_state = self.rts.read_level::<HostToAssistant, AssistantToHost>(
-            Duration::from_millis(10),
-            &mut self.conn,
-        )?;
+        let pin_state = self.pins.resolve("rts")?
+            .read_level::<HostToAssistant, AssistantToHost>(
+                Duration::from_millis(10),
+                &mut self.conn,
+            )?;
         Ok(pin_state.0 == pin::Level::Low)
     }
 
@@ -156,22 +327,520 @@ impl Assistant {
             .map_err(|err| AssistantError::UsartSend(err))
     }
 
-    /// Instruct assistant to send this message to the target's USART/DMA
-    pub fn send_to_target_usart_dma(&mut self, data: &[u8])
-        -> Result<(), AssistantError>
+    /// Instruct assistant to send data to an address via USART
+    ///
+    /// Requires 9 data bits to be configured for `UsartMode::Regular`. The
+    /// address is sent with the address bit set, followed by `data`. Call this
+    /// repeatedly, to send a sequence of addresses and data.
+    pub fn send_to_target_usart_addressed(&mut self, address: u8, data: &[u8])
+        -> Result<(), AssistantError>
+    {
+        self.conn
+            .send(&HostToAssistant::SendUsartAddressed { address, data })
+            .map_err(|err| AssistantError::UsartSend(err))
+    }
+
+    /// Instruct assistant to send a LIN header to the target
+    ///
+    /// The assistant acts as LIN master, sending a break, followed by the
+    /// header of `frame`. The response is left to the slave.
+    pub fn send_lin_header(&mut self, frame: &lin::Frame)
+        -> Result<(), AssistantError>
+    {
+        self.send_lin(&frame.header())
+    }
+
+    /// Instruct assistant to send a complete LIN frame to the target
+    ///
+    /// The assistant acts as LIN master, sending a break, followed by the
+    /// header and response of `frame`.
+    pub fn send_lin_frame(&mut self, frame: &lin::Frame)
+        -> Result<(), AssistantError>
+    {
+        self.send_lin(&frame.to_bytes())
+    }
+
+    fn send_lin(&mut self, data: &[u8]) -> Result<(), AssistantError> {
+        self.conn
+            .send(&HostToAssistant::SendUsart {
+                mode: UsartMode::Lin,
+                data,
+            })
+            .map_err(|err| AssistantError::UsartSend(err))
+    }
+
+    /// Instruct assistant to send this message to the target's USART/DMA
+    pub fn send_to_target_usart_dma(&mut self, data: &[u8])
+        -> Result<(), AssistantError>
+    {
+        self.conn
+            .send(&HostToAssistant::SendUsart { mode: UsartMode::Dma, data })
+            .map_err(|err| AssistantError::UsartSend(err))
+    }
+
+    /// Instruct assistant to send this message to the target's sync USART
+    pub fn send_to_target_usart_sync(&mut self, data: &[u8])
+        -> Result<(), AssistantError>
+    {
+        self.conn
+            .send(&HostToAssistant::SendUsart { mode: UsartMode::Sync, data })
+            .map_err(|err| AssistantError::UsartSend(err))
+    }
+
+    /// Instruct assistant to inject a fault into the target's USART
+    ///
+    /// The fault is relative to the line parameters that were last configured
+    /// using [`configure_usart`], for `UsartMode::Regular`.
+    ///
+    /// [`configure_usart`]: #method.configure_usart
+    pub fn inject_usart_fault(&mut self, fault: usart::Fault)
+        -> Result<(), AssistantError>
+    {
+        self.conn
+            .send(&HostToAssistant::InjectUsartFault {
+                mode: UsartMode::Regular,
+                fault,
+            })
+            .map_err(|err| AssistantError::InjectUsartFault(err))
+    }
+
+    /// Instruct the assistant to run its part of a USART benchmark
+    ///
+    /// `direction` is the direction in which the assistant transfers data.
+    /// When receiving, the assistant starts measuring once the first byte
+    /// arrives, so this should be called before the sender is started. Use
+    /// [`wait_for_usart_benchmark`] to get the result.
+    ///
+    /// [`wait_for_usart_benchmark`]: #method.wait_for_usart_benchmark
+    pub fn start_usart_benchmark(&mut self,
+        mode:      UsartMode,
+        direction: usart::Direction,
+        bytes:     u32,
+    )
+        -> Result<(), AssistantError>
+    {
+        self.conn
+            .send(&HostToAssistant::UsartBenchmark { mode, direction, bytes })
+            .map_err(|err|
+                AssistantError::UsartBenchmark(
+                    AssistantUsartBenchmarkError::Send(err)
+                )
+            )
+    }
+
+    /// Wait for the assistant to report the result of a USART benchmark
+    pub fn wait_for_usart_benchmark(&mut self, timeout: Duration)
+        -> Result<usart::BenchmarkResult, AssistantError>
+    {
+        self.wait_for_usart_benchmark_inner(timeout)
+            .map_err(|err| AssistantError::UsartBenchmark(err))
+    }
+
+    fn wait_for_usart_benchmark_inner(&mut self, timeout: Duration)
+        -> Result<usart::BenchmarkResult, AssistantUsartBenchmarkError>
+    {
+        let mut buf = Vec::new();
+        let message = self.conn
+            .receive_from::<AssistantToHost>(Route::Other, timeout, &mut buf)
+            .map_err(|err| AssistantUsartBenchmarkError::Receive(err))?;
+
+        match message {
+            AssistantToHost::UsartBenchmarkResult(result) => {
+                Ok(result)
+            }
+            message => {
+                Err(
+                    AssistantUsartBenchmarkError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the assistant to start soak testing the target's flow control
+    ///
+    /// The assistant toggles the target's CTS input at random intervals, and
+    /// stops the target whenever its receive queue fills up. Data received
+    /// from the target is still forwarded, so it can be verified using
+    /// [`receive_from_target_usart`].
+    ///
+    /// [`receive_from_target_usart`]: #method.receive_from_target_usart
+    pub fn start_flow_control_soak(&mut self, soak: usart::FlowControlSoak)
+        -> Result<(), AssistantError>
+    {
+        self.conn
+            .send(&HostToAssistant::StartFlowControlSoak(soak))
+            .map_err(|err|
+                AssistantError::FlowControlSoak(
+                    AssistantFlowControlSoakError::Send(err)
+                )
+            )
+    }
+
+    /// Instruct the assistant to stop the flow control soak test
+    ///
+    /// Returns the result, as reported by the assistant.
+    pub fn stop_flow_control_soak(&mut self, timeout: Duration)
+        -> Result<usart::FlowControlSoakResult, AssistantError>
+    {
+        self.stop_flow_control_soak_inner(timeout)
+            .map_err(|err| AssistantError::FlowControlSoak(err))
+    }
+
+    fn stop_flow_control_soak_inner(&mut self, timeout: Duration)
+        -> Result<usart::FlowControlSoakResult, AssistantFlowControlSoakError>
+    {
+        self.conn
+            .send(&HostToAssistant::StopFlowControlSoak)
+            .map_err(|err| AssistantFlowControlSoakError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = self.conn
+            .receive_from::<AssistantToHost>(Route::Other, timeout, &mut buf)
+            .map_err(|err| AssistantFlowControlSoakError::Receive(err))?;
+
+        match message {
+            AssistantToHost::FlowControlSoakResult(result) => {
+                Ok(result)
+            }
+            message => {
+                Err(
+                    AssistantFlowControlSoakError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the assistant to monitor the target's RS-485 driver enable
+    ///
+    /// Blocks the assistant until it has observed driver enable being asserted
+    /// and released again, or until `timeout` has expired. Use
+    /// [`wait_for_rs485_timing`] to get the result.
+    ///
+    /// [`wait_for_rs485_timing`]: #method.wait_for_rs485_timing
+    pub fn start_rs485_monitor(&mut self, timeout: Duration)
+        -> Result<(), AssistantError>
+    {
+        let timeout_ms = timeout.as_millis() as u32;
+
+        self.conn
+            .send(&HostToAssistant::MonitorRs485 { timeout_ms })
+            .map_err(|err|
+                AssistantError::MonitorRs485(AssistantRs485Error::Send(err))
+            )
+    }
+
+    /// Wait for the assistant to report the result of RS-485 monitoring
+    pub fn wait_for_rs485_timing(&mut self, timeout: Duration)
+        -> Result<usart::Rs485Timing, AssistantError>
+    {
+        self.wait_for_rs485_timing_inner(timeout)
+            .map_err(|err| AssistantError::MonitorRs485(err))
+    }
+
+    fn wait_for_rs485_timing_inner(&mut self, timeout: Duration)
+        -> Result<usart::Rs485Timing, AssistantRs485Error>
+    {
+        let mut buf = Vec::new();
+        let message = self.conn
+            .receive_from::<AssistantToHost>(Route::Other, timeout, &mut buf)
+            .map_err(|err| AssistantRs485Error::Receive(err))?;
+
+        match message {
+            AssistantToHost::Rs485Timing(timing) => {
+                Ok(timing)
+            }
+            message => {
+                Err(
+                    AssistantRs485Error::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the assistant to monitor the target's SPI slave select
+    ///
+    /// Blocks the assistant until it has observed slave select being asserted
+    /// and released again, or until `timeout` has expired. Use
+    /// [`wait_for_spi_timing`] to get the result.
+    ///
+    /// [`wait_for_spi_timing`]: #method.wait_for_spi_timing
+    pub fn start_spi_monitor(&mut self, timeout: Duration)
+        -> Result<(), AssistantError>
+    {
+        let timeout_ms = timeout.as_millis() as u32;
+
+        self.conn
+            .send(&HostToAssistant::MonitorSpi { timeout_ms })
+            .map_err(|err|
+                AssistantError::MonitorSpi(AssistantSpiMonitorError::Send(err))
+            )
+    }
+
+    /// Wait for the assistant to report the result of SPI monitoring
+    pub fn wait_for_spi_timing(&mut self, timeout: Duration)
+        -> Result<spi::Timing, AssistantError>
+    {
+        self.wait_for_spi_timing_inner(timeout)
+            .map_err(|err| AssistantError::MonitorSpi(err))
+    }
+
+    fn wait_for_spi_timing_inner(&mut self, timeout: Duration)
+        -> Result<spi::Timing, AssistantSpiMonitorError>
+    {
+        let mut buf = Vec::new();
+        let message = self.conn
+            .receive_from::<AssistantToHost>(Route::Other, timeout, &mut buf)
+            .map_err(|err| AssistantSpiMonitorError::Receive(err))?;
+
+        match message {
+            AssistantToHost::SpiTiming(timing) => {
+                Ok(timing)
+            }
+            message => {
+                Err(
+                    AssistantSpiMonitorError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Wait for the assistant to report the data its SPI slave received
+    ///
+    /// The assistant reports the data of every SPI transfer, once slave select
+    /// has been deasserted.
+    pub fn receive_from_target_spi(&mut self, timeout: Duration)
+        -> Result<Vec<u8>, AssistantError>
+    {
+        self.receive_from_target_spi_inner(timeout)
+            .map_err(|err| AssistantError::SpiReceive(err))
+    }
+
+    fn receive_from_target_spi_inner(&mut self, timeout: Duration)
+        -> Result<Vec<u8>, AssistantSpiReceiveError>
+    {
+        let mut buf = Vec::new();
+        let message = self.conn
+            .receive_from::<AssistantToHost>(Route::Bus, timeout, &mut buf)
+            .map_err(|err| AssistantSpiReceiveError::Receive(err))?;
+
+        match message {
+            AssistantToHost::SpiReceived(data) => {
+                Ok(data.to_vec())
+            }
+            message => {
+                Err(
+                    AssistantSpiReceiveError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the assistant to emulate an I2C slave
+    ///
+    /// Returns an [`I2cSlave`], which provides access to the slave's
+    /// registers. The assistant goes back to its default I2C behavior, once it
+    /// is dropped.
+    ///
+    /// [`I2cSlave`]: struct.I2cSlave.html
+    pub fn start_i2c_slave(&mut self, config: i2c::SlaveConfig)
+        -> Result<I2cSlave<'_>, AssistantError>
+    {
+        self.conn
+            .send(&HostToAssistant::StartI2cSlave(config))
+            .map_err(|err|
+                AssistantError::I2cSlave(AssistantI2cSlaveError::Send(err))
+            )?;
+
+        Ok(I2cSlave(self))
+    }
+
+    /// Instruct the assistant to emulate an SPI NOR flash
+    ///
+    /// Returns an [`SpiFlash`], which provides access to the flash's memory.
+    /// The assistant goes back to its default SPI behavior, once it is
+    /// dropped.
+    ///
+    /// [`SpiFlash`]: struct.SpiFlash.html
+    pub fn start_spi_flash(&mut self, config: spi::FlashConfig)
+        -> Result<SpiFlash<'_>, AssistantError>
+    {
+        self.conn
+            .send(&HostToAssistant::StartSpiFlash(config))
+            .map_err(|err|
+                AssistantError::SpiFlash(AssistantSpiFlashError::Send(err))
+            )?;
+
+        Ok(SpiFlash(self))
+    }
+
+    /// Instruct the assistant to inject a fault into the I2C bus
+    ///
+    /// Apart from `i2c::Fault::HoldSda`, faults are injected by the
+    /// assistant's I2C slave, into the next transaction that addresses it.
+    /// SDA is held low right away, and the assistant doesn't process other
+    /// messages until it is released.
+    pub fn inject_i2c_fault(&mut self, fault: i2c::Fault)
+        -> Result<(), AssistantError>
+    {
+        self.conn
+            .send(&HostToAssistant::InjectI2cFault(fault))
+            .map_err(|err| AssistantError::InjectI2cFault(err))
+    }
+
+    /// Instruct the assistant to start an I2C transfer, as master
+    ///
+    /// Writes `write` to the slave at `address`, then reads `read_len` bytes
+    /// from it, after a repeated start. Either may be empty. Returns the data
+    /// read.
+    pub fn i2c_transfer(&mut self,
+        address:  u8,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, AssistantError>
+    {
+        self.i2c_transfer_inner(address, write, read_len, timeout)
+            .map_err(|err| AssistantError::I2cMaster(err))
+    }
+
+    fn i2c_transfer_inner(&mut self,
+        address:  u8,
+        write:    &[u8],
+        read_len: usize,
+        timeout:  Duration,
+    )
+        -> Result<Vec<u8>, AssistantI2cMasterError>
+    {
+        for &len in &[write.len(), read_len] {
+            if len > i2c::MAX_TRANSFER {
+                return Err(AssistantI2cMasterError::TransferTooLong(len));
+            }
+        }
+
+        self.conn
+            .send(
+                &HostToAssistant::StartI2cTransfer {
+                    address,
+                    write,
+                    read_len: read_len as u8,
+                }
+            )
+            .map_err(|err| AssistantI2cMasterError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = self.conn
+            .receive_from::<AssistantToHost>(Route::Other, timeout, &mut buf)
+            .map_err(|err| AssistantI2cMasterError::Receive(err))?;
+
+        match message {
+            AssistantToHost::I2cTransferReply(data)
+                if data.len() == read_len
+            => {
+                Ok(data.to_vec())
+            }
+            AssistantToHost::I2cError(err) => {
+                Err(AssistantI2cMasterError::I2c(err))
+            }
+            message => {
+                Err(
+                    AssistantI2cMasterError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Returns how often the assistant's I2C slave has been deselected
+    ///
+    /// Counts since the last call. The slave is deselected by a stop
+    /// condition, but not by a repeated start. Not acknowledging an address,
+    /// or a master not acknowledging read data, deselect the slave too.
+    pub fn i2c_deselections(&mut self, timeout: Duration)
+        -> Result<u32, AssistantError>
+    {
+        self.i2c_deselections_inner(timeout)
+            .map_err(|err| AssistantError::I2cSlave(err))
+    }
+
+    fn i2c_deselections_inner(&mut self, timeout: Duration)
+        -> Result<u32, AssistantI2cSlaveError>
+    {
+        self.conn
+            .send(&HostToAssistant::ReadI2cDeselections)
+            .map_err(|err| AssistantI2cSlaveError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = self.conn
+            .receive_from::<AssistantToHost>(Route::Other, timeout, &mut buf)
+            .map_err(|err| AssistantI2cSlaveError::Receive(err))?;
+
+        match message {
+            AssistantToHost::I2cDeselections(deselections) => {
+                Ok(deselections)
+            }
+            message => {
+                Err(
+                    AssistantI2cSlaveError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Instruct the assistant to start an SPI transfer, as master
+    ///
+    /// The assistant switches its SPI to master mode for the duration of the
+    /// transfer. Returns the data received while sending `data`.
+    pub fn spi_transfer(&mut self, data: &[u8], timeout: Duration)
+        -> Result<Vec<u8>, AssistantError>
     {
-        self.conn
-            .send(&HostToAssistant::SendUsart { mode: UsartMode::Dma, data })
-            .map_err(|err| AssistantError::UsartSend(err))
+        self.spi_transfer_inner(data, timeout)
+            .map_err(|err| AssistantError::SpiMaster(err))
     }
 
-    /// Instruct assistant to send this message to the target's sync USART
-    pub fn send_to_target_usart_sync(&mut self, data: &[u8])
-        -> Result<(), AssistantError>
+    fn spi_transfer_inner(&mut self, data: &[u8], timeout: Duration)
+        -> Result<Vec<u8>, AssistantSpiMasterError>
     {
+        if data.len() > spi::MAX_TRANSFER {
+            return Err(AssistantSpiMasterError::TransferTooLong(data.len()));
+        }
+
         self.conn
-            .send(&HostToAssistant::SendUsart { mode: UsartMode::Sync, data })
-            .map_err(|err| AssistantError::UsartSend(err))
+            .send(&HostToAssistant::StartSpiTransfer { data })
+            .map_err(|err| AssistantSpiMasterError::Send(err))?;
+
+        let mut buf = Vec::new();
+        let message = self.conn
+            .receive_from::<AssistantToHost>(Route::Other, timeout, &mut buf)
+            .map_err(|err| AssistantSpiMasterError::Receive(err))?;
+
+        match message {
+            AssistantToHost::SpiTransferReply(reply)
+                if reply.len() == data.len()
+            => {
+                Ok(reply.to_vec())
+            }
+            message => {
+                Err(
+                    AssistantSpiMasterError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
     }
 
     /// Wait to receive the provided data via USART
@@ -229,7 +898,11 @@ impl Assistant {
 
             let mut tmp = Vec::new();
             let message = self.conn
-                .receive::<AssistantToHost>(timeout, &mut tmp)
+                .receive_from::<AssistantToHost>(
+                    Route::Usart(expected_mode),
+                    timeout,
+                    &mut tmp,
+                )
                 .map_err(|err| AssistantUsartWaitError::Receive(err))?;
 
             match message {
@@ -264,7 +937,7 @@ impl Assistant {
     {
         Self::measure_gpio_period(
             &mut self.conn,
-            &mut self.blue_led,
+            &mut self.pins.resolve("blue")?,
             samples,
             timeout,
         )
@@ -285,15 +958,72 @@ impl Assistant {
     {
         Self::measure_gpio_period(
             &mut self.conn,
-            &mut self.pwm,
+            &mut self.pins.resolve("pwm")?,
             samples,
             timeout,
         )
     }
 
+    /// Measures high time, low time, frequency, and duty cycle of the PWM signal
+    ///
+    /// The assistant measures the given number of full cycles of the signal.
+    /// `timeout` must be long enough for all of those cycles to happen.
+    pub fn measure_pwm_duty_cycle(&mut self, cycles: u32, timeout: Duration)
+        -> Result<SignalMeasurement, AssistantError>
+    {
+        self.measure_signal("pwm", cycles, timeout)
+    }
+
+    /// Measures high time, low time, frequency, and duty cycle of a signal
+    ///
+    /// Works like [`measure_pwm_duty_cycle`], for the signal on any of the
+    /// assistant's input pins.
+    ///
+    /// [`measure_pwm_duty_cycle`]: #method.measure_pwm_duty_cycle
+    pub fn measure_signal(&mut self,
+        pin:     &str,
+        cycles:  u32,
+        timeout: Duration,
+    )
+        -> Result<SignalMeasurement, AssistantError>
+    {
+        let measurement = self.pins.resolve(pin)?
+            .measure_signal::<HostToAssistant, AssistantToHost>(
+                cycles,
+                timeout,
+                &mut self.conn,
+            )?;
+        Ok(measurement)
+    }
+
+    /// Measures how fast the target reacts to a level change on its input pin
+    ///
+    /// Toggles the target's input pin `samples` times, and measures how long it
+    /// takes each time until the target's output pin follows. The target must
+    /// be set up to mirror its input pin on its output pin from its pin
+    /// interrupt handler. `timeout` must be long enough for all samples to be
+    /// measured.
+    pub fn measure_interrupt_latency(&mut self,
+        samples:   u32,
+        bin_width: Duration,
+        timeout:   Duration,
+    )
+        -> Result<LatencyMeasurement, AssistantError>
+    {
+        let measurement = self.pins.resolve("red")?
+            .measure_latency::<HostToAssistant, AssistantToHost>(
+                &self.pins.resolve("green")?,
+                samples,
+                bin_width,
+                timeout,
+                &mut self.conn,
+            )?;
+        Ok(measurement)
+    }
+
     fn measure_gpio_period(
         conn:    &mut Conn,
-        pin:     &mut Pin<InputPin>,
+        pin:     &mut Pin<PinId>,
         samples: u32,
         timeout: Duration,
     )
@@ -363,8 +1093,13 @@ impl Assistant {
     {
         loop {
             let mut tmp = Vec::new();
+            // Anything the target sends via USART ends up on this route.
             let message = self.conn
-                .receive::<AssistantToHost>(timeout, &mut tmp);
+                .receive_from::<AssistantToHost>(
+                    Route::Usart(UsartMode::Regular),
+                    timeout,
+                    &mut tmp,
+                );
 
             match message {
                 Ok(message) => {
@@ -388,6 +1123,330 @@ impl Assistant {
 }
 
 
+/// Decides where messages from the assistant are queued
+///
+/// See [`Conn::set_router`].
+///
+/// [`Conn::set_router`]: ../conn/struct.Conn.html#method.set_router
+fn route(frame: &[u8]) -> Route {
+    let message = conn::decode::<AssistantToHost>(frame);
+
+    match message {
+        Some(AssistantToHost::UsartReceive { mode, .. }) => {
+            Route::Usart(mode)
+        }
+        Some(AssistantToHost::Diagnostics(diagnostics)) => {
+            Route::Diagnostics(diagnostics)
+        }
+        Some(AssistantToHost::ReadPinResult(_))
+        | Some(AssistantToHost::MeasureSignalResult(_))
+        | Some(AssistantToHost::MeasureError(_))
+        | Some(AssistantToHost::MeasureLatencyResult(_)) => {
+            Route::Pin
+        }
+        Some(AssistantToHost::SpiReceived(_)) => {
+            Route::Bus
+        }
+        _ => {
+            Route::Other
+        }
+    }
+}
+
+
+/// An I2C slave emulated by the assistant
+///
+/// Returned by [`Assistant::start_i2c_slave`]. Stops the emulation, when
+/// dropped.
+///
+/// [`Assistant::start_i2c_slave`]: struct.Assistant.html#method.start_i2c_slave
+pub struct I2cSlave<'r>(&'r mut Assistant);
+
+impl I2cSlave<'_> {
+    /// Set the slave's registers, starting at `offset`
+    ///
+    /// If `read_only` is `true`, the target can't change those registers.
+    /// Waits until the assistant confirms the write.
+    pub fn write_registers(&mut self,
+        offset:    u8,
+        data:      &[u8],
+        read_only: bool,
+        timeout:   Duration,
+    )
+        -> Result<(), AssistantError>
+    {
+        self.write_registers_inner(offset, data, read_only, timeout)
+            .map_err(|err| AssistantError::I2cSlave(err))
+    }
+
+    fn write_registers_inner(&mut self,
+        offset:    u8,
+        data:      &[u8],
+        read_only: bool,
+        timeout:   Duration,
+    )
+        -> Result<(), AssistantI2cSlaveError>
+    {
+        check_range(offset, data.len())?;
+
+        for (i, chunk) in data.chunks(i2c::MAX_TRANSFER).enumerate() {
+            let chunk_offset = offset as usize + i * i2c::MAX_TRANSFER;
+
+            (self.0).conn
+                .send(
+                    &HostToAssistant::WriteI2cRegisters {
+                        offset: chunk_offset as u8,
+                        data:   chunk,
+                        read_only,
+                    }
+                )
+                .map_err(|err| AssistantI2cSlaveError::Send(err))?;
+
+            let mut buf = Vec::new();
+            let message = (self.0).conn
+                .receive_from::<AssistantToHost>(
+                    Route::Other,
+                    timeout,
+                    &mut buf,
+                )
+                .map_err(|err| AssistantI2cSlaveError::Receive(err))?;
+
+            match message {
+                AssistantToHost::I2cRegistersWritten => {}
+                AssistantToHost::I2cRegistersError => {
+                    return Err(
+                        AssistantI2cSlaveError::AccessFailed {
+                            offset: chunk_offset as u8,
+                            len:    chunk.len(),
+                        }
+                    );
+                }
+                message => {
+                    return Err(
+                        AssistantI2cSlaveError::UnexpectedMessage(
+                            format!("{:?}", message)
+                        )
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read `len` of the slave's registers, starting at `offset`
+    ///
+    /// Can be used to check what the target has written.
+    pub fn read_registers(&mut self,
+        offset:  u8,
+        len:     usize,
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, AssistantError>
+    {
+        self.read_registers_inner(offset, len, timeout)
+            .map_err(|err| AssistantError::I2cSlave(err))
+    }
+
+    fn read_registers_inner(&mut self,
+        offset:  u8,
+        len:     usize,
+        timeout: Duration,
+    )
+        -> Result<Vec<u8>, AssistantI2cSlaveError>
+    {
+        check_range(offset, len)?;
+
+        let mut registers = Vec::new();
+
+        while registers.len() < len {
+            let remaining    = len - registers.len();
+            let chunk_offset = offset as usize + registers.len();
+            let chunk_len    = Ord::min(remaining, i2c::MAX_TRANSFER);
+
+            (self.0).conn
+                .send(
+                    &HostToAssistant::ReadI2cRegisters {
+                        offset: chunk_offset as u8,
+                        len:    chunk_len as u8,
+                    }
+                )
+                .map_err(|err| AssistantI2cSlaveError::Send(err))?;
+
+            let mut buf = Vec::new();
+            let message = (self.0).conn
+                .receive_from::<AssistantToHost>(
+                    Route::Other,
+                    timeout,
+                    &mut buf,
+                )
+                .map_err(|err| AssistantI2cSlaveError::Receive(err))?;
+
+            match message {
+                AssistantToHost::I2cRegisters(data)
+                    if data.len() == chunk_len
+                => {
+                    registers.extend(data);
+                }
+                AssistantToHost::I2cRegistersError => {
+                    return Err(
+                        AssistantI2cSlaveError::AccessFailed {
+                            offset: chunk_offset as u8,
+                            len:    chunk_len,
+                        }
+                    );
+                }
+                message => {
+                    return Err(
+                        AssistantI2cSlaveError::UnexpectedMessage(
+                            format!("{:?}", message)
+                        )
+                    );
+                }
+            }
+        }
+
+        Ok(registers)
+    }
+}
+
+impl Drop for I2cSlave<'_> {
+    fn drop(&mut self) {
+        (self.0).conn.send(&HostToAssistant::StopI2cSlave)
+            .unwrap()
+    }
+}
+
+fn check_range(offset: u8, len: usize) -> Result<(), AssistantI2cSlaveError> {
+    if offset as usize + len > i2c::REGISTERS {
+        return Err(AssistantI2cSlaveError::OutOfRange { offset, len });
+    }
+
+    Ok(())
+}
+
+
+/// An SPI NOR flash emulated by the assistant
+///
+/// Returned by [`Assistant::start_spi_flash`]. Stops the emulation, when
+/// dropped.
+///
+/// [`Assistant::start_spi_flash`]: struct.Assistant.html#method.start_spi_flash
+pub struct SpiFlash<'r>(&'r mut Assistant);
+
+impl SpiFlash<'_> {
+    /// Set the flash's memory, starting at `address`
+    ///
+    /// Unlike programming by the target, this can set bits too.
+    pub fn write(&mut self, address: u32, data: &[u8])
+        -> Result<(), AssistantError>
+    {
+        self.write_inner(address, data)
+            .map_err(|err| AssistantError::SpiFlash(err))
+    }
+
+    fn write_inner(&mut self, address: u32, data: &[u8])
+        -> Result<(), AssistantSpiFlashError>
+    {
+        check_flash_range(address, data.len())?;
+
+        for (i, chunk) in data.chunks(spi::MAX_TRANSFER).enumerate() {
+            let address = address + (i * spi::MAX_TRANSFER) as u32;
+
+            (self.0).conn
+                .send(&HostToAssistant::WriteSpiFlash { address, data: chunk })
+                .map_err(|err| AssistantSpiFlashError::Send(err))?;
+        }
+
+        Ok(())
+    }
+
+    /// Read `len` bytes of the flash's memory, starting at `address`
+    ///
+    /// Can be used to check what the target has programmed.
+    pub fn read(&mut self, address: u32, len: usize, timeout: Duration)
+        -> Result<Vec<u8>, AssistantError>
+    {
+        self.read_inner(address, len, timeout)
+            .map_err(|err| AssistantError::SpiFlash(err))
+    }
+
+    fn read_inner(&mut self, address: u32, len: usize, timeout: Duration)
+        -> Result<Vec<u8>, AssistantSpiFlashError>
+    {
+        check_flash_range(address, len)?;
+
+        let mut memory = Vec::new();
+
+        while memory.len() < len {
+            let remaining     = len - memory.len();
+            let chunk_address = address + memory.len() as u32;
+            let chunk_len     = Ord::min(remaining, spi::MAX_TRANSFER);
+
+            (self.0).conn
+                .send(
+                    &HostToAssistant::ReadSpiFlash {
+                        address: chunk_address,
+                        len:     chunk_len as u8,
+                    }
+                )
+                .map_err(|err| AssistantSpiFlashError::Send(err))?;
+
+            let mut buf = Vec::new();
+            let message = (self.0).conn
+                .receive_from::<AssistantToHost>(
+                    Route::Other,
+                    timeout,
+                    &mut buf,
+                )
+                .map_err(|err| AssistantSpiFlashError::Receive(err))?;
+
+            match message {
+                AssistantToHost::SpiFlash(data)
+                    if data.len() == chunk_len
+                => {
+                    memory.extend(data);
+                }
+                AssistantToHost::SpiFlashError => {
+                    return Err(
+                        AssistantSpiFlashError::ReadFailed {
+                            address: chunk_address,
+                            len:     chunk_len,
+                        }
+                    );
+                }
+                message => {
+                    return Err(
+                        AssistantSpiFlashError::UnexpectedMessage(
+                            format!("{:?}", message)
+                        )
+                    );
+                }
+            }
+        }
+
+        Ok(memory)
+    }
+}
+
+impl Drop for SpiFlash<'_> {
+    fn drop(&mut self) {
+        (self.0).conn.send(&HostToAssistant::StopSpiFlash)
+            .unwrap()
+    }
+}
+
+fn check_flash_range(address: u32, len: usize)
+    -> Result<(), AssistantSpiFlashError>
+{
+    if address as usize + len > spi::FLASH_SIZE {
+        return Err(AssistantSpiFlashError::OutOfRange { address, len });
+    }
+
+    Ok(())
+}
+
+
 #[derive(Debug)]
 pub struct GpioPeriodMeasurement {
     pub min: Duration,
@@ -398,20 +1457,57 @@ pub struct GpioPeriodMeasurement {
 /// All the errors that can be returned by this API
 #[derive(Debug)]
 pub enum AssistantError {
+    ConfigurePin(ConnSendError),
+    ConfigureSpi(ConnSendError),
+    ConfigureUsart(AssistantConfigureUsartError),
     ExpectNothing(AssistantExpectNothingError),
+    FlowControlSoak(AssistantFlowControlSoakError),
+    GenerateWaveform(ConnSendError),
+    I2cMaster(AssistantI2cMasterError),
+    I2cSlave(AssistantI2cSlaveError),
+    InjectI2cFault(ConnSendError),
+    InjectUsartFault(ConnSendError),
+    MeasureLatency(MeasureLatencyError),
+    MeasureSignal(MeasureSignalError),
+    MonitorRs485(AssistantRs485Error),
+    MonitorSpi(AssistantSpiMonitorError),
     PinRead(ReadLevelError),
     SetPinHigh(ConnSendError),
     SetPinLow(ConnSendError),
+    SpiFlash(AssistantSpiFlashError),
+    SpiMaster(AssistantSpiMasterError),
+    SpiReceive(AssistantSpiReceiveError),
+    StopWaveform(ConnSendError),
+    UnknownPin(UnknownPinError),
+    UsartBenchmark(AssistantUsartBenchmarkError),
     UsartSend(ConnSendError),
     UsartWait(AssistantUsartWaitError),
 }
 
+impl From<MeasureLatencyError> for AssistantError {
+    fn from(err: MeasureLatencyError) -> Self {
+        Self::MeasureLatency(err)
+    }
+}
+
+impl From<MeasureSignalError> for AssistantError {
+    fn from(err: MeasureSignalError) -> Self {
+        Self::MeasureSignal(err)
+    }
+}
+
 impl From<ReadLevelError> for AssistantError {
     fn from(err: ReadLevelError) -> Self {
         Self::PinRead(err)
     }
 }
 
+impl From<UnknownPinError> for AssistantError {
+    fn from(err: UnknownPinError) -> Self {
+        Self::UnknownPin(err)
+    }
+}
+
 impl From<AssistantUsartWaitError> for AssistantError {
     fn from(err: AssistantUsartWaitError) -> Self {
         Self::UsartWait(err)
@@ -419,6 +1515,25 @@ impl From<AssistantUsartWaitError> for AssistantError {
 }
 
 
+/// Error initializing the connection to the assistant
+#[derive(Debug)]
+pub enum AssistantInitError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+
+#[derive(Debug)]
+pub enum AssistantConfigureUsartError {
+    /// The assistant can't apply the configuration
+    Config(usart::ConfigError),
+
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
 #[derive(Debug)]
 pub enum AssistantUsartWaitError {
     Receive(ConnReceiveError),
@@ -426,6 +1541,98 @@ pub enum AssistantUsartWaitError {
     UnexpectedMessage(String),
 }
 
+#[derive(Debug)]
+pub enum AssistantUsartBenchmarkError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantFlowControlSoakError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantRs485Error {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantSpiMonitorError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantI2cMasterError {
+    /// The I2C transfer failed on the bus
+    I2c(i2c::Error),
+
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+
+    /// The data to write, or to read, exceeds `i2c::MAX_TRANSFER`
+    TransferTooLong(usize),
+
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantI2cSlaveError {
+    OutOfRange {
+        offset: u8,
+        len:    usize,
+    },
+    AccessFailed {
+        offset: u8,
+        len:    usize,
+    },
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantSpiFlashError {
+    OutOfRange {
+        address: u32,
+        len:     usize,
+    },
+
+    /// The assistant couldn't read the requested memory
+    ReadFailed {
+        address: u32,
+        len:     usize,
+    },
+
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantSpiMasterError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+
+    /// The data exceeds `spi::MAX_TRANSFER`
+    TransferTooLong(usize),
+
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum AssistantSpiReceiveError {
+    Receive(ConnReceiveError),
+    UnexpectedMessage(String),
+}
+
 #[derive(Debug)]
 pub enum AssistantExpectNothingError {
     Receive(ConnReceiveError),
@@ -0,0 +1,101 @@
+//! Evaluation of USART benchmarks
+//!
+//! The firmware only reports byte counts and elapsed timer ticks (see
+//! [`usart::BenchmarkResult`]). This module turns those into durations and
+//! throughput figures.
+//!
+//! [`usart::BenchmarkResult`]: ../../protocol/usart/struct.BenchmarkResult.html
+
+
+use std::time::Duration;
+
+use protocol::usart::{
+    self,
+    Direction,
+};
+
+
+/// The result of a USART benchmark, as measured by both nodes
+#[derive(Debug)]
+pub struct UsartBenchmark {
+    /// The transfer, as measured by the node that sent the data
+    pub sender: Transfer,
+
+    /// The transfer, as measured by the node that received the data
+    pub receiver: Transfer,
+}
+
+
+/// A transfer of data, as measured by one node
+#[derive(Debug)]
+pub struct Transfer {
+    /// The number of bytes that were transferred
+    pub bytes: u32,
+
+    /// The time it took to transfer those bytes
+    pub elapsed: Duration,
+
+    /// The effective throughput in bytes per second
+    ///
+    /// `None`, if not enough bytes were transferred to measure it.
+    pub throughput: Option<f64>,
+
+    /// The average interval between two bytes
+    ///
+    /// `None`, if not enough bytes were transferred to measure it.
+    pub byte_interval: Option<Duration>,
+}
+
+impl Transfer {
+    /// Evaluates the result reported by a node
+    pub fn new(result: &usart::BenchmarkResult) -> Self {
+        let elapsed = Duration::from_nanos(
+            result.ticks as u64 * 1_000_000_000
+                / result.ticks_per_second as u64
+        );
+
+        // A sender starts measuring before the first byte, a receiver only
+        // once the first byte has arrived. The receiver's measurement therefore
+        // covers one byte less.
+        let intervals = match result.direction {
+            Direction::Send    => result.bytes,
+            Direction::Receive => result.bytes.saturating_sub(1),
+        };
+
+        let (throughput, byte_interval) = match intervals {
+            0 => {
+                (None, None)
+            }
+            intervals => {
+                (
+                    Some(intervals as f64 / elapsed.as_secs_f64()),
+                    Some(elapsed / intervals),
+                )
+            }
+        };
+
+        Self {
+            bytes: result.bytes,
+            elapsed,
+            throughput,
+            byte_interval,
+        }
+    }
+
+    /// The average idle time between two characters on the line
+    ///
+    /// Computed from the average byte interval and the time it takes to
+    /// transmit a character with the given line parameters. Returns `None`, if
+    /// the byte interval couldn't be measured.
+    pub fn gap(&self, config: &usart::LineConfig) -> Option<Duration> {
+        let char_time = Duration::from_nanos(
+            config.bits_per_char() as u64 * 1_000_000_000
+                / config.baud_rate as u64
+        );
+
+        self.byte_interval
+            .map(|interval| interval.checked_sub(char_time)
+                .unwrap_or(Duration::from_secs(0))
+            )
+    }
+}
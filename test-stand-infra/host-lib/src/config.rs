@@ -2,6 +2,7 @@
 
 
 use std::{
+    collections::HashMap,
     fs::File,
     io::prelude::*,
 };
@@ -22,6 +23,14 @@ pub struct Config {
 
     /// Path to the serial device connected to the USB/serial converter
     pub serial: Option<String>,
+
+    /// Overrides for the names of the test assistant's pins
+    ///
+    /// Maps the pin names used by the test suite to the names from the pin map
+    /// that the assistant reports during the handshake. Pins that are not
+    /// listed here are looked up using the test suite's name.
+    #[serde(default)]
+    pub assistant_pins: HashMap<String, String>,
 }
 
 impl Config {
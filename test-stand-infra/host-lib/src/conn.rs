@@ -1,9 +1,30 @@
 use std::{
+    collections::VecDeque,
     io,
     slice,
-    time::Duration,
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+        Arc,
+        Condvar,
+        Mutex,
+    },
+    thread::{
+        self,
+        JoinHandle,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
 };
 
+use protocol::{
+    UsartMode,
+    usart,
+};
 use serde::{
     Deserialize,
     Serialize,
@@ -16,9 +37,38 @@ use serialport::{
 use crate::Error;
 
 
+/// How long the background reader blocks, before checking for shutdown
+const READ_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// How many received frames are kept, before new ones are dropped
+const MAX_QUEUED: usize = 1024;
+
+
 /// A connection to a firmware application
+///
+/// A background thread reads from the serial port, so messages are received
+/// even while nobody is waiting for them. Every received frame is assigned a
+/// [`Route`] by the router (see [`set_router`]), and queued until it is
+/// received using [`receive`] or [`receive_from`]. If more than `MAX_QUEUED`
+/// frames pile up, new ones are dropped.
+///
+/// Frames routed to `Route::Diagnostics` are reported as an error by the next
+/// call to [`receive_from`], whatever route it asks for.
+///
+/// If reading from the serial port fails, the connection is dead. Every
+/// receive after that returns the error, once the queued frames are used up.
+/// Once frames have been dropped, every receive returns an error right away,
+/// as any reply might have been lost.
+///
+/// [`Route`]: enum.Route.html
+/// [`set_router`]: #method.set_router
+/// [`receive`]: #method.receive
+/// [`receive_from`]: #method.receive_from
 pub struct Conn {
-    port: Box<dyn SerialPort>,
+    port:     Box<dyn SerialPort>,
+    shared:   Arc<Shared>,
+    shutdown: Arc<AtomicBool>,
+    reader:   Option<JoinHandle<()>>,
 }
 
 impl Conn {
@@ -37,13 +87,51 @@ impl Conn {
         let port = port.try_clone()
             .map_err(|err| ConnInitError(err))?;
 
+        let mut reader_port = port.try_clone()
+            .map_err(|err| ConnInitError(err))?;
+        reader_port.set_timeout(READ_TIMEOUT)
+            .map_err(|err| ConnInitError(err))?;
+
+        let shared   = Arc::new(Shared::new());
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let reader = {
+            let shared   = shared.clone();
+            let shutdown = shutdown.clone();
+
+            thread::spawn(move || read(reader_port, &shared, &shutdown))
+        };
+
         Ok(
             Self {
                 port,
+                shared,
+                shutdown,
+                reader: Some(reader),
             }
         )
     }
 
+    /// Set the function that decides where received frames are routed
+    ///
+    /// The router is passed each frame, after COBS-decoding, as it arrives.
+    /// Frames that have been received, but not taken from the queue yet, are
+    /// routed again. By default, all frames are routed to `Route::Other`.
+    ///
+    /// [`decode`] can be used to decode frames within the router.
+    ///
+    /// [`decode`]: fn.decode.html
+    pub fn set_router<F>(&mut self, router: F)
+        where F: Fn(&[u8]) -> Route + Send + 'static
+    {
+        let mut queue = self.shared.queue.lock().unwrap();
+
+        for (route, frame) in &mut queue.frames {
+            *route = router(frame);
+        }
+        queue.router = Box::new(router);
+    }
+
     /// Send a message
     ///
     /// `message` can be any type that can be serialized using `serde`.
@@ -73,39 +161,245 @@ impl Conn {
     /// - `buf` is the buffer used to receive data into. Its lifetime is tied to
     ///   the return value, as the received type might still borrow data from
     ///   this buffer.
+    ///
+    /// Returns the oldest queued message, regardless of its route.
     pub fn receive<'de, T>(&mut self, timeout: Duration, buf: &'de mut Vec<u8>)
         -> Result<T, ConnReceiveError>
         where T: Deserialize<'de>
     {
-        self.receive_inner(timeout, buf)
+        self.receive_inner(None, timeout, buf)
+            .map_err(|err| ConnReceiveError(err))
+    }
+
+    /// Receive a message that was assigned the given route
+    ///
+    /// Works like [`receive`], except that messages assigned to other routes
+    /// are left in the queue. If the firmware has reported errors (see
+    /// `Route::Diagnostics`), the oldest report is returned as an error
+    /// instead.
+    ///
+    /// [`receive`]: #method.receive
+    pub fn receive_from<'de, T>(&mut self,
+        route:   Route,
+        timeout: Duration,
+        buf:     &'de mut Vec<u8>,
+    )
+        -> Result<T, ConnReceiveError>
+        where T: Deserialize<'de>
+    {
+        self.receive_inner(Some(route), timeout, buf)
             .map_err(|err| ConnReceiveError(err))
     }
 
     fn receive_inner<'de, T>(&mut self,
+        route:   Option<Route>,
         timeout: Duration,
         buf:     &'de mut Vec<u8>,
     )
         -> Result<T, Error>
         where T: Deserialize<'de>
     {
-        self.port.set_timeout(timeout)?;
-        buf.clear();
+        *buf = self.shared.receive(route, timeout)?;
+        let message = postcard::from_bytes(buf)?;
+        Ok(message)
+    }
+}
+
+impl Drop for Conn {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+
+        if let Some(reader) = self.reader.take() {
+            // The reader only panics, if another thread panicked while holding
+            // the lock. Nothing to be done about that here.
+            let _ = reader.join();
+        }
+    }
+}
+
+
+/// Where a received frame is queued
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Route {
+    /// USART data and errors, for the given mode
+    Usart(UsartMode),
+
+    /// Replies to pin requests
+    Pin,
+
+    /// Replies to bus (I2C, SPI) transactions
+    Bus,
+
+    /// Errors the firmware reported, while receiving via USART
+    ///
+    /// These aren't received like other messages. The next call to
+    /// [`Conn::receive_from`] returns them as an error.
+    ///
+    /// [`Conn::receive_from`]: struct.Conn.html#method.receive_from
+    Diagnostics(usart::Diagnostics),
+
+    /// Everything else
+    Other,
+}
+
+
+/// Decodes a frame, for use within a router
+///
+/// Returns `None`, if the frame can't be decoded.
+pub fn decode<'de, T>(frame: &'de [u8]) -> Option<T>
+    where T: Deserialize<'de>
+{
+    postcard::from_bytes(frame).ok()
+}
+
+
+struct Shared {
+    queue:    Mutex<Queue>,
+    received: Condvar,
+}
+
+impl Shared {
+    fn new() -> Self {
+        Self {
+            queue: Mutex::new(
+                Queue {
+                    frames:  VecDeque::new(),
+                    dropped: 0,
+                    error:   None,
+                    router:  Box::new(|_| Route::Other),
+                }
+            ),
+            received: Condvar::new(),
+        }
+    }
+
+    /// Takes the oldest frame with the given route from the queue
+    ///
+    /// Takes the oldest frame regardless of route, if `route` is `None`.
+    /// Otherwise, reported diagnostics take precedence, and are returned as an
+    /// error. Dropped frames take precedence over everything.
+    fn receive(&self, route: Option<Route>, timeout: Duration)
+        -> Result<Vec<u8>, Error>
+    {
+        let deadline  = Instant::now() + timeout;
+        let mut queue = self.queue.lock().unwrap();
 
         loop {
-            let mut b = 0; // initialized to `0`, but could be any value
-            self.port.read_exact(slice::from_mut(&mut b))?;
+            // Like a read error, this is kept, so every later call fails too.
+            if queue.dropped > 0 {
+                return Err(Error::QueueOverflow(queue.dropped));
+            }
+
+            if route.is_some() {
+                let diagnostics = queue.frames
+                    .iter()
+                    .enumerate()
+                    .find_map(|(i, (r, _))| match r {
+                        Route::Diagnostics(diagnostics) => {
+                            Some((i, *diagnostics))
+                        }
+                        _ => {
+                            None
+                        }
+                    });
+                if let Some((position, diagnostics)) = diagnostics {
+                    queue.frames.remove(position);
+                    return Err(Error::Diagnostics(diagnostics));
+                }
+            }
+
+            let position = queue.frames
+                .iter()
+                .position(|(r, _)| route.is_none() || route == Some(*r));
+            if let Some(position) = position {
+                // Can't panic, as we just found the frame at this position.
+                let (_, frame) = queue.frames.remove(position).unwrap();
+                return Ok(frame);
+            }
 
-            buf.push(b);
+            // The error is kept, so every later call fails too. `io::Error`
+            // can't be cloned, so we need to re-create it.
+            if let Some(err) = &queue.error {
+                return Err(io::Error::new(err.kind(), err.to_string()).into());
+            }
 
-            if b == 0 {
-                // We're using COBS encoding, so `0` signifies the end of the
-                // message.
-                break;
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::Error::from(io::ErrorKind::TimedOut).into());
             }
+
+            queue = self.received
+                .wait_timeout(queue, deadline - now)
+                .unwrap()
+                .0;
         }
+    }
+}
 
-        let message = postcard::from_bytes_cobs(buf)?;
-        Ok(message)
+struct Queue {
+    frames:  VecDeque<(Route, Vec<u8>)>,
+    dropped: usize,
+    error:   Option<io::Error>,
+    router:  Router,
+}
+
+impl Queue {
+    /// Decodes and routes a COBS-encoded frame, then queues it
+    ///
+    /// Frames that can't be decoded are queued empty, so receiving them
+    /// results in a deserialization error. If the queue is full, the frame is
+    /// dropped and counted instead.
+    fn push(&mut self, mut frame: Vec<u8>) {
+        if self.frames.len() >= MAX_QUEUED {
+            self.dropped += 1;
+            return;
+        }
+
+        match postcard_cobs::decode_in_place(&mut frame) {
+            Ok(len) => frame.truncate(len),
+            Err(()) => frame.clear(),
+        }
+
+        let route = (self.router)(&frame);
+        self.frames.push_back((route, frame));
+    }
+}
+
+type Router = Box<dyn Fn(&[u8]) -> Route + Send>;
+
+
+fn read<R>(mut port: R, shared: &Shared, shutdown: &AtomicBool)
+    where R: io::Read
+{
+    let mut frame = Vec::new();
+
+    while !shutdown.load(Ordering::Relaxed) {
+        let mut b = 0; // initialized to `0`, but could be any value
+        match port.read(slice::from_mut(&mut b)) {
+            Ok(0) => {
+                continue;
+            }
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                continue;
+            }
+            Err(err) => {
+                shared.queue.lock().unwrap().error = Some(err);
+                shared.received.notify_all();
+                return;
+            }
+        }
+
+        frame.push(b);
+
+        if b == 0 {
+            // We're using COBS encoding, so `0` signifies the end of the
+            // message.
+            shared.queue.lock().unwrap().push(frame);
+            shared.received.notify_all();
+
+            frame = Vec::new();
+        }
     }
 }
 
@@ -135,4 +429,192 @@ impl ConnReceiveError {
             }
         }
     }
+
+    /// The errors the firmware reported, if this is such a report
+    pub fn diagnostics(&self) -> Option<&usart::Diagnostics> {
+        match &self.0 {
+            Error::Diagnostics(diagnostics) => Some(diagnostics),
+            _                               => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::{
+            atomic::{
+                AtomicBool,
+                Ordering,
+            },
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use protocol::usart::{
+        Diagnostics,
+        Link,
+        RxErrorCounts,
+    };
+
+    use super::{
+        read,
+        Route,
+        Shared,
+        MAX_QUEUED,
+    };
+
+
+    #[test]
+    fn it_should_route_interleaved_frames() {
+        // Even numbers go to `Route::Pin`, odd ones to `Route::Bus`.
+        let messages: Vec<u32> = (0..100).collect();
+
+        let mut data = Vec::new();
+        for message in &messages {
+            let mut buf = [0; 16];
+            let frame = postcard::to_slice_cobs(message, &mut buf).unwrap();
+            data.extend_from_slice(frame);
+        }
+
+        let shared = Arc::new(Shared::new());
+        shared.queue.lock().unwrap().router = Box::new(|frame| {
+            match super::decode::<u32>(frame) {
+                Some(n) if n % 2 == 0 => Route::Pin,
+                Some(_)               => Route::Bus,
+                None                  => Route::Other,
+            }
+        });
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let reader   = {
+            let shared   = shared.clone();
+            let shutdown = shutdown.clone();
+            let port     = io::Cursor::new(data);
+
+            thread::spawn(move || read(port, &shared, &shutdown))
+        };
+
+        let timeout = Duration::from_millis(500);
+        let mut even = Vec::new();
+        let mut odd  = Vec::new();
+
+        // Take the odd ones first, so the even ones have to wait in the queue.
+        for _ in 0..messages.len() / 2 {
+            let frame = shared.receive(Some(Route::Bus), timeout).unwrap();
+            odd.push(postcard::from_bytes::<u32>(&frame).unwrap());
+        }
+        for _ in 0..messages.len() / 2 {
+            let frame = shared.receive(Some(Route::Pin), timeout).unwrap();
+            even.push(postcard::from_bytes::<u32>(&frame).unwrap());
+        }
+
+        shutdown.store(true, Ordering::Relaxed);
+        reader.join().unwrap();
+
+        let expected_even: Vec<_> =
+            messages.iter().copied().filter(|n| n % 2 == 0).collect();
+        let expected_odd: Vec<_> =
+            messages.iter().copied().filter(|n| n % 2 == 1).collect();
+
+        assert_eq!(even, expected_even);
+        assert_eq!(odd, expected_odd);
+        assert!(shared.queue.lock().unwrap().frames.is_empty());
+    }
+
+    #[test]
+    fn it_should_keep_reporting_a_read_error() {
+        struct Broken;
+
+        impl io::Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::from(io::ErrorKind::BrokenPipe))
+            }
+        }
+
+        let shared   = Shared::new();
+        let shutdown = AtomicBool::new(false);
+        read(Broken, &shared, &shutdown);
+
+        let timeout = Duration::from_millis(10);
+        for _ in 0..3 {
+            match shared.receive(None, timeout) {
+                Err(crate::Error::Io(err)) => {
+                    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+                }
+                result => {
+                    panic!("Expected read error, got {:?}", result);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_should_report_diagnostics_on_the_next_routed_receive() {
+        let diagnostics = Diagnostics {
+            link:      Link::Host,
+            rx_errors: RxErrorCounts {
+                overrun: 1,
+                .. RxErrorCounts::default()
+            },
+        };
+
+        // `0` is a diagnostics report, everything else a regular message.
+        let shared = Shared::new();
+        shared.queue.lock().unwrap().router = Box::new(move |frame| {
+            match super::decode::<u32>(frame) {
+                Some(0) => Route::Diagnostics(diagnostics),
+                _       => Route::Other,
+            }
+        });
+
+        for message in &[1u32, 0] {
+            let mut buf = [0; 16];
+            let frame = postcard::to_slice_cobs(message, &mut buf).unwrap();
+            shared.queue.lock().unwrap().push(frame.to_vec());
+        }
+
+        let timeout = Duration::from_millis(10);
+
+        // The report is returned first, even though it was received last.
+        match shared.receive(Some(Route::Bus), timeout) {
+            Err(crate::Error::Diagnostics(reported)) => {
+                assert_eq!(reported, diagnostics);
+            }
+            result => {
+                panic!("Expected diagnostics, got {:?}", result);
+            }
+        }
+
+        let frame = shared.receive(Some(Route::Other), timeout).unwrap();
+        assert_eq!(postcard::from_bytes::<u32>(&frame).unwrap(), 1);
+        assert!(shared.queue.lock().unwrap().frames.is_empty());
+    }
+
+    #[test]
+    fn it_should_keep_reporting_dropped_frames() {
+        let shared = Shared::new();
+
+        for message in 0..MAX_QUEUED as u32 + 2 {
+            let mut buf = [0; 16];
+            let frame = postcard::to_slice_cobs(&message, &mut buf).unwrap();
+            shared.queue.lock().unwrap().push(frame.to_vec());
+        }
+
+        let timeout = Duration::from_millis(10);
+        for route in &[None, Some(Route::Other)] {
+            match shared.receive(*route, timeout) {
+                Err(crate::Error::QueueOverflow(dropped)) => {
+                    assert_eq!(dropped, 2);
+                }
+                result => {
+                    panic!("Expected queue overflow, got {:?}", result);
+                }
+            }
+        }
+    }
 }
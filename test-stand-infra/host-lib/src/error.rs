@@ -3,6 +3,8 @@
 
 use std::io;
 
+use protocol::usart;
+
 
 /// The result type for this library
 ///
@@ -26,6 +28,14 @@ pub enum Error {
 
     /// Error occurred while accessing the serial port
     Serial(serialport::Error),
+
+    /// The firmware reported errors while receiving via USART
+    Diagnostics(usart::Diagnostics),
+
+    /// Received frames were dropped, because too many were queued
+    ///
+    /// Contains the number of dropped frames.
+    QueueOverflow(usize),
 }
 
 impl From<toml::de::Error> for Error {
@@ -2,11 +2,16 @@
 
 
 pub mod assistant;
+pub mod benchmark;
 pub mod config;
 pub mod conn;
 pub mod error;
+pub mod lin;
 pub mod pin;
+pub mod rs485;
+pub mod spi;
 pub mod test_stand;
+pub mod usart;
 
 
 pub use self::{
@@ -0,0 +1,243 @@
+//! Construction and verification of LIN frames
+//!
+//! The firmwares only send and receive the bytes of a frame. Protected
+//! identifiers and checksums are computed here.
+
+
+use protocol::usart::LIN_SYNC;
+
+
+/// The maximum number of data bytes in a frame
+pub const MAX_DATA: usize = 8;
+
+
+/// Computes the protected identifier (PID) for a frame identifier
+///
+/// The PID consists of the 6-bit identifier and two parity bits. Returns an
+/// error, if `id` doesn't fit into 6 bits.
+pub fn pid(id: u8) -> Result<u8, InvalidId> {
+    if id > 0x3f {
+        return Err(InvalidId(id));
+    }
+
+    let bit = |n: u8| (id >> n) & 0x1;
+
+    let p0 = bit(0) ^ bit(1) ^ bit(2) ^ bit(4);
+    let p1 = !(bit(1) ^ bit(3) ^ bit(4) ^ bit(5)) & 0x1;
+
+    Ok(id | p0 << 6 | p1 << 7)
+}
+
+/// Extracts the frame identifier from a protected identifier (PID)
+///
+/// Returns an error, if the parity bits are wrong.
+pub fn id(pid: u8) -> Result<u8, ParityError> {
+    let id = pid & 0x3f;
+
+    // Can't fail, as `id` fits into 6 bits.
+    match self::pid(id) {
+        Ok(expected) if expected == pid => Ok(id),
+        _                               => Err(ParityError(pid)),
+    }
+}
+
+/// Computes the checksum of a frame
+///
+/// `pid` is only included in the enhanced checksum.
+pub fn checksum(model: Checksum, pid: u8, data: &[u8]) -> u8 {
+    let initial = match model {
+        Checksum::Classic  => 0,
+        Checksum::Enhanced => pid as u16,
+    };
+
+    // Sum with carry, meaning a carry is added back to the sum.
+    let sum = data.iter().fold(initial, |sum, &b| {
+        let sum = sum + b as u16;
+        match sum > 0xff {
+            true  => sum - 0xff,
+            false => sum,
+        }
+    });
+
+    !(sum as u8)
+}
+
+
+/// The checksum model of a frame
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Checksum {
+    /// Only covers the data bytes (LIN 1.x)
+    Classic,
+
+    /// Covers the protected identifier and data bytes (LIN 2.x)
+    Enhanced,
+}
+
+
+/// A LIN frame
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Frame {
+    /// The protected identifier
+    pub pid: u8,
+
+    /// The data bytes
+    pub data: Vec<u8>,
+
+    /// The checksum model used for the frame
+    pub checksum: Checksum,
+}
+
+impl Frame {
+    /// Create a new frame
+    ///
+    /// Returns an error, if `id` doesn't fit into 6 bits, or if `data` has
+    /// more than [`MAX_DATA`] bytes, or none.
+    ///
+    /// [`MAX_DATA`]: constant.MAX_DATA.html
+    pub fn new(id: u8, data: &[u8], checksum: Checksum)
+        -> Result<Self, FrameError>
+    {
+        let pid = pid(id)
+            .map_err(|err| FrameError::InvalidId(err))?;
+
+        if data.is_empty() || data.len() > MAX_DATA {
+            return Err(FrameError::InvalidLength(data.len()));
+        }
+
+        Ok(
+            Self {
+                pid,
+                data: data.to_vec(),
+                checksum,
+            }
+        )
+    }
+
+    /// The header of the frame, sent by the master
+    ///
+    /// Consists of the sync byte and the protected identifier. The break that
+    /// precedes them is not included.
+    pub fn header(&self) -> [u8; 2] {
+        [LIN_SYNC, self.pid]
+    }
+
+    /// The response of the frame
+    ///
+    /// Consists of the data bytes and the checksum.
+    pub fn response(&self) -> Vec<u8> {
+        let mut response = self.data.clone();
+        response.push(checksum(self.checksum, self.pid, &self.data));
+        response
+    }
+
+    /// All bytes of the frame, after the break
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header().to_vec();
+        bytes.extend(self.response());
+        bytes
+    }
+
+    /// Parses a frame from the bytes that followed a break
+    ///
+    /// Expects the sync byte, the protected identifier, data bytes, and the
+    /// checksum. Returns an error, if any of those are wrong.
+    pub fn parse(bytes: &[u8], checksum: Checksum)
+        -> Result<Self, FrameError>
+    {
+        // Sync, PID, at least one data byte, checksum
+        if bytes.len() < 4 || bytes.len() > MAX_DATA + 3 {
+            return Err(FrameError::InvalidLength(bytes.len()));
+        }
+
+        if bytes[0] != LIN_SYNC {
+            return Err(FrameError::InvalidSync(bytes[0]));
+        }
+
+        let pid = bytes[1];
+        id(pid)
+            .map_err(|err| FrameError::Parity(err))?;
+
+        let (data, received) = bytes[2..].split_at(bytes.len() - 3);
+
+        let expected = self::checksum(checksum, pid, data);
+        if received[0] != expected {
+            return Err(
+                FrameError::Checksum {
+                    expected,
+                    received: received[0],
+                }
+            );
+        }
+
+        Ok(
+            Self {
+                pid,
+                data: data.to_vec(),
+                checksum,
+            }
+        )
+    }
+}
+
+
+/// The frame identifier doesn't fit into 6 bits
+#[derive(Debug)]
+pub struct InvalidId(pub u8);
+
+/// The parity bits of a protected identifier are wrong
+#[derive(Debug)]
+pub struct ParityError(pub u8);
+
+#[derive(Debug)]
+pub enum FrameError {
+    Checksum {
+        expected: u8,
+        received: u8,
+    },
+    InvalidId(InvalidId),
+    InvalidLength(usize),
+    InvalidSync(u8),
+    Parity(ParityError),
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Checksum,
+        checksum,
+        id,
+        pid,
+    };
+
+
+    #[test]
+    fn it_should_compute_protected_identifiers() {
+        // No parity bit set
+        assert_eq!(pid(0x3c).unwrap(), 0x3c);
+        // Only P0 set
+        assert_eq!(pid(0x21).unwrap(), 0x61);
+        // Only P1 set
+        assert_eq!(pid(0x00).unwrap(), 0x80);
+        // Both set
+        assert_eq!(pid(0x01).unwrap(), 0xc1);
+
+        assert!(pid(0x40).is_err());
+    }
+
+    #[test]
+    fn it_should_reject_protected_identifiers_with_wrong_parity() {
+        assert_eq!(id(0x61).unwrap(), 0x21);
+        assert!(id(0x21).is_err());
+        assert!(id(0xe1).is_err());
+    }
+
+    #[test]
+    fn it_should_compute_checksums() {
+        // The example from the LIN 2.2A specification, section 2.8.3
+        let data = [0x4a, 0x55, 0x93, 0xe5];
+
+        assert_eq!(checksum(Checksum::Classic, 0x50, &data), 0xe6);
+        assert_eq!(checksum(Checksum::Enhanced, 0x50, &data), 0x96);
+    }
+}
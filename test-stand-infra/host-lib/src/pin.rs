@@ -2,6 +2,7 @@
 
 
 use std::{
+    collections::HashMap,
     convert::TryInto,
     fmt::Debug,
     mem::transmute,
@@ -14,12 +15,16 @@ use serde::{
     Serialize,
 };
 
-use protocol::pin;
+use protocol::pin::{
+    self,
+    PinId,
+};
 
 use crate::conn::{
     Conn,
     ConnReceiveError,
     ConnSendError,
+    Route,
 };
 
 
@@ -61,6 +66,57 @@ impl<Id> Pin<Id>
         Ok(())
     }
 
+    /// Commands the node to change the pin's configuration
+    ///
+    /// Can be used to switch the pin between input and output, enable pull
+    /// resistors, or select open-drain mode.
+    pub fn configure<M>(&mut self,
+        config: pin::Config,
+        conn: &mut Conn,
+    )
+        -> Result<(), ConnSendError>
+        where
+            M: From<pin::Configure<Id>> + Serialize,
+    {
+        let command = pin::Configure { pin: self.pin, config };
+        let message: M = command.into();
+        conn.send(&message)?;
+
+        Ok(())
+    }
+
+    /// Commands the node to play a waveform on the pin
+    ///
+    /// Constructs the command, converts it into a message that the node will
+    /// understand, then sends that message to the node through `conn`.
+    pub fn generate_waveform<M>(&mut self,
+        waveform: pin::Waveform,
+        conn: &mut Conn,
+    )
+        -> Result<(), ConnSendError>
+        where
+            M: From<pin::GenerateWaveform<Id>> + Serialize,
+    {
+        let command = pin::GenerateWaveform { pin: self.pin, waveform };
+        let message: M = command.into();
+        conn.send(&message)?;
+
+        Ok(())
+    }
+
+    /// Commands the node to stop playing a waveform on the pin
+    pub fn stop_waveform<M>(&mut self, conn: &mut Conn)
+        -> Result<(), ConnSendError>
+        where
+            M: From<pin::StopWaveform<Id>> + Serialize,
+    {
+        let command = pin::StopWaveform { pin: self.pin };
+        let message: M = command.into();
+        conn.send(&message)?;
+
+        Ok(())
+    }
+
     /// Read level for the given pin
     ///
     /// Receives from `conn`, expecting to receive a "level changed" message.
@@ -95,7 +151,7 @@ impl<Id> Pin<Id>
         let mut buf: Vec<u8> = Vec::new();
         let buf = unsafe { transmute(&mut buf) };
 
-        let reply = conn.receive::<Reply>(timeout, buf)
+        let reply = conn.receive_from::<Reply>(Route::Pin, timeout, buf)
             .map_err(|err| ReadLevelError::Receive(err))?;
 
         match reply.try_into() {
@@ -126,6 +182,332 @@ impl<Id> Pin<Id>
             }
         }
     }
+
+    /// Measure the periodic signal on the pin
+    ///
+    /// Commands the node to measure the given number of cycles, then waits for
+    /// the result. `timeout` must be long enough for the node to measure all
+    /// cycles. `cycles` must not be zero.
+    pub fn measure_signal<'de, Request, Reply>(&mut self,
+        cycles:  u32,
+        timeout: Duration,
+        conn:    &mut Conn,
+    )
+        -> Result<SignalMeasurement, MeasureSignalError>
+        where
+            Id: Debug + Eq,
+            Request: From<pin::MeasureSignal<Id>> + Serialize,
+            Reply: TryInto<
+                    Result<pin::MeasureSignalResult<Id>, pin::MeasureError<Id>>,
+                    Error=Reply,
+                >
+                + Debug
+                + Deserialize<'de>,
+    {
+        if cycles == 0 {
+            return Err(MeasureSignalError::NoCycles);
+        }
+
+        let request = pin::MeasureSignal { pin: self.pin, cycles };
+        let request: Request = request.into();
+        conn.send(&request)
+            .map_err(|err| MeasureSignalError::Send(err))?;
+
+        // See comment in `read_level` for why this is sound.
+        let mut buf: Vec<u8> = Vec::new();
+        let buf = unsafe { transmute(&mut buf) };
+
+        let reply = conn.receive_from::<Reply>(Route::Pin, timeout, buf)
+            .map_err(|err| MeasureSignalError::Receive(err))?;
+
+        match reply.try_into() {
+            Ok(Ok(result)) if result.pin == self.pin && result.cycles > 0 => {
+                Ok(SignalMeasurement::from_result(&result))
+            }
+            Ok(Err(err)) if err.pin == self.pin => {
+                Err(MeasureSignalError::Measure(err.kind))
+            }
+            Ok(result) => {
+                Err(
+                    MeasureSignalError::UnexpectedMessage(
+                        format!("{:?}", result)
+                    )
+                )
+            }
+            Err(message) => {
+                Err(
+                    MeasureSignalError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+
+    /// Measure how fast the `input` pin follows this pin
+    ///
+    /// Commands the node to toggle this pin `samples` times, measuring how long
+    /// it takes each time until `input` has the same level. This can be used to
+    /// measure how fast another node reacts to a pin interrupt. `timeout` must
+    /// be long enough for the node to measure all samples.
+    pub fn measure_latency<'de, Request, Reply>(&mut self,
+        input:     &Pin<Id>,
+        samples:   u32,
+        bin_width: Duration,
+        timeout:   Duration,
+        conn:      &mut Conn,
+    )
+        -> Result<LatencyMeasurement, MeasureLatencyError>
+        where
+            Id: Debug + Eq,
+            Request: From<pin::MeasureLatency<Id>> + Serialize,
+            Reply: TryInto<
+                    Result<
+                        pin::MeasureLatencyResult<Id>,
+                        pin::MeasureError<Id>,
+                    >,
+                    Error=Reply,
+                >
+                + Debug
+                + Deserialize<'de>,
+    {
+        let request = pin::MeasureLatency {
+            output:       self.pin,
+            input:        input.pin,
+            samples,
+            bin_width_ns: bin_width.as_nanos() as u32,
+        };
+        let request: Request = request.into();
+        conn.send(&request)
+            .map_err(|err| MeasureLatencyError::Send(err))?;
+
+        // See comment in `read_level` for why this is sound.
+        let mut buf: Vec<u8> = Vec::new();
+        let buf = unsafe { transmute(&mut buf) };
+
+        let reply = conn.receive_from::<Reply>(Route::Pin, timeout, buf)
+            .map_err(|err| MeasureLatencyError::Receive(err))?;
+
+        match reply.try_into() {
+            Ok(Ok(result))
+                if result.output == self.pin && result.input == input.pin
+            => {
+                Ok(LatencyMeasurement::from_result(&result))
+            }
+            Ok(Err(err)) if err.pin == self.pin || err.pin == input.pin => {
+                Err(MeasureLatencyError::Measure(err.kind))
+            }
+            Ok(result) => {
+                Err(
+                    MeasureLatencyError::UnexpectedMessage(
+                        format!("{:?}", result)
+                    )
+                )
+            }
+            Err(message) => {
+                Err(
+                    MeasureLatencyError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                )
+            }
+        }
+    }
+}
+
+
+/// The pins of a test node, as reported by the node during the handshake
+///
+/// Resolves the names that a test suite uses into pin ids. The node's names can
+/// be overridden in the configuration file.
+#[derive(Debug)]
+pub struct PinMap {
+    pins:    Vec<(PinId, Option<String>)>,
+    aliases: HashMap<String, String>,
+}
+
+impl PinMap {
+    /// Create an empty pin map
+    ///
+    /// `aliases` maps names used by the test suite to names from the node's pin
+    /// map.
+    pub fn new(aliases: HashMap<String, String>) -> Self {
+        Self {
+            pins: Vec::new(),
+            aliases,
+        }
+    }
+
+    /// Add a pin that the node reported
+    pub fn add(&mut self, info: pin::PinInfo) {
+        self.pins.push((info.id, info.name.map(|name| name.to_string())));
+    }
+
+    /// Resolve a pin name into a pin
+    ///
+    /// If the configuration defines an alias for `name`, the alias is looked
+    /// up instead. Pins that don't have a name can be resolved using their
+    /// index, for example `"3"`.
+    pub fn resolve(&self, name: &str) -> Result<Pin<PinId>, UnknownPinError> {
+        let name = self.aliases
+            .get(name)
+            .map(|alias| alias.as_str())
+            .unwrap_or(name);
+        let index = name.parse().ok().map(PinId);
+
+        self.pins
+            .iter()
+            .find(|(id, pin_name)| {
+                pin_name.as_deref() == Some(name) || Some(*id) == index
+            })
+            .map(|&(id, _)| Pin::new(id))
+            .ok_or_else(|| UnknownPinError(name.to_string()))
+    }
+}
+
+
+/// The result of measuring a periodic signal
+#[derive(Debug)]
+pub struct SignalMeasurement {
+    /// The number of cycles that were measured
+    pub cycles: u32,
+
+    /// The duration of the high phases
+    pub high: DurationStatistics,
+
+    /// The duration of the low phases
+    pub low: DurationStatistics,
+
+    /// The duration of the full cycles
+    pub period: DurationStatistics,
+
+    /// The frequency of the signal in Hz, based on the mean period
+    pub frequency_hz: f64,
+
+    /// The duty cycle of the signal, as a fraction between `0.0` and `1.0`
+    pub duty_cycle: RatioStatistics,
+}
+
+impl SignalMeasurement {
+    fn from_result<Id>(result: &pin::MeasureSignalResult<Id>) -> Self {
+        let cycles = result.cycles;
+        let period = DurationStatistics::from_us(&result.period_us, cycles);
+
+        let frequency_hz = match period.mean.as_secs_f64() {
+            period if period > 0.0 => 1.0 / period,
+            _                      => 0.0,
+        };
+
+        Self {
+            cycles,
+            high:       DurationStatistics::from_us(&result.high_us, cycles),
+            low:        DurationStatistics::from_us(&result.low_us, cycles),
+            period,
+            frequency_hz,
+            duty_cycle: RatioStatistics::from_permille(
+                &result.duty_cycle_permille,
+                cycles,
+            ),
+        }
+    }
+}
+
+
+/// The result of measuring latency between two pins
+#[derive(Debug)]
+pub struct LatencyMeasurement {
+    /// The number of edges for which a latency was measured
+    pub samples: u32,
+
+    /// The number of edges for which the input pin did not follow in time
+    pub timeouts: u32,
+
+    /// Statistics about the measured latencies
+    ///
+    /// This is `None`, if no latencies could be measured.
+    pub latency: Option<DurationStatistics>,
+
+    /// Histogram of the measured latencies
+    pub histogram: DurationHistogram,
+}
+
+impl LatencyMeasurement {
+    fn from_result<Id>(result: &pin::MeasureLatencyResult<Id>) -> Self {
+        Self {
+            samples:   result.samples,
+            timeouts:  result.timeouts,
+            latency:   result.latency_ns.as_ref().map(|statistics| {
+                DurationStatistics::from_ns(statistics, result.samples)
+            }),
+            histogram: DurationHistogram {
+                bin_width: Duration::from_nanos(
+                    result.histogram.bin_width as u64
+                ),
+                bins:      result.histogram.bins.to_vec(),
+            },
+        }
+    }
+}
+
+
+/// A histogram of measured durations
+#[derive(Debug)]
+pub struct DurationHistogram {
+    /// The width of each bin
+    pub bin_width: Duration,
+
+    /// The number of durations that fall into each bin
+    ///
+    /// Bin `i` counts durations from `i * bin_width` up to (excluding)
+    /// `(i + 1) * bin_width`. The last bin also counts all durations that are
+    /// too long for any of the bins.
+    pub bins: Vec<u32>,
+}
+
+
+/// Statistics about measured durations
+#[derive(Debug)]
+pub struct DurationStatistics {
+    pub min:  Duration,
+    pub max:  Duration,
+    pub mean: Duration,
+}
+
+impl DurationStatistics {
+    fn from_us(statistics: &pin::Statistics, n: u32) -> Self {
+        Self {
+            min:  Duration::from_micros(statistics.min as u64),
+            max:  Duration::from_micros(statistics.max as u64),
+            mean: Duration::from_micros(statistics.sum / n as u64),
+        }
+    }
+
+    fn from_ns(statistics: &pin::Statistics, n: u32) -> Self {
+        Self {
+            min:  Duration::from_nanos(statistics.min as u64),
+            max:  Duration::from_nanos(statistics.max as u64),
+            mean: Duration::from_nanos(statistics.sum / n as u64),
+        }
+    }
+}
+
+
+/// Statistics about measured ratios
+#[derive(Debug)]
+pub struct RatioStatistics {
+    pub min:  f64,
+    pub max:  f64,
+    pub mean: f64,
+}
+
+impl RatioStatistics {
+    fn from_permille(statistics: &pin::Statistics, n: u32) -> Self {
+        Self {
+            min:  statistics.min as f64 / 1000.0,
+            max:  statistics.max as f64 / 1000.0,
+            mean: statistics.sum as f64 / 1000.0 / n as f64,
+        }
+    }
 }
 
 
@@ -136,3 +518,24 @@ pub enum ReadLevelError {
     UnexpectedMessage(String),
     Timeout,
 }
+
+/// The pin map doesn't contain a pin with this name
+#[derive(Debug)]
+pub struct UnknownPinError(pub String);
+
+#[derive(Debug)]
+pub enum MeasureSignalError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    Measure(pin::MeasureErrorKind),
+    NoCycles,
+    UnexpectedMessage(String),
+}
+
+#[derive(Debug)]
+pub enum MeasureLatencyError {
+    Send(ConnSendError),
+    Receive(ConnReceiveError),
+    Measure(pin::MeasureErrorKind),
+    UnexpectedMessage(String),
+}
@@ -0,0 +1,79 @@
+//! Evaluation of RS-485 driver enable timing
+//!
+//! The assistant only reports when it saw the driver enable signal change, and
+//! when the first start bit began (see [`usart::Rs485Timing`]). This module
+//! relates those to the transmission.
+//!
+//! [`usart::Rs485Timing`]: ../../protocol/usart/struct.Rs485Timing.html
+
+
+use std::time::Duration;
+
+use protocol::usart;
+
+
+/// The timing of the driver enable signal, relative to a transmission
+#[derive(Debug)]
+pub struct DriverEnableTiming {
+    /// Time from assertion of driver enable to the start of the first start bit
+    ///
+    /// Negative, if the transmission started before driver enable was
+    /// asserted.
+    pub setup_ns: i64,
+
+    /// Time from the end of the last stop bit to release of driver enable
+    ///
+    /// Negative, if driver enable was released before the transmission ended.
+    pub hold_ns: i64,
+
+    /// How long driver enable was asserted
+    pub asserted: Duration,
+}
+
+impl DriverEnableTiming {
+    /// Evaluates the timing reported by the assistant
+    ///
+    /// `bytes` is the number of bytes that were sent, `config` the line
+    /// parameters they were sent with. The end of the transmission is computed
+    /// from those, assuming that all bytes were sent back to back.
+    ///
+    /// Returns an error, if the assistant didn't observe all required events.
+    pub fn new(
+        timing: &usart::Rs485Timing,
+        bytes:  usize,
+        config: &usart::LineConfig,
+    )
+        -> Result<Self, IncompleteTimingError>
+    {
+        let (asserted, first_start, released) = match *timing {
+            usart::Rs485Timing {
+                de_asserted_ns: Some(asserted),
+                first_start_ns: Some(first_start),
+                de_released_ns: Some(released),
+            } => {
+                (asserted as i64, first_start as i64, released as i64)
+            }
+            timing => {
+                return Err(IncompleteTimingError(timing));
+            }
+        };
+
+        let transmission_ns = bytes as i64
+            * config.bits_per_char() as i64
+            * 1_000_000_000
+            / config.baud_rate as i64;
+
+        Ok(
+            Self {
+                setup_ns: first_start - asserted,
+                hold_ns:  released - (first_start + transmission_ns),
+                asserted: Duration::from_nanos((released - asserted) as u64),
+            }
+        )
+    }
+}
+
+
+/// The assistant didn't observe all events of an RS-485 transmission
+#[derive(Debug)]
+pub struct IncompleteTimingError(pub usart::Rs485Timing);
@@ -0,0 +1,81 @@
+//! Evaluation of SPI slave select timing
+//!
+//! The assistant only reports when it saw slave select and the clock change
+//! (see [`spi::Timing`]). This module relates those to each other.
+//!
+//! [`spi::Timing`]: ../../protocol/spi/struct.Timing.html
+
+
+use std::time::Duration;
+
+use protocol::spi;
+
+
+/// The timing of slave select, relative to the clock of a transfer
+#[derive(Debug)]
+pub struct SlaveSelectTiming {
+    /// Time from assertion of slave select to the first clock edge
+    pub setup: Duration,
+
+    /// Time from the last clock edge to release of slave select
+    pub hold: Duration,
+
+    /// The number of complete bytes that were clocked
+    pub bytes: u32,
+
+    /// The shortest and longest gap between bytes
+    ///
+    /// `None`, if less than two bytes were transferred. Bytes that are
+    /// transferred back to back have a gap of half a clock period.
+    pub gaps: Option<(Duration, Duration)>,
+}
+
+impl SlaveSelectTiming {
+    /// Evaluates the timing reported by the assistant
+    ///
+    /// Returns an error, if the assistant didn't observe all required events.
+    pub fn new(timing: &spi::Timing)
+        -> Result<Self, IncompleteTimingError>
+    {
+        let (asserted, first_clock, last_clock, released) = match *timing {
+            spi::Timing {
+                ssel_asserted_ns: Some(asserted),
+                first_clock_ns:   Some(first_clock),
+                last_clock_ns:    Some(last_clock),
+                ssel_released_ns: Some(released),
+                ..
+            } => {
+                (asserted, first_clock, last_clock, released)
+            }
+            timing => {
+                return Err(IncompleteTimingError(timing));
+            }
+        };
+
+        let gaps = match (timing.min_gap_ns, timing.max_gap_ns) {
+            (Some(min), Some(max)) => {
+                Some((
+                    Duration::from_nanos(min as u64),
+                    Duration::from_nanos(max as u64),
+                ))
+            }
+            _ => {
+                None
+            }
+        };
+
+        Ok(
+            Self {
+                setup: Duration::from_nanos((first_clock - asserted) as u64),
+                hold:  Duration::from_nanos((released - last_clock) as u64),
+                bytes: timing.bytes,
+                gaps,
+            }
+        )
+    }
+}
+
+
+/// The assistant didn't observe all events of an SPI transfer
+#[derive(Debug)]
+pub struct IncompleteTimingError(pub spi::Timing);
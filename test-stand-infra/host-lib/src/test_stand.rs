@@ -7,7 +7,10 @@ use std::sync::{
 use lazy_static::lazy_static;
 
 use crate::{
-    assistant::Assistant,
+    assistant::{
+        Assistant,
+        AssistantInitError,
+    },
     config::{
         Config,
         ConfigReadError,
@@ -78,7 +81,10 @@ impl TestStand {
         if let Some(path) = config.assistant {
             let conn = Conn::new(&path)
                 .map_err(|err| TestStandInitError::ConnInit(err))?;
-            assistant = Ok(Assistant::new(conn));
+            assistant = Ok(
+                Assistant::new(conn, config.assistant_pins)
+                    .map_err(|err| TestStandInitError::AssistantInit(err))?
+            );
         }
 
         Ok(
@@ -95,6 +101,9 @@ impl TestStand {
 /// Error initializing the test stand
 #[derive(Debug)]
 pub enum TestStandInitError {
+    /// Error performing the handshake with the test assistant
+    AssistantInit(AssistantInitError),
+
     /// Error reading configuration
     ConfigRead(ConfigReadError),
 
@@ -0,0 +1,107 @@
+//! Support for monitoring USART reception on a test node
+
+
+use std::{
+    fmt::Debug,
+    mem::transmute,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use serde::Deserialize;
+
+use protocol::usart::RxError;
+
+use crate::conn::{
+    Conn,
+    ConnReceiveError,
+    Route,
+};
+
+
+/// Wait for a node to report the provided USART receive error
+///
+/// Receives messages from `route` and passes them to `classify`, which returns
+/// `Ok(Some(error))` for a reported receive error, `Ok(None)` for messages
+/// that should be ignored (like received data), and gives the message back as
+/// `Err`, if it's unexpected.
+///
+/// Returns all errors that the node reported until then, including the
+/// expected one. Returns an error, if it times out before that, or an I/O
+/// error occurs.
+///
+/// Once the expected error has been reported, keeps receiving until the node
+/// has been quiet for a moment, so anything else caused by the fault doesn't
+/// affect later calls.
+pub fn wait_for_error<'de, Reply>(
+    error:    RxError,
+    timeout:  Duration,
+    route:    Route,
+    conn:     &mut Conn,
+    classify: impl Fn(Reply) -> Result<Option<RxError>, Reply>,
+)
+    -> Result<Vec<RxError>, WaitForErrorError>
+    where Reply: Debug + Deserialize<'de>
+{
+    let mut errors = Vec::new();
+    let     start  = Instant::now();
+
+    loop {
+        let found = errors.contains(&error);
+        if !found && start.elapsed() > timeout {
+            return Err(WaitForErrorError::Timeout);
+        }
+
+        let receive_timeout = match found {
+            true  => Duration::from_millis(10),
+            false => timeout,
+        };
+
+        // Sound, as nothing that references the buffer outlives this loop
+        // iteration. See comment in `Pin::read_level`.
+        let mut buf: Vec<u8> = Vec::new();
+        let buf = unsafe { transmute(&mut buf) };
+
+        let message = match conn.receive_from::<Reply>(
+            route,
+            receive_timeout,
+            buf,
+        ) {
+            Ok(message) => {
+                message
+            }
+            Err(err) if found && err.is_timeout() => {
+                return Ok(errors);
+            }
+            Err(err) => {
+                return Err(WaitForErrorError::Receive(err));
+            }
+        };
+
+        match classify(message) {
+            Ok(Some(reported)) => {
+                errors.push(reported);
+            }
+            Ok(None) => {
+                // Data received while injecting faults is garbage.
+            }
+            Err(message) => {
+                return Err(
+                    WaitForErrorError::UnexpectedMessage(
+                        format!("{:?}", message)
+                    )
+                );
+            }
+        }
+    }
+}
+
+
+#[derive(Debug)]
+pub enum WaitForErrorError {
+    Receive(ConnReceiveError),
+    Timeout,
+    UnexpectedMessage(String),
+}
@@ -0,0 +1,143 @@
+//! Generic protocol related to I2C
+//!
+//! The types in this module are not specific to any test stand setup, and can
+//! be re-used for different test stands.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+
+/// The maximum number of bytes written or read in one I2C transaction
+///
+/// Applies to both directions separately. A combined transaction can write
+/// and read this many bytes each.
+pub const MAX_TRANSFER: usize = 32;
+
+/// The number of registers of an emulated I2C slave
+///
+/// The register pointer is 8 bits wide, and wraps around after the last
+/// register.
+pub const REGISTERS: usize = 256;
+
+
+/// The first 7-bit address that a bus scan probes
+///
+/// Lower addresses are reserved by the I2C specification.
+pub const SCAN_FIRST: u8 = 0x08;
+
+/// The last 7-bit address that a bus scan probes
+///
+/// Higher addresses are reserved, including the ones that start a 10-bit
+/// address.
+pub const SCAN_LAST: u8 = 0x77;
+
+
+/// The address of an I2C slave
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Address {
+    /// A 7-bit address, sent in the same byte as the R/W bit
+    SevenBit(u8),
+
+    /// A 10-bit address, sent as two bytes
+    ///
+    /// The first byte is `0b11110`, followed by the two most significant bits
+    /// of the address and the R/W bit. The second byte contains the 8 least
+    /// significant bits. Reading requires a write of both bytes first,
+    /// followed by a repeated start with just the first one.
+    TenBit(u16),
+}
+
+impl Address {
+    /// The bytes the address is sent as
+    ///
+    /// The first byte is a 7-bit value, which is sent together with the R/W
+    /// bit. The second byte is only present for a 10-bit address.
+    pub fn bytes(&self) -> (u8, Option<u8>) {
+        match *self {
+            Address::SevenBit(address) => {
+                (address & 0x7f, None)
+            }
+            Address::TenBit(address) => {
+                let high_bits = (address >> 8) as u8 & 0x3;
+                (0x78 | high_bits, Some(address as u8))
+            }
+        }
+    }
+}
+
+
+/// Configures an emulated I2C slave
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct SlaveConfig {
+    /// The address the slave acknowledges
+    pub address: Address,
+
+    /// Whether the register pointer advances after every data byte
+    ///
+    /// If enabled, the slave behaves like an EEPROM, with consecutive bytes
+    /// accessing consecutive registers. Otherwise, they all access the same
+    /// register, like when polling a sensor's data register.
+    pub auto_increment: bool,
+}
+
+
+/// A fault that the assistant injects into the I2C bus
+///
+/// Except for `HoldSda`, faults affect the next transaction addressed to the
+/// assistant, and are cleared once they have been injected.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Fault {
+    /// Don't acknowledge the address
+    NackAddress,
+
+    /// Don't acknowledge the data byte with the given index
+    ///
+    /// Only applies to writes. Index `0` is the first byte after the address.
+    NackData {
+        index: u8,
+    },
+
+    /// Hold SCL low for the given duration, before acknowledging the address
+    StretchClock {
+        duration_us: u32,
+    },
+
+    /// Hold SDA low for the given duration, starting right away
+    ///
+    /// Makes the bus appear busy to any master.
+    HoldSda {
+        duration_us: u32,
+    },
+}
+
+
+/// An error that a node saw while acting as I2C master
+///
+/// Nodes map the errors of their I2C driver to these, so the host can check
+/// them regardless of the driver.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Error {
+    /// The slave didn't acknowledge its address
+    AddressNack,
+
+    /// The slave didn't acknowledge a data byte
+    DataNack,
+
+    /// The slave didn't acknowledge something
+    ///
+    /// Reported by drivers that don't distinguish between address and data.
+    Nack,
+
+    /// The bus didn't change state in time, for example due to clock
+    /// stretching
+    Timeout,
+
+    /// Another device drove the bus, while the master was sending
+    ArbitrationLoss,
+
+    /// Any other error
+    Other,
+}
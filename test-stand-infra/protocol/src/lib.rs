@@ -1,7 +1,13 @@
 #![no_std]
 
 
+pub mod i2c;
 pub mod pin;
+pub mod spi;
+pub mod usart;
+
+
+pub use self::pin::PinId;
 
 
 use core::convert::TryFrom;
@@ -15,31 +21,257 @@ use serde::{
 /// A message from the test suite on the host to the test assistant
 #[derive(Debug, Deserialize, Serialize)]
 pub enum HostToAssistant<'r> {
+    /// Ask the assistant to report its pin map
+    ///
+    /// The host sends this right after connecting, to find out which pins the
+    /// assistant provides.
+    Handshake,
+
     /// Instruct the assistant to send data to the target via USART
     SendUsart {
         mode: UsartMode,
         data: &'r [u8],
     },
 
+    /// Instruct the assistant to send data to an address via USART
+    ///
+    /// Requires 9 data bits to be configured for `UsartMode::Regular`. The
+    /// assistant sends `address` with the address bit set, followed by `data`.
+    SendUsartAddressed {
+        address: u8,
+        data:    &'r [u8],
+    },
+
+    /// Instruct the assistant to change the line parameters of a USART
+    ///
+    /// `mode` selects the USART that is used for that mode. The assistant
+    /// replies with `UsartConfigured`, or `UsartConfigError`.
+    ConfigureUsart {
+        mode:   UsartMode,
+        config: usart::LineConfig,
+    },
+
+    /// Instruct the assistant to inject a fault into a USART connection
+    ///
+    /// The assistant applies the fault relative to the line parameters that
+    /// were last set using `ConfigureUsart`.
+    InjectUsartFault {
+        mode: UsartMode,
+
+        #[serde(borrow)]
+        fault: usart::Fault<'r>,
+    },
+
+    /// Instruct the assistant to take part in a USART benchmark
+    ///
+    /// Depending on `direction`, the assistant either sends `bytes` bytes to
+    /// the target, or expects to receive them. It replies with
+    /// `UsartBenchmarkResult`, once it's done.
+    UsartBenchmark {
+        mode:      UsartMode,
+        direction: usart::Direction,
+        bytes:     u32,
+    },
+
+    /// Instruct the assistant to soak test the target's hardware flow control
+    ///
+    /// The assistant drives the target's CTS input, as described by `soak`,
+    /// while receiving data on the USART used for `UsartMode::FlowControl`.
+    StartFlowControlSoak(usart::FlowControlSoak),
+
+    /// Instruct the assistant to stop the flow control soak test
+    ///
+    /// The assistant enables CTS again, and replies with
+    /// `FlowControlSoakResult`.
+    StopFlowControlSoak,
+
+    /// Instruct the assistant to monitor the target's RS-485 driver enable
+    ///
+    /// The assistant watches the driver enable signal and the data line, until
+    /// driver enable has been asserted and released again, or until the
+    /// timeout expires. It replies with `Rs485Timing`.
+    MonitorRs485 {
+        timeout_ms: u32,
+    },
+
+    /// Instruct the assistant to monitor the target's SPI slave select
+    ///
+    /// The assistant watches slave select and the clock of its SPI slave,
+    /// until slave select has been asserted and released again, or until the
+    /// timeout expires. It replies with `SpiTiming`.
+    MonitorSpi {
+        timeout_ms: u32,
+    },
+
+    /// Instruct the assistant to emulate an I2C slave
+    ///
+    /// The emulated slave has `i2c::REGISTERS` registers, which all start out
+    /// as zero and writable. The first byte of every write from the target
+    /// sets the register pointer, further bytes are written to registers.
+    /// Reads start at the register pointer.
+    StartI2cSlave(i2c::SlaveConfig),
+
+    /// Instruct the assistant to set registers of the emulated I2C slave
+    ///
+    /// Sets the registers starting at `offset` to `data`, which must not be
+    /// longer than `i2c::MAX_TRANSFER`. If `read_only` is `true`, the target
+    /// can't change those registers. The assistant replies with
+    /// `I2cRegistersWritten`, or `I2cRegistersError`.
+    WriteI2cRegisters {
+        offset:    u8,
+        data:      &'r [u8],
+        read_only: bool,
+    },
+
+    /// Request the contents of registers of the emulated I2C slave
+    ///
+    /// `len` must not exceed `i2c::MAX_TRANSFER`. The assistant replies with
+    /// `I2cRegisters`, or `I2cRegistersError`.
+    ReadI2cRegisters {
+        offset: u8,
+        len:    u8,
+    },
+
+    /// Instruct the assistant to inject a fault into the I2C bus
+    InjectI2cFault(i2c::Fault),
+
+    /// Request the number of times the assistant's I2C slave was deselected
+    ///
+    /// The slave is deselected by a stop condition, but not by a repeated
+    /// start. It is also deselected, if it doesn't acknowledge its address, or
+    /// if the master doesn't acknowledge data. The assistant replies with
+    /// `I2cDeselections`, and starts counting from zero again.
+    ReadI2cDeselections,
+
+    /// Instruct the assistant to act as I2C master, and start a transfer
+    ///
+    /// The assistant writes `write` to the slave at `address`, then reads
+    /// `read_len` bytes from it, after a repeated start. Either may be empty,
+    /// but neither may be longer than `i2c::MAX_TRANSFER`. The assistant
+    /// replies with `I2cTransferReply` or `I2cError`.
+    StartI2cTransfer {
+        address:  u8,
+        write:    &'r [u8],
+        read_len: u8,
+    },
+
+    /// Instruct the assistant to stop emulating an I2C slave
+    ///
+    /// The assistant goes back to its default behavior, answering reads with
+    /// the data of the last write, shifted left by one bit.
+    StopI2cSlave,
+
+    /// Instruct the assistant to reconfigure its SPI slave
+    ///
+    /// Whatever the configuration, the slave answers every byte with the byte
+    /// it received before, shifted left by one bit. After slave select has
+    /// been deasserted, the assistant sends `SpiReceived`.
+    ConfigureSpi(spi::Config),
+
+    /// Instruct the assistant to act as SPI master, and start a transfer
+    ///
+    /// The assistant switches its SPI to master mode, sends `data`, which must
+    /// not be longer than `spi::MAX_TRANSFER`, then switches back to slave
+    /// mode. It replies with `SpiTransferReply`, which carries the data
+    /// received at the same time.
+    StartSpiTransfer {
+        data: &'r [u8],
+    },
+
+    /// Instruct the assistant to emulate an SPI NOR flash
+    ///
+    /// The emulated flash has `spi::FLASH_SIZE` bytes of memory, which all
+    /// start out erased (`0xff`). It supports the instructions listed in
+    /// `spi::instruction`. While it is emulated, the assistant doesn't send
+    /// `SpiReceived`.
+    StartSpiFlash(spi::FlashConfig),
+
+    /// Instruct the assistant to set the memory of the emulated SPI flash
+    ///
+    /// Sets the memory starting at `address` to `data`, which must not be
+    /// longer than `spi::MAX_TRANSFER`. Unlike programming by the target, this
+    /// can set bits too.
+    WriteSpiFlash {
+        address: u32,
+        data:    &'r [u8],
+    },
+
+    /// Request the memory of the emulated SPI flash
+    ///
+    /// `len` must not exceed `spi::MAX_TRANSFER`. The assistant replies with
+    /// `SpiFlash`, or `SpiFlashError`.
+    ReadSpiFlash {
+        address: u32,
+        len:     u8,
+    },
+
+    /// Instruct the assistant to stop emulating an SPI flash
+    StopSpiFlash,
+
     /// Instruct the assistant to change level of the target's input pin
-    SetPin(pin::SetLevel<OutputPin>),
+    SetPin(pin::SetLevel<PinId>),
 
     /// Ask the assistant for the current level of a pin
-    ReadPin(pin::ReadLevel<InputPin>),
+    ReadPin(pin::ReadLevel<PinId>),
+
+    /// Instruct the assistant to change the configuration of a pin
+    ConfigurePin(pin::Configure<PinId>),
+
+    /// Ask the assistant to measure the periodic signal on a pin
+    MeasureSignal(pin::MeasureSignal<PinId>),
+
+    /// Ask the assistant to measure how fast the target follows an output pin
+    MeasureLatency(pin::MeasureLatency<PinId>),
+
+    /// Instruct the assistant to play a waveform on one of its output pins
+    GenerateWaveform(pin::GenerateWaveform<PinId>),
+
+    /// Instruct the assistant to stop the waveform on one of its output pins
+    StopWaveform(pin::StopWaveform<PinId>),
 }
 
-impl From<pin::SetLevel<OutputPin>> for HostToAssistant<'_> {
-    fn from(set_level: pin::SetLevel<OutputPin>) -> Self {
+impl From<pin::SetLevel<PinId>> for HostToAssistant<'_> {
+    fn from(set_level: pin::SetLevel<PinId>) -> Self {
         Self::SetPin(set_level)
     }
 }
 
-impl From<pin::ReadLevel<InputPin>> for HostToAssistant<'_> {
-    fn from(read_level: pin::ReadLevel<InputPin>) -> Self {
+impl From<pin::ReadLevel<PinId>> for HostToAssistant<'_> {
+    fn from(read_level: pin::ReadLevel<PinId>) -> Self {
         Self::ReadPin(read_level)
     }
 }
 
+impl From<pin::Configure<PinId>> for HostToAssistant<'_> {
+    fn from(configure: pin::Configure<PinId>) -> Self {
+        Self::ConfigurePin(configure)
+    }
+}
+
+impl From<pin::MeasureSignal<PinId>> for HostToAssistant<'_> {
+    fn from(measure_signal: pin::MeasureSignal<PinId>) -> Self {
+        Self::MeasureSignal(measure_signal)
+    }
+}
+
+impl From<pin::MeasureLatency<PinId>> for HostToAssistant<'_> {
+    fn from(measure_latency: pin::MeasureLatency<PinId>) -> Self {
+        Self::MeasureLatency(measure_latency)
+    }
+}
+
+impl From<pin::GenerateWaveform<PinId>> for HostToAssistant<'_> {
+    fn from(generate_waveform: pin::GenerateWaveform<PinId>) -> Self {
+        Self::GenerateWaveform(generate_waveform)
+    }
+}
+
+impl From<pin::StopWaveform<PinId>> for HostToAssistant<'_> {
+    fn from(stop_waveform: pin::StopWaveform<PinId>) -> Self {
+        Self::StopWaveform(stop_waveform)
+    }
+}
+
 
 /// A message from the test assistant to the test suite on the host
 #[derive(Debug, Deserialize, Serialize)]
@@ -50,11 +282,94 @@ pub enum AssistantToHost<'r> {
         data: &'r [u8],
     },
 
+    /// Notify the host that errors occurred while receiving via USART
+    ///
+    /// The host reports this as an error from whatever call comes next.
+    Diagnostics(usart::Diagnostics),
+
+    /// Reply to a `UsartBenchmark` request
+    UsartBenchmarkResult(usart::BenchmarkResult),
+
+    /// Reply to a `StopFlowControlSoak` request
+    FlowControlSoakResult(usart::FlowControlSoakResult),
+
+    /// Reply to a `MonitorRs485` request
+    Rs485Timing(usart::Rs485Timing),
+
+    /// Reply to a `MonitorSpi` request
+    SpiTiming(spi::Timing),
+
+    /// Reply to a `ConfigureUsart` request
+    UsartConfigured,
+
+    /// Reply to a `ConfigureUsart` request that failed
+    ///
+    /// The USART keeps its previous line parameters.
+    UsartConfigError(usart::ConfigError),
+
+    /// Reply to a `ReadI2cRegisters` request
+    I2cRegisters(&'r [u8]),
+
+    /// Reply to a `WriteI2cRegisters` request
+    I2cRegistersWritten,
+
+    /// Reply to a `WriteI2cRegisters` or `ReadI2cRegisters` request that failed
+    ///
+    /// The assistant doesn't emulate a device with registers, or the registers
+    /// are out of range.
+    I2cRegistersError,
+
+    /// Reply to a `ReadI2cDeselections` request
+    I2cDeselections(u32),
+
+    /// Reply to a `StartI2cTransfer` request, carrying the data read
+    I2cTransferReply(&'r [u8]),
+
+    /// Reply to a `StartI2cTransfer` request that failed
+    I2cError(i2c::Error),
+
+    /// Reply to a `StartSpiTransfer` request, carrying the data received
+    SpiTransferReply(&'r [u8]),
+
+    /// Notify the host of the data received by the SPI slave
+    ///
+    /// Sent after slave select has been deasserted. Contains at most
+    /// `spi::MAX_TRANSFER` bytes.
+    SpiReceived(&'r [u8]),
+
+    /// Reply to a `ReadSpiFlash` request
+    SpiFlash(&'r [u8]),
+
+    /// Reply to a `ReadSpiFlash` request that failed
+    ///
+    /// The assistant doesn't emulate a flash, or the memory is out of range.
+    SpiFlashError,
+
     /// Notify the host that the level of a pin has changed
-    ReadPinResult(Option<pin::ReadLevelResult<InputPin>>),
+    ReadPinResult(Option<pin::ReadLevelResult<PinId>>),
+
+    /// Reply to a `MeasureSignal` request
+    MeasureSignalResult(pin::MeasureSignalResult<PinId>),
+
+    /// Reply to a measurement request that can't be carried out
+    MeasureError(pin::MeasureError<PinId>),
+
+    /// Reply to a `MeasureLatency` request
+    MeasureLatencyResult(pin::MeasureLatencyResult<PinId>),
+
+    /// Reply to a `Handshake` request
+    ///
+    /// Announces the number of pins the assistant provides. Each pin is then
+    /// described in a separate `HandshakePin` message.
+    HandshakeResult {
+        pins: u8,
+    },
+
+    /// Describes one of the pins announced in `HandshakeResult`
+    HandshakePin(#[serde(borrow)] pin::PinInfo<'r>),
 }
 
-impl<'r> TryFrom<AssistantToHost<'r>> for pin::ReadLevelResult<InputPin> {
+impl<'r> TryFrom<AssistantToHost<'r>> for pin::ReadLevelResult<PinId> {
     type Error = AssistantToHost<'r>;
 
     fn try_from(value: AssistantToHost<'r>) -> Result<Self, Self::Error> {
@@ -69,6 +384,46 @@ impl<'r> TryFrom<AssistantToHost<'r>> for pin::ReadLevelResult<InputPin> {
     }
 }
 
+impl<'r> TryFrom<AssistantToHost<'r>>
+    for Result<pin::MeasureSignalResult<PinId>, pin::MeasureError<PinId>>
+{
+    type Error = AssistantToHost<'r>;
+
+    fn try_from(value: AssistantToHost<'r>) -> Result<Self, Self::Error> {
+        match value {
+            AssistantToHost::MeasureSignalResult(result) => {
+                Ok(Ok(result))
+            }
+            AssistantToHost::MeasureError(err) => {
+                Ok(Err(err))
+            }
+            _ => {
+                Err(value)
+            }
+        }
+    }
+}
+
+impl<'r> TryFrom<AssistantToHost<'r>>
+    for Result<pin::MeasureLatencyResult<PinId>, pin::MeasureError<PinId>>
+{
+    type Error = AssistantToHost<'r>;
+
+    fn try_from(value: AssistantToHost<'r>) -> Result<Self, Self::Error> {
+        match value {
+            AssistantToHost::MeasureLatencyResult(result) => {
+                Ok(Ok(result))
+            }
+            AssistantToHost::MeasureError(err) => {
+                Ok(Err(err))
+            }
+            _ => {
+                Err(value)
+            }
+        }
+    }
+}
+
 
 /// Specifies which mode a USART transmission uses
 #[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
@@ -77,22 +432,13 @@ pub enum UsartMode {
     Dma,
     FlowControl,
     Sync,
-}
 
+    /// Half-duplex, with the sender controlling an RS-485 driver enable signal
+    Rs485,
 
-/// Represents one of the pins that the assistant is monitoring
-#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
-pub enum InputPin {
-    Blue  = 0,
-    Green = 1,
-    Rts   = 2,
-    Pwm   = 3,
-}
-
-/// Represents one of the pins that the assistant can set
-#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
-pub enum OutputPin {
-    Pin5,
-    Cts,
-    Red,
+    /// LIN, with the assistant as master and the target as slave
+    ///
+    /// The assistant sends a break before the data. The target reports the
+    /// data it receives after a break in this mode, once it acts as a slave.
+    Lin,
 }
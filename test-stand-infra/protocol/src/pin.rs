@@ -4,12 +4,35 @@
 //! be re-used for different test stands.
 
 
+use heapless::Vec;
 use serde::{
     Deserialize,
     Serialize,
 };
 
 
+/// Identifies a pin on a test node
+///
+/// Which physical pin an id refers to is up to the test node. The node reports
+/// all of its pins, along with their names, during the handshake.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq, Hash)]
+pub struct PinId(pub u8);
+
+
+/// Describes one of the pins of a test node
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct PinInfo<'r> {
+    /// The id that identifies the pin in all pin-related messages
+    pub id: PinId,
+
+    /// The name of the pin, if it has one
+    ///
+    /// The host uses the name to resolve the pins it needs.
+    #[serde(borrow)]
+    pub name: Option<&'r str>,
+}
+
+
 /// Sent by the host to command a test node to set a pin to a specific level
 #[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
 pub struct SetLevel<Id> {
@@ -49,6 +72,385 @@ pub struct ReadLevelResult<Id> {
 }
 
 
+/// Sent by the host to command a test node to change a pin's configuration
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Configure<Id> {
+    /// The pin that should be configured
+    pub pin: Id,
+
+    /// The new configuration of the pin
+    pub config: Config,
+}
+
+
+/// The electrical configuration of a pin
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Config {
+    /// Whether the pin is an input or an output
+    pub direction: Direction,
+
+    /// Which pull resistor to enable, if any
+    pub pull: Pull,
+
+    /// How the pin drives its output
+    ///
+    /// Ignored, if the pin is configured as an input.
+    pub output_mode: OutputMode,
+}
+
+impl Config {
+    /// Configuration for a floating input pin
+    pub fn input() -> Self {
+        Self {
+            direction:   Direction::Input,
+            pull:        Pull::None,
+            output_mode: OutputMode::PushPull,
+        }
+    }
+
+    /// Configuration for an input pin with the given pull resistor
+    pub fn input_with_pull(pull: Pull) -> Self {
+        Self {
+            pull,
+            .. Self::input()
+        }
+    }
+
+    /// Configuration for a push-pull output pin with the given initial level
+    pub fn output(level: Level) -> Self {
+        Self {
+            direction:   Direction::Output(level),
+            pull:        Pull::None,
+            output_mode: OutputMode::PushPull,
+        }
+    }
+
+    /// Configuration for an open-drain output pin
+    pub fn open_drain(level: Level, pull: Pull) -> Self {
+        Self {
+            direction:   Direction::Output(level),
+            pull,
+            output_mode: OutputMode::OpenDrain,
+        }
+    }
+}
+
+
+/// The direction of a pin
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Direction {
+    /// The pin is an input
+    Input,
+
+    /// The pin is an output, initially set to the given level
+    Output(Level),
+}
+
+
+/// The pull resistor configuration of a pin
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Pull {
+    None,
+    Up,
+    Down,
+}
+
+
+/// Specifies how an output pin drives its level
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum OutputMode {
+    /// The pin actively drives both high and low levels
+    PushPull,
+
+    /// The pin only drives the low level, and floats when set high
+    OpenDrain,
+}
+
+
+/// Sent by the host to request a measurement of a periodic signal on a pin
+///
+/// The test node measures the given number of full cycles of the signal, then
+/// replies with a `MeasureSignalResult` message. If it can't measure the
+/// signal, it replies with a `MeasureError` message instead.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct MeasureSignal<Id> {
+    /// The pin whose signal to measure
+    pub pin: Id,
+
+    /// The number of cycles to measure
+    ///
+    /// A cycle consists of a high phase, followed by a low phase. Must not be
+    /// zero.
+    pub cycles: u32,
+}
+
+
+/// Sent by a test node in response to a `MeasureSignal` message
+///
+/// All durations are in microseconds.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct MeasureSignalResult<Id> {
+    /// The pin whose signal was measured
+    pub pin: Id,
+
+    /// The number of cycles that were measured
+    pub cycles: u32,
+
+    /// Statistics about the duration of the high phases
+    pub high_us: Statistics,
+
+    /// Statistics about the duration of the low phases
+    pub low_us: Statistics,
+
+    /// Statistics about the duration of the full cycles
+    pub period_us: Statistics,
+
+    /// Statistics about the duty cycles, in per mille
+    pub duty_cycle_permille: Statistics,
+}
+
+
+/// Sent by a test node, if it can't carry out a requested measurement
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct MeasureError<Id> {
+    /// The pin that the measurement was requested for
+    pub pin: Id,
+
+    /// Why the measurement can't be carried out
+    pub kind: MeasureErrorKind,
+}
+
+
+/// The reason for a `MeasureError`
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum MeasureErrorKind {
+    /// The test node can't measure on this pin
+    UnsupportedPin,
+
+    /// The request asked for zero cycles or samples
+    NothingToMeasure,
+
+    /// The test node is generating a waveform that the measurement would stop
+    Busy,
+}
+
+
+/// Statistics about a series of measured values
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Statistics {
+    /// The smallest value measured
+    pub min: u32,
+
+    /// The largest value measured
+    pub max: u32,
+
+    /// The sum of all values measured
+    ///
+    /// Divide by the number of values to get the mean.
+    pub sum: u64,
+}
+
+impl Statistics {
+    /// Create a new instance of `Statistics` that contains a single value
+    pub fn new(value: u32) -> Self {
+        Self {
+            min: value,
+            max: value,
+            sum: value as u64,
+        }
+    }
+
+    /// Add another value to the statistics
+    pub fn add(&mut self, value: u32) {
+        self.min = Ord::min(self.min, value);
+        self.max = Ord::max(self.max, value);
+        self.sum += value as u64;
+    }
+}
+
+
+/// Sent by the host to command a test node to measure interrupt latency
+///
+/// The test node repeatedly toggles the `output` pin, and measures how long it
+/// takes until the `input` pin follows. This is intended to measure how fast
+/// another test node reacts to a level change using an interrupt. If the node
+/// can't measure, it replies with a `MeasureError` message.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct MeasureLatency<Id> {
+    /// The pin that is toggled
+    pub output: Id,
+
+    /// The pin that is expected to follow the output pin
+    pub input: Id,
+
+    /// The number of edges to measure
+    pub samples: u32,
+
+    /// The width of each bin in the resulting histogram, in nanoseconds
+    pub bin_width_ns: u32,
+}
+
+
+/// Sent by a test node in response to a `MeasureLatency` message
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct MeasureLatencyResult<Id> {
+    /// The pin that was toggled
+    pub output: Id,
+
+    /// The pin that was expected to follow the output pin
+    pub input: Id,
+
+    /// The number of edges for which the input pin did follow
+    pub samples: u32,
+
+    /// The number of edges for which the input pin did not follow in time
+    pub timeouts: u32,
+
+    /// Statistics about the measured latencies, in nanoseconds
+    ///
+    /// This is `None`, if no latencies could be measured.
+    pub latency_ns: Option<Statistics>,
+
+    /// Histogram of the measured latencies
+    pub histogram: Histogram,
+}
+
+
+/// The maximum number of bins in a `Histogram`
+pub const MAX_BINS: usize = 32;
+
+/// A histogram of measured values
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Histogram {
+    /// The width of each bin
+    pub bin_width: u32,
+
+    /// The number of values that fall into each bin
+    ///
+    /// The first bin counts values from `0` up to (excluding) `bin_width`, the
+    /// second bin counts values from `bin_width` up to `2 * bin_width`, and so
+    /// on. The last bin also counts all values that are too large for any of
+    /// the bins.
+    pub bins: Vec<u32, MAX_BINS>,
+}
+
+impl Histogram {
+    /// Create an empty histogram
+    ///
+    /// # Panics
+    ///
+    /// Panics, if `bin_width` is zero.
+    pub fn new(bin_width: u32) -> Self {
+        assert!(bin_width > 0);
+
+        let mut bins = Vec::new();
+        bins.resize(MAX_BINS, 0)
+            // Can't fail, as we're resizing to exactly the capacity.
+            .unwrap();
+
+        Self {
+            bin_width,
+            bins,
+        }
+    }
+
+    /// Add a value to the histogram
+    pub fn add(&mut self, value: u32) {
+        let i = Ord::min(value / self.bin_width, MAX_BINS as u32 - 1);
+        self.bins[i as usize] += 1;
+    }
+}
+
+
+/// Sent by the host to command a test node to play a waveform on a pin
+///
+/// The waveform replaces any waveform that is currently playing on the pin. It
+/// keeps playing until it ends, or until a `StopWaveform` message is received.
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct GenerateWaveform<Id> {
+    /// The pin on which the waveform should be generated
+    pub pin: Id,
+
+    /// The waveform to generate
+    pub waveform: Waveform,
+}
+
+
+/// Sent by the host to command a test node to stop playing a waveform
+///
+/// The pin keeps the level it had when the waveform was stopped.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct StopWaveform<Id> {
+    /// The pin whose waveform should be stopped
+    pub pin: Id,
+}
+
+
+/// A waveform that a test node can generate on an output pin
+#[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Waveform {
+    /// A sequence of levels, each held for a specific duration
+    Steps {
+        /// The steps that make up the waveform
+        steps: Vec<Step, MAX_STEPS>,
+
+        /// Whether to start over after the last step
+        ///
+        /// If this is `false`, the pin keeps the level of the last step.
+        repeat: bool,
+    },
+
+    /// A periodic square wave
+    Square {
+        /// The frequency of the square wave, in Hz
+        frequency_hz: u32,
+
+        /// The portion of each period that the signal is high, in percent
+        ///
+        /// Values larger than `100` are treated like `100`.
+        duty_cycle_percent: u8,
+    },
+}
+
+impl Waveform {
+    /// Create a waveform from a sequence of steps
+    ///
+    /// Returns an error, if `steps` contains more than [`MAX_STEPS`] steps.
+    pub fn steps(steps: &[Step], repeat: bool)
+        -> Result<Self, TooManyStepsError>
+    {
+        let steps = Vec::from_slice(steps)
+            .map_err(|()| TooManyStepsError)?;
+        Ok(Self::Steps { steps, repeat })
+    }
+
+    /// Create a square wave with the given frequency and duty cycle
+    pub fn square(frequency_hz: u32, duty_cycle_percent: u8) -> Self {
+        Self::Square { frequency_hz, duty_cycle_percent }
+    }
+}
+
+
+/// A single step in a [`Waveform::Steps`] waveform
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Step {
+    /// The level of the pin during this step
+    pub level: Level,
+
+    /// The duration of this step, in microseconds
+    pub duration_us: u32,
+}
+
+
+/// The maximum number of steps in a [`Waveform::Steps`] waveform
+pub const MAX_STEPS: usize = 16;
+
+
+/// Returned by [`Waveform::steps`], if too many steps were passed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TooManyStepsError;
+
+
 /// Represents the electrical level of a pin
 #[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
 pub enum Level {
@@ -0,0 +1,177 @@
+//! Generic protocol related to SPI
+//!
+//! The types in this module are not specific to any test stand setup, and can
+//! be re-used for different test stands.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+
+/// The maximum number of bytes exchanged in one SPI transfer
+pub const MAX_TRANSFER: usize = 32;
+
+/// The size of the memory of an emulated SPI flash, in bytes
+///
+/// Addresses wrap around after the end of the memory, like on a real flash.
+pub const FLASH_SIZE: usize = 2048;
+
+/// The size of a page of an emulated SPI flash, in bytes
+///
+/// A page program instruction wraps around at the end of the page.
+pub const FLASH_PAGE_SIZE: usize = 256;
+
+
+/// The instructions an emulated SPI flash supports
+///
+/// These are the same for most SPI NOR flash devices. Instructions that take
+/// an address are followed by 3 address bytes, most significant first.
+pub mod instruction {
+    /// Read the JEDEC ID: manufacturer, memory type, capacity
+    pub const READ_JEDEC_ID: u8 = 0x9f;
+
+    /// Read data, starting at an address
+    pub const READ_DATA: u8 = 0x03;
+
+    /// Program data, starting at an address
+    ///
+    /// Requires a preceding `WRITE_ENABLE`. Programming can only clear bits.
+    pub const PAGE_PROGRAM: u8 = 0x02;
+
+    /// Read the status register, see `STATUS_*`
+    pub const READ_STATUS: u8 = 0x05;
+
+    /// Set the write enable latch
+    pub const WRITE_ENABLE: u8 = 0x06;
+
+    /// Clear the write enable latch
+    pub const WRITE_DISABLE: u8 = 0x04;
+
+    /// Status register bit: a program operation is in progress
+    ///
+    /// The emulated flash programs immediately, so this is never set.
+    pub const STATUS_BUSY: u8 = 0x01;
+
+    /// Status register bit: the write enable latch is set
+    pub const STATUS_WEL: u8 = 0x02;
+}
+
+
+/// Configures both ends of an SPI connection
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Config {
+    /// Clock polarity and phase
+    pub mode: Mode,
+
+    /// Whether the most or least significant bit is transferred first
+    pub bit_order: BitOrder,
+
+    /// The value the master divides its SPI clock by, minus one
+    ///
+    /// Ignored by the slave, which is clocked by the master.
+    pub clock_divider: u16,
+}
+
+impl Default for Config {
+    /// The default configuration is mode 0, MSB first, with a clock divider
+    /// of `0x0fff`
+    ///
+    /// This matches the divider the LPC845 target used, before its SPI
+    /// configuration could be changed.
+    fn default() -> Self {
+        Self {
+            mode:          Mode::Mode0,
+            bit_order:     BitOrder::MsbFirst,
+            clock_divider: 0x0fff,
+        }
+    }
+}
+
+
+/// Configures an emulated SPI flash
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct FlashConfig {
+    /// The JEDEC ID: manufacturer, memory type, capacity
+    pub jedec_id: [u8; 3],
+}
+
+
+/// The timing of an SPI transfer, as observed by a monitoring node
+///
+/// All times are in nanoseconds, relative to the moment the monitor first saw
+/// slave select inactive. Each time is `None`, if the respective event wasn't
+/// observed before the monitor timed out.
+///
+/// Every byte is 8 clock cycles, so 16 clock edges, long. A gap is the time
+/// from the last clock edge of a byte to the first clock edge of the next one.
+/// Bytes that are transferred back to back have a gap of half a clock period.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Timing {
+    /// When slave select was asserted
+    pub ssel_asserted_ns: Option<u32>,
+
+    /// When the clock changed for the first time, after slave select was
+    /// asserted
+    pub first_clock_ns: Option<u32>,
+
+    /// When the clock changed for the last time, before slave select was
+    /// released
+    pub last_clock_ns: Option<u32>,
+
+    /// When slave select was released
+    pub ssel_released_ns: Option<u32>,
+
+    /// The number of complete bytes that were clocked
+    pub bytes: u32,
+
+    /// The shortest gap between two bytes, `None` for less than two bytes
+    pub min_gap_ns: Option<u32>,
+
+    /// The longest gap between two bytes, `None` for less than two bytes
+    pub max_gap_ns: Option<u32>,
+}
+
+
+/// The clock polarity (CPOL) and phase (CPHA) of an SPI connection
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Mode {
+    /// CPOL = 0, CPHA = 0
+    Mode0,
+
+    /// CPOL = 0, CPHA = 1
+    Mode1,
+
+    /// CPOL = 1, CPHA = 0
+    Mode2,
+
+    /// CPOL = 1, CPHA = 1
+    Mode3,
+}
+
+impl Mode {
+    /// Indicates whether the clock is high while idle (CPOL = 1)
+    pub fn idle_high(&self) -> bool {
+        match self {
+            Mode::Mode0 | Mode::Mode1 => false,
+            Mode::Mode2 | Mode::Mode3 => true,
+        }
+    }
+
+    /// Indicates whether data is captured on the second clock edge (CPHA = 1)
+    pub fn capture_on_second_edge(&self) -> bool {
+        match self {
+            Mode::Mode0 | Mode::Mode2 => false,
+            Mode::Mode1 | Mode::Mode3 => true,
+        }
+    }
+}
+
+
+/// The order in which the bits of a byte are transferred
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum BitOrder {
+    MsbFirst,
+    LsbFirst,
+}
@@ -0,0 +1,331 @@
+//! Generic protocol related to USARTs
+//!
+//! The types in this module are not specific to any test stand setup, and can
+//! be re-used for different test stands.
+
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use crate::UsartMode;
+
+
+/// The value of the sync byte that follows the break of a LIN frame
+pub const LIN_SYNC: u8 = 0x55;
+
+
+/// The line parameters of a USART
+///
+/// Both sides of a USART connection must use the same parameters.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct LineConfig {
+    /// The baud rate in bits per second
+    pub baud_rate: u32,
+
+    /// The number of data bits per character
+    pub data_bits: DataBits,
+
+    /// The type of parity bit, if any
+    pub parity: Parity,
+
+    /// The number of stop bits
+    pub stop_bits: StopBits,
+}
+
+impl LineConfig {
+    /// The number of bits it takes to transmit one character
+    ///
+    /// Includes the start bit, data bits, parity bit, and stop bits.
+    pub fn bits_per_char(&self) -> u32 {
+        let data_bits = match self.data_bits {
+            DataBits::Seven => 7,
+            DataBits::Eight => 8,
+            DataBits::Nine  => 9,
+        };
+        let parity_bits = match self.parity {
+            Parity::None               => 0,
+            Parity::Even | Parity::Odd => 1,
+        };
+        let stop_bits = match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        };
+
+        1 + data_bits + parity_bits + stop_bits
+    }
+}
+
+impl Default for LineConfig {
+    /// The default configuration is 115200 baud, 8 data bits, no parity, and 1
+    /// stop bit (115200 8N1)
+    fn default() -> Self {
+        Self {
+            baud_rate: 115_200,
+            data_bits: DataBits::Eight,
+            parity:    Parity::None,
+            stop_bits: StopBits::One,
+        }
+    }
+}
+
+
+/// Why a test node can't apply a `LineConfig`
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum ConfigError {
+    /// The baud rate can't be derived from the USART's clock
+    UnsupportedBaudRate(u32),
+
+    /// The USART doesn't support this combination of data bits and parity
+    UnsupportedFormat,
+
+    /// The line parameters of the USART used in this mode can't be changed
+    UnsupportedMode,
+}
+
+
+/// The number of data bits per character
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum DataBits {
+    Seven,
+    Eight,
+
+    /// 8 data bits, plus a bit that marks addresses (multiprocessor mode)
+    Nine,
+}
+
+
+/// The type of parity bit
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+
+/// The number of stop bits
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+
+/// How a USART receiver splits received data into frames
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum RxFraming {
+    /// Received data is delivered in chunks of a fixed size
+    ///
+    /// Data doesn't have frame boundaries in this mode.
+    Chunks,
+
+    /// A frame ends, once the line has been idle for two character times
+    ///
+    /// Allows for some jitter, as LPC8xx USARTs detect this in software.
+    Idle,
+
+    /// A frame ends, if no character has been received for the given time
+    CharTimeout {
+        timeout_us: u32,
+    },
+}
+
+
+/// The reason a receiver considered a frame to be complete
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum FrameEnd {
+    /// The line was idle for two character times (see `RxFraming::Idle`)
+    Idle,
+
+    /// The character timeout expired (see `RxFraming::CharTimeout`)
+    Timeout,
+
+    /// The receive buffer was full
+    ///
+    /// More data of the same frame might follow in the next frame.
+    BufferFull,
+}
+
+
+/// An error that was detected by a USART receiver
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum RxError {
+    /// The line was held low for longer than a character
+    Break,
+
+    /// A character's stop bit was not detected
+    Framing,
+
+    /// Noise was detected while receiving a character
+    Noise,
+
+    /// A character was received before the previous one had been read
+    Overrun,
+
+    /// A character's parity bit was wrong
+    Parity,
+}
+
+
+/// Diagnostic information about a USART
+///
+/// Firmware sends this to the host, whenever new errors have occurred while
+/// receiving.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Diagnostics {
+    /// The USART that the errors occurred on
+    pub link: Link,
+
+    /// The errors that occurred since the last report
+    pub rx_errors: RxErrorCounts,
+}
+
+
+/// Identifies one of the USARTs of a firmware
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Link {
+    /// The USART that connects the firmware to the host
+    Host,
+
+    /// The USART that is used in the given mode
+    Usart(UsartMode),
+}
+
+
+/// The number of errors that occurred while receiving via a USART
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, Eq, PartialEq)]
+pub struct RxErrorCounts {
+    pub framing: u32,
+    pub noise:   u32,
+    pub overrun: u32,
+    pub parity:  u32,
+
+    /// Received bytes that were dropped, because the receive queue was full
+    pub queue_full: u32,
+}
+
+impl RxErrorCounts {
+    /// Indicates whether no errors have been counted
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the number of errors that were counted since `earlier`
+    ///
+    /// Expects both to be snapshots of the same, continuously increasing
+    /// counters. Handles counters that have wrapped around.
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            framing:    self.framing.wrapping_sub(earlier.framing),
+            noise:      self.noise.wrapping_sub(earlier.noise),
+            overrun:    self.overrun.wrapping_sub(earlier.overrun),
+            parity:     self.parity.wrapping_sub(earlier.parity),
+            queue_full: self.queue_full.wrapping_sub(earlier.queue_full),
+        }
+    }
+}
+
+
+/// A fault that can be injected into a USART connection
+///
+/// All faults are relative to the line parameters that the receiver has been
+/// configured with.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Fault<'r> {
+    /// Send a character with the wrong parity bit
+    ///
+    /// Requires the receiver to expect a parity bit.
+    Parity,
+
+    /// Send a character that is missing its stop bit
+    Framing,
+
+    /// Hold the line low for the given number of microseconds
+    Break {
+        duration_us: u32,
+    },
+
+    /// Send data at a different baud rate than the receiver expects
+    BaudRate {
+        baud_rate: u32,
+        data:      &'r [u8],
+    },
+}
+
+
+/// The direction in which a node transfers data during a benchmark
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum Direction {
+    Send,
+    Receive,
+}
+
+
+/// The result of a USART benchmark, as measured by one node
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct BenchmarkResult {
+    /// The direction in which the node transferred data
+    pub direction: Direction,
+
+    /// The number of bytes that were transferred
+    ///
+    /// Can be lower than the requested number, if bytes were lost.
+    pub bytes: u32,
+
+    /// The number of timer ticks until the last byte was transferred
+    ///
+    /// A sender starts counting before sending the first byte, a receiver
+    /// when receiving the first byte.
+    pub ticks: u32,
+
+    /// The frequency of the timer that measured `ticks`
+    pub ticks_per_second: u32,
+}
+
+
+/// Parameters for soak testing hardware flow control
+///
+/// The receiving node toggles its flow control output at random intervals,
+/// between `min_interval_us` and `max_interval_us`. Regardless of that, it
+/// stops the sender while its receive queue is nearly full.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct FlowControlSoak {
+    /// The seed for the random intervals
+    ///
+    /// The same seed results in the same sequence of intervals.
+    pub seed: u32,
+
+    pub min_interval_us: u32,
+    pub max_interval_us: u32,
+}
+
+
+/// The result of a flow control soak test, as measured by the receiver
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct FlowControlSoakResult {
+    /// How often the flow control output was toggled at random
+    pub toggles: u32,
+
+    /// How often the sender was stopped, because the receive queue filled up
+    pub throttled: u32,
+}
+
+
+/// The timing of an RS-485 transmission, as observed by a monitoring node
+///
+/// All times are in nanoseconds, relative to the moment the monitor first saw
+/// the driver enable signal inactive. Each time is `None`, if the respective
+/// event wasn't observed before the monitor timed out.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub struct Rs485Timing {
+    /// When the driver enable signal was asserted
+    pub de_asserted_ns: Option<u32>,
+
+    /// When the data line went low for the first time (first start bit)
+    pub first_start_ns: Option<u32>,
+
+    /// When the driver enable signal was released
+    pub de_released_ns: Option<u32>,
+}